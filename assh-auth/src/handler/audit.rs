@@ -0,0 +1,76 @@
+//! The audit trail of the authentication attempts.
+
+use std::time::SystemTime;
+
+use ssh_key::Fingerprint;
+
+/// The outcome of an authentication attempt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// The user is authenticated.
+    Success,
+
+    /// The method succeeded, but more methods are required.
+    Partial,
+
+    /// The attempt failed.
+    Failure,
+
+    /// The method needs more exchanges to conclude, such as a `publickey` query.
+    Continue,
+}
+
+/// The key presented in a `publickey` attempt.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyDetails {
+    /// The name of the signature algorithm requested by the client.
+    pub algorithm: String,
+
+    /// The fingerprint of the key, or of the certified key, if it could be parsed.
+    pub fingerprint: Option<Fingerprint>,
+
+    /// Whether the attempt is signed, or only queries the acceptability of the key.
+    pub signed: bool,
+}
+
+/// An authentication attempt, as reported to the [`Audit`] sink, which never holds any password.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthEvent {
+    /// The time of the attempt.
+    pub timestamp: SystemTime,
+
+    /// The username of the attempt.
+    pub username: String,
+
+    /// The name of the method of the attempt, such as `publickey` or a custom method.
+    pub method: String,
+
+    /// The key presented in a `publickey` attempt.
+    pub key: Option<KeyDetails>,
+
+    /// The outcome of the attempt.
+    pub outcome: Outcome,
+}
+
+/// A sink for the [`AuthEvent`]s, whose errors are logged without interrupting the authentication.
+pub trait Audit: Send + Sync {
+    /// Record the authentication `event`.
+    fn record(&mut self, event: AuthEvent) -> Result<(), Box<dyn std::error::Error + Send + Sync>>;
+}
+
+impl<T: FnMut(AuthEvent) + Send + Sync> Audit for T {
+    fn record(&mut self, event: AuthEvent) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        (self)(event);
+
+        Ok(())
+    }
+}
+
+/// The [`Audit`] sink of the service.
+pub(super) struct Sink(pub Box<dyn Audit>);
+
+impl std::fmt::Debug for Sink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Sink(..)")
+    }
+}
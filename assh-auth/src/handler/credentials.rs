@@ -0,0 +1,146 @@
+//! An asynchronous store of credentials, backing both the `password` and `publickey` methods.
+
+use std::sync::Arc;
+
+use futures::Future;
+use ssh_key::{Certificate, PublicKey};
+
+use super::{password, publickey, AuthContext};
+
+mod memory;
+pub use memory::Memory;
+
+/// An interface to a store of credentials, such as a database or a directory service.
+///
+/// The failures of the backend are left to the store to report, and are expected
+/// to be handled as an unknown user, or as credentials which don't match.
+pub trait CredentialStore: Send + Sync {
+    /// Whether the `user` is known to the store.
+    fn lookup_user(&self, user: &str) -> impl Future<Output = bool>;
+
+    /// Whether the `password` matches the credentials of the `user`.
+    ///
+    /// This is also called for unknown users, for the timing of the responses to stay uniform.
+    fn verify_password(&self, user: &str, password: &str) -> impl Future<Output = bool>;
+
+    /// The keys authorized to authenticate as the `user`.
+    fn authorized_keys_for(&self, user: &str) -> impl Future<Output = Vec<PublicKey>>;
+}
+
+/// A store shared across the sessions, each with it's own [`Credentials`].
+impl<T: CredentialStore> CredentialStore for Arc<T> {
+    fn lookup_user(&self, user: &str) -> impl Future<Output = bool> {
+        (**self).lookup_user(user)
+    }
+
+    fn verify_password(&self, user: &str, password: &str) -> impl Future<Output = bool> {
+        (**self).verify_password(user, password)
+    }
+
+    fn authorized_keys_for(&self, user: &str) -> impl Future<Output = Vec<PublicKey>> {
+        (**self).authorized_keys_for(user)
+    }
+}
+
+/// A [`password::Password`] and [`publickey::Publickey`] implementation backed by a [`CredentialStore`].
+///
+/// The users looked up and their authorized keys are cached for the lifetime of the adapter,
+/// so that a client trying several keys in a session only hits the backend once,
+/// while the passwords are verified against the store on each attempt, including for unknown users.
+///
+/// The password changes are not supported, and always rejected.
+#[derive(Debug)]
+pub struct Credentials<S> {
+    store: S,
+    users: hashbrown::HashMap<String, bool>,
+    keys: hashbrown::HashMap<String, Vec<PublicKey>>,
+}
+
+impl<S: CredentialStore> Credentials<S> {
+    /// Create an adapter over the `store`, with an empty cache.
+    pub fn new(store: S) -> Self {
+        Self {
+            store,
+            users: Default::default(),
+            keys: Default::default(),
+        }
+    }
+
+    /// Access the underlying store.
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    async fn exists(&mut self, user: &str) -> bool {
+        if let Some(exists) = self.users.get(user) {
+            return *exists;
+        }
+
+        let exists = self.store.lookup_user(user).await;
+        self.users.insert(user.into(), exists);
+
+        exists
+    }
+
+    async fn authorizes(&mut self, user: &str, key: &PublicKey) -> bool {
+        if !self.exists(user).await {
+            return false;
+        }
+
+        if !self.keys.contains_key(user) {
+            let keys = self.store.authorized_keys_for(user).await;
+            self.keys.insert(user.into(), keys);
+        }
+
+        self.keys[user]
+            .iter()
+            .any(|authorized| authorized.key_data() == key.key_data())
+    }
+}
+
+impl<S: CredentialStore> password::Password for Credentials<S> {
+    async fn process(
+        &mut self,
+        _: &AuthContext<'_>,
+        user: String,
+        password: String,
+        newpassword: Option<String>,
+    ) -> password::Response {
+        // Verify the password of the unknown users as well, for the timing not to disclose them.
+        let verified = self.store.verify_password(&user, &password).await;
+
+        if verified && newpassword.is_none() && self.exists(&user).await {
+            password::Response::Accept
+        } else {
+            password::Response::Reject
+        }
+    }
+}
+
+impl<S: CredentialStore> publickey::Publickey for Credentials<S> {
+    async fn process(
+        &mut self,
+        _: &AuthContext<'_>,
+        user: String,
+        key: PublicKey,
+    ) -> publickey::Response {
+        if self.authorizes(&user, &key).await {
+            publickey::Response::Accept
+        } else {
+            publickey::Response::Reject
+        }
+    }
+
+    async fn process_certificate(
+        &mut self,
+        _: &AuthContext<'_>,
+        user: String,
+        _: Certificate,
+    ) -> publickey::Response {
+        if self.exists(&user).await {
+            publickey::Response::Accept
+        } else {
+            publickey::Response::Reject
+        }
+    }
+}
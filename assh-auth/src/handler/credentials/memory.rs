@@ -0,0 +1,65 @@
+//! A reference implementation of the store, holding the credentials in memory.
+
+use ssh_key::PublicKey;
+
+use super::CredentialStore;
+use crate::handler::password::Verifier;
+
+/// A [`CredentialStore`] holding the credentials of each user in memory.
+///
+/// The passwords are verified as with a [`Verifier`],
+/// which this store uses to keep only a digest of them.
+#[derive(Debug, Default)]
+pub struct Memory {
+    users: hashbrown::HashSet<String>,
+    passwords: Verifier,
+    keys: hashbrown::HashMap<String, Vec<PublicKey>>,
+}
+
+impl Memory {
+    /// Create an empty store, without any user.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the `user` to the store, without any credentials.
+    pub fn user(mut self, user: impl Into<String>) -> Self {
+        self.users.insert(user.into());
+
+        self
+    }
+
+    /// Set the `password` of the `user`, adding it to the store.
+    pub fn password(mut self, user: impl Into<String>, password: &str) -> Self {
+        let user = user.into();
+
+        self.passwords = std::mem::take(&mut self.passwords).user(user.clone(), password);
+        self.users.insert(user);
+
+        self
+    }
+
+    /// Authorize the `key` for the `user`, adding it to the store.
+    pub fn key(mut self, user: impl Into<String>, key: PublicKey) -> Self {
+        let user = user.into();
+
+        self.keys.entry(user.clone()).or_default().push(key);
+        self.users.insert(user);
+
+        self
+    }
+}
+
+impl CredentialStore for Memory {
+    async fn lookup_user(&self, user: &str) -> bool {
+        self.users.contains(user)
+    }
+
+    async fn verify_password(&self, user: &str, password: &str) -> bool {
+        self.passwords.verify(user, password)
+    }
+
+    async fn authorized_keys_for(&self, user: &str) -> Vec<PublicKey> {
+        self.keys.get(user).cloned().unwrap_or_default()
+    }
+}
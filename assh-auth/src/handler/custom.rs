@@ -0,0 +1,100 @@
+//! Custom authentication methods, unknown to the protocol, such as `token-v1@example.com`.
+
+use assh::{side::Side, Pipe, Result, Session};
+use futures::Future;
+use ssh_encoding::Decode;
+use ssh_packet::arch::Ascii;
+
+/// The SSH `SSH_MSG_USERAUTH_REQUEST` message number.
+const SSH_MSG_USERAUTH_REQUEST: u8 = 50;
+
+/// The response to the authentication request.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Response {
+    /// _Accept_ the authentication request.
+    Accept,
+
+    /// _Reject_ the authentication request.
+    Reject,
+
+    /// Neither accept nor reject the request, after sending a method-specific message
+    /// to the client, which is expected to send another request for the method.
+    Continue,
+}
+
+/// An interface to custom authentication methods.
+///
+/// The requests for these methods reach the handler with the payload following the method name,
+/// and the handler is free to exchange method-specific messages with the client through the session
+/// before responding, such as with the message numbers `60` to `79` reserved for the methods.
+pub trait Custom: Send + Sync {
+    /// The names of the handled methods, advertised to the client along with the standard ones.
+    fn methods(&self) -> &[String];
+
+    /// Process the authentication request of the `user` for the `method`,
+    /// with the method-specific `payload` of the request.
+    fn process<IO, S>(
+        &mut self,
+        session: &mut Session<IO, S>,
+        user: String,
+        method: &str,
+        payload: &[u8],
+    ) -> impl Future<Output = Result<Response>>
+    where
+        IO: Pipe,
+        S: Side;
+}
+
+/// A default implementation without any custom method.
+impl Custom for () {
+    fn methods(&self) -> &[String] {
+        &[]
+    }
+
+    async fn process<IO, S>(
+        &mut self,
+        _: &mut Session<IO, S>,
+        _: String,
+        _: &str,
+        _: &[u8],
+    ) -> Result<Response>
+    where
+        IO: Pipe,
+        S: Side,
+    {
+        Ok(Response::Reject)
+    }
+}
+
+/// A `SSH_MSG_USERAUTH_REQUEST` for a method unknown to the protocol.
+#[derive(Debug)]
+pub(super) struct Request<'p> {
+    pub username: String,
+    pub service_name: Ascii<'static>,
+    pub method: String,
+    pub payload: &'p [u8],
+}
+
+impl<'p> Request<'p> {
+    /// Parse the request from the `payload` of the packet, if it's well-formed.
+    pub fn parse(payload: &'p [u8]) -> Option<Self> {
+        let (&SSH_MSG_USERAUTH_REQUEST, mut buffer) = payload.split_first()? else {
+            return None;
+        };
+
+        let username = String::decode(&mut buffer).ok()?;
+        let service_name = Ascii::owned(String::decode(&mut buffer).ok()?).ok()?;
+        let method = String::decode(&mut buffer).ok()?;
+
+        if !method.is_ascii() {
+            return None;
+        }
+
+        Some(Self {
+            username,
+            service_name,
+            method,
+            payload: buffer,
+        })
+    }
+}
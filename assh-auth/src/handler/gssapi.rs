@@ -0,0 +1,191 @@
+//! The `gssapi-with-mic` authentication method, as described in RFC4462 section 3.
+//!
+//! The protocol plumbing is handled here, while the _GSS-API_ processing itself,
+//! such as with _Kerberos_, is delegated to a [`Gssapi`] mechanism.
+
+use futures::Future;
+use ssh_encoding::{Decode, Encode};
+
+use super::AuthContext;
+
+/// The name of the method in the protocol.
+pub(super) const METHOD: &str = "gssapi-with-mic";
+
+const SSH_MSG_USERAUTH_REQUEST: u8 = 50;
+const SSH_MSG_USERAUTH_GSSAPI_RESPONSE: u8 = 60;
+const SSH_MSG_USERAUTH_GSSAPI_TOKEN: u8 = 61;
+const SSH_MSG_USERAUTH_GSSAPI_EXCHANGE_COMPLETE: u8 = 63;
+const SSH_MSG_USERAUTH_GSSAPI_ERROR: u8 = 64;
+const SSH_MSG_USERAUTH_GSSAPI_ERRTOK: u8 = 65;
+const SSH_MSG_USERAUTH_GSSAPI_MIC: u8 = 66;
+
+/// The progress of the security context after accepting a token from the client.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Step {
+    /// The context is not established yet, send the token to the client and await it's next one.
+    Continue(Vec<u8>),
+
+    /// The context is established, possibly with a last token to be sent to the client,
+    /// which is then expected to send the _MIC_ of the session.
+    Complete(Option<Vec<u8>>),
+
+    /// The context failed, with an optional error token to be sent to the client.
+    Failure(Option<Vec<u8>>),
+}
+
+/// The response to the authentication request.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Response {
+    /// _Accept_ the authentication request.
+    Accept,
+
+    /// _Reject_ the authentication request.
+    Reject,
+}
+
+/// An interface to a _GSS-API_ mechanism, such as a binding to the system's _Kerberos_ library.
+///
+/// Each exchange is started with [`Gssapi::start`], abandoning the context in progress if any,
+/// followed by the tokens of the client until the context is established, and finally by the
+/// _MIC_ computed by the client over the session, as the proof that it holds the same context.
+pub trait Gssapi: Send + Sync {
+    /// The object identifiers of the supported mechanisms, _DER_-encoded as sent by the clients,
+    /// such as `06 09 2a 86 48 86 f7 12 01 02 02` for _Kerberos V5_.
+    fn mechanisms(&self) -> &[Vec<u8>];
+
+    /// Start a new security context to authenticate the `user` with the `mechanism`,
+    /// in the `context` of the session, returning whether to proceed with the exchange.
+    fn start(
+        &mut self,
+        context: &AuthContext<'_>,
+        user: String,
+        mechanism: &[u8],
+    ) -> impl Future<Output = bool>;
+
+    /// Accept the `token` of the client in the security context, as with `GSS_Accept_sec_context`.
+    fn accept(&mut self, context: &AuthContext<'_>, token: &[u8]) -> impl Future<Output = Step>;
+
+    /// Verify the `mic` of the `message` with the established security context, as with `GSS_VerifyMIC`,
+    /// and whether the authenticated principal is authorized to log in as the `user`.
+    fn verify(
+        &mut self,
+        context: &AuthContext<'_>,
+        user: String,
+        message: &[u8],
+        mic: &[u8],
+    ) -> impl Future<Output = Response>;
+}
+
+/// A default implementation without any mechanism, which rejects all requests.
+impl Gssapi for () {
+    fn mechanisms(&self) -> &[Vec<u8>] {
+        &[]
+    }
+
+    async fn start(&mut self, _: &AuthContext<'_>, _: String, _: &[u8]) -> bool {
+        false
+    }
+
+    async fn accept(&mut self, _: &AuthContext<'_>, _: &[u8]) -> Step {
+        Step::Failure(None)
+    }
+
+    async fn verify(&mut self, _: &AuthContext<'_>, _: String, _: &[u8], _: &[u8]) -> Response {
+        Response::Reject
+    }
+}
+
+/// Parse the mechanisms supported by the client, from the `payload` of the request.
+pub(super) fn mechanisms(mut payload: &[u8]) -> Option<Vec<Vec<u8>>> {
+    (0..u32::decode(&mut payload).ok()?)
+        .map(|_| Vec::decode(&mut payload).ok())
+        .collect()
+}
+
+/// Whether the `payload` is one of the messages of the exchange sent by the client.
+pub(super) fn is_exchange(payload: &[u8]) -> bool {
+    matches!(
+        payload.first(),
+        Some(
+            &SSH_MSG_USERAUTH_GSSAPI_TOKEN
+                | &SSH_MSG_USERAUTH_GSSAPI_EXCHANGE_COMPLETE
+                | &SSH_MSG_USERAUTH_GSSAPI_ERROR
+                | &SSH_MSG_USERAUTH_GSSAPI_ERRTOK
+                | &SSH_MSG_USERAUTH_GSSAPI_MIC
+        )
+    )
+}
+
+/// A message of the exchange sent by the client.
+#[derive(Debug)]
+pub(super) enum Message {
+    Token(Vec<u8>),
+    Mic(Vec<u8>),
+    Error(String),
+    ErrorToken,
+    ExchangeComplete,
+}
+
+impl Message {
+    /// Parse the message from the `payload` of the packet, if it's well-formed.
+    pub fn parse(payload: &[u8]) -> Option<Self> {
+        let (&message, mut buffer) = payload.split_first()?;
+
+        Some(match message {
+            SSH_MSG_USERAUTH_GSSAPI_TOKEN => Self::Token(Vec::decode(&mut buffer).ok()?),
+            SSH_MSG_USERAUTH_GSSAPI_MIC => Self::Mic(Vec::decode(&mut buffer).ok()?),
+            SSH_MSG_USERAUTH_GSSAPI_ERROR => {
+                // Skip the major and minor statuses, to the message.
+                let (_major, _minor) = (
+                    u32::decode(&mut buffer).ok()?,
+                    u32::decode(&mut buffer).ok()?,
+                );
+
+                Self::Error(String::decode(&mut buffer).ok()?)
+            }
+            SSH_MSG_USERAUTH_GSSAPI_ERRTOK => Self::ErrorToken,
+            SSH_MSG_USERAUTH_GSSAPI_EXCHANGE_COMPLETE => Self::ExchangeComplete,
+            _ => return None,
+        })
+    }
+}
+
+/// The `SSH_MSG_USERAUTH_GSSAPI_RESPONSE` message, with the selected `mechanism`.
+pub(super) fn response(mechanism: &[u8]) -> ssh_encoding::Result<ssh_packet::Packet> {
+    let mut payload = vec![SSH_MSG_USERAUTH_GSSAPI_RESPONSE];
+    mechanism.encode(&mut payload)?;
+
+    Ok(ssh_packet::Packet { payload })
+}
+
+/// The `SSH_MSG_USERAUTH_GSSAPI_TOKEN` message, with the `token` of the server.
+pub(super) fn token(token: &[u8]) -> ssh_encoding::Result<ssh_packet::Packet> {
+    let mut payload = vec![SSH_MSG_USERAUTH_GSSAPI_TOKEN];
+    token.encode(&mut payload)?;
+
+    Ok(ssh_packet::Packet { payload })
+}
+
+/// The `SSH_MSG_USERAUTH_GSSAPI_ERRTOK` message, with the error `token` of the server.
+pub(super) fn error_token(token: &[u8]) -> ssh_encoding::Result<ssh_packet::Packet> {
+    let mut payload = vec![SSH_MSG_USERAUTH_GSSAPI_ERRTOK];
+    token.encode(&mut payload)?;
+
+    Ok(ssh_packet::Packet { payload })
+}
+
+/// The data signed by the _MIC_ of the client, as described in RFC4462 section 3.5.
+pub(super) fn mic_data(
+    session_id: &[u8],
+    username: &str,
+    service_name: &str,
+) -> ssh_encoding::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    session_id.encode(&mut data)?;
+    SSH_MSG_USERAUTH_REQUEST.encode(&mut data)?;
+    username.encode(&mut data)?;
+    service_name.encode(&mut data)?;
+    METHOD.encode(&mut data)?;
+
+    Ok(data)
+}
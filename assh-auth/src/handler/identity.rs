@@ -0,0 +1,25 @@
+//! The identity of the authenticated user, handed to the downstream services.
+
+use ssh_key::PublicKey;
+
+use super::publickey::KeyOptions;
+
+/// The identity of the authenticated user, inserted in the session's [`assh::Extensions`]
+/// upon success before handing the session to the downstream service.
+#[non_exhaustive]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Identity {
+    /// The username, in it's canonical form.
+    pub username: String,
+
+    /// The names of the methods which succeeded, in order, the last one concluding the authentication.
+    pub methods: Vec<String>,
+
+    /// The key which authenticated the user with the `publickey` method,
+    /// or the certified key for a certificate.
+    pub public_key: Option<PublicKey>,
+
+    /// The options of the `authorized_keys` entry which authorized the [`Self::public_key`], if any,
+    /// whose restrictions such as `no-pty` or `no-port-forwarding` are to be enforced by the service.
+    pub key_options: Option<KeyOptions>,
+}
@@ -0,0 +1,79 @@
+//! The `keyboard-interactive` authentication method, as described in RFC4256.
+
+use futures::Future;
+
+use super::AuthContext;
+
+mod totp;
+pub use totp::Totp;
+
+/// A prompt of a [`Challenge`], to be answered by the user.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Prompt {
+    /// The text of the prompt, such as `Password: `.
+    pub prompt: String,
+
+    /// Whether the client should echo the response while the user types it.
+    pub echo: bool,
+}
+
+/// A set of prompts sent to the client, whose responses are handed to [`KeyboardInteractive::respond`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Challenge {
+    /// The name of the challenge, which may be displayed as a title by the client.
+    pub name: String,
+
+    /// The instructions displayed to the user before the prompts.
+    pub instruction: String,
+
+    /// The prompts of the challenge, which may be empty to only display the instructions.
+    pub prompts: Vec<Prompt>,
+}
+
+/// The response to the authentication request.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Response {
+    /// _Accept_ the authentication request.
+    Accept,
+
+    /// _Reject_ the authentication request.
+    Reject,
+
+    /// Send the [`Challenge`] to the client, and await the responses of the user.
+    Challenge(Challenge),
+}
+
+/// An interface to the `keyboard-interactive` authentication method.
+///
+/// The challenges are answered in the subsequent packets of the client, and a new authentication
+/// request from the client abandons the challenge in progress, as permitted by the protocol.
+pub trait KeyboardInteractive: Send + Sync {
+    /// Start the authentication request of the `user`, in the `context` of the session,
+    /// with the `submethods` hinted by the client as a comma-separated list, usually empty.
+    fn start(
+        &mut self,
+        context: &AuthContext<'_>,
+        user: String,
+        submethods: String,
+    ) -> impl Future<Output = Response>;
+
+    /// Process the `responses` of the `user` to the prompts of the last [`Challenge`],
+    /// which always match them in number.
+    fn respond(
+        &mut self,
+        context: &AuthContext<'_>,
+        user: String,
+        responses: Vec<String>,
+    ) -> impl Future<Output = Response>;
+}
+
+/// A default implementation of the method that rejects all requests.
+impl KeyboardInteractive for () {
+    async fn start(&mut self, _: &AuthContext<'_>, _: String, _: String) -> Response {
+        Response::Reject
+    }
+
+    async fn respond(&mut self, _: &AuthContext<'_>, _: String, _: Vec<String>) -> Response {
+        Response::Reject
+    }
+}
@@ -0,0 +1,219 @@
+//! A second factor of _time-based one-time passwords_, as described in RFC6238.
+
+use std::{
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, SystemTime},
+};
+
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+use subtle::ConstantTimeEq;
+
+use super::{AuthContext, Challenge, KeyboardInteractive, Prompt, Response};
+
+/// The prompt of the verification code.
+const PROMPT: &str = "Verification code: ";
+
+/// The verification state of a user, shared across the sessions.
+#[derive(Debug, Default, Clone, Copy)]
+struct State {
+    /// The last time-step accepted for the user, which can't be replayed.
+    last: Option<u64>,
+
+    /// The drift of the user's device, in time-steps.
+    drift: i64,
+}
+
+/// A [`KeyboardInteractive`] implementation prompting for the _TOTP_ verification code of each user,
+/// computed with `HMAC-SHA1` from their shared secret, as most authenticator applications do.
+///
+/// The codes are compared in constant-time, and each code is only accepted once, along with the
+/// ones of the preceding time-steps, so a code can't be replayed by an observer within it's window.
+/// The replay protection is shared by the clones of the [`Totp`], to be used across the sessions.
+///
+/// This is meant as a second factor, required after another method with
+/// [`Auth::required_methods`](crate::handler::Auth::required_methods).
+#[derive(Clone)]
+pub struct Totp {
+    secrets: hashbrown::HashMap<String, Vec<u8>>,
+    states: Arc<Mutex<hashbrown::HashMap<String, State>>>,
+    step: u64,
+    digits: u32,
+    window: u64,
+    drift: u64,
+}
+
+impl Default for Totp {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::fmt::Debug for Totp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Totp")
+            .field("users", &self.secrets.keys().collect::<Vec<_>>())
+            .field("step", &self.step)
+            .field("digits", &self.digits)
+            .field("window", &self.window)
+            .field("drift", &self.drift)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Totp {
+    /// Create a [`Totp`] without any users, with 6-digit codes over 30-second time-steps,
+    /// accepting the codes of the adjacent time-steps.
+    pub fn new() -> Self {
+        Self {
+            secrets: Default::default(),
+            states: Default::default(),
+            step: 30,
+            digits: 6,
+            window: 1,
+            drift: 0,
+        }
+    }
+
+    /// Add the shared `secret` of the `user`, replacing it's previous secret.
+    pub fn user(mut self, user: impl Into<String>, secret: impl Into<Vec<u8>>) -> Self {
+        self.secrets.insert(user.into(), secret.into());
+
+        self
+    }
+
+    /// Set the duration of the time-steps, defaults to 30 seconds.
+    pub fn step(mut self, step: Duration) -> Self {
+        self.step = step.as_secs().max(1);
+
+        self
+    }
+
+    /// Set the number of digits of the codes, between 6 and 9, defaults to 6.
+    pub fn digits(mut self, digits: u32) -> Self {
+        self.digits = digits.clamp(6, 9);
+
+        self
+    }
+
+    /// Set the number of time-steps accepted before and after the current one,
+    /// to tolerate the clock skew and the network delay, defaults to 1.
+    pub fn window(mut self, steps: u64) -> Self {
+        self.window = steps;
+
+        self
+    }
+
+    /// Track the drift of each user's device across the authentications, by up to `steps` time-steps,
+    /// to resynchronize with it as described in RFC6238 section 6, defaults to 0, disabling the tracking.
+    pub fn drift(mut self, steps: u64) -> Self {
+        self.drift = steps;
+
+        self
+    }
+
+    /// Generate the code of the `user` at `time`, such as to verify it's provisioning.
+    pub fn generate(&self, user: &str, time: SystemTime) -> Option<String> {
+        let secret = self.secrets.get(user)?;
+
+        Some(self.code(secret, self.counter(time)))
+    }
+
+    fn counter(&self, time: SystemTime) -> u64 {
+        time.duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs()
+            / self.step
+    }
+
+    /// Compute the code for the `counter`, as described in RFC4226 section 5.
+    fn code(&self, secret: &[u8], counter: u64) -> String {
+        let mut mac = Hmac::<Sha1>::new_from_slice(secret).expect("HMAC can take keys of any size");
+        mac.update(&counter.to_be_bytes());
+        let hash = mac.finalize().into_bytes();
+
+        let offset = (hash[hash.len() - 1] & 0xf) as usize;
+        let binary = u32::from_be_bytes([
+            hash[offset] & 0x7f,
+            hash[offset + 1],
+            hash[offset + 2],
+            hash[offset + 3],
+        ]);
+
+        format!(
+            "{:0width$}",
+            binary % 10u32.pow(self.digits),
+            width = self.digits as usize
+        )
+    }
+
+    /// Verify the `code` of the `user` at `time`, and record it's use.
+    pub fn verify(&self, user: &str, code: &str, time: SystemTime) -> bool {
+        // Verify against an empty secret for unknown users, to keep the timing uniform.
+        let secret = self.secrets.get(user);
+        let now = self.counter(time);
+
+        let mut states = self.states.lock().unwrap_or_else(PoisonError::into_inner);
+        let state = states.get(user).copied().unwrap_or_default();
+
+        let center = now.saturating_add_signed(state.drift);
+        let mut matched = None;
+        for counter in center.saturating_sub(self.window)..=center.saturating_add(self.window) {
+            let expected = self.code(secret.map_or(&[], Vec::as_slice), counter);
+
+            // Go through the whole window without short-circuiting, to keep the timing uniform.
+            if bool::from(expected.as_bytes().ct_eq(code.as_bytes())) {
+                matched = Some(counter);
+            }
+        }
+
+        match matched {
+            Some(counter)
+                if secret.is_some() && !matches!(state.last, Some(last) if counter <= last) =>
+            {
+                let drift = counter.abs_diff(now).min(self.drift) as i64;
+
+                states.insert(
+                    user.into(),
+                    State {
+                        last: Some(counter),
+                        drift: if counter < now { -drift } else { drift },
+                    },
+                );
+
+                true
+            }
+            Some(_) if secret.is_some() => {
+                tracing::warn!("Refused a replayed verification code for user `{user}`");
+
+                false
+            }
+            _ => false,
+        }
+    }
+}
+
+impl KeyboardInteractive for Totp {
+    async fn start(&mut self, _: &AuthContext<'_>, _: String, _: String) -> Response {
+        // Challenge the unknown users as well, to not disclose which users have a secret.
+        Response::Challenge(Challenge {
+            prompts: vec![Prompt {
+                prompt: PROMPT.into(),
+                echo: false,
+            }],
+            ..Default::default()
+        })
+    }
+
+    async fn respond(
+        &mut self,
+        _: &AuthContext<'_>,
+        user: String,
+        responses: Vec<String>,
+    ) -> Response {
+        match responses.as_slice() {
+            [code] if self.verify(&user, code.trim(), SystemTime::now()) => Response::Accept,
+            _ => Response::Reject,
+        }
+    }
+}
@@ -1,5 +1,8 @@
 use enumset::EnumSetType;
-use ssh_packet::{arch::Ascii, userauth};
+use ssh_packet::{
+    arch::{ascii, Ascii},
+    userauth,
+};
 
 /// Possible authentication methods in the SSH protocol.
 #[derive(Debug, EnumSetType)]
@@ -18,9 +21,13 @@ pub enum Method {
 
     /// The SSH `keyboard-interactive` authentication method.
     KeyboardInteractive,
+
+    /// The SSH `gssapi-with-mic` authentication method.
+    GssapiWithMic,
 }
 
 impl Method {
+    /// The name of the method in the protocol.
     pub fn to_ascii(self) -> Ascii<'static> {
         match self {
             Self::None => userauth::Method::NONE,
@@ -28,6 +35,7 @@ impl Method {
             Self::Password => userauth::Method::PASSWORD,
             Self::Hostbased => userauth::Method::HOSTBASED,
             Self::KeyboardInteractive => userauth::Method::KEYBOARD_INTERACTIVE,
+            Self::GssapiWithMic => ascii!("gssapi-with-mic"),
         }
     }
 }
@@ -1,22 +1,153 @@
 //! Authentication _handling_ mechanics.
 
-use assh::{service::Handler, side::Side, Error, Pipe, Result, Session};
+use std::{
+    net::IpAddr,
+    time::{Duration, Instant, SystemTime},
+};
+
+use assh::{
+    service::{Handler, Handlers},
+    side::Side,
+    Error, Pipe, Result, Session,
+};
 use enumset::EnumSet;
-use ssh_key::{public::PublicKey, Signature};
+use futures_time::future::FutureExt;
+use ssh_key::{
+    certificate::CertType, public::PublicKey, Algorithm, Certificate, HashAlg, Signature,
+};
 use ssh_packet::{
-    arch::{Ascii, NameList, Utf8},
+    arch::{Ascii, Bytes, NameList, Utf8},
     crypto::signature,
     trans::DisconnectReason,
-    userauth,
+    userauth, Id,
 };
 
 mod method;
-use method::Method;
+pub use method::Method;
+
+mod identity;
+pub use identity::Identity;
+
+pub mod audit;
+pub use audit::AuthEvent;
+
+mod throttle;
+use throttle::Throttle;
 
+pub mod credentials;
+pub mod custom;
+pub mod gssapi;
+pub mod keyboard_interactive;
 pub mod none;
 pub mod password;
 pub mod publickey;
 
+/// The suffix of the certificate algorithms names, as described in OpenSSH's `PROTOCOL.certkeys`.
+const CERTIFICATE_SUFFIX: &[u8] = b"-cert-v01@openssh.com";
+
+/// The context of an authentication request, handed to the methods' handlers.
+#[derive(Debug, Clone, Copy)]
+pub struct AuthContext<'a> {
+    /// The initial exchange hash of the session.
+    pub session_id: &'a [u8],
+
+    /// The [`Id`] of the connected peer.
+    pub peer_id: &'a Id,
+
+    /// The name of the service requested upon successful authentication.
+    pub service_name: &'a str,
+
+    /// The number of the authentication request in the session, starting at `1`.
+    pub attempt: usize,
+
+    /// The address of the connected peer, if provided with [`Auth::peer_addr`].
+    pub peer_addr: Option<IpAddr>,
+}
+
+impl<'a> AuthContext<'a> {
+    fn new<IO: Pipe, S: Side>(session: &'a Session<IO, S>, request: &Request<'a>) -> Self {
+        Self {
+            session_id: session.session_id().unwrap_or_default(),
+            peer_id: session.peer_id(),
+            service_name: request.service_name,
+            attempt: request.attempt,
+            peer_addr: request.peer_addr,
+        }
+    }
+}
+
+/// The request being processed, as seen by the methods.
+struct Request<'r> {
+    username: &'r str,
+    service_name: &'r Ascii<'r>,
+    attempt: usize,
+    peer_addr: Option<IpAddr>,
+}
+
+/// The policy applied to the `publickey` requests with a signature which doesn't verify.
+///
+/// Such a signature is syntactically valid but cryptographically wrong,
+/// which is a strong signal of an attack rather than of a misconfigured client.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum InvalidSignaturePolicy {
+    /// Fail the attempt, as any other rejected authentication.
+    #[default]
+    Failure,
+
+    /// Disconnect the client immediately, with [`DisconnectReason::ProtocolError`].
+    Disconnect,
+}
+
+/// The callback validating and canonicalizing the usernames.
+type UsernameCallback = Box<dyn FnMut(&str) -> Option<String> + Send + Sync>;
+
+/// The validation of the usernames, applied to each authentication request.
+struct UsernameValidator(UsernameCallback);
+
+impl std::fmt::Debug for UsernameValidator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("UsernameValidator(..)")
+    }
+}
+
+/// The callback computing the authentication banner of a session.
+type BannerCallback = Box<dyn FnOnce(&AuthContext<'_>) -> Option<String> + Send + Sync>;
+
+/// The authentication banner, sent upon the first authentication request.
+enum Banner {
+    Static(Utf8<'static>),
+    Dynamic(BannerCallback),
+}
+
+impl std::fmt::Debug for Banner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Static(message) => f.debug_tuple("Static").field(message).finish(),
+            Self::Dynamic(_) => f.write_str("Dynamic(..)"),
+        }
+    }
+}
+
+/// The `keyboard-interactive` challenge awaiting the responses of the client.
+#[derive(Debug)]
+struct Challenged {
+    username: String,
+    user: String,
+    service_name: Ascii<'static>,
+    attempt: usize,
+    prompts: usize,
+}
+
+/// The `gssapi-with-mic` exchange in progress with the client.
+#[derive(Debug)]
+struct Exchange {
+    username: String,
+    user: String,
+    service_name: Ascii<'static>,
+    attempt: usize,
+    established: bool,
+}
+
 #[derive(Debug, PartialEq)]
 enum Attempt {
     Success,
@@ -25,100 +156,307 @@ enum Attempt {
     Continue,
 }
 
-/// The authentication service [`Handler`] for sessions.
+/// The configuration and the progress of the authentication, apart from the handlers.
 #[derive(Debug)]
-pub struct Auth<H, N = (), P = (), PK = ()> {
-    banner: Option<Utf8<'static>>,
-    // TODO: (compliance) Add a total attempts counter, to disconnect when exceeded.
-    // TODO: (compliance) Retain methods per user-basis, because each user can attempt all the methods.
+struct Config {
+    banner: Option<Banner>,
+    grace_timeout: Duration,
+    max_attempts: usize,
+    attempts: usize,
+    throttle: Throttle,
     methods: EnumSet<Method>,
+    remaining: Option<(String, EnumSet<Method>)>,
+    chains: Vec<EnumSet<Method>>,
+    partial: Option<(String, EnumSet<Method>)>,
+    authenticated_key: Option<(PublicKey, Option<publickey::KeyOptions>)>,
+    certificate_authorities: Vec<PublicKey>,
+    on_invalid_signature: InvalidSignaturePolicy,
+    publickey_algorithms: Option<Vec<Algorithm>>,
+    audit: Option<audit::Sink>,
+    validate_username: Option<UsernameValidator>,
+    peer_addr: Option<IpAddr>,
+    challenged: Option<Challenged>,
+    exchange: Option<Exchange>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            banner: Default::default(),
+            grace_timeout: Duration::from_secs(120),
+            max_attempts: 6,
+            attempts: 0,
+            throttle: Default::default(),
+            methods: Method::None.into(), // always insert the `none` method
+            remaining: Default::default(),
+            chains: Default::default(),
+            partial: Default::default(),
+            authenticated_key: Default::default(),
+            certificate_authorities: Default::default(),
+            on_invalid_signature: Default::default(),
+            publickey_algorithms: Default::default(),
+            audit: Default::default(),
+            validate_username: Default::default(),
+            peer_addr: Default::default(),
+            challenged: Default::default(),
+            exchange: Default::default(),
+        }
+    }
+}
+
+/// The authentication service [`Handler`] for sessions.
+#[derive(Debug)]
+pub struct Auth<H, N = (), P = (), PK = (), C = (), KI = (), G = ()> {
+    config: Config,
 
     handler: H,
 
     none: N,
     password: P,
     publickey: PK,
+    custom: C,
+    keyboard_interactive: KI,
+    gssapi: G,
 }
 
 impl<H> Auth<H>
 where
-    H: Handler,
+    H: Handlers,
 {
-    /// Create an [`Auth`] layer, rejecting all authentication by default.
+    /// Create an [`Auth`] layer for the `service`, or for one of multiple services with a tuple of handlers,
+    /// rejecting all authentication by default.
     pub fn new(service: H) -> Self {
         Self {
-            banner: Default::default(),
-            methods: Method::None.into(), // always insert the `none` method
+            config: Default::default(),
 
             handler: service,
 
             none: (),
             password: (),
             publickey: (),
+            custom: (),
+            keyboard_interactive: (),
+            gssapi: (),
         }
     }
 }
 
-impl<H, N, P, PK> Auth<H, N, P, PK>
+impl<H, N, P, PK, C, KI, G> Auth<H, N, P, PK, C, KI, G>
 where
-    H: Handler,
+    H: Handlers,
     N: none::None,
     P: password::Password,
     PK: publickey::Publickey,
+    C: custom::Custom,
+    KI: keyboard_interactive::KeyboardInteractive,
+    G: gssapi::Gssapi,
 {
     /// Set the authentication banner text to be displayed upon authentication (the string should be `\r\n` terminated).
     pub fn banner(mut self, banner: impl Into<Utf8<'static>>) -> Self {
-        self.banner = Some(banner.into());
+        self.config.banner = Some(Banner::Static(banner.into()));
+
+        self
+    }
+
+    /// Set a `callback` computing the authentication banner text for each session,
+    /// evaluated upon the first authentication request, and skipping the banner if it returns `None`.
+    pub fn banner_with(
+        mut self,
+        callback: impl FnOnce(&AuthContext<'_>) -> Option<String> + Send + Sync + 'static,
+    ) -> Self {
+        self.config.banner = Some(Banner::Dynamic(Box::new(callback)));
+
+        self
+    }
+
+    /// Set the maximum duration of the authentication, from the service request to the success,
+    /// before disconnecting the client with [`Error::AuthTimeout`],
+    /// defaults to 120 seconds as OpenSSH's `LoginGraceTime`.
+    pub fn grace_timeout(mut self, timeout: Duration) -> Self {
+        self.config.grace_timeout = timeout;
+
+        self
+    }
+
+    /// Set the maximum number of failed authentication attempts across all methods,
+    /// before disconnecting the client, defaults to 6 as OpenSSH's `MaxAuthTries`.
+    ///
+    /// As in OpenSSH, the `none` method and the unsigned `publickey` queries are not counted.
+    pub fn max_attempts(mut self, max_attempts: usize) -> Self {
+        self.config.max_attempts = max_attempts;
+
+        self
+    }
+
+    /// Set the fixed `delay` applied before answering each failed authentication attempt,
+    /// to slow down the online guessing of credentials, defaults to no delay.
+    pub fn failure_delay(mut self, delay: Duration) -> Self {
+        self.config.throttle.delay = delay;
+
+        self
+    }
+
+    /// Add a random delay of up to `jitter` to the failure delay,
+    /// to prevent the timing of the answers from being predictable.
+    pub fn failure_jitter(mut self, jitter: Duration) -> Self {
+        self.config.throttle.jitter = jitter;
+
+        self
+    }
+
+    /// Double the failure delay after each consecutive failed attempt, up to the `max` delay,
+    /// the progress being reset by a partial success.
+    pub fn failure_backoff(mut self, max: Duration) -> Self {
+        self.config.throttle.backoff = Some(max);
+
+        self
+    }
+
+    /// Pad the handling of each failed authentication attempt to at least the `minimum` duration
+    /// since it's reception, such as 250 milliseconds, before answering it, so that the timing of the
+    /// answer doesn't disclose how far the attempt went, such as whether the user exists, defaults to no padding.
+    ///
+    /// As the `none` method is used to query the available methods, it's failures are not padded.
+    pub fn failure_minimum_time(mut self, minimum: Duration) -> Self {
+        self.config.throttle.minimum = minimum;
+
+        self
+    }
+
+    /// Require all the `methods` to succeed for the same username to authenticate,
+    /// as OpenSSH's `AuthenticationMethods`, the intermediate successes being reported as partial.
+    ///
+    /// Calling this multiple times declares alternative chains, either of which being sufficient,
+    /// and the progress is reset if the username changes between the attempts.
+    ///
+    /// The chains can't be combined with the [`Self::custom`] methods.
+    pub fn required_methods(mut self, methods: impl IntoIterator<Item = Method>) -> Self {
+        self.config.chains.push(methods.into_iter().collect());
+
+        self
+    }
+
+    /// Trust the _certificate authority_ `key` to sign the users' certificates,
+    /// which are then validated and handed to [`publickey::Publickey::process_certificate`].
+    pub fn certificate_authority(mut self, key: PublicKey) -> Self {
+        self.config.certificate_authorities.push(key);
+
+        self
+    }
+
+    /// Set the policy applied to the `publickey` requests with a signature which doesn't verify,
+    /// defaults to [`InvalidSignaturePolicy::Failure`].
+    pub fn on_invalid_signature(mut self, policy: InvalidSignaturePolicy) -> Self {
+        self.config.on_invalid_signature = policy;
+
+        self
+    }
+
+    /// Restrict the signature algorithms accepted for the `publickey` method to the `algorithms`,
+    /// such as [`Algorithm::Rsa`] with a `Some` hash to refuse the _SHA-1_ based `ssh-rsa`
+    /// signatures while accepting the same keys with `rsa-sha2-256` and `rsa-sha2-512`.
+    ///
+    /// The certificates are restricted by the algorithm of their signature, defaults to all algorithms.
+    pub fn publickey_algorithms(mut self, algorithms: impl IntoIterator<Item = Algorithm>) -> Self {
+        self.config.publickey_algorithms = Some(algorithms.into_iter().collect());
+
+        self
+    }
+
+    /// The signature algorithms accepted for the `publickey` method, as restricted with [`Self::publickey_algorithms`],
+    /// to be advertised to the clients with [`assh::side::server::Server::server_sig_algs`].
+    pub fn server_sig_algs(&self) -> Vec<Algorithm> {
+        match &self.config.publickey_algorithms {
+            Some(algorithms) => algorithms.clone(),
+            None => assh::side::server::Algorithms::default().server_sig_algs,
+        }
+    }
+
+    /// Report each authentication attempt to the `audit` sink, as an [`AuthEvent`].
+    pub fn audit(mut self, audit: impl audit::Audit + 'static) -> Self {
+        self.config.audit = Some(audit::Sink(Box::new(audit)));
+
+        self
+    }
+
+    /// Set a `callback` validating the username of each authentication request before any method,
+    /// returning it's canonical form, such as lowercased, or `None` to fail the request.
+    ///
+    /// The canonical username is the one handed to the methods' handlers and reported to the audit sink,
+    /// while the signatures of the `publickey` method are still verified against the requested one.
+    pub fn validate_username(
+        mut self,
+        callback: impl FnMut(&str) -> Option<String> + Send + Sync + 'static,
+    ) -> Self {
+        self.config.validate_username = Some(UsernameValidator(Box::new(callback)));
+
+        self
+    }
+
+    /// Set the address of the connected peer, handed to the methods' handlers in the [`AuthContext`],
+    /// such as to evaluate the `from` option of the `authorized_keys` entries.
+    pub fn peer_addr(mut self, addr: impl Into<IpAddr>) -> Self {
+        self.config.peer_addr = Some(addr.into());
 
         self
     }
 
     /// Set the authentication handler for the `none` method.
-    pub fn none(self, none: impl none::None) -> Auth<H, impl none::None, P, PK> {
+    pub fn none(self, none: impl none::None) -> Auth<H, impl none::None, P, PK, C, KI, G> {
         let Self {
-            banner,
-            mut methods,
+            mut config,
             handler,
             none: _,
             password,
             publickey,
+            custom,
+            keyboard_interactive,
+            gssapi,
         } = self;
 
-        methods |= Method::None;
+        config.methods |= Method::None;
 
         Auth {
-            banner,
-            methods,
+            config,
             handler,
             none,
             password,
             publickey,
+            custom,
+            keyboard_interactive,
+            gssapi,
         }
     }
 
     /// Set the authentication handler for the `password` method.
+    ///
+    /// The [`password::Verifier`] is the safe default to verify passwords against known credentials.
     pub fn password(
         self,
         password: impl password::Password,
-    ) -> Auth<H, N, impl password::Password, PK> {
+    ) -> Auth<H, N, impl password::Password, PK, C, KI, G> {
         let Self {
-            banner,
-            mut methods,
+            mut config,
             handler,
             none,
             password: _,
             publickey,
+            custom,
+            keyboard_interactive,
+            gssapi,
         } = self;
 
-        methods |= Method::Password;
+        config.methods |= Method::Password;
 
         Auth {
-            banner,
-            methods,
+            config,
             handler,
             none,
             password,
             publickey,
+            custom,
+            keyboard_interactive,
+            gssapi,
         }
     }
 
@@ -126,40 +464,628 @@ where
     pub fn publickey(
         self,
         publickey: impl publickey::Publickey,
-    ) -> Auth<H, N, P, impl publickey::Publickey> {
+    ) -> Auth<H, N, P, impl publickey::Publickey, C, KI, G> {
         let Self {
-            banner,
-            mut methods,
+            mut config,
             handler,
             none,
             password,
             publickey: _,
+            custom,
+            keyboard_interactive,
+            gssapi,
+        } = self;
+
+        config.methods |= Method::Publickey;
+
+        Auth {
+            config,
+            handler,
+            none,
+            password,
+            publickey,
+            custom,
+            keyboard_interactive,
+            gssapi,
+        }
+    }
+
+    /// Set the authentication handler for the custom methods, unknown to the protocol.
+    ///
+    /// As the custom methods can't be part of the [`Self::required_methods`] chains,
+    /// declaring both fails the authentication with [`Error::Config`] upon the service request.
+    pub fn custom(
+        self,
+        custom: impl custom::Custom,
+    ) -> Auth<H, N, P, PK, impl custom::Custom, KI, G> {
+        let Self {
+            config,
+            handler,
+            none,
+            password,
+            publickey,
+            custom: _,
+            keyboard_interactive,
+            gssapi,
+        } = self;
+
+        Auth {
+            config,
+            handler,
+            none,
+            password,
+            publickey,
+            custom,
+            keyboard_interactive,
+            gssapi,
+        }
+    }
+
+    /// Set the authentication handler for the `keyboard-interactive` method.
+    pub fn keyboard_interactive(
+        self,
+        keyboard_interactive: impl keyboard_interactive::KeyboardInteractive,
+    ) -> Auth<H, N, P, PK, C, impl keyboard_interactive::KeyboardInteractive, G> {
+        let Self {
+            mut config,
+            handler,
+            none,
+            password,
+            publickey,
+            custom,
+            keyboard_interactive: _,
+            gssapi,
         } = self;
 
-        methods |= Method::Publickey;
+        config.methods |= Method::KeyboardInteractive;
 
         Auth {
-            banner,
-            methods,
+            config,
             handler,
             none,
             password,
             publickey,
+            custom,
+            keyboard_interactive,
+            gssapi,
         }
     }
 
+    /// Set the authentication handler for the `gssapi-with-mic` method, driven by the `gssapi` mechanism.
+    pub fn gssapi(
+        self,
+        gssapi: impl gssapi::Gssapi,
+    ) -> Auth<H, N, P, PK, C, KI, impl gssapi::Gssapi> {
+        let Self {
+            mut config,
+            handler,
+            none,
+            password,
+            publickey,
+            custom,
+            keyboard_interactive,
+            gssapi: _,
+        } = self;
+
+        config.methods |= Method::GssapiWithMic;
+
+        Auth {
+            config,
+            handler,
+            none,
+            password,
+            publickey,
+            custom,
+            keyboard_interactive,
+            gssapi,
+        }
+    }
+
+    /// The canonical form of the `username`, or `None` if it's refused.
+    fn canonical_username(&mut self, username: &str) -> Option<String> {
+        match &mut self.config.validate_username {
+            Some(UsernameValidator(callback)) => {
+                let canonical = callback(username);
+                if canonical.is_none() {
+                    tracing::debug!("Refused the username `{}`", username.escape_debug());
+                }
+
+                canonical
+            }
+            None => Some(username.into()),
+        }
+    }
+
+    /// The methods remaining for the `username`, each username being able to attempt all of them,
+    /// and resetting them if it changed so that cycling through usernames doesn't grow the state.
+    fn remaining(&mut self, username: &str) -> &mut EnumSet<Method> {
+        let methods = self.config.methods;
+
+        if self
+            .config
+            .remaining
+            .as_ref()
+            .is_some_and(|(user, _)| user != username)
+        {
+            self.config.remaining = None;
+        }
+
+        let (_, remaining) = self
+            .config
+            .remaining
+            .get_or_insert_with(|| (username.into(), methods));
+
+        remaining
+    }
+
+    /// Consume the attempt of the `method` by the `username`, returning whether it was remaining.
+    ///
+    /// As in OpenSSH, the `none` method is never consumed since it's used to query the methods,
+    /// and neither is the `publickey` method to let the clients try each of their keys,
+    /// the attempts being bounded by [`Self::max_attempts`] instead.
+    fn consume(&mut self, username: &str, method: Method) -> bool {
+        let remaining = self.remaining(username);
+
+        match method {
+            Method::None | Method::Publickey => remaining.contains(method),
+            method => remaining.remove(method),
+        }
+    }
+
+    /// Conclude the `publickey` attempt with the `key` from the `response` of the handler,
+    /// keeping the key and it's options for the [`Identity`] if it was accepted.
+    fn accept_key(&mut self, key: PublicKey, response: publickey::Response) -> Attempt {
+        let options = match response {
+            publickey::Response::Accept => None,
+            publickey::Response::Restricted(options) => Some(*options),
+            publickey::Response::Reject => return Attempt::Failure,
+        };
+        self.config.authenticated_key = Some((key, options));
+
+        Attempt::Success
+    }
+
+    /// The methods which already succeeded for the `username`, resetting them if it changed.
+    fn completed(&mut self, username: &str) -> EnumSet<Method> {
+        match &self.config.partial {
+            Some((user, completed)) if user == username => *completed,
+            _ => {
+                self.config.partial = None;
+                self.config.authenticated_key = None;
+
+                EnumSet::empty()
+            }
+        }
+    }
+
+    /// Conclude the successful `attempt` of the `method` by the `user`, after the `completed` ones,
+    /// as partial if it doesn't complete any of the required chains.
+    fn progress(
+        &mut self,
+        user: &str,
+        completed: EnumSet<Method>,
+        method: Method,
+        attempt: Attempt,
+    ) -> Attempt {
+        match attempt {
+            Attempt::Success if !self.config.chains.is_empty() => {
+                let completed = completed | method;
+
+                if self
+                    .config
+                    .chains
+                    .iter()
+                    .any(|chain| chain.is_subset(completed))
+                {
+                    Attempt::Success
+                } else {
+                    self.config.partial = Some((user.into(), completed));
+
+                    Attempt::Partial
+                }
+            }
+            attempt => attempt,
+        }
+    }
+
+    /// The methods allowed to be attempted next, to progress in one of the required chains.
+    fn allowed(&self, completed: EnumSet<Method>) -> EnumSet<Method> {
+        if self.config.chains.is_empty() {
+            return EnumSet::all();
+        }
+
+        self.config
+            .chains
+            .iter()
+            .filter(|chain| chain.is_superset(completed))
+            .fold(EnumSet::empty(), |allowed, chain| {
+                allowed | (*chain - completed)
+            })
+    }
+
+    /// Validate the user `certificate` presented for the `username`,
+    /// as described in OpenSSH's `PROTOCOL.certkeys`.
+    fn validate(&self, certificate: &Certificate, username: &str) -> Result<(), &'static str> {
+        if !self
+            .config
+            .certificate_authorities
+            .iter()
+            .any(|ca| ca.key_data() == certificate.signature_key())
+        {
+            return Err("it is not signed by a trusted authority");
+        }
+        if certificate.cert_type() != CertType::User {
+            return Err("it is not a user certificate");
+        }
+        if !certificate.critical_options().is_empty() {
+            return Err("it has unsupported critical options");
+        }
+        if !certificate
+            .valid_principals()
+            .iter()
+            .any(|principal| principal == username)
+        {
+            return Err("it is not valid for this user");
+        }
+
+        let now = SystemTime::now();
+        if now < certificate.valid_after_time() || now >= certificate.valid_before_time() {
+            return Err("it is outside of it's validity period");
+        }
+
+        let fingerprint = certificate.signature_key().fingerprint(HashAlg::Sha256);
+        certificate
+            .validate([&fingerprint])
+            .map_err(|_| "it's signature is invalid")
+    }
+
+    /// The signature algorithm requested by the client with the `algorithm` name, if it's allowed
+    /// and usable with keys of the `key` algorithm, the _RSA_ keys being usable with all hashes.
+    fn signature_algorithm(&self, algorithm: &[u8], key: &Algorithm) -> Option<Algorithm> {
+        let name = algorithm
+            .strip_suffix(CERTIFICATE_SUFFIX)
+            .unwrap_or(algorithm);
+        let algorithm = Algorithm::new(std::str::from_utf8(name).ok()?).ok()?;
+
+        let compatible = match (&algorithm, key) {
+            (Algorithm::Rsa { .. }, Algorithm::Rsa { .. }) => true,
+            (algorithm, key) => algorithm == key,
+        };
+        let allowed = match &self.config.publickey_algorithms {
+            Some(allowed) => allowed.contains(&algorithm),
+            None => true,
+        };
+
+        (compatible && allowed).then_some(algorithm)
+    }
+
+    /// Report the `outcome` of the attempt to the audit sink, logging it's failures.
+    fn record(
+        &mut self,
+        username: &str,
+        method: &str,
+        key: Option<audit::KeyDetails>,
+        outcome: audit::Outcome,
+    ) {
+        let Some(audit::Sink(sink)) = &mut self.config.audit else {
+            return;
+        };
+
+        let event = AuthEvent {
+            timestamp: SystemTime::now(),
+            username: username.into(),
+            method: method.into(),
+            key,
+            outcome,
+        };
+        if let Err(err) = sink.record(event) {
+            tracing::error!("Unable to record the authentication event: {err}");
+        }
+    }
+
+    /// Send the authentication banner, upon the first authentication request.
+    async fn send_banner<IO: Pipe, S: Side>(
+        &mut self,
+        session: &mut Session<IO, S>,
+        request: &Request<'_>,
+    ) -> Result<()> {
+        let banner = self.config.banner.take().and_then(|banner| match banner {
+            Banner::Static(message) => Some(message),
+            Banner::Dynamic(callback) => {
+                callback(&AuthContext::new(session, request)).map(Into::into)
+            }
+        });
+
+        if let Some(message) = banner {
+            session
+                .send(&userauth::Banner {
+                    message,
+                    ..Default::default()
+                })
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Answer to a `publickey` request with a signature which doesn't verify, as per the policy.
+    async fn invalid_signature<IO: Pipe, S: Side>(
+        &self,
+        session: &mut Session<IO, S>,
+        username: &str,
+    ) -> Result<Attempt> {
+        tracing::debug!("Invalid `publickey` signature for user `{username}`");
+
+        match self.config.on_invalid_signature {
+            InvalidSignaturePolicy::Failure => Ok(Attempt::Failure),
+            InvalidSignaturePolicy::Disconnect => Err(session
+                .disconnect(
+                    DisconnectReason::ProtocolError,
+                    "The publickey signature is invalid",
+                )
+                .await
+                .into()),
+        }
+    }
+
+    async fn handle_certificate<IO: Pipe, S: Side>(
+        &mut self,
+        session: &mut Session<IO, S>,
+        username: Utf8<'_>,
+        algorithm: Bytes<'_>,
+        blob: Bytes<'_>,
+        signature: Option<Bytes<'_>>,
+        request: &Request<'_>,
+    ) -> Result<Attempt> {
+        let certificate = Certificate::from_bytes(&blob)
+            .map_err(|_| "it is malformed")
+            .and_then(|certificate| self.validate(&certificate, &username).map(|()| certificate))
+            .and_then(|certificate| {
+                self.signature_algorithm(&algorithm, &certificate.algorithm())
+                    .map(|expected| (expected, certificate))
+                    .ok_or("it's signature algorithm is not allowed")
+            });
+
+        let (expected, certificate) = match certificate {
+            Ok(certificate) => certificate,
+            Err(reason) => {
+                tracing::debug!("Rejected the certificate of user `{username}`, as {reason}");
+
+                return Ok(Attempt::Failure);
+            }
+        };
+
+        Ok(match signature {
+            None => {
+                session.send(&userauth::PkOk { blob, algorithm }).await?;
+
+                Attempt::Continue
+            }
+            Some(signature) => {
+                let signature = Signature::try_from(signature.as_ref())?;
+                if signature.algorithm() != expected {
+                    return Ok(Attempt::Failure);
+                }
+
+                let key = PublicKey::from(certificate.public_key().clone());
+                let message = signature::Publickey {
+                    session_id: session.session_id().unwrap_or_default().into(),
+                    username: Utf8::borrowed(request.username),
+                    service_name: request.service_name.as_borrow(),
+                    algorithm,
+                    blob,
+                };
+
+                if message.verify(&key, &signature).is_err() {
+                    return self.invalid_signature(session, &username).await;
+                }
+
+                let response = self
+                    .publickey
+                    .process_certificate(
+                        &AuthContext::new(session, request),
+                        username.into_string(),
+                        certificate,
+                    )
+                    .await;
+
+                self.accept_key(key, response)
+            }
+        })
+    }
+
+    /// Send the challenge of the `keyboard-interactive` `response` to the client, if any,
+    /// awaiting it's responses in the next packet.
+    async fn challenge<IO: Pipe, S: Side>(
+        &mut self,
+        session: &mut Session<IO, S>,
+        user: &str,
+        request: &Request<'_>,
+        response: keyboard_interactive::Response,
+    ) -> Result<Attempt> {
+        Ok(match response {
+            keyboard_interactive::Response::Accept => Attempt::Success,
+            keyboard_interactive::Response::Reject => Attempt::Failure,
+            keyboard_interactive::Response::Challenge(challenge) => {
+                self.config.challenged = Some(Challenged {
+                    username: request.username.into(),
+                    user: user.into(),
+                    service_name: Ascii::owned(request.service_name.to_string())
+                        .expect("The service name was already validated as ASCII"),
+                    attempt: request.attempt,
+                    prompts: challenge.prompts.len(),
+                });
+
+                session
+                    .send(&userauth::InfoRequest {
+                        name: challenge.name.into(),
+                        instruction: challenge.instruction.into(),
+                        language: Default::default(),
+                        prompts: challenge
+                            .prompts
+                            .into_iter()
+                            .map(|prompt| userauth::InfoRequestPrompt {
+                                prompt: prompt.prompt.into(),
+                                echo: prompt.echo.into(),
+                            })
+                            .collect(),
+                    })
+                    .await?;
+
+                Attempt::Continue
+            }
+        })
+    }
+
+    /// Start the `gssapi-with-mic` exchange with one of the mechanisms of the `payload`,
+    /// awaiting the tokens of the client in the next packets.
+    async fn start_exchange<IO: Pipe, S: Side>(
+        &mut self,
+        session: &mut Session<IO, S>,
+        user: &str,
+        request: &Request<'_>,
+        payload: &[u8],
+    ) -> Result<Attempt> {
+        let mechanism = gssapi::mechanisms(payload).and_then(|mechanisms| {
+            mechanisms.into_iter().find(|mechanism| {
+                self.gssapi
+                    .mechanisms()
+                    .iter()
+                    .any(|supported| supported == mechanism)
+            })
+        });
+
+        let Some(mechanism) = mechanism else {
+            tracing::debug!("Rejected the `gssapi-with-mic` request of user `{user}`, as no mechanism is supported");
+
+            return Ok(Attempt::Failure);
+        };
+
+        if !self
+            .gssapi
+            .start(&AuthContext::new(session, request), user.into(), &mechanism)
+            .await
+        {
+            return Ok(Attempt::Failure);
+        }
+
+        self.config.exchange = Some(Exchange {
+            username: request.username.into(),
+            user: user.into(),
+            service_name: Ascii::owned(request.service_name.to_string())
+                .expect("The service name was already validated as ASCII"),
+            attempt: request.attempt,
+            established: false,
+        });
+        session.send(gssapi::response(&mechanism)?).await?;
+
+        Ok(Attempt::Continue)
+    }
+
+    /// Continue the `gssapi-with-mic` `exchange` with the message in the `payload`.
+    async fn continue_exchange<IO: Pipe, S: Side>(
+        &mut self,
+        session: &mut Session<IO, S>,
+        payload: &[u8],
+        exchange: Exchange,
+    ) -> Result<Attempt> {
+        let request = Request {
+            username: &exchange.username,
+            service_name: &exchange.service_name,
+            attempt: exchange.attempt,
+            peer_addr: self.config.peer_addr,
+        };
+
+        Ok(match gssapi::Message::parse(payload) {
+            Some(gssapi::Message::Token(token)) if !exchange.established => {
+                match self
+                    .gssapi
+                    .accept(&AuthContext::new(session, &request), &token)
+                    .await
+                {
+                    gssapi::Step::Continue(token) => {
+                        session.send(gssapi::token(&token)?).await?;
+                        self.config.exchange = Some(exchange);
+
+                        Attempt::Continue
+                    }
+                    gssapi::Step::Complete(token) => {
+                        if let Some(token) = token {
+                            session.send(gssapi::token(&token)?).await?;
+                        }
+                        self.config.exchange = Some(Exchange {
+                            established: true,
+                            ..exchange
+                        });
+
+                        Attempt::Continue
+                    }
+                    gssapi::Step::Failure(token) => {
+                        if let Some(token) = token {
+                            session.send(gssapi::error_token(&token)?).await?;
+                        }
+
+                        Attempt::Failure
+                    }
+                }
+            }
+            Some(gssapi::Message::Mic(mic)) if exchange.established => {
+                let message = gssapi::mic_data(
+                    session.session_id().unwrap_or_default(),
+                    request.username,
+                    request.service_name,
+                )?;
+
+                match self
+                    .gssapi
+                    .verify(
+                        &AuthContext::new(session, &request),
+                        exchange.user.clone(),
+                        &message,
+                        &mic,
+                    )
+                    .await
+                {
+                    gssapi::Response::Accept => Attempt::Success,
+                    gssapi::Response::Reject => Attempt::Failure,
+                }
+            }
+            Some(gssapi::Message::Error(message)) => {
+                tracing::debug!(
+                    "The client reported a `gssapi-with-mic` failure: {}",
+                    message.escape_debug()
+                );
+                self.config.exchange = Some(exchange);
+
+                Attempt::Continue
+            }
+            _ => {
+                // The `SSH_MSG_USERAUTH_GSSAPI_EXCHANGE_COMPLETE` is refused, as a _MIC_ is required.
+                tracing::debug!(
+                    "Rejected the `gssapi-with-mic` exchange of user `{}`, as it's out of sequence",
+                    exchange.user
+                );
+
+                Attempt::Failure
+            }
+        })
+    }
+
     async fn handle_attempt<IO: Pipe, S: Side>(
         &mut self,
         session: &mut Session<IO, S>,
         username: Utf8<'_>,
         method: userauth::Method<'_>,
-        service_name: &Ascii<'_>,
+        request: &Request<'_>,
     ) -> Result<Attempt> {
         Ok(match method {
             userauth::Method::None => {
                 tracing::debug!("Attempt using method `none` for user `{username}`");
 
-                match self.none.process(username.into_string()) {
+                match self
+                    .none
+                    .process(&AuthContext::new(session, request), username.into_string())
+                {
                     none::Response::Accept => Attempt::Success,
                     none::Response::Reject => Attempt::Failure,
                 }
@@ -176,14 +1102,19 @@ where
                     std::str::from_utf8(&algorithm).unwrap_or("unknown"),
                 );
 
-                let key = PublicKey::from_bytes(&blob);
+                if algorithm.ends_with(CERTIFICATE_SUFFIX) {
+                    return self
+                        .handle_certificate(session, username, algorithm, blob, signature, request)
+                        .await;
+                }
+
+                let key = PublicKey::from_bytes(&blob).ok().and_then(|key| {
+                    Some((self.signature_algorithm(&algorithm, &key.algorithm())?, key))
+                });
 
                 match signature {
                     None => {
-                        // Authentication has not actually been attempted, so we allow it again.
-                        self.methods |= Method::Publickey;
-
-                        if key.is_ok() {
+                        if key.is_some() {
                             session.send(&userauth::PkOk { blob, algorithm }).await?;
 
                             Attempt::Continue
@@ -192,28 +1123,47 @@ where
                         }
                     }
                     Some(signature) => match key {
-                        Ok(key) if key.algorithm().as_str().as_bytes() == algorithm.as_ref() => {
+                        Some((expected, key)) => {
+                            let signature = Signature::try_from(signature.as_ref())?;
+                            if signature.algorithm() != expected {
+                                return Ok(Attempt::Failure);
+                            }
+
                             let message = signature::Publickey {
                                 session_id: session.session_id().unwrap_or_default().into(),
-                                username: username.as_borrow(),
-                                service_name: service_name.as_borrow(),
+                                username: Utf8::borrowed(request.username),
+                                service_name: request.service_name.as_borrow(),
                                 algorithm,
                                 blob,
                             };
 
-                            if message
-                                .verify(&key, &Signature::try_from(signature.as_ref())?)
-                                .is_ok()
-                                && self.publickey.process(username.into_string(), key)
-                                    == publickey::Response::Accept
-                            {
-                                Attempt::Success
-                            } else {
-                                // TODO: (reliability) Does a faked signature needs to cause disconnection ?
-                                Attempt::Failure
+                            if message.verify(&key, &signature).is_err() {
+                                return self.invalid_signature(session, &username).await;
                             }
+
+                            let context = AuthContext::new(session, request);
+                            let response = match publickey::SecurityKey::from_signature(&signature)
+                            {
+                                Some(flags) => {
+                                    self.publickey
+                                        .process_security_key(
+                                            &context,
+                                            username.into_string(),
+                                            key.clone(),
+                                            flags,
+                                        )
+                                        .await
+                                }
+                                None => {
+                                    self.publickey
+                                        .process(&context, username.into_string(), key.clone())
+                                        .await
+                                }
+                            };
+
+                            self.accept_key(key, response)
                         }
-                        _ => Attempt::Failure,
+                        None => Attempt::Failure,
                     },
                 }
             }
@@ -224,14 +1174,19 @@ where
                     new.is_some(),
                 );
 
-                match self.password.process(
-                    username.into_string(),
-                    password.into_string(),
-                    new.map(Utf8::into_string),
-                ) {
+                match self
+                    .password
+                    .process(
+                        &AuthContext::new(session, request),
+                        username.to_string(),
+                        password.into_string(),
+                        new.map(Utf8::into_string),
+                    )
+                    .await
+                {
                     password::Response::Accept => Attempt::Success,
                     password::Response::PasswordExpired { prompt } => {
-                        self.methods |= Method::Password;
+                        *self.remaining(&username) |= Method::Password;
 
                         session
                             .send(&userauth::PasswdChangereq {
@@ -251,16 +1206,36 @@ where
                 todo!("Server-side `hostbased` method is not implemented")
             }
 
-            userauth::Method::KeyboardInteractive { .. } => {
-                // TODO: (feature) Add keyboard-interactive authentication.
-                todo!("Server-side `keyboard-interactive` method is not implemented")
+            userauth::Method::KeyboardInteractive { submethods, .. } => {
+                tracing::debug!(
+                    "Attempt using method `keyboard-interactive` (submethods: {submethods}) for user `{username}`"
+                );
+
+                let response = self
+                    .keyboard_interactive
+                    .start(
+                        &AuthContext::new(session, request),
+                        username.to_string(),
+                        submethods.into_string(),
+                    )
+                    .await;
+
+                self.challenge(session, &username, request, response)
+                    .await?
             }
         })
     }
 }
 
-impl<H: Handler, N: none::None, P: password::Password, PK: publickey::Publickey> Handler
-    for Auth<H, N, P, PK>
+impl<H, N, P, PK, C, KI, G> Handler for Auth<H, N, P, PK, C, KI, G>
+where
+    H: Handlers,
+    N: none::None,
+    P: password::Password,
+    PK: publickey::Publickey,
+    C: custom::Custom,
+    KI: keyboard_interactive::KeyboardInteractive,
+    G: gssapi::Gssapi,
 {
     type Err = H::Err;
     type Ok<IO: Pipe, S: Side> = H::Ok<IO, S>;
@@ -275,66 +1250,244 @@ impl<H: Handler, N: none::None, P: password::Password, PK: publickey::Publickey>
         IO: Pipe,
         S: Side,
     {
-        if let Some(message) = self.banner.take() {
-            session
-                .send(&userauth::Banner {
-                    message,
-                    ..Default::default()
-                })
-                .await?;
+        if !self.config.chains.is_empty() && !self.custom.methods().is_empty() {
+            let _ = session
+                .disconnect(
+                    DisconnectReason::ByApplication,
+                    "Authentication is misconfigured",
+                )
+                .await;
+
+            return Err(Error::Config(
+                "The custom methods can't be combined with the required methods",
+            )
+            .into());
         }
 
+        let deadline = Instant::now() + self.config.grace_timeout;
+        let mut requests = 0;
+
         loop {
-            if let Ok(userauth::Request {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let Ok(packet) = session
+                .recv()
+                .timeout(futures_time::time::Duration::from(remaining))
+                .await
+            else {
+                let _ = session
+                    .disconnect(
+                        DisconnectReason::ByApplication,
+                        "Authentication grace time exceeded",
+                    )
+                    .await;
+
+                break Err(Error::AuthTimeout.into());
+            };
+
+            let packet = packet?;
+            let received = Instant::now();
+
+            let (user, method, key, counted, attempt, service_name) = if let Ok(
+                userauth::Request {
+                    username,
+                    service_name,
+                    method,
+                },
+            ) = packet.to()
+            {
+                // A new request aborts the `keyboard-interactive` challenge
+                // or the `gssapi-with-mic` exchange in progress, if any.
+                self.config.challenged = None;
+                self.config.exchange = None;
+
+                // The `none` method is used to query the available methods, and is not counted.
+                let counted = !matches!(method, userauth::Method::None);
+                requests += 1;
+
+                let request = Request {
+                    username: &username,
+                    service_name: &service_name,
+                    attempt: requests,
+                    peer_addr: self.config.peer_addr,
+                };
+                self.send_banner(&mut session, &request).await?;
+
+                let canonical = self.canonical_username(&username);
+                let user = canonical.clone().unwrap_or_else(|| username.to_string());
+                let kind = *method.as_ref();
+                let completed = self.completed(&user);
+
+                let key = match &method {
+                    userauth::Method::Publickey {
+                        algorithm,
+                        blob,
+                        signature,
+                    } if self.config.audit.is_some() => Some(audit::KeyDetails {
+                        algorithm: String::from_utf8_lossy(algorithm).into_owned(),
+                        fingerprint: PublicKey::from_bytes(blob)
+                            .or_else(|_| {
+                                Certificate::from_bytes(blob)
+                                    .map(|certificate| certificate.public_key().clone().into())
+                            })
+                            .ok()
+                            .map(|key| key.fingerprint(HashAlg::Sha256)),
+                        signed: signature.is_some(),
+                    }),
+                    _ => None,
+                };
+
+                let attempt = if canonical.is_some()
+                    && self.allowed(completed).contains(kind)
+                    && self.consume(&user, kind)
+                {
+                    let attempt = self
+                        .handle_attempt(&mut session, user.as_str().into(), method, &request)
+                        .await?;
+
+                    self.progress(&user, completed, kind, attempt)
+                } else {
+                    Attempt::Failure
+                };
+
+                (
+                    user,
+                    kind.to_ascii().to_string(),
+                    key,
+                    counted,
+                    attempt,
+                    service_name.into_string(),
+                )
+            } else if let Some(custom::Request {
                 username,
                 service_name,
                 method,
-            }) = session.recv().await?.to()
+                payload,
+            }) = custom::Request::parse(&packet.payload)
             {
-                if self.methods.remove(*method.as_ref()) {
+                self.config.challenged = None;
+                self.config.exchange = None;
+                requests += 1;
+
+                let request = Request {
+                    username: &username,
+                    service_name: &service_name,
+                    attempt: requests,
+                    peer_addr: self.config.peer_addr,
+                };
+                self.send_banner(&mut session, &request).await?;
+
+                tracing::debug!("Attempt using method `{method}` for user `{username}`");
+
+                let canonical = self.canonical_username(&username);
+                let user = canonical.clone().unwrap_or_else(|| username.clone());
+
+                let attempt = if method == gssapi::METHOD {
+                    let completed = self.completed(&user);
+
+                    if canonical.is_some()
+                        && self.allowed(completed).contains(Method::GssapiWithMic)
+                        && self.consume(&user, Method::GssapiWithMic)
+                    {
+                        self.start_exchange(&mut session, &user, &request, payload)
+                            .await?
+                    } else {
+                        Attempt::Failure
+                    }
+                } else if canonical.is_some()
+                    && self.custom.methods().iter().any(|name| *name == method)
+                {
                     match self
-                        .handle_attempt(&mut session, username, method, &service_name)
+                        .custom
+                        .process(&mut session, user.clone(), &method, payload)
                         .await?
                     {
-                        Attempt::Success => {
-                            break if service_name == H::SERVICE_NAME {
-                                session.send(&userauth::Success).await?;
-
-                                self.handler.on_request(session).await
-                            } else {
-                                Err(Error::from(
-                                    session
-                                        .disconnect(
-                                            DisconnectReason::ServiceNotAvailable,
-                                            "Requested service is unknown",
-                                        )
-                                        .await,
-                                )
-                                .into())
-                            }
-                        }
-                        attempt @ Attempt::Failure | attempt @ Attempt::Partial => {
-                            session
-                                .send(&userauth::Failure {
-                                    continue_with: NameList::from_iter(
-                                        self.methods.iter().map(Method::to_ascii),
-                                    ),
-                                    partial_success: (attempt == Attempt::Partial).into(),
-                                })
-                                .await?;
-                        }
-                        Attempt::Continue => (),
+                        custom::Response::Accept => Attempt::Success,
+                        custom::Response::Reject => Attempt::Failure,
+                        custom::Response::Continue => Attempt::Continue,
                     }
                 } else {
-                    session
-                        .send(&userauth::Failure {
-                            continue_with: NameList::from_iter(
-                                self.methods.iter().map(Method::to_ascii),
-                            ),
-                            partial_success: false.into(),
-                        })
+                    Attempt::Failure
+                };
+
+                (
+                    user,
+                    method,
+                    None,
+                    true,
+                    attempt,
+                    service_name.into_string(),
+                )
+            } else if let Some(exchange) = self
+                .config
+                .exchange
+                .take()
+                .filter(|_| gssapi::is_exchange(&packet.payload))
+            {
+                let user = exchange.user.clone();
+                let service_name = exchange.service_name.to_string();
+                let completed = self.completed(&user);
+
+                let attempt = self
+                    .continue_exchange(&mut session, &packet.payload, exchange)
+                    .await?;
+                let attempt = self.progress(&user, completed, Method::GssapiWithMic, attempt);
+
+                (
+                    user,
+                    gssapi::METHOD.into(),
+                    None,
+                    true,
+                    attempt,
+                    service_name,
+                )
+            } else if let (Some(challenged), Ok(userauth::InfoResponse { responses })) =
+                (self.config.challenged.take(), packet.to())
+            {
+                let Challenged {
+                    username,
+                    user,
+                    service_name,
+                    attempt,
+                    prompts,
+                } = challenged;
+                let request = Request {
+                    username: &username,
+                    service_name: &service_name,
+                    attempt,
+                    peer_addr: self.config.peer_addr,
+                };
+
+                let completed = self.completed(&user);
+                let attempt = if responses.len() == prompts {
+                    let response = self
+                        .keyboard_interactive
+                        .respond(
+                            &AuthContext::new(&session, &request),
+                            user.clone(),
+                            responses.into_iter().map(Utf8::into_string).collect(),
+                        )
+                        .await;
+                    let attempt = self
+                        .challenge(&mut session, &user, &request, response)
                         .await?;
-                }
+
+                    self.progress(&user, completed, Method::KeyboardInteractive, attempt)
+                } else {
+                    tracing::debug!(
+                        "Rejected the `keyboard-interactive` responses of user `{user}`, as {prompts} were expected"
+                    );
+
+                    Attempt::Failure
+                };
+
+                (
+                    user,
+                    Method::KeyboardInteractive.to_ascii().into_string(),
+                    None,
+                    true,
+                    attempt,
+                    service_name.into_string(),
+                )
             } else {
                 break Err(Error::from(
                     session
@@ -348,6 +1501,91 @@ impl<H: Handler, N: none::None, P: password::Password, PK: publickey::Publickey>
                         .await,
                 )
                 .into());
+            };
+
+            let outcome = match attempt {
+                Attempt::Success => audit::Outcome::Success,
+                Attempt::Partial => audit::Outcome::Partial,
+                Attempt::Failure => audit::Outcome::Failure,
+                Attempt::Continue => audit::Outcome::Continue,
+            };
+            self.record(&user, &method, key, outcome);
+
+            match attempt {
+                Attempt::Success => {
+                    break if self.handler.handles(&service_name) {
+                        session.send(&userauth::Success).await?;
+                        session.activate_compression();
+
+                        // Take the key first, as it's reset along with the partial success.
+                        let (public_key, key_options) =
+                            self.config.authenticated_key.take().unzip();
+                        let methods = self
+                            .completed(&user)
+                            .iter()
+                            .map(|method| method.to_ascii().into_string())
+                            .chain([method])
+                            .collect();
+                        session.extensions_mut().insert(Identity {
+                            username: user,
+                            methods,
+                            public_key,
+                            key_options: key_options.flatten(),
+                        });
+
+                        self.handler.dispatch(&service_name, session).await
+                    } else {
+                        Err(Error::from(
+                            session
+                                .disconnect(
+                                    DisconnectReason::ServiceNotAvailable,
+                                    "Requested service is unknown",
+                                )
+                                .await,
+                        )
+                        .into())
+                    };
+                }
+                attempt @ Attempt::Failure | attempt @ Attempt::Partial => {
+                    if attempt == Attempt::Partial {
+                        self.config.throttle.reset();
+                    }
+                    if attempt == Attempt::Failure && counted {
+                        self.config.attempts += 1;
+
+                        // The last failure is throttled as the others, so that the disconnection doesn't answer earlier.
+                        self.config.throttle.failure().await;
+                        self.config.throttle.pad(received).await;
+
+                        if self.config.attempts >= self.config.max_attempts {
+                            break Err(Error::from(
+                                session
+                                    .disconnect(
+                                        DisconnectReason::NoMoreAuthMethodsAvailable,
+                                        "Too many authentication failures",
+                                    )
+                                    .await,
+                            )
+                            .into());
+                        }
+                    }
+
+                    let completed = self.completed(&user);
+                    let allowed = *self.remaining(&user) & self.allowed(completed);
+
+                    session
+                        .send(&userauth::Failure {
+                            continue_with: NameList::from_iter(
+                                allowed
+                                    .iter()
+                                    .map(|method| method.to_ascii().into_string())
+                                    .chain(self.custom.methods().iter().cloned()),
+                            ),
+                            partial_success: (attempt == Attempt::Partial).into(),
+                        })
+                        .await?;
+                }
+                Attempt::Continue => (),
             }
         }
     }
@@ -1,5 +1,7 @@
 //! The `none` authentication method.
 
+use super::AuthContext;
+
 /// The response to the authentication request.
 #[derive(Debug, PartialEq, Eq)]
 pub enum Response {
@@ -12,19 +14,38 @@ pub enum Response {
 
 /// An interface to the `none` authentication method.
 pub trait None: Send + Sync {
-    /// Process the authentication request.
-    fn process(&mut self, user: String) -> Response;
+    /// Process the authentication request, in the `context` of the session.
+    fn process(&mut self, context: &AuthContext<'_>, user: String) -> Response;
 }
 
+/// An adapter for the closures ignoring the context of the request.
 impl<T: FnMut(String) -> Response + Send + Sync> None for T {
-    fn process(&mut self, user: String) -> Response {
+    fn process(&mut self, _: &AuthContext<'_>, user: String) -> Response {
         (self)(user)
     }
 }
 
+/// An adapter for the closures taking the context of the request, see [`with_context`].
+#[derive(Debug)]
+pub struct WithContext<T>(T);
+
+/// Use the `callback` to process the requests, along with their context.
+pub fn with_context<T>(callback: T) -> WithContext<T>
+where
+    T: FnMut(&AuthContext<'_>, String) -> Response + Send + Sync,
+{
+    WithContext(callback)
+}
+
+impl<T: FnMut(&AuthContext<'_>, String) -> Response + Send + Sync> None for WithContext<T> {
+    fn process(&mut self, context: &AuthContext<'_>, user: String) -> Response {
+        (self.0)(context, user)
+    }
+}
+
 /// A default implementation of the method that rejects all requests.
 impl None for () {
-    fn process(&mut self, _: String) -> Response {
+    fn process(&mut self, _: &AuthContext<'_>, _: String) -> Response {
         Response::Reject
     }
 }
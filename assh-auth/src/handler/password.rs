@@ -1,5 +1,18 @@
 //! The `password` authentication method.
 
+use futures::Future;
+
+use super::AuthContext;
+
+mod verifier;
+pub use verifier::Verifier;
+
+#[cfg(feature = "hashed-password")]
+mod hashed;
+#[cfg(feature = "hashed-password")]
+#[cfg_attr(docsrs, doc(cfg(feature = "hashed-password")))]
+pub use hashed::Hashed;
+
 /// The response to the authentication request.
 #[derive(Debug, PartialEq, Eq)]
 pub enum Response {
@@ -18,19 +31,65 @@ pub enum Response {
 
 /// An interface to the `password` authentication method.
 pub trait Password: Send + Sync {
-    /// Process the authentication request.
-    fn process(&mut self, user: String, password: String, newpassword: Option<String>) -> Response;
+    /// Process the authentication request, in the `context` of the session.
+    fn process(
+        &mut self,
+        context: &AuthContext<'_>,
+        user: String,
+        password: String,
+        newpassword: Option<String>,
+    ) -> impl Future<Output = Response>;
 }
 
+/// An adapter for the closures ignoring the context of the request.
 impl<T: FnMut(String, String, Option<String>) -> Response + Send + Sync> Password for T {
-    fn process(&mut self, user: String, password: String, newpassword: Option<String>) -> Response {
+    async fn process(
+        &mut self,
+        _: &AuthContext<'_>,
+        user: String,
+        password: String,
+        newpassword: Option<String>,
+    ) -> Response {
         (self)(user, password, newpassword)
     }
 }
 
+/// An adapter for the closures taking the context of the request, see [`with_context`].
+#[derive(Debug)]
+pub struct WithContext<T>(T);
+
+/// Use the `callback` to process the requests, along with their context.
+pub fn with_context<T>(callback: T) -> WithContext<T>
+where
+    T: FnMut(&AuthContext<'_>, String, String, Option<String>) -> Response + Send + Sync,
+{
+    WithContext(callback)
+}
+
+impl<T> Password for WithContext<T>
+where
+    T: FnMut(&AuthContext<'_>, String, String, Option<String>) -> Response + Send + Sync,
+{
+    async fn process(
+        &mut self,
+        context: &AuthContext<'_>,
+        user: String,
+        password: String,
+        newpassword: Option<String>,
+    ) -> Response {
+        (self.0)(context, user, password, newpassword)
+    }
+}
+
 /// A default implementation of the method that rejects all requests.
 impl Password for () {
-    fn process(&mut self, _: String, _: String, _: Option<String>) -> Response {
+    async fn process(
+        &mut self,
+        _: &AuthContext<'_>,
+        _: String,
+        _: String,
+        _: Option<String>,
+    ) -> Response {
         Response::Reject
     }
 }
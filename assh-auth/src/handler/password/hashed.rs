@@ -0,0 +1,96 @@
+//! An implementation of the method against the password hashes of the users.
+
+use password_hash::PasswordHash;
+
+use super::{AuthContext, Password, Response};
+
+/// Verify the `password` against the `hash`, blocking the current thread for the duration of the hashing.
+fn verify(user: &str, hash: &str, password: &str) -> bool {
+    // The `bcrypt` hashes are in the modular crypt format, predating the PHC string format.
+    if hash.starts_with("$2") {
+        return bcrypt::verify(password, hash).unwrap_or_else(|err| {
+            tracing::warn!("Unable to verify the password hash of user `{user}`: {err}");
+
+            false
+        });
+    }
+
+    let hash = match PasswordHash::new(hash) {
+        Ok(hash) => hash,
+        Err(err) => {
+            tracing::warn!("Unable to parse the password hash of user `{user}`: {err}");
+
+            return false;
+        }
+    };
+
+    match hash.verify_password(&[&argon2::Argon2::default(), &scrypt::Scrypt], password) {
+        Ok(()) => true,
+        Err(password_hash::Error::Password) => false,
+        Err(err) => {
+            tracing::warn!("Unable to verify the password hash of user `{user}`: {err}");
+
+            false
+        }
+    }
+}
+
+/// A [`Password`] implementation verifying the passwords against the stored hash of each user,
+/// either in the _PHC_ string format for `argon2` and `scrypt`, or in the `$2b$` format for `bcrypt`.
+///
+/// As the hashing schemes are deliberately expensive, the verification runs on a thread-pool
+/// without blocking the executor. The malformed or unsupported hashes never match and are logged,
+/// the unknown users are rejected without hashing, which is to be hidden with [`crate::handler::Auth::failure_minimum_time`],
+/// and the password changes are always rejected.
+#[derive(Default)]
+pub struct Hashed {
+    hashes: hashbrown::HashMap<String, String>,
+}
+
+impl std::fmt::Debug for Hashed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Hashed")
+            .field("users", &self.hashes.keys().collect::<Vec<_>>())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Hashed {
+    /// Create a [`Hashed`] verifier without any users, rejecting all requests.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the password `hash` of the `user`, replacing it's previous hash.
+    pub fn user(mut self, user: impl Into<String>, hash: impl Into<String>) -> Self {
+        self.hashes.insert(user.into(), hash.into());
+
+        self
+    }
+}
+
+impl Password for Hashed {
+    async fn process(
+        &mut self,
+        _: &AuthContext<'_>,
+        user: String,
+        password: String,
+        newpassword: Option<String>,
+    ) -> Response {
+        let Some(hash) = self.hashes.get(&user).cloned() else {
+            return Response::Reject;
+        };
+
+        if newpassword.is_some() {
+            tracing::debug!("Rejected the password change of user `{user}`, as it's unsupported");
+
+            return Response::Reject;
+        }
+
+        if blocking::unblock(move || verify(&user, &hash, &password)).await {
+            Response::Accept
+        } else {
+            Response::Reject
+        }
+    }
+}
@@ -0,0 +1,128 @@
+//! A safe implementation of the method, against a set of credentials.
+
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+
+use super::{AuthContext, Password, Response};
+
+/// The callback deciding whether a user is allowed to change it's password.
+type Policy = Box<dyn FnMut(&str, &str) -> bool + Send + Sync>;
+
+/// The digest of a normalized password, compared in constant-time.
+type Digested = [u8; 32];
+
+/// Normalize the `password` with the `SASLprep` profile of RFC4013, and digest it.
+fn digest(password: &str) -> Option<Digested> {
+    let password = stringprep::saslprep(password).ok()?;
+
+    Some(Sha256::digest(password.as_bytes()).into())
+}
+
+/// A [`Password`] implementation verifying the passwords against the credentials of each user.
+///
+/// The passwords are normalized with `SASLprep` as described in RFC4013, so that the
+/// _Unicode_ passwords match whatever form the client typed them in, and are compared in
+/// constant-time, including for unknown users. Only a digest of the passwords is kept in memory.
+///
+/// This is not a replacement for a proper password hashing scheme when storing credentials.
+pub struct Verifier {
+    credentials: hashbrown::HashMap<String, Digested>,
+    policy: Option<Policy>,
+}
+
+impl Default for Verifier {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::fmt::Debug for Verifier {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Verifier")
+            .field("users", &self.credentials.keys().collect::<Vec<_>>())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Verifier {
+    /// Create a [`Verifier`] without any credentials, rejecting all requests.
+    pub fn new() -> Self {
+        Self {
+            credentials: Default::default(),
+            policy: None,
+        }
+    }
+
+    /// Add the `password` as the credentials of the `user`, replacing it's previous password.
+    ///
+    /// Passwords containing characters prohibited by `SASLprep` are never matched.
+    pub fn user(mut self, user: impl Into<String>, password: &str) -> Self {
+        match digest(password) {
+            Some(digest) => {
+                self.credentials.insert(user.into(), digest);
+            }
+            None => {
+                self.credentials.remove(&user.into());
+            }
+        }
+
+        self
+    }
+
+    /// Allow the users to change their password, as decided by the `policy` called with the
+    /// username and the new password, otherwise the password changes are rejected.
+    pub fn password_change(
+        mut self,
+        policy: impl FnMut(&str, &str) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.policy = Some(Box::new(policy));
+
+        self
+    }
+
+    pub(crate) fn verify(&self, user: &str, password: &str) -> bool {
+        // Compare to a digest nothing can match for unknown users, to keep the timing uniform.
+        let expected = self.credentials.get(user).copied();
+        let unmatchable = [0; 32];
+
+        let Some(password) = digest(password) else {
+            return false;
+        };
+
+        bool::from(password.ct_eq(expected.as_ref().unwrap_or(&unmatchable))) && expected.is_some()
+    }
+}
+
+impl Password for Verifier {
+    async fn process(
+        &mut self,
+        _: &AuthContext<'_>,
+        user: String,
+        password: String,
+        newpassword: Option<String>,
+    ) -> Response {
+        if !self.verify(&user, &password) {
+            return Response::Reject;
+        }
+
+        let Some(newpassword) = newpassword else {
+            return Response::Accept;
+        };
+
+        let allowed = match &mut self.policy {
+            Some(policy) => policy(&user, &newpassword),
+            None => false,
+        };
+
+        match digest(&newpassword) {
+            Some(digest) if allowed => {
+                tracing::debug!("Changed the password of user `{user}`");
+
+                self.credentials.insert(user, digest);
+
+                Response::Accept
+            }
+            _ => Response::Reject,
+        }
+    }
+}
@@ -1,7 +1,19 @@
 //! The `publickey` authentication method.
 
 #[doc(no_inline)]
-pub use ssh_key::PublicKey;
+pub use ssh_key::{Certificate, PublicKey};
+
+use ssh_key::{Algorithm, Signature};
+
+use futures::Future;
+
+use super::AuthContext;
+
+mod authorized_keys;
+pub use authorized_keys::{AuthorizedKeys, Entry, Error, Loaded};
+
+mod options;
+pub use options::KeyOptions;
 
 /// The response to the authentication request.
 #[derive(Debug, PartialEq, Eq)]
@@ -9,25 +21,142 @@ pub enum Response {
     /// _Accept_ the authentication request.
     Accept,
 
+    /// _Accept_ the authentication request, restricting the session with the `options` of the key.
+    Restricted(Box<KeyOptions>),
+
     /// _Reject_ the authentication request.
     Reject,
 }
 
+/// The flags and counter of a signature by a _security key_, such as a `sk-ssh-ed25519@openssh.com` key,
+/// as described in OpenSSH's `PROTOCOL.u2f`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SecurityKey {
+    /// Whether the presence of the user was asserted when signing, usually by touching the key.
+    pub user_presence: bool,
+
+    /// Whether the user was verified by the key when signing, such as with a PIN or a biometric.
+    pub user_verification: bool,
+
+    /// The signature counter of the key, which may be used to detect cloned keys.
+    pub counter: u32,
+}
+
+impl SecurityKey {
+    const USER_PRESENCE: u8 = 0x01;
+    const USER_VERIFICATION: u8 = 0x04;
+
+    /// Parse the flags and counter trailing the `signature`, if it's made by a security key.
+    pub(super) fn from_signature(signature: &Signature) -> Option<Self> {
+        if !matches!(
+            signature.algorithm(),
+            Algorithm::SkEd25519 | Algorithm::SkEcdsaSha2NistP256
+        ) {
+            return None;
+        }
+
+        let data = signature.as_bytes();
+        let (_, trailer) = data.split_at_checked(data.len().checked_sub(5)?)?;
+        let (&flags, counter) = trailer.split_first()?;
+
+        Some(Self {
+            user_presence: flags & Self::USER_PRESENCE != 0,
+            user_verification: flags & Self::USER_VERIFICATION != 0,
+            counter: u32::from_be_bytes(counter.try_into().ok()?),
+        })
+    }
+}
+
 /// An interface to the `publickey` authentication method.
 pub trait Publickey: Send + Sync {
-    /// Process the authentication request.
-    fn process(&mut self, user: String, key: PublicKey) -> Response;
+    /// Process the authentication request, in the `context` of the session.
+    fn process(
+        &mut self,
+        context: &AuthContext<'_>,
+        user: String,
+        key: PublicKey,
+    ) -> impl Future<Output = Response>;
+
+    /// Process the authentication request with the `key` of a _security key_, whose signature carries the `flags`,
+    /// requiring the presence of the user before processing it as any other key by default, as OpenSSH does.
+    fn process_security_key(
+        &mut self,
+        context: &AuthContext<'_>,
+        user: String,
+        key: PublicKey,
+        flags: SecurityKey,
+    ) -> impl Future<Output = Response> {
+        async move {
+            if flags.user_presence {
+                self.process(context, user, key).await
+            } else {
+                tracing::debug!(
+                    "Rejected the security key of user `{user}`, as it's presence wasn't asserted"
+                );
+
+                Response::Reject
+            }
+        }
+    }
+
+    /// Process the authentication request with a `certificate`, which has already been validated
+    /// against the trusted _certificate authorities_, accepting it by default.
+    fn process_certificate(
+        &mut self,
+        context: &AuthContext<'_>,
+        user: String,
+        certificate: Certificate,
+    ) -> impl Future<Output = Response> {
+        let _ = (context, user, certificate);
+
+        async { Response::Accept }
+    }
 }
 
+/// An adapter for the closures ignoring the context of the request.
 impl<T: FnMut(String, PublicKey) -> Response + Send + Sync> Publickey for T {
-    fn process(&mut self, user: String, key: PublicKey) -> Response {
+    async fn process(&mut self, _: &AuthContext<'_>, user: String, key: PublicKey) -> Response {
         (self)(user, key)
     }
 }
 
+/// An adapter for the closures taking the context of the request, see [`with_context`].
+#[derive(Debug)]
+pub struct WithContext<T>(T);
+
+/// Use the `callback` to process the requests, along with their context.
+pub fn with_context<T>(callback: T) -> WithContext<T>
+where
+    T: FnMut(&AuthContext<'_>, String, PublicKey) -> Response + Send + Sync,
+{
+    WithContext(callback)
+}
+
+impl<T: FnMut(&AuthContext<'_>, String, PublicKey) -> Response + Send + Sync> Publickey
+    for WithContext<T>
+{
+    async fn process(
+        &mut self,
+        context: &AuthContext<'_>,
+        user: String,
+        key: PublicKey,
+    ) -> Response {
+        (self.0)(context, user, key)
+    }
+}
+
 /// A default implementation of the method that rejects all requests.
 impl Publickey for () {
-    fn process(&mut self, _: String, _: PublicKey) -> Response {
+    async fn process(&mut self, _: &AuthContext<'_>, _: String, _: PublicKey) -> Response {
+        Response::Reject
+    }
+
+    async fn process_certificate(
+        &mut self,
+        _: &AuthContext<'_>,
+        _: String,
+        _: Certificate,
+    ) -> Response {
         Response::Reject
     }
 }
@@ -0,0 +1,412 @@
+//! An implementation of the method backed by OpenSSH `authorized_keys` files,
+//! as described in the `AUTHORIZED_KEYS FILE FORMAT` section of `sshd(8)`.
+
+use std::{
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use ssh_key::{Algorithm, Certificate, PublicKey};
+
+use super::{AuthContext, KeyOptions, Publickey, Response, SecurityKey};
+
+/// The placeholder substituted with the username in path templates.
+const USER_PLACEHOLDER: &str = "{user}";
+
+/// The error types that can occur when loading an `authorized_keys` file.
+#[non_exhaustive]
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The file could not be read.
+    #[error("Unable to read `{}`: {source}", path.display())]
+    Io {
+        /// The path of the file.
+        path: PathBuf,
+
+        /// The underlying I/O error.
+        source: std::io::Error,
+    },
+
+    /// The username cannot be substituted in the path template without escaping it's directory.
+    #[error("The username `{0}` is not usable in a path")]
+    Username(String),
+
+    /// A line of the file is malformed, it has been skipped.
+    #[error("Malformed entry at line {line}: {reason}")]
+    Malformed {
+        /// The line number, starting at `1`.
+        line: usize,
+
+        /// The reason the line has been rejected.
+        reason: String,
+    },
+}
+
+/// An entry of an `authorized_keys` file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    /// The options prefixing the key, such as `no-pty` or `command="..."`, as written in the file.
+    pub options: Vec<String>,
+
+    /// The options prefixing the key, parsed.
+    pub key_options: KeyOptions,
+
+    /// The public key, with the comment of the entry.
+    pub key: PublicKey,
+}
+
+impl Entry {
+    /// Whether the entry has the option `name`, with or without a value.
+    pub fn has_option(&self, name: &str) -> bool {
+        self.options.iter().any(|option| {
+            let option = option
+                .split_once('=')
+                .map_or(option.as_str(), |(name, _)| name);
+
+            option.eq_ignore_ascii_case(name)
+        })
+    }
+
+    fn parse(line: &str) -> Result<Self, String> {
+        let first = line.split(char::is_whitespace).next().unwrap_or_default();
+        let known =
+            Algorithm::new(first).is_ok_and(|algorithm| !matches!(algorithm, Algorithm::Other(_)));
+
+        match parse_key(line) {
+            Ok(key) => Ok(Self {
+                options: Vec::new(),
+                key_options: Default::default(),
+                key,
+            }),
+
+            // The line starts with a known key type, so it's not prefixed with options.
+            Err(err) if known => Err(err),
+
+            Err(_) => {
+                let (options, rest) = parse_options(line)?;
+
+                Ok(Self {
+                    key_options: KeyOptions::parse(&options)?,
+                    options,
+                    key: parse_key(rest.trim_start())?,
+                })
+            }
+        }
+    }
+}
+
+/// Parse the comma-separated options prefixing the key, honoring the double-quoted values,
+/// and return them along with the rest of the line.
+fn parse_options(line: &str) -> Result<(Vec<String>, &str), String> {
+    let mut options = Vec::new();
+    let (mut start, mut quoted, mut escaped) = (0, false, false);
+
+    for (index, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            ',' | ' ' | '\t' if !quoted => {
+                if index == start {
+                    return Err(format!("empty option at column {}", index + 1));
+                }
+                options.push(line[start..index].to_owned());
+                start = index + 1;
+
+                if c != ',' {
+                    return Ok((options, &line[index..]));
+                }
+            }
+            _ => (),
+        }
+    }
+
+    if quoted {
+        Err("unterminated quoted option".into())
+    } else {
+        Err("missing key after the options".into())
+    }
+}
+
+/// Parse the key type, the base64-encoded key and the optional comment.
+fn parse_key(line: &str) -> Result<PublicKey, String> {
+    let (kind, rest) = line
+        .split_once(char::is_whitespace)
+        .ok_or("missing key data")?;
+    let rest = rest.trim_start();
+    let (blob, comment) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+
+    if blob.is_empty() {
+        return Err("missing key data".into());
+    }
+
+    let mut key = PublicKey::from_openssh(&format!("{kind} {blob}"))
+        .map_err(|err| format!("invalid `{kind}` key: {err}"))?;
+    if key.algorithm().as_str() != kind {
+        return Err(format!(
+            "the key type `{kind}` doesn't match the `{}` key data",
+            key.algorithm()
+        ));
+    }
+    key.set_comment(comment.trim());
+
+    Ok(key)
+}
+
+/// The entries of an `authorized_keys` file, along with it's malformed lines.
+#[derive(Debug, Default)]
+pub struct Loaded {
+    /// The successfully parsed entries, in the order of the file.
+    pub entries: Vec<Entry>,
+
+    /// The malformed lines, as [`Error::Malformed`], which have been skipped.
+    pub errors: Vec<Error>,
+}
+
+impl Loaded {
+    /// Parse the `contents` of an `authorized_keys` file,
+    /// skipping the blank lines and the `#` comments.
+    pub fn parse(contents: &str) -> Self {
+        let mut loaded = Self::default();
+
+        for (index, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            match Entry::parse(line) {
+                Ok(entry) => loaded.entries.push(entry),
+                Err(reason) => loaded.errors.push(Error::Malformed {
+                    line: index + 1,
+                    reason,
+                }),
+            }
+        }
+
+        loaded
+    }
+}
+
+#[derive(Debug)]
+enum Source {
+    File(PathBuf),
+    Template(String),
+}
+
+#[derive(Debug)]
+struct Cached {
+    stamp: Option<(SystemTime, u64)>,
+    loaded: Loaded,
+}
+
+/// A [`Publickey`] implementation accepting the keys listed in an `authorized_keys` file.
+///
+/// The files are loaded on the first authentication request of each user, and reloaded lazily
+/// whenever their modification time or size changes, or after a call to [`Self::refresh`].
+/// A missing file authorizes no keys, and the malformed lines are skipped and logged.
+///
+/// The entries with the `cert-authority` option only accept the certificates they have signed,
+/// and the `from`, `expiry-time`, `principals`, `no-touch-required` and `verify-required` options are enforced,
+/// the `from` patterns being matched against [`AuthContext::peer_addr`].
+/// The other options of the accepted entry are handed to the downstream services with [`Response::Restricted`].
+#[derive(Debug)]
+pub struct AuthorizedKeys {
+    source: Source,
+    cache: hashbrown::HashMap<PathBuf, Cached>,
+}
+
+impl AuthorizedKeys {
+    /// Use the same `authorized_keys` file at `path` for all the users.
+    pub fn file(path: impl Into<PathBuf>) -> Self {
+        Self {
+            source: Source::File(path.into()),
+            cache: Default::default(),
+        }
+    }
+
+    /// Use a per-user `authorized_keys` file, with the `{user}` placeholder
+    /// of the `template` substituted with the username, such as `/home/{user}/.ssh/authorized_keys`.
+    pub fn template(template: impl Into<String>) -> Self {
+        Self {
+            source: Source::Template(template.into()),
+            cache: Default::default(),
+        }
+    }
+
+    /// Forget the loaded files, to reload them on the next authentication requests.
+    pub fn refresh(&mut self) {
+        self.cache.clear();
+    }
+
+    fn path(&self, user: &str) -> Result<PathBuf, Error> {
+        match &self.source {
+            Source::File(path) => Ok(path.clone()),
+            Source::Template(template) => {
+                if user.is_empty() || user == "." || user == ".." || user.contains(['/', '\0']) {
+                    return Err(Error::Username(user.into()));
+                }
+
+                Ok(template.replace(USER_PLACEHOLDER, user).into())
+            }
+        }
+    }
+
+    /// Load the `authorized_keys` file of the `user`, reloading it if it was modified since.
+    pub fn load(&mut self, user: &str) -> Result<&Loaded, Error> {
+        let path = self.path(user)?;
+
+        let stamp = match std::fs::metadata(&path) {
+            Ok(metadata) => Some((
+                metadata.modified().map_err(|source| Error::Io {
+                    path: path.clone(),
+                    source,
+                })?,
+                metadata.len(),
+            )),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => None,
+            Err(source) => return Err(Error::Io { path, source }),
+        };
+
+        if !matches!(self.cache.get(&path), Some(cached) if cached.stamp == stamp) {
+            let loaded = match stamp {
+                Some(_) => read(&path)?,
+                None => Loaded::default(),
+            };
+
+            self.cache.insert(path.clone(), Cached { stamp, loaded });
+        }
+
+        Ok(&self.cache[&path].loaded)
+    }
+
+    /// The options of the first entry authorizing the `key`, or the `certificate` it signed,
+    /// for the `user` in the `context` of the session.
+    fn authorizes(
+        &mut self,
+        context: &AuthContext<'_>,
+        user: &str,
+        certificate: Option<&Certificate>,
+        key: &ssh_key::public::KeyData,
+    ) -> Option<KeyOptions> {
+        let loaded = match self.load(user) {
+            Ok(loaded) => loaded,
+            Err(err) => {
+                tracing::warn!("Unable to load the authorized keys of `{user}`: {err}");
+
+                return None;
+            }
+        };
+
+        let now = SystemTime::now();
+        loaded
+            .entries
+            .iter()
+            .filter(|entry| {
+                entry.key_options.cert_authority == certificate.is_some()
+                    && entry.key.key_data() == key
+            })
+            .find(|entry| {
+                let options = &entry.key_options;
+                let principals = match certificate {
+                    Some(certificate) if !options.principals.is_empty() => certificate
+                        .valid_principals()
+                        .iter()
+                        .any(|principal| options.principals.contains(principal)),
+                    _ => true,
+                };
+
+                let permitted = options.permits_from(context.peer_addr)
+                    && options.permits_at(now)
+                    && principals;
+                if !permitted {
+                    tracing::debug!(
+                        "The options of the authorized key `{}` of `{user}` rejected the request",
+                        entry.key.fingerprint(Default::default())
+                    );
+                }
+
+                permitted
+            })
+            .map(|entry| entry.key_options.clone())
+    }
+}
+
+fn read(path: &Path) -> Result<Loaded, Error> {
+    let contents = std::fs::read_to_string(path).map_err(|source| Error::Io {
+        path: path.into(),
+        source,
+    })?;
+
+    let loaded = Loaded::parse(&contents);
+    for err in &loaded.errors {
+        tracing::warn!("Skipped an entry of `{}`: {err}", path.display());
+    }
+
+    Ok(loaded)
+}
+
+/// Accept the request with the `options` of the entry, if any, restricting the session.
+fn accept(options: Option<KeyOptions>) -> Response {
+    match options {
+        Some(options)
+            if options
+                == KeyOptions {
+                    cert_authority: options.cert_authority,
+                    ..Default::default()
+                } =>
+        {
+            Response::Accept
+        }
+        Some(options) => Response::Restricted(options.into()),
+        None => Response::Reject,
+    }
+}
+
+impl Publickey for AuthorizedKeys {
+    async fn process(
+        &mut self,
+        context: &AuthContext<'_>,
+        user: String,
+        key: PublicKey,
+    ) -> Response {
+        accept(self.authorizes(context, &user, None, key.key_data()))
+    }
+
+    async fn process_security_key(
+        &mut self,
+        context: &AuthContext<'_>,
+        user: String,
+        key: PublicKey,
+        flags: SecurityKey,
+    ) -> Response {
+        accept(
+            self.authorizes(context, &user, None, key.key_data())
+                .filter(|options| {
+                    let permitted = (flags.user_presence || !options.touch_required)
+                        && (flags.user_verification || !options.verify_required);
+                    if !permitted {
+                        tracing::debug!(
+                            "Rejected the security key of user `{user}`, as it's flags don't satisfy the options"
+                        );
+                    }
+
+                    permitted
+                }),
+        )
+    }
+
+    async fn process_certificate(
+        &mut self,
+        context: &AuthContext<'_>,
+        user: String,
+        certificate: Certificate,
+    ) -> Response {
+        accept(self.authorizes(
+            context,
+            &user,
+            Some(&certificate),
+            certificate.signature_key(),
+        ))
+    }
+}
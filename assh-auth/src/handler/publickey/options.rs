@@ -0,0 +1,355 @@
+//! The options of the `authorized_keys` entries, restricting the sessions of the keys they authorize.
+
+use std::{
+    net::IpAddr,
+    time::{Duration, SystemTime},
+};
+
+/// The options of an `authorized_keys` entry, as described in the `AUTHORIZED_KEYS FILE FORMAT` section of `sshd(8)`.
+///
+/// The `from`, `expiry-time`, `principals`, `no-touch-required` and `verify-required` options are enforced
+/// by [`super::AuthorizedKeys`], the other restrictions are to be enforced by the downstream services
+/// from the [`crate::handler::Identity::key_options`] of the authenticated user.
+#[non_exhaustive]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyOptions {
+    /// The patterns of the `from` option, matched against the address of the peer.
+    pub from: Vec<String>,
+
+    /// The forced command of the `command` option, executed in place of the requested one.
+    pub command: Option<String>,
+
+    /// The variables of the `environment` options, set in the environment of the commands.
+    pub environment: Vec<(String, String)>,
+
+    /// The date after which the key is no longer accepted, from the `expiry-time` option.
+    pub expiry_time: Option<SystemTime>,
+
+    /// The principals of the `principals` option, of which certificates must have one.
+    pub principals: Vec<String>,
+
+    /// The `host:port` destinations of the `permitopen` options, the only ones allowed for local forwarding.
+    pub permit_open: Vec<String>,
+
+    /// The `[host:]port` sockets of the `permitlisten` options, the only ones allowed for remote forwarding.
+    pub permit_listen: Vec<String>,
+
+    /// The device of the `tunnel` option, forced for the tunnel forwarding requests.
+    pub tunnel: Option<String>,
+
+    /// Whether the agent forwarding is allowed, unset by `no-agent-forwarding`.
+    pub agent_forwarding: bool,
+
+    /// Whether the port forwarding is allowed, unset by `no-port-forwarding`.
+    pub port_forwarding: bool,
+
+    /// Whether the allocation of a pseudo-terminal is allowed, unset by `no-pty`.
+    pub pty: bool,
+
+    /// Whether the execution of `~/.ssh/rc` is allowed, unset by `no-user-rc`.
+    pub user_rc: bool,
+
+    /// Whether the _X11_ forwarding is allowed, unset by `no-X11-forwarding`.
+    pub x11_forwarding: bool,
+
+    /// Whether the entry is a _certificate authority_, from the `cert-authority` option.
+    pub cert_authority: bool,
+
+    /// Whether the security keys must assert the presence of the user, unset by `no-touch-required`.
+    pub touch_required: bool,
+
+    /// Whether the security keys must verify the user, from the `verify-required` option.
+    pub verify_required: bool,
+
+    /// The options which are not known, as written in the file.
+    pub unknown: Vec<String>,
+}
+
+impl Default for KeyOptions {
+    fn default() -> Self {
+        Self {
+            from: Default::default(),
+            command: Default::default(),
+            environment: Default::default(),
+            expiry_time: Default::default(),
+            principals: Default::default(),
+            permit_open: Default::default(),
+            permit_listen: Default::default(),
+            tunnel: Default::default(),
+            agent_forwarding: true,
+            port_forwarding: true,
+            pty: true,
+            user_rc: true,
+            x11_forwarding: true,
+            cert_authority: false,
+            touch_required: true,
+            verify_required: false,
+            unknown: Default::default(),
+        }
+    }
+}
+
+impl KeyOptions {
+    /// Parse the `options` of an entry, as written in the file, in order.
+    pub fn parse<S: AsRef<str>>(options: &[S]) -> Result<Self, String> {
+        let mut parsed = Self::default();
+
+        for option in options {
+            let option = option.as_ref();
+            let (name, value) = match option.split_once('=') {
+                Some((name, value)) => (name, Some(unquote(name, value)?)),
+                None => (option, None),
+            };
+            let flag = |enabled: bool| match value {
+                None => Ok(enabled),
+                Some(_) => Err(format!("unexpected value for the `{name}` option")),
+            };
+            let value = || {
+                value
+                    .clone()
+                    .ok_or(format!("missing value for the `{name}` option"))
+            };
+
+            match name.to_ascii_lowercase().as_str() {
+                "restrict" => {
+                    flag(true)?;
+
+                    parsed.agent_forwarding = false;
+                    parsed.port_forwarding = false;
+                    parsed.pty = false;
+                    parsed.user_rc = false;
+                    parsed.x11_forwarding = false;
+                }
+                "agent-forwarding" => parsed.agent_forwarding = flag(true)?,
+                "no-agent-forwarding" => parsed.agent_forwarding = flag(false)?,
+                "port-forwarding" => parsed.port_forwarding = flag(true)?,
+                "no-port-forwarding" => parsed.port_forwarding = flag(false)?,
+                "pty" => parsed.pty = flag(true)?,
+                "no-pty" => parsed.pty = flag(false)?,
+                "user-rc" => parsed.user_rc = flag(true)?,
+                "no-user-rc" => parsed.user_rc = flag(false)?,
+                "x11-forwarding" => parsed.x11_forwarding = flag(true)?,
+                "no-x11-forwarding" => parsed.x11_forwarding = flag(false)?,
+                "cert-authority" => parsed.cert_authority = flag(true)?,
+                "no-touch-required" => parsed.touch_required = flag(false)?,
+                "verify-required" => parsed.verify_required = flag(true)?,
+                "command" => parsed.command = Some(value()?),
+                "tunnel" => parsed.tunnel = Some(value()?),
+                "from" => parsed.from.extend(list(&value()?)),
+                "principals" => parsed.principals.extend(list(&value()?)),
+                "permitopen" => parsed.permit_open.push(value()?),
+                "permitlisten" => parsed.permit_listen.push(value()?),
+                "environment" => {
+                    let value = value()?;
+                    let (variable, value) = value
+                        .split_once('=')
+                        .filter(|(variable, _)| !variable.is_empty())
+                        .ok_or("malformed `environment` option, expected `NAME=value`")?;
+
+                    parsed.environment.push((variable.into(), value.into()));
+                }
+                "expiry-time" => parsed.expiry_time = Some(timespec(&value()?)?),
+                _ => parsed.unknown.push(option.into()),
+            }
+        }
+
+        Ok(parsed)
+    }
+
+    /// Whether the entry authorizes the peer at `addr`, matching the `from` patterns if any,
+    /// a single matching negated pattern rejecting the peer.
+    ///
+    /// The patterns are only matched against the textual address of the peer, or it's _CIDR_ ranges,
+    /// the host names not being resolved.
+    pub fn permits_from(&self, addr: Option<IpAddr>) -> bool {
+        if self.from.is_empty() {
+            return true;
+        }
+
+        let Some(addr) = addr else {
+            return false;
+        };
+
+        let mut permitted = false;
+        for pattern in &self.from {
+            let (negated, pattern) = match pattern.strip_prefix('!') {
+                Some(pattern) => (true, pattern),
+                None => (false, pattern.as_str()),
+            };
+
+            let matches = match pattern.split_once('/') {
+                Some((network, prefix)) => cidr(addr, network, prefix),
+                None => wildcard(pattern, &addr.to_string()),
+            };
+
+            match (matches, negated) {
+                (true, true) => return false,
+                (true, false) => permitted = true,
+                _ => (),
+            }
+        }
+
+        permitted
+    }
+
+    /// Whether the entry is still valid at `now`, according to it's `expiry-time` option.
+    pub fn permits_at(&self, now: SystemTime) -> bool {
+        !matches!(self.expiry_time, Some(expiry) if now >= expiry)
+    }
+
+    /// Whether the local forwarding to `host:port` is allowed, according to the port forwarding restriction
+    /// and the `permitopen` options, where a port of `*` matches any port.
+    pub fn permits_open(&self, host: &str, port: u32) -> bool {
+        self.port_forwarding
+            && (self.permit_open.is_empty()
+                || self
+                    .permit_open
+                    .iter()
+                    .any(|permitted| socket(permitted, host, port)))
+    }
+
+    /// Whether the remote forwarding from `host:port` is allowed, according to the port forwarding restriction
+    /// and the `permitlisten` options, where a port of `*` matches any port.
+    pub fn permits_listen(&self, host: &str, port: u32) -> bool {
+        self.port_forwarding
+            && (self.permit_listen.is_empty()
+                || self
+                    .permit_listen
+                    .iter()
+                    .any(|permitted| socket(permitted, host, port)))
+    }
+}
+
+/// Strip the double-quotes around the `value` of the option `name`, and unescape the quotes within it.
+fn unquote(name: &str, value: &str) -> Result<String, String> {
+    match value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+    {
+        Some(value) => Ok(value.replace("\\\"", "\"")),
+        None if !value.contains('"') => Ok(value.into()),
+        None => Err(format!("malformed quoting for the `{name}` option")),
+    }
+}
+
+/// Split the comma-separated `list`, skipping the empty items.
+fn list(list: &str) -> impl Iterator<Item = String> + '_ {
+    list.split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(Into::into)
+}
+
+/// Parse a `YYYYMMDD[HHMM[SS]]` timespec, optionally suffixed with `Z`,
+/// always interpreted in _UTC_ as the local timezone isn't known.
+fn timespec(value: &str) -> Result<SystemTime, String> {
+    let invalid = || format!("invalid `expiry-time` timespec `{value}`");
+
+    let digits = value.strip_suffix(['Z', 'z']).unwrap_or(value);
+    if !matches!(digits.len(), 8 | 12 | 14) || !digits.bytes().all(|c| c.is_ascii_digit()) {
+        return Err(invalid());
+    }
+
+    let field = |range: std::ops::Range<usize>| -> i64 {
+        digits
+            .get(range)
+            .map_or(0, |field| field.parse().unwrap_or(0))
+    };
+    let (year, month, day) = (field(0..4), field(4..6), field(6..8));
+    let (hour, minute, second) = (field(8..10), field(10..12), field(12..14));
+
+    if !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+        || hour > 23
+        || minute > 59
+        || second > 59
+    {
+        return Err(invalid());
+    }
+
+    // The days since the epoch of the civil date, from Howard Hinnant's `days_from_civil`.
+    let (year, month) = if month <= 2 {
+        (year - 1, month + 9)
+    } else {
+        (year, month - 3)
+    };
+    let (era, yoe) = (year.div_euclid(400), year.rem_euclid(400));
+    let doy = (153 * month + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
+
+    let seconds = days * 86400 + hour * 3600 + minute * 60 + second;
+
+    Ok(
+        SystemTime::UNIX_EPOCH
+            + Duration::from_secs(u64::try_from(seconds).map_err(|_| invalid())?),
+    )
+}
+
+/// Match the `text` against the `pattern`, with `*` matching any sequence and `?` any character.
+fn wildcard(pattern: &str, text: &str) -> bool {
+    match pattern.split_at_checked(1) {
+        Some(("*", rest)) => (0..=text.len())
+            .filter(|index| text.is_char_boundary(*index))
+            .any(|index| wildcard(rest, &text[index..])),
+        Some(("?", rest)) => {
+            let mut chars = text.chars();
+
+            chars.next().is_some() && wildcard(rest, chars.as_str())
+        }
+        Some(_) | None => {
+            let mut pattern = pattern.chars();
+            let mut text = text.chars();
+
+            match (pattern.next(), text.next()) {
+                (None, None) => true,
+                (Some(p), Some(t)) if p.eq_ignore_ascii_case(&t) => {
+                    wildcard(pattern.as_str(), text.as_str())
+                }
+                _ => false,
+            }
+        }
+    }
+}
+
+/// Whether the `addr` is in the _CIDR_ range of the `network` with the `prefix` length.
+fn cidr(addr: IpAddr, network: &str, prefix: &str) -> bool {
+    let (Ok(network), Ok(prefix)) = (network.parse::<IpAddr>(), prefix.parse::<u32>()) else {
+        return false;
+    };
+
+    match (addr, network) {
+        (IpAddr::V4(addr), IpAddr::V4(network)) if prefix <= 32 => {
+            let mask = u32::MAX.checked_shl(32 - prefix).unwrap_or(0);
+
+            u32::from(addr) & mask == u32::from(network) & mask
+        }
+        (IpAddr::V6(addr), IpAddr::V6(network)) if prefix <= 128 => {
+            let mask = u128::MAX.checked_shl(128 - prefix).unwrap_or(0);
+
+            u128::from(addr) & mask == u128::from(network) & mask
+        }
+        _ => false,
+    }
+}
+
+/// Whether the `permitted` socket, as `[host:]port`, matches the `host` and `port`,
+/// where a missing host or a host of `*` matches any host.
+fn socket(permitted: &str, host: &str, port: u32) -> bool {
+    let (permitted_host, permitted_port) = match permitted.rsplit_once(':') {
+        Some((permitted_host, permitted_port)) => (Some(permitted_host), permitted_port),
+        None => (None, permitted),
+    };
+    let permitted_host = permitted_host.map(|host| {
+        host.strip_prefix('[')
+            .and_then(|host| host.strip_suffix(']'))
+            .unwrap_or(host)
+    });
+
+    let host = match permitted_host {
+        None | Some("*") => true,
+        Some(permitted) => permitted.eq_ignore_ascii_case(host),
+    };
+
+    host && (permitted_port == "*" || permitted_port.parse() == Ok(port))
+}
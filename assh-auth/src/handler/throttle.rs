@@ -0,0 +1,52 @@
+//! The throttling of the failed authentication attempts.
+
+use futures_time::time::Duration;
+use rand::Rng;
+
+/// The delays applied before answering the failed authentication attempts.
+#[derive(Debug, Default)]
+pub(super) struct Throttle {
+    pub delay: std::time::Duration,
+    pub jitter: std::time::Duration,
+    pub backoff: Option<std::time::Duration>,
+    pub minimum: std::time::Duration,
+
+    consecutive: u32,
+}
+
+impl Throttle {
+    /// Register a failed attempt, and wait for the resulting delay.
+    pub async fn failure(&mut self) {
+        self.consecutive = self.consecutive.saturating_add(1);
+
+        let mut delay = self.delay;
+        if let Some(max) = self.backoff {
+            delay = delay
+                .saturating_mul(2u32.saturating_pow(self.consecutive - 1))
+                .min(max.max(self.delay));
+        }
+        if !self.jitter.is_zero() {
+            delay += rand::thread_rng().gen_range(std::time::Duration::ZERO..self.jitter);
+        }
+
+        if !delay.is_zero() {
+            tracing::debug!("Delaying the failed attempt by {delay:?}");
+
+            futures_time::task::sleep(Duration::from(delay)).await;
+        }
+    }
+
+    /// Wait for the minimum duration since the reception of the failed attempt at `received`.
+    pub async fn pad(&self, received: std::time::Instant) {
+        let remaining = self.minimum.saturating_sub(received.elapsed());
+
+        if !remaining.is_zero() {
+            futures_time::task::sleep(Duration::from(remaining)).await;
+        }
+    }
+
+    /// Reset the consecutive failures, after a partial success.
+    pub fn reset(&mut self) {
+        self.consecutive = 0;
+    }
+}
@@ -0,0 +1,82 @@
+//! The private keys loaded from OpenSSH-formatted files, decrypted only when signing.
+
+use std::sync::Arc;
+
+use assh::{
+    algorithm::{HostKeySigner, Key, SignFuture},
+    Error,
+};
+use ssh_key::{HashAlg, PrivateKey, PublicKey};
+use zeroize::Zeroizing;
+
+/// The number of times the passphrase is requested before giving up on the key, as OpenSSH does.
+const PASSPHRASE_PROMPTS: usize = 3;
+
+/// The callback providing the passphrase of an encrypted key, or `None` to skip it.
+pub type Callback = Arc<dyn Fn() -> Option<String> + Send + Sync>;
+
+/// The [`Callback`] of a key file.
+#[derive(Clone)]
+pub struct Passphrase(pub Callback);
+
+impl std::fmt::Debug for Passphrase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Passphrase").finish_non_exhaustive()
+    }
+}
+
+/// An encrypted private key, decrypted with the [`Passphrase`] for each signature then wiped,
+/// hashing with the `hash` if it's an _RSA_ key.
+#[derive(Debug)]
+pub struct Encrypted {
+    pub key: PrivateKey,
+    pub passphrase: Passphrase,
+    pub hash: HashAlg,
+}
+
+impl Encrypted {
+    /// Decrypt the key, requesting the passphrase again if it's wrong.
+    fn decrypt(&self) -> Result<PrivateKey, Error> {
+        for _ in 0..PASSPHRASE_PROMPTS {
+            let Some(passphrase) = (self.passphrase.0)().map(Zeroizing::new) else {
+                break;
+            };
+
+            match self.key.decrypt(passphrase.as_bytes()) {
+                Ok(key) => return Ok(key),
+                Err(ssh_key::Error::Crypto) => {
+                    tracing::debug!(
+                        "Wrong passphrase for the key `{}`",
+                        self.key.fingerprint(Default::default())
+                    );
+                }
+                Err(err) => return Err(Error::KeyCorrupt(err)),
+            }
+        }
+
+        Err(Error::KeyPassphrase)
+    }
+}
+
+impl HostKeySigner for Encrypted {
+    fn public_key(&self) -> &PublicKey {
+        self.key.public_key()
+    }
+
+    fn algorithm(&self) -> Key {
+        match self.key.algorithm() {
+            Key::Rsa { .. } => Key::Rsa {
+                hash: Some(self.hash),
+            },
+            algorithm => algorithm,
+        }
+    }
+
+    fn sign<'a>(&'a self, data: &'a [u8]) -> SignFuture<'a> {
+        Box::pin(async move {
+            let key = self.decrypt()?;
+
+            super::rsa_sha2::sign(&key, self.hash, data)
+        })
+    }
+}
@@ -1,38 +1,75 @@
+use assh::algorithm::HostKeySigner;
 use ssh_key::PrivateKey;
 use ssh_packet::{arch::Ascii, userauth};
 
+use std::path::PathBuf;
+
+use super::{keyfile::Passphrase, Challenge};
+
+/// The callback answering the challenges of the `keyboard-interactive` method.
+type Callback = Box<dyn FnMut(&Challenge) -> Vec<String> + Send + Sync>;
+
+/// The [`Callback`] of the `keyboard-interactive` method.
+pub struct Responder(pub Callback);
+
+impl std::fmt::Debug for Responder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Responder").finish_non_exhaustive()
+    }
+}
+
 /// Possible authentication methods in the SSH protocol.
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug)]
 pub enum Method {
     /// The SSH `none` authentication method.
     None,
 
-    /// The SSH `publickey` authentication method.
-    Publickey { key: Box<PrivateKey> },
+    /// The SSH `publickey` authentication method, probing the server for the key unless `probe` is `false`.
+    Publickey { key: Box<PrivateKey>, probe: bool },
 
     /// The SSH `password` authentication method.
     Password { password: String },
+
+    /// The SSH `keyboard-interactive` authentication method.
+    KeyboardInteractive { responder: Responder },
+
+    /// The SSH `publickey` authentication method, with a key loaded from an OpenSSH-formatted file.
+    File {
+        path: PathBuf,
+        passphrase: Passphrase,
+    },
+
+    /// The SSH `publickey` authentication method, with a key held by an `ssh-agent`.
+    #[cfg(unix)]
+    Agent { key: assh::agent::AgentKey },
 }
 
 impl Method {
-    pub fn as_ascii(&self) -> Ascii<'_> {
+    pub fn as_ascii(&self) -> Ascii<'static> {
         match self {
             Self::None { .. } => userauth::Method::NONE,
             Self::Publickey { .. } => userauth::Method::PUBLICKEY,
             Self::Password { .. } => userauth::Method::PASSWORD,
+            Self::KeyboardInteractive { .. } => userauth::Method::KEYBOARD_INTERACTIVE,
+            Self::File { .. } => userauth::Method::PUBLICKEY,
+            #[cfg(unix)]
+            Self::Agent { .. } => userauth::Method::PUBLICKEY,
         }
     }
-}
-
-impl std::hash::Hash for Method {
-    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
-        core::mem::discriminant(self).hash(state);
 
-        // Allow keys with different fingerprints to exist alongside
-        if let Self::Publickey { key } = self {
-            key.fingerprint(ssh_key::HashAlg::Sha256)
-                .as_bytes()
-                .hash(state);
+    /// Whether `self` takes the place of the `other` method, keeping it's position in the order,
+    /// which allows keys with different fingerprints to exist alongside.
+    pub fn replaces(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Publickey { key, .. }, Self::Publickey { key: other, .. }) => {
+                key.public_key() == other.public_key()
+            }
+            (Self::File { path, .. }, Self::File { path: other, .. }) => path == other,
+            #[cfg(unix)]
+            (Self::Agent { key }, Self::Agent { key: other }) => {
+                key.public_key() == other.public_key()
+            }
+            _ => core::mem::discriminant(self) == core::mem::discriminant(other),
         }
     }
 }
@@ -1,10 +1,11 @@
 //! Authentication _request_ mechanics.
 
-use hashbrown::HashSet;
+use std::{path::PathBuf, sync::Arc};
 
-use assh::{service::Request, side::Side, Error, Pipe, Result, Session};
+use assh::{algorithm::HostKeySigner, service::Request, side::Side, Error, Pipe, Result, Session};
 use ssh_packet::{
     arch::{self, Ascii, Utf8},
+    binrw::BinWrite,
     crypto::signature,
     trans::DisconnectReason,
     userauth, Packet,
@@ -13,20 +14,53 @@ use ssh_packet::{
 mod method;
 use method::Method;
 
+mod keyfile;
+mod rsa_sha2;
+
 // TODO: (feature) Add hostbased authentication.
-// TODO: (feature) Add keyboard-interactive authentication.
-// TODO: (compliance) Handle the SSH banner in the `request` side.
 
 #[doc(no_inline)]
 pub use ssh_key::PrivateKey;
 
+#[cfg(unix)]
+#[doc(no_inline)]
+pub use assh::agent::Agent;
+
+#[doc(no_inline)]
+pub use crate::handler::keyboard_interactive::{Challenge, Prompt};
+
+/// The callback choosing a new password from the prompt of the server, or declining the change.
+type Callback = Box<dyn FnMut(&str) -> Option<String> + Send + Sync>;
+
+/// The [`Callback`] answering the password change requests.
+struct PasswordChange(Callback);
+
+impl std::fmt::Debug for PasswordChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("PasswordChange").finish_non_exhaustive()
+    }
+}
+
+/// The callback displaying the banners of the server.
+struct OnBanner(Box<dyn Fn(&str) + Send + Sync>);
+
+impl std::fmt::Debug for OnBanner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("OnBanner").finish_non_exhaustive()
+    }
+}
+
 /// The authentication service [`Request`] for sessions.
 #[derive(Debug)]
 pub struct Auth<R> {
     username: Utf8<'static>,
     service: R,
 
-    methods: HashSet<Method>,
+    methods: Vec<Method>,
+    password_change: Option<PasswordChange>,
+    on_banner: Option<OnBanner>,
+    #[cfg(unix)]
+    agent: Option<(usize, Agent)>,
 }
 
 impl<R: Request> Auth<R> {
@@ -34,100 +68,323 @@ impl<R: Request> Auth<R> {
     ///
     /// # Note
     /// 1. The layer always starts with the `none` authentication method
-    ///    to discover the methods available on the server.
-    /// 2. While the `publickey` method allows for multiple keys,
-    ///    the `password` method will only keep the last one provided to [`Self::password`].
+    ///    to discover the methods available on the server, before any of the configured methods.
+    /// 2. The configured methods are then attempted in the order of the builder calls,
+    ///    skipping the ones the server doesn't allow to continue with.
+    /// 3. While the `publickey` method allows for multiple keys, the `password` and `keyboard-interactive`
+    ///    methods will only keep the last one provided to [`Self::password`] and [`Self::keyboard_interactive`],
+    ///    at the position of the first one, as is the case for a key provided twice.
     pub fn new(username: impl Into<Utf8<'static>>, service: R) -> Self {
         Self {
             username: username.into(),
             service,
 
             methods: Default::default(),
+            password_change: None,
+            on_banner: None,
+            #[cfg(unix)]
+            agent: None,
         }
     }
 
     /// Attempt to authenticate with the `password` method.
     pub fn password(mut self, password: impl Into<String>) -> Self {
-        self.methods.replace(Method::Password {
+        self.push(Method::Password {
             password: password.into(),
         });
 
         self
     }
 
+    /// Attempt to authenticate with the `publickey` method, with the OpenSSH-formatted private key at `path`.
+    ///
+    /// The file is read when the method is attempted, and if the key is encrypted, the `passphrase`
+    /// is only requested once the server accepts the key, and requested again if it's wrong.
+    /// The key is decrypted for the signature and wiped right after, and it's skipped if the file
+    /// fails to load, or the `passphrase` returns `None` or is wrong three times.
+    pub fn publickey_from_file(
+        mut self,
+        path: impl Into<PathBuf>,
+        passphrase: impl Fn() -> Option<String> + Send + Sync + 'static,
+    ) -> Self {
+        self.push(Method::File {
+            path: path.into(),
+            passphrase: keyfile::Passphrase(Arc::new(passphrase)),
+        });
+
+        self
+    }
+
+    /// Attempt to authenticate with the `publickey` method, with each of the keys held by the `agent`,
+    /// listed when the authentication starts.
+    ///
+    /// The keys are signing through the agent, and if it's unreachable, the authentication
+    /// continues with the other methods. The keys are attempted in the order listed by the agent,
+    /// at the position of this call among the other methods.
+    #[cfg(unix)]
+    pub fn agent(mut self, agent: Agent) -> Self {
+        self.agent = Some((self.methods.len(), agent));
+
+        self
+    }
+
+    /// Answer the password change requests of the server with the `callback`, called with it's prompt
+    /// and returning the new password, or `None` to decline and continue with the other methods.
+    ///
+    /// Without a callback, the password changes are always declined.
+    pub fn password_change(
+        mut self,
+        callback: impl FnMut(&str) -> Option<String> + Send + Sync + 'static,
+    ) -> Self {
+        self.password_change = Some(PasswordChange(Box::new(callback)));
+
+        self
+    }
+
     /// Attempt to authenticate with the `publickey` method.
+    ///
+    /// The server is first asked whether it accepts the key with an unsigned request,
+    /// and the key only signs the request once accepted.
     pub fn publickey(mut self, key: impl Into<PrivateKey>) -> Self {
-        self.methods.replace(Method::Publickey {
+        self.push(Method::Publickey {
+            key: key.into().into(),
+            probe: true,
+        });
+
+        self
+    }
+
+    /// Attempt to authenticate with the `publickey` method, sending the signed request right away
+    /// without asking the server whether it accepts the key, for the servers not answering these probes.
+    ///
+    /// This saves a round-trip, at the cost of a signature even if the server refuses the key.
+    pub fn publickey_without_probe(mut self, key: impl Into<PrivateKey>) -> Self {
+        self.push(Method::Publickey {
             key: key.into().into(),
+            probe: false,
+        });
+
+        self
+    }
+
+    /// Attempt to authenticate with the `keyboard-interactive` method, as described in RFC4256,
+    /// answering each [`Challenge`] of the server with the `responder`, one response per prompt.
+    ///
+    /// The challenges without prompts only carry instructions, and are answered with no responses
+    /// whatever the `responder` returns.
+    pub fn keyboard_interactive(
+        mut self,
+        responder: impl FnMut(&Challenge) -> Vec<String> + Send + Sync + 'static,
+    ) -> Self {
+        self.push(Method::KeyboardInteractive {
+            responder: method::Responder(Box::new(responder)),
         });
 
         self
     }
 
+    /// Add the `method` at the end of the order, unless it replaces one already provided.
+    fn push(&mut self, method: Method) {
+        match self.methods.iter_mut().find(|m| method.replaces(m)) {
+            Some(m) => *m = method,
+            None => self.methods.push(method),
+        }
+    }
+
+    /// Take the first configured method the server allows to `continue_with`,
+    /// leaving the other ones in place since they may be allowed after a partial success.
     fn next_method(&mut self, continue_with: &arch::NameList) -> Option<Method> {
-        self.methods
-            .extract_if(|m| {
-                continue_with
-                    .into_iter()
-                    .any(|method| m.as_ascii() == method)
-            })
-            .next()
+        let index = self.methods.iter().position(|m| {
+            continue_with
+                .into_iter()
+                .any(|method| m.as_ascii() == method)
+        })?;
+
+        Some(self.methods.remove(index))
+    }
+
+    /// The comma-separated names of the configured methods not yet attempted.
+    fn remaining(&self) -> String {
+        let mut names = Vec::new();
+        for name in self.methods.iter().map(Method::as_ascii) {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+
+        names
+            .iter()
+            .map(|name| &**name)
+            .collect::<Vec<_>>()
+            .join(",")
     }
 
+    /// Display the banners sent by the server with the `callback`, such as legal notices,
+    /// which is called for each of them, whenever they are received during the authentication.
+    ///
+    /// Without a callback, the banners are discarded.
+    pub fn on_banner(mut self, callback: impl Fn(&str) + Send + Sync + 'static) -> Self {
+        self.on_banner = Some(OnBanner(Box::new(callback)));
+
+        self
+    }
+
+    /// Receive the next response of the server, handing the banners received meanwhile to the callback.
+    async fn recv<IO: Pipe, S: Side>(&self, session: &mut Session<IO, S>) -> Result<Packet> {
+        loop {
+            let packet = session.recv().await?;
+
+            if let Ok(userauth::Banner { message, .. }) = packet.to() {
+                if let Some(on_banner) = &self.on_banner {
+                    (on_banner.0)(&message);
+                }
+
+                continue;
+            }
+
+            break Ok(packet);
+        }
+    }
+
+    /// Attempt to authenticate with the `publickey` method with the `key`, signing with it's algorithm,
+    /// or `None` if the signature failed.
+    ///
+    /// Unless `probe` is `false`, the server is first asked whether it accepts the key, so it signs only once accepted.
+    async fn attempt_publickey<IO: Pipe, S: Side>(
+        &self,
+        session: &mut Session<IO, S>,
+        key: &dyn HostKeySigner,
+        probe: bool,
+    ) -> Result<Option<Packet>> {
+        let build = |method| userauth::Request {
+            username: self.username.clone(),
+            service_name: R::SERVICE_NAME,
+            method,
+        };
+
+        let algorithm = key.algorithm();
+        let blob = key.public_key().to_bytes()?;
+
+        // Probe the server to know if this algorithm is implemented.
+        let response;
+        let (algorithm, blob) = if probe {
+            session
+                .send(&build(userauth::Method::Publickey {
+                    algorithm: algorithm.as_str().as_bytes().into(),
+                    blob: blob.as_slice().into(),
+                    signature: None,
+                }))
+                .await?;
+
+            response = self.recv(session).await?;
+            let Ok(userauth::PkOk { algorithm, blob }) = response.to() else {
+                return Ok(Some(response));
+            };
+
+            (algorithm, blob)
+        } else {
+            (algorithm.as_str().as_bytes().into(), blob.as_slice().into())
+        };
+
+        // Actually sign the message with the key to perform real authentication.
+        let mut message = Vec::new();
+        signature::Publickey {
+            session_id: session.session_id().unwrap_or_default().into(),
+            username: self.username.as_borrow(),
+            service_name: R::SERVICE_NAME,
+            algorithm: algorithm.as_borrow(),
+            blob: blob.as_borrow(),
+        }
+        .write(&mut std::io::Cursor::new(&mut message))?;
+
+        let signature = match key.sign(&message).await {
+            Ok(signature) => signature,
+            Err(err) => {
+                tracing::warn!(
+                    "Unable to sign with the key `{}`: {err}",
+                    key.public_key().fingerprint(Default::default())
+                );
+
+                return Ok(None);
+            }
+        };
+
+        session
+            .send(&build(userauth::Method::Publickey {
+                algorithm,
+                blob,
+                signature: Some(Vec::try_from(signature)?.into()),
+            }))
+            .await?;
+
+        self.recv(session).await.map(Some)
+    }
+
+    /// Attempt to authenticate with the `method`, returning the concluding response of the server,
+    /// or `None` if the attempt was abandoned.
     async fn attempt_method<IO: Pipe, S: Side>(
         &mut self,
         session: &mut Session<IO, S>,
-        method: &Method,
-    ) -> Result<Packet> {
+        method: &mut Method,
+    ) -> Result<Option<Packet>> {
         let build = |method| userauth::Request {
             username: self.username.clone(),
             service_name: R::SERVICE_NAME,
             method,
         };
 
+        // The hash of the signatures for the _RSA_ keys, as `ssh-rsa` is refused by most servers.
+        let hash = rsa_sha2::hash(session.server_sig_algs());
+
         match method {
             Method::None => {
                 session.send(&build(userauth::Method::None)).await?;
 
-                session.recv().await
+                self.recv(session).await.map(Some)
             }
-            Method::Publickey { key } => {
-                let algorithm = key.algorithm();
+            Method::Publickey { key, probe } => {
+                if key.algorithm().is_rsa() {
+                    let key = rsa_sha2::Rsa {
+                        key: key.as_ref().clone(),
+                        hash,
+                    };
 
-                // Probe the server to know if this algorithm is implemented.
-                session
-                    .send(&build(userauth::Method::Publickey {
-                        algorithm: algorithm.as_str().as_bytes().into(),
-                        blob: key.public_key().to_bytes()?.into(),
-                        signature: None,
-                    }))
-                    .await?;
+                    self.attempt_publickey(session, &key, *probe).await
+                } else {
+                    self.attempt_publickey(session, key.as_ref(), *probe).await
+                }
+            }
+            Method::File { path, passphrase } => {
+                let key = match PrivateKey::read_openssh_file(path) {
+                    Ok(key) => key,
+                    Err(err) => {
+                        tracing::warn!("Unable to load the key `{}`: {err}", path.display());
 
-                let response = session.recv().await?;
-                if let Ok(userauth::PkOk { algorithm, blob }) = response.to() {
-                    // Actually sign the message with the key to perform real authentication.
-                    let signature = signature::Publickey {
-                        session_id: session.session_id().unwrap_or_default().into(),
-                        username: self.username.as_borrow(),
-                        service_name: R::SERVICE_NAME,
-                        algorithm: algorithm.as_borrow(),
-                        blob: blob.as_borrow(),
+                        return Ok(None);
                     }
-                    .sign(key.as_ref());
+                };
 
-                    session
-                        .send(&build(userauth::Method::Publickey {
-                            algorithm,
-                            blob,
-                            signature: Some(signature.as_bytes().into()),
-                        }))
-                        .await?;
+                if key.is_encrypted() {
+                    let key = keyfile::Encrypted {
+                        key,
+                        passphrase: passphrase.clone(),
+                        hash,
+                    };
 
-                    session.recv().await
+                    self.attempt_publickey(session, &key, true).await
+                } else if key.algorithm().is_rsa() {
+                    self.attempt_publickey(session, &rsa_sha2::Rsa { key, hash }, true)
+                        .await
                 } else {
-                    Ok(response)
+                    self.attempt_publickey(session, &key, true).await
                 }
             }
+            #[cfg(unix)]
+            Method::Agent { key } => {
+                let key = key.clone().rsa_hash(hash);
+
+                self.attempt_publickey(session, &key, true).await
+            }
             Method::Password { password } => {
                 session
                     .send(&build(userauth::Method::Password {
@@ -136,11 +393,70 @@ impl<R: Request> Auth<R> {
                     }))
                     .await?;
 
-                let response = session.recv().await?;
-                if let Ok(userauth::PasswdChangereq { prompt: _, .. }) = response.to() {
-                    todo!() // TODO: (compliance) Handle the change request case
-                } else {
-                    Ok(response)
+                // Change the password as long as the server requests it, which it may repeat
+                // if the new password isn't acceptable, as described in RFC4252 section 8.
+                loop {
+                    let response = self.recv(session).await?;
+                    let Ok(userauth::PasswdChangereq { prompt, .. }) = response.to() else {
+                        break Ok(Some(response));
+                    };
+
+                    let Some(new) = self
+                        .password_change
+                        .as_mut()
+                        .and_then(|callback| (callback.0)(&prompt))
+                    else {
+                        tracing::debug!("Declined the password change requested by the server");
+
+                        break Ok(None);
+                    };
+
+                    session
+                        .send(&build(userauth::Method::Password {
+                            password: password.as_str().into(),
+                            new: Some(new.into()),
+                        }))
+                        .await?;
+                }
+            }
+            Method::KeyboardInteractive { responder } => {
+                session
+                    .send(&build(userauth::Method::KeyboardInteractive {
+                        language: Default::default(),
+                        submethods: Default::default(),
+                    }))
+                    .await?;
+
+                // Answer the challenges of the server until it concludes the attempt.
+                loop {
+                    let response = self.recv(session).await?;
+                    let Ok(request) = response.to::<userauth::InfoRequest>() else {
+                        break Ok(Some(response));
+                    };
+
+                    let challenge = Challenge {
+                        name: request.name.into_string(),
+                        instruction: request.instruction.into_string(),
+                        prompts: request
+                            .prompts
+                            .into_iter()
+                            .map(|prompt| Prompt {
+                                prompt: prompt.prompt.into_string(),
+                                echo: *prompt.echo,
+                            })
+                            .collect(),
+                    };
+
+                    let mut responses = (responder.0)(&challenge);
+                    if challenge.prompts.is_empty() {
+                        responses.clear();
+                    }
+
+                    session
+                        .send(&userauth::InfoResponse {
+                            responses: responses.into_iter().map(Into::into).collect(),
+                        })
+                        .await?;
                 }
             }
         }
@@ -161,39 +477,98 @@ impl<R: Request> Request for Auth<R> {
         IO: Pipe,
         S: Side,
     {
+        #[cfg(unix)]
+        if let Some((index, agent)) = self.agent.take() {
+            match agent.identities().await {
+                Ok(keys) => {
+                    self.methods.splice(
+                        index..index,
+                        keys.into_iter().map(|key| Method::Agent { key }),
+                    );
+                }
+                Err(err) => tracing::warn!("Unable to list the keys of the agent: {err}"),
+            }
+        }
+
         let mut method = Method::None;
+        let mut failure: Option<Packet> = None;
+        let mut partial = Vec::new();
 
         loop {
-            let response = self.attempt_method(&mut session, &method).await?;
+            match self.attempt_method(&mut session, &mut method).await? {
+                Some(response) if response.to::<userauth::Success>().is_ok() => {
+                    session.activate_compression();
 
-            if response.to::<userauth::Success>().is_ok() {
-                break self.service.on_accept(session).await;
-            } else if let Ok(userauth::Failure { continue_with, .. }) = response.to() {
-                // TODO: (compliance) Take care of partial success
+                    break self.service.on_accept(session).await;
+                }
+                Some(response) if response.to::<userauth::Failure>().is_ok() => {
+                    // The method succeeded but the server requires more of them, it's not attempted again
+                    // and the authentication continues with the remaining ones the server allows.
+                    if response
+                        .to::<userauth::Failure>()
+                        .is_ok_and(|failure| *failure.partial_success)
+                    {
+                        tracing::debug!(
+                            "Partially authenticated with the `{}` method",
+                            method.as_ascii()
+                        );
 
-                if let Some(next) = self.next_method(&continue_with) {
-                    method = next;
-                } else {
+                        partial.push(method.as_ascii());
+                    }
+
+                    failure = Some(response);
+                }
+                // The attempt was abandoned, continue with the methods of the last failure.
+                None => (),
+                Some(_) => {
                     break Err(Error::from(
                         session
                             .disconnect(
-                                DisconnectReason::NoMoreAuthMethodsAvailable,
-                                "Exhausted available authentication methods",
+                                DisconnectReason::ProtocolError,
+                                format!(
+                                    "Unexpected message in the context of the `{}` service request",
+                                    Self::SERVICE_NAME
+                                ),
                             )
                             .await,
                     )
                     .into());
-                };
+                }
+            }
+
+            let continue_with = failure
+                .as_ref()
+                .and_then(|failure| failure.to::<userauth::Failure>().ok())
+                .map(|userauth::Failure { continue_with, .. }| continue_with);
+
+            if let Some(next) = continue_with
+                .as_ref()
+                .and_then(|continue_with| self.next_method(continue_with))
+            {
+                method = next;
             } else {
+                let mut description = match &continue_with {
+                    Some(continue_with) if !self.methods.is_empty() => format!(
+                        "None of the remaining methods `{}` are allowed to continue, the server expects one of `{}`",
+                        self.remaining(),
+                        continue_with.0
+                    ),
+                    _ => "Exhausted available authentication methods".into(),
+                };
+                if !partial.is_empty() {
+                    description += &format!(
+                        ", after the partial success of `{}`",
+                        partial
+                            .iter()
+                            .map(|name| &**name)
+                            .collect::<Vec<_>>()
+                            .join(",")
+                    );
+                }
+
                 break Err(Error::from(
                     session
-                        .disconnect(
-                            DisconnectReason::ProtocolError,
-                            format!(
-                                "Unexpected message in the context of the `{}` service request",
-                                Self::SERVICE_NAME
-                            ),
-                        )
+                        .disconnect(DisconnectReason::NoMoreAuthMethodsAvailable, description)
                         .await,
                 )
                 .into());
@@ -0,0 +1,97 @@
+//! The `rsa-sha2-*` signatures of the _RSA_ keys, as described in RFC8332.
+
+use assh::{
+    algorithm::{HostKeySigner, Key, SignFuture},
+    Result,
+};
+use rsa::signature::{SignatureEncoding, Signer};
+use ssh_key::{private::KeypairData, HashAlg, PrivateKey, PublicKey, Signature};
+
+/// Select the strongest `rsa-sha2-*` hash advertised in the `server-sig-algs` of the server,
+/// falling back to `rsa-sha2-256` without it, as OpenSSH does.
+pub fn hash(server_sig_algs: Option<&[String]>) -> HashAlg {
+    match server_sig_algs {
+        Some(algorithms) if algorithms.iter().any(|name| name == "rsa-sha2-512") => HashAlg::Sha512,
+        _ => HashAlg::Sha256,
+    }
+}
+
+/// Sign the `data` with the `key`, hashing with the `hash` if it's an _RSA_ key.
+pub fn sign(key: &PrivateKey, hash: HashAlg, data: &[u8]) -> Result<Signature> {
+    let KeypairData::Rsa(keypair) = key.key_data() else {
+        return Ok(Signer::<Signature>::try_sign(key, data)?);
+    };
+
+    // The `ssh-key` crate fails to convert the keypair, by using `p` as both of it's primes.
+    let private = rsa::RsaPrivateKey::from_components(
+        (&keypair.public.n).try_into()?,
+        (&keypair.public.e).try_into()?,
+        (&keypair.private.d).try_into()?,
+        vec![
+            (&keypair.private.p).try_into()?,
+            (&keypair.private.q).try_into()?,
+        ],
+    )
+    .map_err(|_| ssh_key::Error::Crypto)?;
+
+    let (hash, signature) = match hash {
+        HashAlg::Sha256 => (
+            HashAlg::Sha256,
+            rsa::pkcs1v15::SigningKey::<sha2::Sha256>::new(private)
+                .try_sign(data)?
+                .to_vec(),
+        ),
+        _ => (
+            HashAlg::Sha512,
+            rsa::pkcs1v15::SigningKey::<sha2::Sha512>::new(private)
+                .try_sign(data)?
+                .to_vec(),
+        ),
+    };
+
+    Ok(Signature::new(Key::Rsa { hash: Some(hash) }, signature)?)
+}
+
+/// An _RSA_ private key, signing with the `hash`.
+#[derive(Debug)]
+pub struct Rsa {
+    pub key: PrivateKey,
+    pub hash: HashAlg,
+}
+
+impl HostKeySigner for Rsa {
+    fn public_key(&self) -> &PublicKey {
+        self.key.public_key()
+    }
+
+    fn algorithm(&self) -> Key {
+        Key::Rsa {
+            hash: Some(self.hash),
+        }
+    }
+
+    fn sign<'a>(&'a self, data: &'a [u8]) -> SignFuture<'a> {
+        Box::pin(async move { sign(&self.key, self.hash, data) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hash_follows_the_server_sig_algs() {
+        let advertised = |names: &[&str]| names.iter().map(|&name| name.into()).collect::<Vec<_>>();
+
+        assert_eq!(hash(None), HashAlg::Sha256);
+        assert_eq!(
+            hash(Some(&advertised(&["ssh-ed25519", "rsa-sha2-256"]))),
+            HashAlg::Sha256
+        );
+        assert_eq!(
+            hash(Some(&advertised(&["rsa-sha2-256", "rsa-sha2-512"]))),
+            HashAlg::Sha512
+        );
+        assert_eq!(hash(Some(&advertised(&["ssh-rsa"]))), HashAlg::Sha256);
+    }
+}
@@ -0,0 +1,300 @@
+use std::path::PathBuf;
+
+use assh_auth::handler::{
+    publickey::{AuthorizedKeys, Error, KeyOptions, Loaded, Publickey, Response},
+    AuthContext,
+};
+use futures::executor::block_on;
+use ssh_key::{Algorithm, PrivateKey, PublicKey};
+use ssh_packet::Id;
+
+fn key() -> PublicKey {
+    PrivateKey::random(&mut rand::thread_rng(), Algorithm::Ed25519)
+        .unwrap()
+        .public_key()
+        .clone()
+}
+
+fn line(key: &PublicKey) -> String {
+    key.to_openssh().unwrap()
+}
+
+fn context(peer_id: &Id) -> AuthContext<'_> {
+    AuthContext {
+        session_id: &[],
+        peer_id,
+        service_name: "ssh-connection",
+        attempt: 1,
+        peer_addr: None,
+    }
+}
+
+fn scratch(name: &str) -> PathBuf {
+    let path =
+        std::env::temp_dir().join(format!("assh-auth-{name}-{:016x}", rand::random::<u64>()));
+    std::fs::create_dir(&path).unwrap();
+
+    path
+}
+
+#[test]
+fn entries_are_parsed() {
+    let (plain, optioned, quoted) = (key(), key(), key());
+
+    let loaded = Loaded::parse(&format!(
+        "# A comment\n\
+        \n\
+        {} alice@laptop with spaces\n\
+        no-pty,no-port-forwarding   {}\n\
+        command=\"echo a, b\\\" c\",from=\"10.0.0.0/8\" {} quoted # not a comment\n\
+        ssh-ed25519\n\
+        ssh-ed25519 AAAA\n\
+        no-pty,,restrict {}\n\
+        command=\"unterminated {}\n\
+        ssh-rsa {}\n",
+        line(&plain),
+        line(&optioned),
+        line(&quoted),
+        line(&plain),
+        line(&plain),
+        line(&plain).trim_start_matches("ssh-ed25519 "),
+    ));
+
+    assert_eq!(loaded.entries.len(), 3);
+    assert_eq!(loaded.entries[0].key.key_data(), plain.key_data());
+    assert_eq!(loaded.entries[0].key.comment(), "alice@laptop with spaces");
+    assert!(loaded.entries[0].options.is_empty());
+
+    assert_eq!(loaded.entries[1].key.key_data(), optioned.key_data());
+    assert_eq!(loaded.entries[1].options, ["no-pty", "no-port-forwarding"]);
+    assert!(loaded.entries[1].has_option("NO-PTY"));
+    assert!(!loaded.entries[1].has_option("restrict"));
+
+    assert_eq!(loaded.entries[2].key.key_data(), quoted.key_data());
+    assert_eq!(
+        loaded.entries[2].options,
+        ["command=\"echo a, b\\\" c\"", "from=\"10.0.0.0/8\""]
+    );
+    assert!(loaded.entries[2].has_option("command"));
+    assert_eq!(loaded.entries[2].key.comment(), "quoted # not a comment");
+
+    let lines = loaded
+        .errors
+        .iter()
+        .map(|err| match err {
+            Error::Malformed { line, .. } => *line,
+            err => panic!("Unexpected error: {err}"),
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(lines, [6, 7, 8, 9, 10]);
+    assert!(loaded.errors[4].to_string().contains("line 10"));
+}
+
+#[test]
+fn files_are_reloaded() {
+    let dir = scratch("reload");
+    let (alice, bob, other) = (key(), key(), key());
+
+    std::fs::create_dir(dir.join("alice")).unwrap();
+    std::fs::write(dir.join("alice/authorized_keys"), line(&alice)).unwrap();
+
+    let id = Id::v2("test", None::<&str>);
+    let context = context(&id);
+    let mut keys = AuthorizedKeys::template(format!("{}/{{user}}/authorized_keys", dir.display()));
+
+    assert_eq!(
+        block_on(keys.process(&context, "alice".into(), alice.clone())),
+        Response::Accept
+    );
+    assert_eq!(
+        block_on(keys.process(&context, "alice".into(), other.clone())),
+        Response::Reject
+    );
+    assert_eq!(
+        block_on(keys.process(&context, "bob".into(), bob.clone())),
+        Response::Reject
+    );
+    assert_eq!(
+        block_on(keys.process(&context, "../alice".into(), alice.clone())),
+        Response::Reject
+    );
+    assert!(matches!(keys.load(".."), Err(Error::Username(_))));
+
+    // The modified file is reloaded lazily.
+    std::fs::write(
+        dir.join("alice/authorized_keys"),
+        format!("{}\n{}\n", line(&alice), line(&other)),
+    )
+    .unwrap();
+    assert_eq!(
+        block_on(keys.process(&context, "alice".into(), other.clone())),
+        Response::Accept
+    );
+
+    std::fs::create_dir(dir.join("bob")).unwrap();
+    std::fs::write(dir.join("bob/authorized_keys"), line(&bob)).unwrap();
+    assert_eq!(
+        block_on(keys.process(&context, "bob".into(), bob.clone())),
+        Response::Accept
+    );
+
+    keys.refresh();
+    assert_eq!(keys.load("bob").unwrap().entries.len(), 1);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn certificate_authorities_only_accept_certificates() {
+    let dir = scratch("ca");
+    let ca = PrivateKey::random(&mut rand::thread_rng(), Algorithm::Ed25519).unwrap();
+    let user = PrivateKey::random(&mut rand::thread_rng(), Algorithm::Ed25519).unwrap();
+
+    std::fs::write(
+        dir.join("authorized_keys"),
+        format!("cert-authority {}\n", line(ca.public_key())),
+    )
+    .unwrap();
+
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let mut builder = ssh_key::certificate::Builder::new_with_random_nonce(
+        &mut rand::thread_rng(),
+        user.public_key(),
+        now,
+        now + 60,
+    )
+    .unwrap();
+    builder.valid_principal("alice").unwrap();
+    let certificate = builder.sign(&ca).unwrap();
+
+    let id = Id::v2("test", None::<&str>);
+    let context = context(&id);
+    let mut keys = AuthorizedKeys::file(dir.join("authorized_keys"));
+
+    assert_eq!(
+        block_on(keys.process(&context, "alice".into(), ca.public_key().clone())),
+        Response::Reject
+    );
+    assert_eq!(
+        block_on(keys.process_certificate(&context, "alice".into(), certificate)),
+        Response::Accept
+    );
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn options_are_parsed() {
+    let options = KeyOptions::parse(&[
+        "restrict",
+        "PTY",
+        "command=\"echo \\\"hi\\\"\"",
+        "from=\"10.0.0.0/8,!10.1.*\"",
+        "environment=\"LANG=C=D\"",
+        "permitopen=\"localhost:*\"",
+        "expiry-time=\"20240102030405Z\"",
+        "no-touch-required",
+        "frobnicate=\"yes\"",
+    ])
+    .unwrap();
+
+    assert!(options.pty);
+    assert!(!options.port_forwarding && !options.agent_forwarding && !options.x11_forwarding);
+    assert_eq!(options.command.as_deref(), Some("echo \"hi\""));
+    assert_eq!(options.from, ["10.0.0.0/8", "!10.1.*"]);
+    assert_eq!(options.environment, [("LANG".into(), "C=D".into())]);
+    assert_eq!(options.permit_open, ["localhost:*"]);
+    assert_eq!(
+        options.expiry_time,
+        Some(std::time::UNIX_EPOCH + std::time::Duration::from_secs(1704164645))
+    );
+    assert!(!options.touch_required);
+    assert_eq!(options.unknown, ["frobnicate=\"yes\""]);
+
+    assert!(KeyOptions::parse(&["no-pty=\"yes\""]).is_err());
+    assert!(KeyOptions::parse(&["command"]).is_err());
+    assert!(KeyOptions::parse(&["environment=\"=value\""]).is_err());
+    assert!(KeyOptions::parse(&["expiry-time=\"20241301\""]).is_err());
+    assert!(Loaded::parse(&format!("from {}", line(&key())))
+        .entries
+        .is_empty());
+}
+
+#[test]
+fn options_restrict_the_peers() {
+    let options = KeyOptions::parse(&["from=\"10.0.0.0/8,!10.1.0.0/16,192.168.1.?,::1\""]).unwrap();
+
+    assert!(options.permits_from(Some([10, 2, 3, 4].into())));
+    assert!(!options.permits_from(Some([10, 1, 3, 4].into())));
+    assert!(options.permits_from(Some([192, 168, 1, 7].into())));
+    assert!(!options.permits_from(Some([192, 168, 1, 17].into())));
+    assert!(options.permits_from(Some(std::net::Ipv6Addr::LOCALHOST.into())));
+    assert!(!options.permits_from(Some([127, 0, 0, 1].into())));
+    assert!(!options.permits_from(None));
+    assert!(KeyOptions::default().permits_from(None));
+
+    let options =
+        KeyOptions::parse(&["permitopen=\"localhost:*\"", "permitopen=\"[::1]:22\""]).unwrap();
+    assert!(options.permits_open("localhost", 8080));
+    assert!(options.permits_open("::1", 22));
+    assert!(!options.permits_open("::1", 23));
+    assert!(!options.permits_open("example.com", 80));
+    assert!(!KeyOptions::parse(&["no-port-forwarding"])
+        .unwrap()
+        .permits_open("localhost", 80));
+}
+
+#[test]
+fn options_are_enforced() {
+    let dir = scratch("options");
+    let (restricted, expired, remote) = (key(), key(), key());
+
+    std::fs::write(
+        dir.join("authorized_keys"),
+        format!(
+            "no-pty,no-port-forwarding {}\n\
+            expiry-time=\"20000101\" {}\n\
+            from=\"10.0.0.0/8\" {}\n",
+            line(&restricted),
+            line(&expired),
+            line(&remote),
+        ),
+    )
+    .unwrap();
+
+    let id = Id::v2("test", None::<&str>);
+    let local = AuthContext {
+        peer_addr: Some([127, 0, 0, 1].into()),
+        ..context(&id)
+    };
+    let private = AuthContext {
+        peer_addr: Some([10, 0, 0, 1].into()),
+        ..context(&id)
+    };
+    let mut keys = AuthorizedKeys::file(dir.join("authorized_keys"));
+
+    match block_on(keys.process(&local, "alice".into(), restricted.clone())) {
+        Response::Restricted(options) => {
+            assert!(!options.pty && !options.port_forwarding);
+            assert!(options.agent_forwarding);
+        }
+        response => panic!("Unexpected response: {response:?}"),
+    }
+    assert_eq!(
+        block_on(keys.process(&local, "alice".into(), expired.clone())),
+        Response::Reject
+    );
+    assert_eq!(
+        block_on(keys.process(&local, "alice".into(), remote.clone())),
+        Response::Reject
+    );
+    assert!(matches!(
+        block_on(keys.process(&private, "alice".into(), remote.clone())),
+        Response::Restricted(_)
+    ));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,94 @@
+#![cfg(feature = "hashed-password")]
+
+use assh_auth::handler::{
+    password::{Hashed, Password, Response},
+    AuthContext,
+};
+use ssh_packet::Id;
+
+/// The `argon2id` vector of the reference implementation, for `password` salted with `somesalt`.
+const ARGON2: &str =
+    "$argon2id$v=19$m=65536,t=2,p=1$c29tZXNhbHQ$CTFhFdXPJO1aFaMaO6Mm5c8y7cJHAph8ArZWb2GRPPc";
+
+/// A `bcrypt` vector of OpenBSD's test suite, for `U*U`.
+const BCRYPT: &str = "$2a$05$CCCCCCCCCCCCCCCCCCCCC.E5YPO9kmyuRGyh0XouQYb4YMJKvyOeW";
+
+/// A `scrypt` vector for `correct horse`, salted with `assh-auth-scrypt`.
+const SCRYPT: &str =
+    "$scrypt$ln=10,r=8,p=1$YXNzaC1hdXRoLXNjcnlwdA$y7xi6AuyqQH6baXDqkYE2GMNYZ6TDgJubIXzoEqbd7M";
+
+fn process(hashed: &mut Hashed, user: &str, password: &str, new: Option<&str>) -> Response {
+    let peer_id = Id::v2("test", None::<&str>);
+    let context = AuthContext {
+        session_id: &[],
+        peer_id: &peer_id,
+        service_name: "ssh-connection",
+        attempt: 1,
+        peer_addr: None,
+    };
+
+    futures::executor::block_on(hashed.process(
+        &context,
+        user.into(),
+        password.into(),
+        new.map(Into::into),
+    ))
+}
+
+#[test]
+fn hashes_are_verified() {
+    let mut hashed = Hashed::new()
+        .user("alice", ARGON2)
+        .user("bob", BCRYPT)
+        .user("carol", SCRYPT);
+
+    assert_eq!(
+        process(&mut hashed, "alice", "password", None),
+        Response::Accept
+    );
+    assert_eq!(
+        process(&mut hashed, "alice", "Password", None),
+        Response::Reject
+    );
+    assert_eq!(process(&mut hashed, "bob", "U*U", None), Response::Accept);
+    assert_eq!(process(&mut hashed, "bob", "U*V", None), Response::Reject);
+    assert_eq!(
+        process(&mut hashed, "carol", "correct horse", None),
+        Response::Accept
+    );
+    assert_eq!(
+        process(&mut hashed, "carol", "correct", None),
+        Response::Reject
+    );
+    assert_eq!(
+        process(&mut hashed, "dave", "password", None),
+        Response::Reject
+    );
+}
+
+#[test]
+fn malformed_hashes_are_rejected() {
+    let mut hashed = Hashed::new()
+        .user("alice", "password")
+        .user("bob", "$argon2id$v=19$garbage")
+        .user("carol", "$2b$04$short")
+        .user("dave", "$md5$c29tZXNhbHQ$c29tZWhhc2g");
+
+    for user in ["alice", "bob", "carol", "dave"] {
+        assert_eq!(
+            process(&mut hashed, user, "password", None),
+            Response::Reject
+        );
+    }
+}
+
+#[test]
+fn password_changes_are_rejected() {
+    let mut hashed = Hashed::new().user("bob", BCRYPT);
+
+    assert_eq!(
+        process(&mut hashed, "bob", "U*U", Some("hunter2")),
+        Response::Reject
+    );
+    assert_eq!(process(&mut hashed, "bob", "U*U", None), Response::Accept);
+}
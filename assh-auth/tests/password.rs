@@ -0,0 +1,106 @@
+use assh_auth::handler::{
+    password::{Password, Response, Verifier},
+    AuthContext,
+};
+use ssh_packet::Id;
+
+fn process(verifier: &mut Verifier, user: &str, password: &str, new: Option<&str>) -> Response {
+    let peer_id = Id::v2("test", None::<&str>);
+    let context = AuthContext {
+        session_id: &[],
+        peer_id: &peer_id,
+        service_name: "ssh-connection",
+        attempt: 1,
+        peer_addr: None,
+    };
+
+    futures::executor::block_on(verifier.process(
+        &context,
+        user.into(),
+        password.into(),
+        new.map(Into::into),
+    ))
+}
+
+#[test]
+fn passwords_are_verified() {
+    let mut verifier = Verifier::new()
+        .user("alice", "hunter2")
+        .user("bob", "correct horse");
+
+    assert_eq!(
+        process(&mut verifier, "alice", "hunter2", None),
+        Response::Accept
+    );
+    assert_eq!(
+        process(&mut verifier, "alice", "hunter3", None),
+        Response::Reject
+    );
+    assert_eq!(process(&mut verifier, "alice", "", None), Response::Reject);
+    assert_eq!(
+        process(&mut verifier, "bob", "hunter2", None),
+        Response::Reject
+    );
+    assert_eq!(
+        process(&mut verifier, "eve", "hunter2", None),
+        Response::Reject
+    );
+}
+
+#[test]
+fn passwords_are_normalized() {
+    // The precomposed `é` and the decomposed `e` with a combining acute accent are equivalent,
+    // as are the non-ASCII spaces and the ASCII space.
+    let mut verifier = Verifier::new().user("alice", "caf\u{e9}\u{a0}cr\u{e8}me");
+
+    assert_eq!(
+        process(&mut verifier, "alice", "cafe\u{301} cre\u{300}me", None),
+        Response::Accept
+    );
+    assert_eq!(
+        process(&mut verifier, "alice", "cafe cre\u{300}me", None),
+        Response::Reject
+    );
+
+    // A password with prohibited characters never matches.
+    let mut verifier = Verifier::new().user("bob", "nul\u{0}");
+    assert_eq!(
+        process(&mut verifier, "bob", "nul\u{0}", None),
+        Response::Reject
+    );
+}
+
+#[test]
+fn password_changes_follow_the_policy() {
+    let mut verifier = Verifier::new().user("alice", "hunter2");
+
+    // Without a policy, the changes are rejected.
+    assert_eq!(
+        process(&mut verifier, "alice", "hunter2", Some("hunter3")),
+        Response::Reject
+    );
+
+    let mut verifier = verifier.password_change(|_, new| new.len() >= 8);
+
+    assert_eq!(
+        process(&mut verifier, "alice", "wrong", Some("long enough")),
+        Response::Reject
+    );
+    assert_eq!(
+        process(&mut verifier, "alice", "hunter2", Some("short")),
+        Response::Reject
+    );
+    assert_eq!(
+        process(&mut verifier, "alice", "hunter2", Some("long enough")),
+        Response::Accept
+    );
+
+    assert_eq!(
+        process(&mut verifier, "alice", "hunter2", None),
+        Response::Reject
+    );
+    assert_eq!(
+        process(&mut verifier, "alice", "long enough", None),
+        Response::Accept
+    );
+}
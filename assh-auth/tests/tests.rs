@@ -54,3 +54,3734 @@ async fn basic_none() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+#[tokio::test]
+async fn too_many_attempts_disconnect() -> Result<(), Box<dyn std::error::Error>> {
+    use assh::{error::DisconnectedError, Error};
+    use ssh_packet::{
+        arch::{ascii, Utf8},
+        trans::{DisconnectReason, ServiceAccept, ServiceRequest},
+        userauth,
+    };
+
+    let duplex = tokio::io::duplex(ssh_packet::PACKET_MAX_SIZE * 16);
+
+    let (server, client) = tokio::join!(
+        async {
+            let server = Server {
+                keys: vec![ssh_key::private::PrivateKey::random(
+                    &mut rand::thread_rng(),
+                    ssh_key::Algorithm::Ed25519,
+                )
+                .unwrap()],
+                ..Default::default()
+            };
+            let server = assh::Session::new(BufStream::new(duplex.0).compat(), server).await?;
+
+            server
+                .handle(
+                    handler::Auth::new(cookie::Cookie::default())
+                        .password(|_, _, _| handler::password::Response::Reject)
+                        .max_attempts(3),
+                )
+                .await
+        },
+        async {
+            let client = Client::default();
+            let mut client = assh::Session::new(BufStream::new(duplex.1).compat(), client).await?;
+
+            client
+                .send(&ServiceRequest {
+                    service_name: ascii!("ssh-userauth"),
+                })
+                .await?;
+            client.recv().await?.to::<ServiceAccept>()?;
+
+            let mut failures = 0;
+            for method in [
+                userauth::Method::None,
+                userauth::Method::Password {
+                    password: Utf8::borrowed("guess"),
+                    new: None,
+                },
+                userauth::Method::Password {
+                    password: Utf8::borrowed("guess"),
+                    new: None,
+                },
+                userauth::Method::Password {
+                    password: Utf8::borrowed("guess"),
+                    new: None,
+                },
+            ] {
+                client
+                    .send(&userauth::Request {
+                        username: Utf8::borrowed("user"),
+                        service_name: ascii!("ssh-connection"),
+                        method,
+                    })
+                    .await?;
+                match client.recv().await {
+                    Ok(packet) => {
+                        packet.to::<userauth::Failure>()?;
+
+                        failures += 1;
+                    }
+                    Err(Error::Disconnected(DisconnectedError {
+                        reason: DisconnectReason::NoMoreAuthMethodsAvailable,
+                        ..
+                    })) => break,
+                    Err(err) => return Err(err),
+                }
+            }
+
+            Ok::<_, Error>(failures)
+        },
+    );
+
+    // The `none` query is not counted, and the third failed password disconnects.
+    assert!(matches!(client, Ok(3)));
+    assert!(matches!(
+        server,
+        Err(Error::Disconnected(DisconnectedError {
+            reason: DisconnectReason::NoMoreAuthMethodsAvailable,
+            ..
+        }))
+    ));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn unknown_services_are_not_authenticated() -> Result<(), Box<dyn std::error::Error>> {
+    use assh::{error::DisconnectedError, Error};
+    use ssh_packet::{
+        arch::{ascii, Utf8},
+        trans::{DisconnectReason, ServiceAccept, ServiceRequest},
+        userauth,
+    };
+
+    let duplex = tokio::io::duplex(ssh_packet::PACKET_MAX_SIZE * 16);
+    let cookie = cookie::Cookie::default();
+
+    let (server, client) = tokio::join!(
+        async {
+            let server = Server {
+                keys: vec![ssh_key::private::PrivateKey::random(
+                    &mut rand::thread_rng(),
+                    ssh_key::Algorithm::Ed25519,
+                )
+                .unwrap()],
+                ..Default::default()
+            };
+            let server = assh::Session::new(BufStream::new(duplex.0).compat(), server).await?;
+
+            server
+                .handle(
+                    handler::Auth::new(cookie.clone()).none(|_| handler::none::Response::Accept),
+                )
+                .await
+        },
+        async {
+            let client = Client::default();
+            let mut client = assh::Session::new(BufStream::new(duplex.1).compat(), client).await?;
+
+            client
+                .send(&ServiceRequest {
+                    service_name: ascii!("ssh-userauth"),
+                })
+                .await?;
+            client.recv().await?.to::<ServiceAccept>()?;
+
+            client
+                .send(&userauth::Request {
+                    username: Utf8::borrowed("user"),
+                    service_name: ascii!("unknown-service@assh.rs"),
+                    method: userauth::Method::None,
+                })
+                .await?;
+
+            // The client is disconnected without ever being told the authentication succeeded.
+            client.recv().await.map(|packet| packet.payload)
+        },
+    );
+
+    assert!(!cookie.is_flagged());
+    assert!(matches!(
+        client,
+        Err(Error::Disconnected(DisconnectedError {
+            reason: DisconnectReason::ServiceNotAvailable,
+            ..
+        }))
+    ));
+    assert!(matches!(
+        server,
+        Err(Error::Disconnected(DisconnectedError {
+            reason: DisconnectReason::ServiceNotAvailable,
+            ..
+        }))
+    ));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn none_probes_are_not_consumed() -> Result<(), Box<dyn std::error::Error>> {
+    use assh::Error;
+    use ssh_packet::{
+        arch::{ascii, Utf8},
+        trans::{ServiceAccept, ServiceRequest},
+        userauth,
+    };
+
+    let duplex = tokio::io::duplex(ssh_packet::PACKET_MAX_SIZE * 16);
+
+    let (_, client) = tokio::join!(
+        async {
+            let server = Server {
+                keys: vec![ssh_key::private::PrivateKey::random(
+                    &mut rand::thread_rng(),
+                    ssh_key::Algorithm::Ed25519,
+                )
+                .unwrap()],
+                ..Default::default()
+            };
+            let server = assh::Session::new(BufStream::new(duplex.0).compat(), server).await?;
+
+            server
+                .handle(
+                    handler::Auth::new(cookie::Cookie::default())
+                        .password(|_, _, _| handler::password::Response::Reject)
+                        .publickey(|_, _| handler::publickey::Response::Reject),
+                )
+                .await
+        },
+        async {
+            let client = Client::default();
+            let mut client = assh::Session::new(BufStream::new(duplex.1).compat(), client).await?;
+
+            client
+                .send(&ServiceRequest {
+                    service_name: ascii!("ssh-userauth"),
+                })
+                .await?;
+            client.recv().await?.to::<ServiceAccept>()?;
+
+            let mut continue_with = Vec::new();
+            for method in [
+                userauth::Method::None,
+                userauth::Method::None,
+                userauth::Method::Password {
+                    password: Utf8::borrowed("guess"),
+                    new: None,
+                },
+                userauth::Method::None,
+            ] {
+                client
+                    .send(&userauth::Request {
+                        username: Utf8::borrowed("user"),
+                        service_name: ascii!("ssh-connection"),
+                        method,
+                    })
+                    .await?;
+
+                let failure = client.recv().await?.to::<userauth::Failure>()?;
+                continue_with.push(
+                    failure
+                        .continue_with
+                        .into_iter()
+                        .map(|method| method.to_string())
+                        .collect::<Vec<_>>(),
+                );
+            }
+
+            Ok::<_, Error>(continue_with)
+        },
+    );
+
+    // Each probe yields the full list of the methods remaining, `none` included.
+    assert_eq!(
+        client?,
+        [
+            vec!["none", "publickey", "password"],
+            vec!["none", "publickey", "password"],
+            vec!["none", "publickey"],
+            vec!["none", "publickey"],
+        ]
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn methods_are_tracked_per_username() -> Result<(), Box<dyn std::error::Error>> {
+    use std::sync::{Arc, Mutex};
+
+    use assh::Error;
+    use ssh_packet::{
+        arch::{ascii, Utf8},
+        trans::{ServiceAccept, ServiceRequest},
+        userauth,
+    };
+
+    let duplex = tokio::io::duplex(ssh_packet::PACKET_MAX_SIZE * 16);
+    let processed = Arc::new(Mutex::new(Vec::new()));
+
+    let (_, client) = tokio::join!(
+        async {
+            let server = Server {
+                keys: vec![ssh_key::private::PrivateKey::random(
+                    &mut rand::thread_rng(),
+                    ssh_key::Algorithm::Ed25519,
+                )
+                .unwrap()],
+                ..Default::default()
+            };
+            let server = assh::Session::new(BufStream::new(duplex.0).compat(), server).await?;
+
+            let processed = processed.clone();
+            server
+                .handle(handler::Auth::new(cookie::Cookie::default()).password(
+                    move |user, _, _| {
+                        processed.lock().unwrap().push(user);
+
+                        handler::password::Response::Reject
+                    },
+                ))
+                .await
+        },
+        async {
+            let client = Client::default();
+            let mut client = assh::Session::new(BufStream::new(duplex.1).compat(), client).await?;
+
+            client
+                .send(&ServiceRequest {
+                    service_name: ascii!("ssh-userauth"),
+                })
+                .await?;
+            client.recv().await?.to::<ServiceAccept>()?;
+
+            let mut continue_with = Vec::new();
+            for username in ["alice", "alice", "bob"] {
+                client
+                    .send(&userauth::Request {
+                        username: Utf8::borrowed(username),
+                        service_name: ascii!("ssh-connection"),
+                        method: userauth::Method::Password {
+                            password: Utf8::borrowed("guess"),
+                            new: None,
+                        },
+                    })
+                    .await?;
+
+                let failure = client.recv().await?.to::<userauth::Failure>()?;
+                continue_with.push(
+                    failure
+                        .continue_with
+                        .into_iter()
+                        .map(|method| method.to_string())
+                        .collect::<Vec<_>>(),
+                );
+            }
+
+            Ok::<_, Error>(continue_with)
+        },
+    );
+
+    // Each username is able to attempt the `password` method once.
+    assert_eq!(*processed.lock().unwrap(), ["alice", "bob"]);
+    assert_eq!(client?, [["none"], ["none"], ["none"]]);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn required_methods_are_chained() -> Result<(), Box<dyn std::error::Error>> {
+    use assh_auth::handler::Method;
+
+    let duplex = tokio::io::duplex(ssh_packet::PACKET_MAX_SIZE * 16);
+    let key =
+        ssh_key::private::PrivateKey::random(&mut rand::thread_rng(), ssh_key::Algorithm::Ed25519)?;
+
+    let cookie0 = cookie::Cookie::default();
+    let cookie1 = cookie::Cookie::default();
+
+    tokio::try_join!(
+        async {
+            let server = Server {
+                keys: vec![ssh_key::private::PrivateKey::random(
+                    &mut rand::thread_rng(),
+                    ssh_key::Algorithm::Ed25519,
+                )
+                .unwrap()],
+                ..Default::default()
+            };
+            let server = assh::Session::new(BufStream::new(duplex.0).compat(), server).await?;
+
+            server
+                .handle(
+                    handler::Auth::new(cookie0.clone())
+                        .publickey(|_, _| handler::publickey::Response::Accept)
+                        .password(|_, password: String, _| {
+                            if password == "hunter2" {
+                                handler::password::Response::Accept
+                            } else {
+                                handler::password::Response::Reject
+                            }
+                        })
+                        .required_methods([Method::Publickey, Method::Password]),
+                )
+                .await
+        },
+        async {
+            let client = Client::default();
+            let client = assh::Session::new(BufStream::new(duplex.1).compat(), client).await?;
+
+            client
+                .request(
+                    request::Auth::new("user", cookie1.clone())
+                        .publickey(key.clone())
+                        .password("hunter2"),
+                )
+                .await
+        },
+    )?;
+
+    assert!(cookie0.is_flagged() && cookie1.is_flagged());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn partial_success_is_reset_on_username_change() -> Result<(), Box<dyn std::error::Error>> {
+    use assh::Error;
+    use assh_auth::handler::Method;
+    use ssh_packet::{
+        arch::{ascii, Utf8},
+        trans::{ServiceAccept, ServiceRequest},
+        userauth,
+    };
+
+    let duplex = tokio::io::duplex(ssh_packet::PACKET_MAX_SIZE * 16);
+
+    let (_, client) = tokio::join!(
+        async {
+            let server = Server {
+                keys: vec![ssh_key::private::PrivateKey::random(
+                    &mut rand::thread_rng(),
+                    ssh_key::Algorithm::Ed25519,
+                )
+                .unwrap()],
+                ..Default::default()
+            };
+            let server = assh::Session::new(BufStream::new(duplex.0).compat(), server).await?;
+
+            server
+                .handle(
+                    handler::Auth::new(cookie::Cookie::default())
+                        .none(|_| handler::none::Response::Accept)
+                        .password(|_, _, _| handler::password::Response::Accept)
+                        .required_methods([Method::Password, Method::None]),
+                )
+                .await
+        },
+        async {
+            let client = Client::default();
+            let mut client = assh::Session::new(BufStream::new(duplex.1).compat(), client).await?;
+
+            client
+                .send(&ServiceRequest {
+                    service_name: ascii!("ssh-userauth"),
+                })
+                .await?;
+            client.recv().await?.to::<ServiceAccept>()?;
+
+            let mut failures = Vec::new();
+            for (username, method) in [
+                (
+                    "alice",
+                    userauth::Method::Password {
+                        password: Utf8::borrowed("secret"),
+                        new: None,
+                    },
+                ),
+                ("bob", userauth::Method::None),
+                ("alice", userauth::Method::None),
+            ] {
+                client
+                    .send(&userauth::Request {
+                        username: Utf8::borrowed(username),
+                        service_name: ascii!("ssh-connection"),
+                        method,
+                    })
+                    .await?;
+
+                let failure = client.recv().await?.to::<userauth::Failure>()?;
+                failures.push((
+                    *failure.partial_success,
+                    failure
+                        .continue_with
+                        .into_iter()
+                        .map(|method| method.to_string())
+                        .collect::<Vec<_>>(),
+                ));
+            }
+
+            Ok::<_, Error>(failures)
+        },
+    );
+
+    // The progress and the consumed methods of `alice` are lost when `bob` attempts to authenticate.
+    assert_eq!(
+        client?,
+        [
+            (true, vec!["none".to_string()]),
+            (true, vec!["password".to_string()]),
+            (true, vec!["password".to_string()]),
+        ]
+    );
+
+    Ok(())
+}
+
+/// Certify the `key` with the `ca` for the `principal`.
+fn certify(
+    key: &ssh_key::PrivateKey,
+    ca: &ssh_key::PrivateKey,
+    cert_type: ssh_key::certificate::CertType,
+    principal: &str,
+) -> ssh_key::Certificate {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+
+    let mut builder = ssh_key::certificate::Builder::new_with_random_nonce(
+        &mut rand::thread_rng(),
+        key.public_key(),
+        now - 60,
+        now + 3600,
+    )
+    .unwrap();
+    builder.cert_type(cert_type).unwrap();
+    builder.valid_principal(principal).unwrap();
+    builder.extension("permit-pty", "").unwrap();
+
+    builder.sign(ca).unwrap()
+}
+
+/// Attempt to authenticate as `username` with the `certificate` of the `key`,
+/// against a server trusting the `ca`, returning the certificates processed by the handler.
+async fn certificate_attempt(
+    ca: &ssh_key::PublicKey,
+    username: &str,
+    certificate: &ssh_key::Certificate,
+    key: &ssh_key::PrivateKey,
+) -> Result<(bool, Vec<ssh_key::Certificate>), Box<dyn std::error::Error>> {
+    use std::sync::{Arc, Mutex};
+
+    use assh::Error;
+    use ssh_packet::{
+        arch::{ascii, Utf8},
+        crypto::signature,
+        trans::{ServiceAccept, ServiceRequest},
+        userauth,
+    };
+
+    struct Recorder(Arc<Mutex<Vec<ssh_key::Certificate>>>);
+
+    impl handler::publickey::Publickey for Recorder {
+        async fn process(
+            &mut self,
+            _: &handler::AuthContext<'_>,
+            _: String,
+            _: ssh_key::PublicKey,
+        ) -> handler::publickey::Response {
+            handler::publickey::Response::Reject
+        }
+
+        async fn process_certificate(
+            &mut self,
+            _: &handler::AuthContext<'_>,
+            _: String,
+            certificate: ssh_key::Certificate,
+        ) -> handler::publickey::Response {
+            self.0.lock().unwrap().push(certificate);
+
+            handler::publickey::Response::Accept
+        }
+    }
+
+    let duplex = tokio::io::duplex(ssh_packet::PACKET_MAX_SIZE * 16);
+    let processed = Arc::new(Mutex::new(Vec::new()));
+    let service_name = ascii!("dummy-service@assh.rs");
+
+    let (_, client) = tokio::join!(
+        async {
+            let server = Server {
+                keys: vec![ssh_key::private::PrivateKey::random(
+                    &mut rand::thread_rng(),
+                    ssh_key::Algorithm::Ed25519,
+                )
+                .unwrap()],
+                ..Default::default()
+            };
+            let server = assh::Session::new(BufStream::new(duplex.0).compat(), server).await?;
+
+            server
+                .handle(
+                    handler::Auth::new(cookie::Cookie::default())
+                        .publickey(Recorder(processed.clone()))
+                        .certificate_authority(ca.clone()),
+                )
+                .await
+        },
+        async {
+            let client = Client::default();
+            let mut client = assh::Session::new(BufStream::new(duplex.1).compat(), client).await?;
+
+            client
+                .send(&ServiceRequest {
+                    service_name: ascii!("ssh-userauth"),
+                })
+                .await?;
+            client.recv().await?.to::<ServiceAccept>()?;
+
+            let algorithm = b"ssh-ed25519-cert-v01@openssh.com";
+            let blob = certificate.to_bytes()?;
+            let signature: ssh_key::Signature = signature::Publickey {
+                session_id: client.session_id().unwrap_or_default().into(),
+                username: Utf8::borrowed(username),
+                service_name: service_name.clone(),
+                algorithm: algorithm.as_slice().into(),
+                blob: blob.as_slice().into(),
+            }
+            .sign(key);
+
+            client
+                .send(&userauth::Request {
+                    username: Utf8::borrowed(username),
+                    service_name,
+                    method: userauth::Method::Publickey {
+                        algorithm: algorithm.as_slice().into(),
+                        blob: blob.as_slice().into(),
+                        signature: Some(Vec::try_from(signature)?.into()),
+                    },
+                })
+                .await?;
+
+            Ok::<_, Error>(client.recv().await?.to::<userauth::Success>().is_ok())
+        },
+    );
+
+    let processed = processed.lock().unwrap().clone();
+
+    Ok((client?, processed))
+}
+
+#[tokio::test]
+async fn user_certificates_are_validated() -> Result<(), Box<dyn std::error::Error>> {
+    use ssh_key::{certificate::CertType, Algorithm, PrivateKey};
+
+    let random = || PrivateKey::random(&mut rand::thread_rng(), Algorithm::Ed25519);
+    let (key, ca, other) = (random()?, random()?, random()?);
+
+    let certificate = certify(&key, &ca, CertType::User, "alice");
+    let (success, processed) =
+        certificate_attempt(ca.public_key(), "alice", &certificate, &key).await?;
+    assert!(success);
+    assert_eq!(processed, std::slice::from_ref(&certificate));
+    assert!(processed[0].extensions().contains_key("permit-pty"));
+
+    for (username, certificate, key) in [
+        // The certificate is not valid for this user.
+        ("bob", certificate.clone(), &key),
+        // The certificate is not a user certificate.
+        ("alice", certify(&key, &ca, CertType::Host, "alice"), &key),
+        // The certificate is not signed by a trusted authority.
+        (
+            "alice",
+            certify(&key, &other, CertType::User, "alice"),
+            &key,
+        ),
+        // The signature is not made by the certified key.
+        ("alice", certificate.clone(), &other),
+    ] {
+        let (success, processed) =
+            certificate_attempt(ca.public_key(), username, &certificate, key).await?;
+
+        assert!(!success);
+        assert!(processed.is_empty());
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn context_is_passed_to_handlers() -> Result<(), Box<dyn std::error::Error>> {
+    use std::sync::{Arc, Mutex};
+
+    let duplex = tokio::io::duplex(ssh_packet::PACKET_MAX_SIZE * 16);
+
+    let client = Client::default();
+    let peer_id = client.id.clone();
+    let contexts = Arc::new(Mutex::new(Vec::new()));
+    let cookie0 = cookie::Cookie::default();
+    let cookie1 = cookie::Cookie::default();
+
+    tokio::try_join!(
+        async {
+            let server = Server {
+                keys: vec![ssh_key::private::PrivateKey::random(
+                    &mut rand::thread_rng(),
+                    ssh_key::Algorithm::Ed25519,
+                )
+                .unwrap()],
+                ..Default::default()
+            };
+            let server = assh::Session::new(BufStream::new(duplex.0).compat(), server).await?;
+
+            let record = |context: &handler::AuthContext<'_>| {
+                assert!(!context.session_id.is_empty());
+                assert_eq!(context.peer_id, &peer_id);
+
+                contexts.lock().unwrap().push((
+                    context.session_id.to_vec(),
+                    context.attempt,
+                    context.service_name.to_string(),
+                ));
+            };
+
+            server
+                .handle(
+                    handler::Auth::new(cookie0.clone())
+                        .none(handler::none::with_context(|context, _| {
+                            record(context);
+
+                            handler::none::Response::Reject
+                        }))
+                        .password(handler::password::with_context(
+                            |context, _, password: String, _| {
+                                record(context);
+
+                                if password == "hunter2" {
+                                    handler::password::Response::Accept
+                                } else {
+                                    handler::password::Response::Reject
+                                }
+                            },
+                        )),
+                )
+                .await
+        },
+        async {
+            let client = assh::Session::new(BufStream::new(duplex.1).compat(), client).await?;
+
+            client
+                .request(request::Auth::new("user", cookie1.clone()).password("hunter2"))
+                .await
+        },
+    )?;
+
+    assert!(cookie0.is_flagged() && cookie1.is_flagged());
+
+    let contexts = contexts.lock().unwrap();
+    assert_eq!(contexts.len(), 2);
+    assert_eq!(contexts[0].0, contexts[1].0);
+    assert_eq!(
+        contexts
+            .iter()
+            .map(|(_, attempt, service_name)| (*attempt, service_name.as_str()))
+            .collect::<Vec<_>>(),
+        [(1, "dummy-service@assh.rs"), (2, "dummy-service@assh.rs")]
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn failures_are_delayed() -> Result<(), Box<dyn std::error::Error>> {
+    use std::time::{Duration, Instant};
+
+    use assh::Error;
+    use ssh_packet::{
+        arch::{ascii, Utf8},
+        trans::{ServiceAccept, ServiceRequest},
+        userauth,
+    };
+
+    let duplex = tokio::io::duplex(ssh_packet::PACKET_MAX_SIZE * 16);
+
+    let (_, client) = tokio::join!(
+        async {
+            let server = Server {
+                keys: vec![ssh_key::private::PrivateKey::random(
+                    &mut rand::thread_rng(),
+                    ssh_key::Algorithm::Ed25519,
+                )
+                .unwrap()],
+                ..Default::default()
+            };
+            let server = assh::Session::new(BufStream::new(duplex.0).compat(), server).await?;
+
+            server
+                .handle(
+                    handler::Auth::new(cookie::Cookie::default())
+                        .password(|_, _, _| handler::password::Response::Reject)
+                        .failure_delay(Duration::from_millis(200))
+                        .failure_backoff(Duration::from_millis(300)),
+                )
+                .await
+        },
+        async {
+            let client = Client::default();
+            let mut client = assh::Session::new(BufStream::new(duplex.1).compat(), client).await?;
+
+            client
+                .send(&ServiceRequest {
+                    service_name: ascii!("ssh-userauth"),
+                })
+                .await?;
+            client.recv().await?.to::<ServiceAccept>()?;
+
+            let mut elapsed = Vec::new();
+            for username in ["alice", "bob"] {
+                let start = Instant::now();
+
+                client
+                    .send(&userauth::Request {
+                        username: Utf8::borrowed(username),
+                        service_name: ascii!("ssh-connection"),
+                        method: userauth::Method::Password {
+                            password: Utf8::borrowed("guess"),
+                            new: None,
+                        },
+                    })
+                    .await?;
+                client.recv().await?.to::<userauth::Failure>()?;
+
+                elapsed.push(start.elapsed());
+            }
+
+            Ok::<_, Error>(elapsed)
+        },
+    );
+
+    // The second consecutive failure is delayed twice as long, capped to the backoff maximum.
+    let elapsed = client?;
+    assert!(elapsed[0] >= Duration::from_millis(200));
+    assert!(elapsed[1] >= Duration::from_millis(300));
+
+    Ok(())
+}
+
+/// A password verification taking longer for the known users, as with a hashed password.
+struct Slow;
+
+impl handler::password::Password for Slow {
+    async fn process(
+        &mut self,
+        _: &handler::AuthContext<'_>,
+        user: String,
+        _: String,
+        _: Option<String>,
+    ) -> handler::password::Response {
+        if user == "alice" {
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        }
+
+        handler::password::Response::Reject
+    }
+}
+
+#[tokio::test]
+async fn failures_are_padded() -> Result<(), Box<dyn std::error::Error>> {
+    use std::time::{Duration, Instant};
+
+    use assh::Error;
+    use ssh_packet::{
+        arch::{ascii, Utf8},
+        trans::{ServiceAccept, ServiceRequest},
+        userauth,
+    };
+
+    let duplex = tokio::io::duplex(ssh_packet::PACKET_MAX_SIZE * 16);
+
+    let (_, client) = tokio::join!(
+        async {
+            let server = Server {
+                keys: vec![ssh_key::private::PrivateKey::random(
+                    &mut rand::thread_rng(),
+                    ssh_key::Algorithm::Ed25519,
+                )
+                .unwrap()],
+                ..Default::default()
+            };
+            let server = assh::Session::new(BufStream::new(duplex.0).compat(), server).await?;
+
+            server
+                .handle(
+                    handler::Auth::new(cookie::Cookie::default())
+                        .password(Slow)
+                        .failure_minimum_time(Duration::from_millis(250)),
+                )
+                .await
+        },
+        async {
+            let client = Client::default();
+            let mut client = assh::Session::new(BufStream::new(duplex.1).compat(), client).await?;
+
+            client
+                .send(&ServiceRequest {
+                    service_name: ascii!("ssh-userauth"),
+                })
+                .await?;
+            client.recv().await?.to::<ServiceAccept>()?;
+
+            let mut elapsed = Vec::new();
+            for username in ["alice", "nobody"] {
+                let start = Instant::now();
+
+                client
+                    .send(&userauth::Request {
+                        username: Utf8::borrowed(username),
+                        service_name: ascii!("ssh-connection"),
+                        method: userauth::Method::Password {
+                            password: Utf8::borrowed("guess"),
+                            new: None,
+                        },
+                    })
+                    .await?;
+                client.recv().await?.to::<userauth::Failure>()?;
+
+                elapsed.push(start.elapsed());
+            }
+
+            Ok::<_, Error>(elapsed)
+        },
+    );
+
+    // The known and unknown users are answered after the same minimum time.
+    let elapsed = client?;
+    assert!(elapsed
+        .iter()
+        .all(|elapsed| *elapsed >= Duration::from_millis(250)));
+    assert!(elapsed[0].max(elapsed[1]) - elapsed[0].min(elapsed[1]) < Duration::from_millis(50));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn last_failure_is_throttled() -> Result<(), Box<dyn std::error::Error>> {
+    use std::time::{Duration, Instant};
+
+    use assh::Error;
+    use ssh_packet::{
+        arch::{ascii, Utf8},
+        trans::{ServiceAccept, ServiceRequest},
+        userauth,
+    };
+
+    // Either the delay or the padding holds back the disconnection of the last allowed attempt.
+    for (delay, minimum) in [
+        (Duration::from_millis(250), Duration::ZERO),
+        (Duration::ZERO, Duration::from_millis(250)),
+    ] {
+        let duplex = tokio::io::duplex(ssh_packet::PACKET_MAX_SIZE * 16);
+
+        let (server, client) = tokio::join!(
+            async {
+                let server = Server {
+                    keys: vec![ssh_key::private::PrivateKey::random(
+                        &mut rand::thread_rng(),
+                        ssh_key::Algorithm::Ed25519,
+                    )
+                    .unwrap()],
+                    ..Default::default()
+                };
+                let server = assh::Session::new(BufStream::new(duplex.0).compat(), server).await?;
+
+                server
+                    .handle(
+                        handler::Auth::new(cookie::Cookie::default())
+                            .password(|_, _, _| handler::password::Response::Reject)
+                            .max_attempts(1)
+                            .failure_delay(delay)
+                            .failure_minimum_time(minimum),
+                    )
+                    .await
+            },
+            async {
+                let client = Client::default();
+                let mut client =
+                    assh::Session::new(BufStream::new(duplex.1).compat(), client).await?;
+
+                client
+                    .send(&ServiceRequest {
+                        service_name: ascii!("ssh-userauth"),
+                    })
+                    .await?;
+                client.recv().await?.to::<ServiceAccept>()?;
+
+                let start = Instant::now();
+                client
+                    .send(&userauth::Request {
+                        username: Utf8::borrowed("user"),
+                        service_name: ascii!("ssh-connection"),
+                        method: userauth::Method::Password {
+                            password: Utf8::borrowed("guess"),
+                            new: None,
+                        },
+                    })
+                    .await?;
+                let disconnected = client.recv().await;
+
+                Ok::<_, Error>((disconnected, start.elapsed()))
+            },
+        );
+
+        assert!(matches!(server, Err(Error::Disconnected(_))));
+
+        let (disconnected, elapsed) = client?;
+        assert!(matches!(disconnected, Err(Error::Disconnected(_))));
+        assert!(elapsed >= Duration::from_millis(250), "{elapsed:?}");
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn grace_timeout_disconnects() -> Result<(), Box<dyn std::error::Error>> {
+    use std::time::{Duration, Instant};
+
+    use assh::{error::DisconnectedError, Error};
+    use ssh_packet::{
+        arch::{ascii, Utf8},
+        trans::{DisconnectReason, ServiceAccept, ServiceRequest},
+        userauth,
+    };
+
+    let duplex = tokio::io::duplex(ssh_packet::PACKET_MAX_SIZE * 16);
+    let key =
+        ssh_key::private::PrivateKey::random(&mut rand::thread_rng(), ssh_key::Algorithm::Ed25519)?;
+
+    let (server, client) = tokio::join!(
+        async {
+            let server = Server {
+                keys: vec![ssh_key::private::PrivateKey::random(
+                    &mut rand::thread_rng(),
+                    ssh_key::Algorithm::Ed25519,
+                )
+                .unwrap()],
+                ..Default::default()
+            };
+            let server = assh::Session::new(BufStream::new(duplex.0).compat(), server).await?;
+
+            server
+                .handle(
+                    handler::Auth::new(cookie::Cookie::default())
+                        .publickey(|_, _| handler::publickey::Response::Accept)
+                        .grace_timeout(Duration::from_millis(500)),
+                )
+                .await
+        },
+        async {
+            let client = Client::default();
+            let mut client = assh::Session::new(BufStream::new(duplex.1).compat(), client).await?;
+
+            client
+                .send(&ServiceRequest {
+                    service_name: ascii!("ssh-userauth"),
+                })
+                .await?;
+            client.recv().await?.to::<ServiceAccept>()?;
+
+            // Keep probing the key without ever signing, which doesn't extend the grace time.
+            let start = Instant::now();
+            let blob = key.public_key().to_bytes()?;
+            loop {
+                // The probe may race with the disconnection, which is then received.
+                let sent = client
+                    .send(&userauth::Request {
+                        username: Utf8::borrowed("user"),
+                        service_name: ascii!("ssh-connection"),
+                        method: userauth::Method::Publickey {
+                            algorithm: key.algorithm().as_str().as_bytes().into(),
+                            blob: blob.as_slice().into(),
+                            signature: None,
+                        },
+                    })
+                    .await;
+
+                match client.recv().await {
+                    Ok(packet) => {
+                        sent?;
+                        packet.to::<userauth::PkOk>()?;
+
+                        tokio::time::sleep(Duration::from_millis(100)).await;
+                    }
+                    Err(err) => break Ok::<_, Error>((err, start.elapsed())),
+                }
+            }
+        },
+    );
+
+    assert!(matches!(server, Err(Error::AuthTimeout)));
+
+    let (err, elapsed) = client?;
+    assert!(matches!(
+        err,
+        Error::Disconnected(DisconnectedError {
+            reason: DisconnectReason::ByApplication,
+            ..
+        })
+    ));
+    assert!(elapsed >= Duration::from_millis(400));
+
+    Ok(())
+}
+
+/// Send a `publickey` request for the `blob`, signed with the `key`, to a server with the `policy`.
+async fn signed_attempt(
+    policy: handler::InvalidSignaturePolicy,
+    blob: &[u8],
+    key: &ssh_key::PrivateKey,
+) -> Result<assh::Result<ssh_packet::Packet>, Box<dyn std::error::Error>> {
+    use assh::Error;
+    use ssh_packet::{
+        arch::{ascii, Utf8},
+        crypto::signature,
+        trans::{ServiceAccept, ServiceRequest},
+        userauth,
+    };
+
+    let duplex = tokio::io::duplex(ssh_packet::PACKET_MAX_SIZE * 16);
+
+    let (_, client) = tokio::join!(
+        async {
+            let server = Server {
+                keys: vec![ssh_key::private::PrivateKey::random(
+                    &mut rand::thread_rng(),
+                    ssh_key::Algorithm::Ed25519,
+                )
+                .unwrap()],
+                ..Default::default()
+            };
+            let server = assh::Session::new(BufStream::new(duplex.0).compat(), server).await?;
+
+            server
+                .handle(
+                    handler::Auth::new(cookie::Cookie::default())
+                        .publickey(|_, _| handler::publickey::Response::Accept)
+                        .on_invalid_signature(policy),
+                )
+                .await
+        },
+        async {
+            let client = Client::default();
+            let mut client = assh::Session::new(BufStream::new(duplex.1).compat(), client).await?;
+
+            client
+                .send(&ServiceRequest {
+                    service_name: ascii!("ssh-userauth"),
+                })
+                .await?;
+            client.recv().await?.to::<ServiceAccept>()?;
+
+            let algorithm = key.algorithm();
+            let algorithm = algorithm.as_str().as_bytes();
+            let signature: ssh_key::Signature = signature::Publickey {
+                session_id: client.session_id().unwrap_or_default().into(),
+                username: Utf8::borrowed("user"),
+                service_name: ascii!("dummy-service@assh.rs"),
+                algorithm: algorithm.into(),
+                blob: blob.into(),
+            }
+            .sign(key);
+
+            client
+                .send(&userauth::Request {
+                    username: Utf8::borrowed("user"),
+                    service_name: ascii!("dummy-service@assh.rs"),
+                    method: userauth::Method::Publickey {
+                        algorithm: algorithm.into(),
+                        blob: blob.into(),
+                        signature: Some(Vec::try_from(signature)?.into()),
+                    },
+                })
+                .await?;
+
+            Ok::<_, Error>(client.recv().await)
+        },
+    );
+
+    Ok(client?)
+}
+
+#[tokio::test]
+async fn invalid_signatures_follow_the_policy() -> Result<(), Box<dyn std::error::Error>> {
+    use assh::{error::DisconnectedError, Error};
+    use assh_auth::handler::InvalidSignaturePolicy;
+    use ssh_packet::{trans::DisconnectReason, userauth};
+
+    let random = || {
+        ssh_key::private::PrivateKey::random(&mut rand::thread_rng(), ssh_key::Algorithm::Ed25519)
+    };
+    let (key, forger) = (random()?, random()?);
+    let blob = key.public_key().to_bytes()?;
+
+    // A forged signature fails the attempt by default.
+    signed_attempt(InvalidSignaturePolicy::Failure, &blob, &forger)
+        .await??
+        .to::<userauth::Failure>()?;
+
+    assert!(matches!(
+        signed_attempt(InvalidSignaturePolicy::Disconnect, &blob, &forger).await?,
+        Err(Error::Disconnected(DisconnectedError {
+            reason: DisconnectReason::ProtocolError,
+            ..
+        }))
+    ));
+
+    // An unparseable key is not a forged signature.
+    signed_attempt(InvalidSignaturePolicy::Disconnect, b"garbage", &forger)
+        .await??
+        .to::<userauth::Failure>()?;
+
+    // While a genuine signature still succeeds.
+    signed_attempt(InvalidSignaturePolicy::Disconnect, &blob, &key)
+        .await??
+        .to::<userauth::Success>()?;
+
+    Ok(())
+}
+
+/// Send a `none` request to a server with the `auth` handler, returning the received banner.
+async fn banner_attempt(
+    auth: handler::Auth<cookie::Cookie>,
+) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    use assh::Error;
+    use ssh_packet::{
+        arch::{ascii, Utf8},
+        trans::{ServiceAccept, ServiceRequest},
+        userauth,
+    };
+
+    let duplex = tokio::io::duplex(ssh_packet::PACKET_MAX_SIZE * 16);
+
+    let (_, client) = tokio::join!(
+        async {
+            let server = Server {
+                keys: vec![ssh_key::private::PrivateKey::random(
+                    &mut rand::thread_rng(),
+                    ssh_key::Algorithm::Ed25519,
+                )
+                .unwrap()],
+                ..Default::default()
+            };
+            let server = assh::Session::new(BufStream::new(duplex.0).compat(), server).await?;
+
+            server.handle(auth).await
+        },
+        async {
+            let client = Client::default();
+            let mut client = assh::Session::new(BufStream::new(duplex.1).compat(), client).await?;
+
+            client
+                .send(&ServiceRequest {
+                    service_name: ascii!("ssh-userauth"),
+                })
+                .await?;
+            client.recv().await?.to::<ServiceAccept>()?;
+
+            client
+                .send(&userauth::Request {
+                    username: Utf8::borrowed("user"),
+                    service_name: ascii!("ssh-connection"),
+                    method: userauth::Method::None,
+                })
+                .await?;
+
+            let mut packet = client.recv().await?;
+            let banner = match packet.to::<userauth::Banner>() {
+                Ok(banner) => {
+                    packet = client.recv().await?;
+
+                    Some(banner.message.into_string())
+                }
+                Err(_) => None,
+            };
+            packet.to::<userauth::Failure>()?;
+
+            Ok::<_, Error>(banner)
+        },
+    );
+
+    Ok(client?)
+}
+
+#[tokio::test]
+async fn banners_are_computed_per_session() -> Result<(), Box<dyn std::error::Error>> {
+    assert_eq!(
+        banner_attempt(handler::Auth::new(cookie::Cookie::default()).banner("Welcome\r\n")).await?,
+        Some("Welcome\r\n".into())
+    );
+
+    let peer_id = Client::default().id.to_string();
+    assert_eq!(
+        banner_attempt(
+            handler::Auth::new(cookie::Cookie::default()).banner_with(|context| Some(format!(
+                "Hello `{}` for {}\r\n",
+                context.peer_id, context.service_name
+            )))
+        )
+        .await?,
+        Some(format!("Hello `{peer_id}` for ssh-connection\r\n"))
+    );
+
+    assert_eq!(
+        banner_attempt(handler::Auth::new(cookie::Cookie::default()).banner_with(|_| None)).await?,
+        None
+    );
+
+    Ok(())
+}
+
+/// Send a `publickey` request for the `key` with the `algorithm`, signed by the `sign` callback,
+/// to a server restricted to the `rsa-sha2-256` and `rsa-sha2-512` signature algorithms.
+async fn rsa_attempt(
+    key: &ssh_key::PublicKey,
+    algorithm: &str,
+    sign: impl FnOnce(&[u8]) -> Option<ssh_key::Signature>,
+) -> Result<ssh_packet::Packet, Box<dyn std::error::Error>> {
+    use ssh_key::{Algorithm, HashAlg};
+    use ssh_packet::{
+        arch::{ascii, Utf8},
+        binrw::BinWrite,
+        crypto::signature,
+        trans::{ServiceAccept, ServiceRequest},
+        userauth,
+    };
+
+    let duplex = tokio::io::duplex(ssh_packet::PACKET_MAX_SIZE * 16);
+
+    let (_, client) = tokio::join!(
+        async {
+            let server = Server {
+                keys: vec![ssh_key::private::PrivateKey::random(
+                    &mut rand::thread_rng(),
+                    ssh_key::Algorithm::Ed25519,
+                )
+                .unwrap()],
+                ..Default::default()
+            };
+            let server = assh::Session::new(BufStream::new(duplex.0).compat(), server).await?;
+
+            server
+                .handle(
+                    handler::Auth::new(cookie::Cookie::default())
+                        .publickey(|_, _| handler::publickey::Response::Accept)
+                        .publickey_algorithms([
+                            Algorithm::Rsa {
+                                hash: Some(HashAlg::Sha256),
+                            },
+                            Algorithm::Rsa {
+                                hash: Some(HashAlg::Sha512),
+                            },
+                        ]),
+                )
+                .await
+        },
+        async {
+            let client = Client::default();
+            let mut client = assh::Session::new(BufStream::new(duplex.1).compat(), client).await?;
+
+            client
+                .send(&ServiceRequest {
+                    service_name: ascii!("ssh-userauth"),
+                })
+                .await?;
+            client.recv().await?.to::<ServiceAccept>()?;
+
+            let blob = key.to_bytes()?;
+            let message = signature::Publickey {
+                session_id: client.session_id().unwrap_or_default().into(),
+                username: Utf8::borrowed("user"),
+                service_name: ascii!("dummy-service@assh.rs"),
+                algorithm: algorithm.as_bytes().into(),
+                blob: blob.as_slice().into(),
+            };
+            let mut data = Vec::new();
+            message.write(&mut std::io::Cursor::new(&mut data))?;
+
+            let signature = sign(&data).map(Vec::try_from).transpose()?.map(Into::into);
+
+            client
+                .send(&userauth::Request {
+                    username: Utf8::borrowed("user"),
+                    service_name: ascii!("dummy-service@assh.rs"),
+                    method: userauth::Method::Publickey {
+                        algorithm: algorithm.as_bytes().into(),
+                        blob: blob.as_slice().into(),
+                        signature,
+                    },
+                })
+                .await?;
+
+            client.recv().await
+        },
+    );
+
+    Ok(client?)
+}
+
+#[tokio::test]
+async fn publickey_algorithms_are_restricted() -> Result<(), Box<dyn std::error::Error>> {
+    use rsa::signature::{SignatureEncoding, Signer};
+    use ssh_key::{Algorithm, HashAlg, Signature};
+    use ssh_packet::userauth;
+
+    let private = rsa::RsaPrivateKey::new(&mut rand::thread_rng(), 2048)?;
+    let key = ssh_key::PublicKey::from(ssh_key::public::RsaPublicKey::try_from(
+        private.to_public_key(),
+    )?);
+
+    let sign = |hash: HashAlg| {
+        let private = private.clone();
+
+        move |data: &[u8]| {
+            let signature = match hash {
+                HashAlg::Sha256 => rsa::pkcs1v15::SigningKey::<sha2::Sha256>::new(private)
+                    .sign(data)
+                    .to_vec(),
+                _ => rsa::pkcs1v15::SigningKey::<sha2::Sha512>::new(private)
+                    .sign(data)
+                    .to_vec(),
+            };
+
+            Signature::new(Algorithm::Rsa { hash: Some(hash) }, signature).ok()
+        }
+    };
+
+    // The `ssh-rsa` key blob is usable with the `rsa-sha2-*` signatures.
+    rsa_attempt(&key, "rsa-sha2-256", |_| None)
+        .await?
+        .to::<userauth::PkOk>()?;
+    rsa_attempt(&key, "rsa-sha2-256", sign(HashAlg::Sha256))
+        .await?
+        .to::<userauth::Success>()?;
+    rsa_attempt(&key, "rsa-sha2-512", sign(HashAlg::Sha512))
+        .await?
+        .to::<userauth::Success>()?;
+
+    // While the `ssh-rsa` signatures are refused before verification.
+    rsa_attempt(&key, "ssh-rsa", |_| None)
+        .await?
+        .to::<userauth::Failure>()?;
+
+    // And the signature must match the requested algorithm.
+    rsa_attempt(&key, "rsa-sha2-512", sign(HashAlg::Sha256))
+        .await?
+        .to::<userauth::Failure>()?;
+
+    Ok(())
+}
+
+/// Authenticate a client with an _RSA_ key against a server advertising the `server_sig_algs`,
+/// returning the signature algorithm of the accepted attempt.
+async fn rsa_client_attempt(
+    key: &ssh_key::PrivateKey,
+    server_sig_algs: Option<Vec<ssh_key::Algorithm>>,
+) -> Result<String, Box<dyn std::error::Error>> {
+    use std::sync::{Arc, Mutex};
+
+    use assh_auth::handler::audit::Outcome;
+
+    let duplex = tokio::io::duplex(ssh_packet::PACKET_MAX_SIZE * 16);
+    let events = Arc::new(Mutex::new(Vec::new()));
+
+    tokio::try_join!(
+        async {
+            let mut server = Server {
+                keys: vec![ssh_key::private::PrivateKey::random(
+                    &mut rand::thread_rng(),
+                    ssh_key::Algorithm::Ed25519,
+                )
+                .unwrap()],
+                ..Default::default()
+            };
+            if let Some(server_sig_algs) = server_sig_algs {
+                server = server.server_sig_algs(server_sig_algs);
+            }
+            let server = assh::Session::new(BufStream::new(duplex.0).compat(), server).await?;
+
+            server
+                .handle(
+                    handler::Auth::new(cookie::Cookie::default())
+                        .publickey(|_, _| handler::publickey::Response::Accept)
+                        .audit({
+                            let events = events.clone();
+
+                            move |event| events.lock().unwrap().push(event)
+                        }),
+                )
+                .await
+        },
+        async {
+            let client = Client::default();
+            let client = assh::Session::new(BufStream::new(duplex.1).compat(), client).await?;
+
+            client
+                .request(
+                    request::Auth::new("user", cookie::Cookie::default()).publickey(key.clone()),
+                )
+                .await
+        },
+    )?;
+
+    let events = events.lock().unwrap();
+    let success = events
+        .iter()
+        .find(|event| event.outcome == Outcome::Success)
+        .and_then(|event| event.key.as_ref())
+        .unwrap();
+
+    Ok(success.algorithm.clone())
+}
+
+#[tokio::test]
+async fn clients_sign_with_rsa_sha2() -> Result<(), Box<dyn std::error::Error>> {
+    use ssh_key::{Algorithm, HashAlg};
+
+    let key = ssh_key::PrivateKey::random(&mut rand::thread_rng(), Algorithm::Rsa { hash: None })?;
+
+    // The strongest hash advertised by the server is selected.
+    assert_eq!(rsa_client_attempt(&key, None).await?, "rsa-sha2-512");
+    assert_eq!(
+        rsa_client_attempt(
+            &key,
+            Some(vec![
+                Algorithm::Ed25519,
+                Algorithm::Rsa {
+                    hash: Some(HashAlg::Sha256)
+                }
+            ])
+        )
+        .await?,
+        "rsa-sha2-256"
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn attempts_are_audited() -> Result<(), Box<dyn std::error::Error>> {
+    use std::sync::{Arc, Mutex};
+
+    use assh_auth::handler::audit::Outcome;
+
+    let duplex = tokio::io::duplex(ssh_packet::PACKET_MAX_SIZE * 16);
+    let key =
+        ssh_key::private::PrivateKey::random(&mut rand::thread_rng(), ssh_key::Algorithm::Ed25519)?;
+    let events = Arc::new(Mutex::new(Vec::new()));
+
+    let cookie0 = cookie::Cookie::default();
+    let cookie1 = cookie::Cookie::default();
+
+    tokio::try_join!(
+        async {
+            let server = Server {
+                keys: vec![ssh_key::private::PrivateKey::random(
+                    &mut rand::thread_rng(),
+                    ssh_key::Algorithm::Ed25519,
+                )
+                .unwrap()],
+                ..Default::default()
+            };
+            let server = assh::Session::new(BufStream::new(duplex.0).compat(), server).await?;
+
+            server
+                .handle(
+                    handler::Auth::new(cookie0.clone())
+                        .password(|_, _, _| handler::password::Response::Reject)
+                        .publickey(|_, _| handler::publickey::Response::Accept)
+                        .audit({
+                            let events = events.clone();
+
+                            move |event| events.lock().unwrap().push(event)
+                        }),
+                )
+                .await
+        },
+        async {
+            let client = Client::default();
+            let client = assh::Session::new(BufStream::new(duplex.1).compat(), client).await?;
+
+            client
+                .request(
+                    request::Auth::new("user", cookie1.clone())
+                        .password("hunter2")
+                        .publickey(key.clone()),
+                )
+                .await
+        },
+    )?;
+
+    assert!(cookie0.is_flagged() && cookie1.is_flagged());
+
+    let events = events.lock().unwrap();
+    let outcomes = events
+        .iter()
+        .map(|event| (event.method.as_str(), event.outcome))
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        outcomes,
+        [
+            ("none", Outcome::Failure),
+            ("password", Outcome::Failure),
+            ("publickey", Outcome::Continue),
+            ("publickey", Outcome::Success)
+        ]
+    );
+    assert!(events.iter().all(|event| event.username == "user"));
+
+    let [.., query, signed] = &events[..] else {
+        unreachable!()
+    };
+    assert!(!query.key.as_ref().unwrap().signed);
+
+    let details = signed.key.as_ref().unwrap();
+    assert!(details.signed);
+    assert_eq!(details.algorithm, "ssh-ed25519");
+    assert_eq!(
+        details.fingerprint,
+        Some(key.public_key().fingerprint(Default::default()))
+    );
+    assert!(!format!("{events:?}").contains("hunter2"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn methods_are_attempted_in_order() -> Result<(), Box<dyn std::error::Error>> {
+    use std::sync::{Arc, Mutex};
+
+    let duplex = tokio::io::duplex(ssh_packet::PACKET_MAX_SIZE * 16);
+    let rejected =
+        ssh_key::private::PrivateKey::random(&mut rand::thread_rng(), ssh_key::Algorithm::Ed25519)?;
+    let accepted =
+        ssh_key::private::PrivateKey::random(&mut rand::thread_rng(), ssh_key::Algorithm::Ed25519)?;
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let passwords = Arc::new(Mutex::new(Vec::new()));
+
+    tokio::try_join!(
+        async {
+            let server = Server {
+                keys: vec![ssh_key::private::PrivateKey::random(
+                    &mut rand::thread_rng(),
+                    ssh_key::Algorithm::Ed25519,
+                )
+                .unwrap()],
+                ..Default::default()
+            };
+            let server = assh::Session::new(BufStream::new(duplex.0).compat(), server).await?;
+
+            let passwords = passwords.clone();
+            let key = accepted.public_key().clone();
+            server
+                .handle(
+                    handler::Auth::new(cookie::Cookie::default())
+                        .password(move |_, password, _| {
+                            passwords.lock().unwrap().push(password);
+
+                            handler::password::Response::Reject
+                        })
+                        .publickey(move |_, public: ssh_key::PublicKey| {
+                            if public.key_data() == key.key_data() {
+                                handler::publickey::Response::Accept
+                            } else {
+                                handler::publickey::Response::Reject
+                            }
+                        })
+                        .keyboard_interactive(Rounds)
+                        .audit({
+                            let events = events.clone();
+
+                            move |event| events.lock().unwrap().push(event)
+                        }),
+                )
+                .await
+        },
+        async {
+            let client = Client::default();
+            let client = assh::Session::new(BufStream::new(duplex.1).compat(), client).await?;
+
+            // The second password replaces the first one, at it's position.
+            client
+                .request(
+                    request::Auth::new("user", cookie::Cookie::default())
+                        .password("first")
+                        .publickey(rejected.clone())
+                        .keyboard_interactive(|_| vec!["24".into()])
+                        .password("second")
+                        .publickey(accepted.clone()),
+                )
+                .await
+        },
+    )?;
+
+    // The events are audited per message, so collapse the multi-step exchanges into one attempt.
+    let mut methods = events
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|event| event.method.clone())
+        .collect::<Vec<_>>();
+    methods.dedup();
+    assert_eq!(
+        methods,
+        [
+            "none",
+            "password",
+            "publickey",
+            "keyboard-interactive",
+            "publickey"
+        ]
+    );
+    assert_eq!(*passwords.lock().unwrap(), ["second"]);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn disallowed_methods_are_not_attempted() -> Result<(), Box<dyn std::error::Error>> {
+    use std::sync::{Arc, Mutex};
+
+    let duplex = tokio::io::duplex(ssh_packet::PACKET_MAX_SIZE * 16);
+    let events = Arc::new(Mutex::new(Vec::new()));
+
+    let (server, client) = tokio::join!(
+        async {
+            let server = Server {
+                keys: vec![ssh_key::private::PrivateKey::random(
+                    &mut rand::thread_rng(),
+                    ssh_key::Algorithm::Ed25519,
+                )
+                .unwrap()],
+                ..Default::default()
+            };
+            let server = assh::Session::new(BufStream::new(duplex.0).compat(), server).await?;
+
+            server
+                .handle(
+                    handler::Auth::new(cookie::Cookie::default())
+                        .publickey(|_, _| handler::publickey::Response::Accept)
+                        .audit({
+                            let events = events.clone();
+
+                            move |event| events.lock().unwrap().push(event)
+                        }),
+                )
+                .await
+        },
+        async {
+            let client = Client::default();
+            let client = assh::Session::new(BufStream::new(duplex.1).compat(), client).await?;
+
+            client
+                .request(
+                    request::Auth::new("user", cookie::Cookie::default())
+                        .password("hunter2")
+                        .keyboard_interactive(|_| vec!["42".into()]),
+                )
+                .await
+        },
+    );
+
+    assert!(server.is_err());
+
+    // The error carries both the remaining methods and the ones the server expects.
+    let err = client.unwrap_err().to_string();
+    assert!(
+        err.contains("`password,keyboard-interactive`") && err.contains("publickey`"),
+        "{err}"
+    );
+
+    let events = events.lock().unwrap();
+    assert!(events.iter().all(|event| event.method == "none"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn keys_are_probed_unless_configured() -> Result<(), Box<dyn std::error::Error>> {
+    use std::sync::{Arc, Mutex};
+
+    use assh_auth::handler::audit::Outcome;
+
+    let duplex = tokio::io::duplex(ssh_packet::PACKET_MAX_SIZE * 16);
+    let rejected =
+        ssh_key::private::PrivateKey::random(&mut rand::thread_rng(), ssh_key::Algorithm::Ed25519)?;
+    let accepted =
+        ssh_key::private::PrivateKey::random(&mut rand::thread_rng(), ssh_key::Algorithm::Ed25519)?;
+    let events = Arc::new(Mutex::new(Vec::new()));
+
+    tokio::try_join!(
+        async {
+            let server = Server {
+                keys: vec![ssh_key::private::PrivateKey::random(
+                    &mut rand::thread_rng(),
+                    ssh_key::Algorithm::Ed25519,
+                )
+                .unwrap()],
+                ..Default::default()
+            };
+            let server = assh::Session::new(BufStream::new(duplex.0).compat(), server).await?;
+
+            let key = accepted.public_key().clone();
+            server
+                .handle(
+                    handler::Auth::new(cookie::Cookie::default())
+                        .publickey(move |_, public: ssh_key::PublicKey| {
+                            if public.key_data() == key.key_data() {
+                                handler::publickey::Response::Accept
+                            } else {
+                                handler::publickey::Response::Reject
+                            }
+                        })
+                        .audit({
+                            let events = events.clone();
+
+                            move |event| events.lock().unwrap().push(event)
+                        }),
+                )
+                .await
+        },
+        async {
+            let client = Client::default();
+            let client = assh::Session::new(BufStream::new(duplex.1).compat(), client).await?;
+
+            client
+                .request(
+                    request::Auth::new("user", cookie::Cookie::default())
+                        .publickey(rejected.clone())
+                        .publickey_without_probe(accepted.clone()),
+                )
+                .await
+        },
+    )?;
+
+    // The probed key is queried before signing, while the other one signs it's first request.
+    let events = events.lock().unwrap();
+    let attempts = events
+        .iter()
+        .filter_map(|event| {
+            let key = event.key.as_ref()?;
+
+            Some((key.fingerprint?, key.signed, event.outcome))
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        attempts,
+        [
+            (
+                rejected.public_key().fingerprint(Default::default()),
+                false,
+                Outcome::Continue
+            ),
+            (
+                rejected.public_key().fingerprint(Default::default()),
+                true,
+                Outcome::Failure
+            ),
+            (
+                accepted.public_key().fingerprint(Default::default()),
+                true,
+                Outcome::Success
+            )
+        ]
+    );
+
+    Ok(())
+}
+
+/// A custom method requesting a token, which is only valid if it's `secret`.
+struct Token(Vec<String>);
+
+impl handler::custom::Custom for Token {
+    fn methods(&self) -> &[String] {
+        &self.0
+    }
+
+    async fn process<IO, S>(
+        &mut self,
+        session: &mut assh::Session<IO, S>,
+        _: String,
+        _: &str,
+        payload: &[u8],
+    ) -> assh::Result<handler::custom::Response>
+    where
+        IO: assh::Pipe,
+        S: assh::side::Side,
+    {
+        Ok(match payload {
+            [] => {
+                // Challenge the client with a method-specific message.
+                session
+                    .send(ssh_packet::Packet { payload: vec![60] })
+                    .await?;
+
+                handler::custom::Response::Continue
+            }
+            b"\0\0\0\x06secret" => handler::custom::Response::Accept,
+            _ => handler::custom::Response::Reject,
+        })
+    }
+}
+
+/// Build a raw `SSH_MSG_USERAUTH_REQUEST` for the `method`, followed by the `payload`.
+fn raw_request(method: &str, payload: &[u8]) -> ssh_packet::Packet {
+    let mut buffer = vec![50];
+    for string in ["user", "dummy-service@assh.rs", method] {
+        buffer.extend_from_slice(&(string.len() as u32).to_be_bytes());
+        buffer.extend_from_slice(string.as_bytes());
+    }
+    buffer.extend_from_slice(payload);
+
+    ssh_packet::Packet { payload: buffer }
+}
+
+#[tokio::test]
+async fn custom_methods_are_handled() -> Result<(), Box<dyn std::error::Error>> {
+    use assh::Error;
+    use ssh_packet::{
+        arch::ascii,
+        trans::{ServiceAccept, ServiceRequest},
+        userauth,
+    };
+
+    let duplex = tokio::io::duplex(ssh_packet::PACKET_MAX_SIZE * 16);
+    let cookie = cookie::Cookie::default();
+
+    let (server, client) = tokio::join!(
+        async {
+            let server = Server {
+                keys: vec![ssh_key::private::PrivateKey::random(
+                    &mut rand::thread_rng(),
+                    ssh_key::Algorithm::Ed25519,
+                )
+                .unwrap()],
+                ..Default::default()
+            };
+            let server = assh::Session::new(BufStream::new(duplex.0).compat(), server).await?;
+
+            server
+                .handle(
+                    handler::Auth::new(cookie.clone())
+                        .custom(Token(vec!["token-v1@corp.example".into()])),
+                )
+                .await
+        },
+        async {
+            let client = Client::default();
+            let mut client = assh::Session::new(BufStream::new(duplex.1).compat(), client).await?;
+
+            client
+                .send(&ServiceRequest {
+                    service_name: ascii!("ssh-userauth"),
+                })
+                .await?;
+            client.recv().await?.to::<ServiceAccept>()?;
+
+            // Unknown methods are failed, rather than disconnecting the client.
+            client
+                .send(raw_request("unknown@corp.example", b"\0\0\0\0"))
+                .await?;
+            let failure = client.recv().await?.to::<userauth::Failure>()?;
+            let continue_with = failure
+                .continue_with
+                .into_iter()
+                .map(|method| method.to_string())
+                .collect::<Vec<_>>();
+
+            client
+                .send(raw_request("token-v1@corp.example", b""))
+                .await?;
+            let challenge = client.recv().await?.payload;
+
+            client
+                .send(raw_request("token-v1@corp.example", b"\0\0\0\x05guess"))
+                .await?;
+            client.recv().await?.to::<userauth::Failure>()?;
+
+            client
+                .send(raw_request("token-v1@corp.example", b"\0\0\0\x06secret"))
+                .await?;
+            client.recv().await?.to::<userauth::Success>()?;
+
+            Ok::<_, Error>((continue_with, challenge))
+        },
+    );
+
+    server?;
+    assert!(cookie.is_flagged());
+    assert_eq!(
+        client?,
+        (
+            vec!["none".to_string(), "token-v1@corp.example".into()],
+            vec![60]
+        )
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn custom_methods_refuse_required_methods() -> Result<(), Box<dyn std::error::Error>> {
+    use assh::Error;
+    use ssh_packet::{
+        arch::ascii,
+        trans::{ServiceAccept, ServiceRequest},
+    };
+
+    let duplex = tokio::io::duplex(ssh_packet::PACKET_MAX_SIZE * 16);
+    let cookie = cookie::Cookie::default();
+
+    let (server, client) = tokio::join!(
+        async {
+            let server = Server {
+                keys: vec![ssh_key::private::PrivateKey::random(
+                    &mut rand::thread_rng(),
+                    ssh_key::Algorithm::Ed25519,
+                )
+                .unwrap()],
+                ..Default::default()
+            };
+            let server = assh::Session::new(BufStream::new(duplex.0).compat(), server).await?;
+
+            server
+                .handle(
+                    handler::Auth::new(cookie.clone())
+                        .custom(Token(vec!["token-v1@corp.example".into()]))
+                        .required_methods([handler::Method::None]),
+                )
+                .await
+        },
+        async {
+            let client = Client::default();
+            let mut client = assh::Session::new(BufStream::new(duplex.1).compat(), client).await?;
+
+            client
+                .send(&ServiceRequest {
+                    service_name: ascii!("ssh-userauth"),
+                })
+                .await?;
+            client.recv().await?.to::<ServiceAccept>()?;
+
+            client.recv().await
+        },
+    );
+
+    assert!(matches!(server, Err(Error::Config(_))));
+    assert!(!cookie.is_flagged());
+    assert!(matches!(client, Err(Error::Disconnected(_))));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn publickey_remains_available_across_keys() -> Result<(), Box<dyn std::error::Error>> {
+    use assh::Error;
+    use ssh_packet::{
+        arch::{ascii, Utf8},
+        crypto::signature,
+        trans::{ServiceAccept, ServiceRequest},
+        userauth,
+    };
+
+    let duplex = tokio::io::duplex(ssh_packet::PACKET_MAX_SIZE * 16);
+    let keys = [(); 2].map(|()| {
+        ssh_key::private::PrivateKey::random(&mut rand::thread_rng(), ssh_key::Algorithm::Ed25519)
+            .unwrap()
+    });
+    let authorized = keys[1].public_key().clone();
+
+    let (server, client) = tokio::join!(
+        async {
+            let server = Server {
+                keys: vec![ssh_key::private::PrivateKey::random(
+                    &mut rand::thread_rng(),
+                    ssh_key::Algorithm::Ed25519,
+                )
+                .unwrap()],
+                ..Default::default()
+            };
+            let server = assh::Session::new(BufStream::new(duplex.0).compat(), server).await?;
+
+            server
+                .handle(
+                    handler::Auth::new(cookie::Cookie::default())
+                        .password(|_, _, _| handler::password::Response::Reject)
+                        .publickey(move |_, key: ssh_key::PublicKey| {
+                            if key.key_data() == authorized.key_data() {
+                                handler::publickey::Response::Accept
+                            } else {
+                                handler::publickey::Response::Reject
+                            }
+                        }),
+                )
+                .await
+        },
+        async {
+            let client = Client::default();
+            let mut client = assh::Session::new(BufStream::new(duplex.1).compat(), client).await?;
+
+            client
+                .send(&ServiceRequest {
+                    service_name: ascii!("ssh-userauth"),
+                })
+                .await?;
+            client.recv().await?.to::<ServiceAccept>()?;
+
+            let mut continue_with = Vec::new();
+            for key in &keys {
+                let algorithm = key.algorithm();
+                let algorithm = algorithm.as_str().as_bytes();
+                let blob = key.public_key().to_bytes()?;
+                let signature: ssh_key::Signature = signature::Publickey {
+                    session_id: client.session_id().unwrap_or_default().into(),
+                    username: Utf8::borrowed("user"),
+                    service_name: ascii!("dummy-service@assh.rs"),
+                    algorithm: algorithm.into(),
+                    blob: blob.as_slice().into(),
+                }
+                .sign(key);
+
+                client
+                    .send(&userauth::Request {
+                        username: Utf8::borrowed("user"),
+                        service_name: ascii!("dummy-service@assh.rs"),
+                        method: userauth::Method::Publickey {
+                            algorithm: algorithm.into(),
+                            blob: blob.as_slice().into(),
+                            signature: Some(Vec::try_from(signature)?.into()),
+                        },
+                    })
+                    .await?;
+
+                let packet = client.recv().await?;
+                if let Ok(failure) = packet.to::<userauth::Failure>() {
+                    continue_with.extend(
+                        failure
+                            .continue_with
+                            .into_iter()
+                            .map(|method| method.to_string()),
+                    );
+                } else {
+                    packet.to::<userauth::Success>()?;
+                }
+            }
+
+            Ok::<_, Error>(continue_with)
+        },
+    );
+
+    // The failed signature of the first key leaves `publickey` available for the second one.
+    server?;
+    assert_eq!(client?, ["none", "publickey", "password"]);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn clients_try_each_of_their_keys() -> Result<(), Box<dyn std::error::Error>> {
+    let duplex = tokio::io::duplex(ssh_packet::PACKET_MAX_SIZE * 16);
+    let keys = [(); 3].map(|()| {
+        ssh_key::private::PrivateKey::random(&mut rand::thread_rng(), ssh_key::Algorithm::Ed25519)
+            .unwrap()
+    });
+    let authorized = keys[2].public_key().clone();
+
+    let cookie0 = cookie::Cookie::default();
+    let cookie1 = cookie::Cookie::default();
+
+    tokio::try_join!(
+        async {
+            let server = Server {
+                keys: vec![ssh_key::private::PrivateKey::random(
+                    &mut rand::thread_rng(),
+                    ssh_key::Algorithm::Ed25519,
+                )
+                .unwrap()],
+                ..Default::default()
+            };
+            let server = assh::Session::new(BufStream::new(duplex.0).compat(), server).await?;
+
+            server
+                .handle(handler::Auth::new(cookie0.clone()).publickey(
+                    move |_, key: ssh_key::PublicKey| {
+                        if key.key_data() == authorized.key_data() {
+                            handler::publickey::Response::Accept
+                        } else {
+                            handler::publickey::Response::Reject
+                        }
+                    },
+                ))
+                .await
+        },
+        async {
+            let client = Client::default();
+            let client = assh::Session::new(BufStream::new(duplex.1).compat(), client).await?;
+
+            let [first, second, authorized] = keys.clone();
+            client
+                .request(
+                    request::Auth::new("user", cookie1.clone())
+                        .publickey(first)
+                        .publickey(second)
+                        .publickey(authorized),
+                )
+                .await
+        },
+    )?;
+
+    assert!(cookie0.is_flagged() && cookie1.is_flagged());
+
+    Ok(())
+}
+
+struct Counting {
+    store: handler::credentials::Memory,
+    lookups: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+}
+
+impl handler::credentials::CredentialStore for Counting {
+    async fn lookup_user(&self, user: &str) -> bool {
+        self.lookups
+            .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+
+        self.store.lookup_user(user).await
+    }
+
+    async fn verify_password(&self, user: &str, password: &str) -> bool {
+        self.store.verify_password(user, password).await
+    }
+
+    async fn authorized_keys_for(&self, user: &str) -> Vec<ssh_key::PublicKey> {
+        self.lookups
+            .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+
+        self.store.authorized_keys_for(user).await
+    }
+}
+
+#[tokio::test]
+async fn credential_stores_are_cached_per_session() -> Result<(), Box<dyn std::error::Error>> {
+    let duplex = tokio::io::duplex(ssh_packet::PACKET_MAX_SIZE * 16);
+    let keys = [(); 3].map(|()| {
+        ssh_key::private::PrivateKey::random(&mut rand::thread_rng(), ssh_key::Algorithm::Ed25519)
+            .unwrap()
+    });
+    let lookups = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let store = Counting {
+        store: handler::credentials::Memory::new().key("user", keys[2].public_key().clone()),
+        lookups: lookups.clone(),
+    };
+
+    let cookie0 = cookie::Cookie::default();
+    let cookie1 = cookie::Cookie::default();
+
+    tokio::try_join!(
+        async {
+            let server = Server {
+                keys: vec![ssh_key::private::PrivateKey::random(
+                    &mut rand::thread_rng(),
+                    ssh_key::Algorithm::Ed25519,
+                )
+                .unwrap()],
+                ..Default::default()
+            };
+            let server = assh::Session::new(BufStream::new(duplex.0).compat(), server).await?;
+
+            server
+                .handle(
+                    handler::Auth::new(cookie0.clone())
+                        .publickey(handler::credentials::Credentials::new(store)),
+                )
+                .await
+        },
+        async {
+            let client = Client::default();
+            let client = assh::Session::new(BufStream::new(duplex.1).compat(), client).await?;
+
+            let [first, second, authorized] = keys.clone();
+            client
+                .request(
+                    request::Auth::new("user", cookie1.clone())
+                        .publickey(first)
+                        .publickey(second)
+                        .publickey(authorized),
+                )
+                .await
+        },
+    )?;
+
+    assert!(cookie0.is_flagged() && cookie1.is_flagged());
+    assert_eq!(lookups.load(std::sync::atomic::Ordering::SeqCst), 2);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn credential_stores_verify_passwords() -> Result<(), Box<dyn std::error::Error>> {
+    let duplex = tokio::io::duplex(ssh_packet::PACKET_MAX_SIZE * 16);
+
+    let cookie0 = cookie::Cookie::default();
+    let cookie1 = cookie::Cookie::default();
+
+    tokio::try_join!(
+        async {
+            let server = Server {
+                keys: vec![ssh_key::private::PrivateKey::random(
+                    &mut rand::thread_rng(),
+                    ssh_key::Algorithm::Ed25519,
+                )
+                .unwrap()],
+                ..Default::default()
+            };
+            let server = assh::Session::new(BufStream::new(duplex.0).compat(), server).await?;
+
+            server
+                .handle(handler::Auth::new(cookie0.clone()).password(
+                    handler::credentials::Credentials::new(
+                        handler::credentials::Memory::new().password("user", "hunter2"),
+                    ),
+                ))
+                .await
+        },
+        async {
+            let client = Client::default();
+            let client = assh::Session::new(BufStream::new(duplex.1).compat(), client).await?;
+
+            client
+                .request(request::Auth::new("user", cookie1.clone()).password("hunter2"))
+                .await
+        },
+    )?;
+
+    assert!(cookie0.is_flagged() && cookie1.is_flagged());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn usernames_are_validated() -> Result<(), Box<dyn std::error::Error>> {
+    use std::sync::{Arc, Mutex};
+
+    use assh::Error;
+    use ssh_packet::{
+        arch::{ascii, Utf8},
+        trans::{ServiceAccept, ServiceRequest},
+        userauth,
+    };
+
+    let duplex = tokio::io::duplex(ssh_packet::PACKET_MAX_SIZE * 16);
+    let processed = Arc::new(Mutex::new(Vec::new()));
+    let events = Arc::new(Mutex::new(Vec::new()));
+
+    let (_, client) = tokio::join!(
+        async {
+            let server = Server {
+                keys: vec![ssh_key::private::PrivateKey::random(
+                    &mut rand::thread_rng(),
+                    ssh_key::Algorithm::Ed25519,
+                )
+                .unwrap()],
+                ..Default::default()
+            };
+            let server = assh::Session::new(BufStream::new(duplex.0).compat(), server).await?;
+
+            server
+                .handle(
+                    handler::Auth::new(cookie::Cookie::default())
+                        .validate_username(|username| {
+                            (!username.is_empty() && !username.contains(['/', '\0']))
+                                .then(|| username.to_lowercase())
+                        })
+                        .password({
+                            let processed = processed.clone();
+
+                            move |username: String, _, _| {
+                                processed.lock().unwrap().push(username);
+
+                                handler::password::Response::Reject
+                            }
+                        })
+                        .audit({
+                            let events = events.clone();
+
+                            move |event: handler::AuthEvent| {
+                                events.lock().unwrap().push(event.username)
+                            }
+                        }),
+                )
+                .await
+        },
+        async {
+            let client = Client::default();
+            let mut client = assh::Session::new(BufStream::new(duplex.1).compat(), client).await?;
+
+            client
+                .send(&ServiceRequest {
+                    service_name: ascii!("ssh-userauth"),
+                })
+                .await?;
+            client.recv().await?.to::<ServiceAccept>()?;
+
+            for username in ["Alice", "../bob"] {
+                client
+                    .send(&userauth::Request {
+                        username: Utf8::borrowed(username),
+                        service_name: ascii!("ssh-connection"),
+                        method: userauth::Method::Password {
+                            password: Utf8::borrowed("guess"),
+                            new: None,
+                        },
+                    })
+                    .await?;
+
+                client.recv().await?.to::<userauth::Failure>()?;
+            }
+
+            Ok::<_, Error>(())
+        },
+    );
+
+    client?;
+
+    // The refused username never reaches the handler, and the other one is canonicalized.
+    assert_eq!(*processed.lock().unwrap(), ["alice"]);
+    assert_eq!(*events.lock().unwrap(), ["alice", "../bob"]);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn server_sig_algs_follow_the_accepted_algorithms() -> Result<(), Box<dyn std::error::Error>>
+{
+    use assh::Error;
+    use ssh_key::{Algorithm, HashAlg};
+    use ssh_packet::{
+        arch::ascii,
+        trans::{ServiceAccept, ServiceRequest},
+    };
+
+    let duplex = tokio::io::duplex(ssh_packet::PACKET_MAX_SIZE * 16);
+    let auth = handler::Auth::new(cookie::Cookie::default())
+        .publickey(|_, _| handler::publickey::Response::Accept)
+        .publickey_algorithms([
+            Algorithm::Ed25519,
+            Algorithm::Rsa {
+                hash: Some(HashAlg::Sha512),
+            },
+        ]);
+
+    let (_, client) = tokio::join!(
+        async {
+            let server = Server {
+                keys: vec![ssh_key::private::PrivateKey::random(
+                    &mut rand::thread_rng(),
+                    ssh_key::Algorithm::Ed25519,
+                )
+                .unwrap()],
+                ..Default::default()
+            }
+            .server_sig_algs(auth.server_sig_algs());
+            let server = assh::Session::new(BufStream::new(duplex.0).compat(), server).await?;
+
+            server.handle(auth).await
+        },
+        async {
+            let client = Client::default();
+            let mut client = assh::Session::new(BufStream::new(duplex.1).compat(), client).await?;
+
+            client
+                .send(&ServiceRequest {
+                    service_name: ascii!("ssh-userauth"),
+                })
+                .await?;
+            client.recv().await?.to::<ServiceAccept>()?;
+
+            Ok::<_, Error>(client.server_sig_algs().map(<[String]>::to_vec))
+        },
+    );
+
+    assert_eq!(
+        client?,
+        Some(vec!["ssh-ed25519".into(), "rsa-sha2-512".into()])
+    );
+
+    Ok(())
+}
+
+/// A downstream service capturing the [`handler::Identity`] of the authenticated user.
+#[derive(Debug, Clone, Default)]
+struct IdentityProbe(std::sync::Arc<std::sync::Mutex<Option<handler::Identity>>>);
+
+impl assh::service::Handler for IdentityProbe {
+    type Err = assh::Error;
+    type Ok<IO: assh::Pipe, S: assh::side::Side> = ();
+
+    const SERVICE_NAME: ssh_packet::arch::Ascii<'static> =
+        ssh_packet::arch::ascii!("dummy-service@assh.rs");
+
+    async fn on_request<IO, S>(
+        &mut self,
+        session: assh::Session<IO, S>,
+    ) -> Result<Self::Ok<IO, S>, Self::Err>
+    where
+        IO: assh::Pipe,
+        S: assh::side::Side,
+    {
+        *self.0.lock().unwrap() = session.extensions().get::<handler::Identity>().cloned();
+
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn identity_is_handed_to_the_service() -> Result<(), Box<dyn std::error::Error>> {
+    let duplex = tokio::io::duplex(ssh_packet::PACKET_MAX_SIZE * 16);
+    let key =
+        ssh_key::private::PrivateKey::random(&mut rand::thread_rng(), ssh_key::Algorithm::Ed25519)?;
+    let probe = IdentityProbe::default();
+
+    tokio::try_join!(
+        async {
+            let server = Server {
+                keys: vec![ssh_key::private::PrivateKey::random(
+                    &mut rand::thread_rng(),
+                    ssh_key::Algorithm::Ed25519,
+                )
+                .unwrap()],
+                ..Default::default()
+            };
+            let server = assh::Session::new(BufStream::new(duplex.0).compat(), server).await?;
+
+            server
+                .handle(
+                    handler::Auth::new(probe.clone())
+                        .password(|_, _, _| handler::password::Response::Accept)
+                        .publickey(|_, _| handler::publickey::Response::Accept)
+                        .required_methods([handler::Method::Password, handler::Method::Publickey]),
+                )
+                .await
+        },
+        async {
+            let client = Client::default();
+            let client = assh::Session::new(BufStream::new(duplex.1).compat(), client).await?;
+
+            client
+                .request(
+                    request::Auth::new("user", cookie::Cookie::default())
+                        .password("hunter2")
+                        .publickey(key.clone()),
+                )
+                .await
+        },
+    )?;
+
+    let identity = probe.0.lock().unwrap().take().unwrap();
+    assert_eq!(identity.username, "user");
+    assert_eq!(identity.methods, ["password", "publickey"]);
+    assert_eq!(identity.public_key.as_ref(), Some(key.public_key()));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn keyboard_interactive_is_chained() -> Result<(), Box<dyn std::error::Error>> {
+    use assh::Error;
+    use ssh_packet::{
+        arch::{ascii, Utf8},
+        trans::{ServiceAccept, ServiceRequest},
+        userauth,
+    };
+
+    let duplex = tokio::io::duplex(ssh_packet::PACKET_MAX_SIZE * 16);
+    let totp = handler::keyboard_interactive::Totp::new().user("user", *b"12345678901234567890");
+
+    let cookie = cookie::Cookie::default();
+
+    let (server, client) = tokio::join!(
+        async {
+            let server = Server {
+                keys: vec![ssh_key::private::PrivateKey::random(
+                    &mut rand::thread_rng(),
+                    ssh_key::Algorithm::Ed25519,
+                )
+                .unwrap()],
+                ..Default::default()
+            };
+            let server = assh::Session::new(BufStream::new(duplex.0).compat(), server).await?;
+
+            server
+                .handle(
+                    handler::Auth::new(cookie.clone())
+                        .password(|_, _, _| handler::password::Response::Accept)
+                        .keyboard_interactive(totp.clone())
+                        .required_methods([
+                            handler::Method::Password,
+                            handler::Method::KeyboardInteractive,
+                        ]),
+                )
+                .await
+        },
+        async {
+            let client = Client::default();
+            let mut client = assh::Session::new(BufStream::new(duplex.1).compat(), client).await?;
+
+            client
+                .send(&ServiceRequest {
+                    service_name: ascii!("ssh-userauth"),
+                })
+                .await?;
+            client.recv().await?.to::<ServiceAccept>()?;
+
+            client
+                .send(&userauth::Request {
+                    username: Utf8::borrowed("user"),
+                    service_name: ascii!("dummy-service@assh.rs"),
+                    method: userauth::Method::Password {
+                        password: Utf8::borrowed("hunter2"),
+                        new: None,
+                    },
+                })
+                .await?;
+            let failure = client.recv().await?.to::<userauth::Failure>()?;
+            assert!(*failure.partial_success);
+            assert!(failure
+                .continue_with
+                .into_iter()
+                .eq([ascii!("keyboard-interactive")]));
+
+            client
+                .send(&userauth::Request {
+                    username: Utf8::borrowed("user"),
+                    service_name: ascii!("dummy-service@assh.rs"),
+                    method: userauth::Method::KeyboardInteractive {
+                        language: Default::default(),
+                        submethods: Default::default(),
+                    },
+                })
+                .await?;
+            let request = client.recv().await?.to::<userauth::InfoRequest>()?;
+            assert_eq!(request.prompts.len(), 1);
+            assert!(!*request.prompts[0].echo);
+
+            let code = totp.generate("user", std::time::SystemTime::now()).unwrap();
+            client
+                .send(&userauth::InfoResponse {
+                    responses: vec![code.into()],
+                })
+                .await?;
+            client.recv().await?.to::<userauth::Success>()?;
+
+            Ok::<_, Error>(())
+        },
+    );
+    client?;
+    server?;
+
+    assert!(cookie.is_flagged());
+
+    Ok(())
+}
+
+/// A _security key_ signing with the `flags`, as described in OpenSSH's `PROTOCOL.u2f`.
+struct SecurityKeySigner {
+    key: ssh_key::PrivateKey,
+    flags: u8,
+    counter: u32,
+}
+
+impl SecurityKeySigner {
+    fn public_key(&self) -> ssh_key::PublicKey {
+        ssh_key::public::KeyData::SkEd25519(ssh_key::public::SkEd25519::new(
+            *self.key.public_key().key_data().ed25519().unwrap(),
+            "ssh:",
+        ))
+        .into()
+    }
+}
+
+impl signature::Signer<ssh_key::Signature> for SecurityKeySigner {
+    fn try_sign(&self, message: &[u8]) -> signature::Result<ssh_key::Signature> {
+        use sha2::{Digest, Sha256};
+
+        let trailer = [[self.flags].as_slice(), &self.counter.to_be_bytes()].concat();
+        let data = [
+            Sha256::digest("ssh:").as_slice(),
+            &trailer,
+            Sha256::digest(message).as_slice(),
+        ]
+        .concat();
+
+        let signature: ssh_key::Signature = self.key.try_sign(&data)?;
+
+        ssh_key::Signature::new(
+            ssh_key::Algorithm::SkEd25519,
+            [signature.as_bytes(), &trailer].concat(),
+        )
+        .map_err(signature::Error::from_source)
+    }
+}
+
+/// Send a `publickey` request signed by the security key `signer`, to a server with the `publickey` handler.
+async fn security_key_attempt(
+    publickey: impl handler::publickey::Publickey,
+    signer: &SecurityKeySigner,
+) -> Result<ssh_packet::Packet, Box<dyn std::error::Error>> {
+    use ssh_packet::{
+        arch::{ascii, Utf8},
+        crypto::signature,
+        trans::{ServiceAccept, ServiceRequest},
+        userauth,
+    };
+
+    let duplex = tokio::io::duplex(ssh_packet::PACKET_MAX_SIZE * 16);
+
+    let (_, client) = tokio::join!(
+        async {
+            let server = Server {
+                keys: vec![ssh_key::private::PrivateKey::random(
+                    &mut rand::thread_rng(),
+                    ssh_key::Algorithm::Ed25519,
+                )
+                .unwrap()],
+                ..Default::default()
+            };
+            let server = assh::Session::new(BufStream::new(duplex.0).compat(), server).await?;
+
+            server
+                .handle(handler::Auth::new(cookie::Cookie::default()).publickey(publickey))
+                .await
+        },
+        async {
+            let client = Client::default();
+            let mut client = assh::Session::new(BufStream::new(duplex.1).compat(), client).await?;
+
+            client
+                .send(&ServiceRequest {
+                    service_name: ascii!("ssh-userauth"),
+                })
+                .await?;
+            client.recv().await?.to::<ServiceAccept>()?;
+
+            let algorithm = ssh_key::Algorithm::SkEd25519;
+            let algorithm = algorithm.as_str().as_bytes();
+            let blob = signer.public_key().to_bytes()?;
+            let signature: ssh_key::Signature = signature::Publickey {
+                session_id: client.session_id().unwrap_or_default().into(),
+                username: Utf8::borrowed("user"),
+                service_name: ascii!("dummy-service@assh.rs"),
+                algorithm: algorithm.into(),
+                blob: blob.as_slice().into(),
+            }
+            .sign(signer);
+
+            client
+                .send(&userauth::Request {
+                    username: Utf8::borrowed("user"),
+                    service_name: ascii!("dummy-service@assh.rs"),
+                    method: userauth::Method::Publickey {
+                        algorithm: algorithm.into(),
+                        blob: blob.into(),
+                        signature: Some(Vec::try_from(signature)?.into()),
+                    },
+                })
+                .await?;
+
+            client.recv().await
+        },
+    );
+
+    Ok(client?)
+}
+
+#[tokio::test]
+async fn security_keys_require_presence() -> Result<(), Box<dyn std::error::Error>> {
+    use ssh_packet::userauth;
+
+    let random = |flags| SecurityKeySigner {
+        key: ssh_key::PrivateKey::random(&mut rand::thread_rng(), ssh_key::Algorithm::Ed25519)
+            .unwrap(),
+        flags,
+        counter: 1,
+    };
+    let accept = |_, _| handler::publickey::Response::Accept;
+
+    security_key_attempt(accept, &random(0x01))
+        .await?
+        .to::<userauth::Success>()?;
+    security_key_attempt(accept, &random(0x00))
+        .await?
+        .to::<userauth::Failure>()?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn security_key_flags_are_handed_to_handlers() -> Result<(), Box<dyn std::error::Error>> {
+    use std::sync::{Arc, Mutex};
+
+    use ssh_packet::userauth;
+
+    /// Require the verification of the user, such as with a PIN.
+    struct Verified(Arc<Mutex<Option<handler::publickey::SecurityKey>>>);
+
+    impl handler::publickey::Publickey for Verified {
+        async fn process(
+            &mut self,
+            _: &handler::AuthContext<'_>,
+            _: String,
+            _: ssh_key::PublicKey,
+        ) -> handler::publickey::Response {
+            handler::publickey::Response::Reject
+        }
+
+        async fn process_security_key(
+            &mut self,
+            _: &handler::AuthContext<'_>,
+            _: String,
+            _: ssh_key::PublicKey,
+            flags: handler::publickey::SecurityKey,
+        ) -> handler::publickey::Response {
+            *self.0.lock().unwrap() = Some(flags);
+
+            if flags.user_verification {
+                handler::publickey::Response::Accept
+            } else {
+                handler::publickey::Response::Reject
+            }
+        }
+    }
+
+    let key = ssh_key::PrivateKey::random(&mut rand::thread_rng(), ssh_key::Algorithm::Ed25519)?;
+    let flags = Arc::new(Mutex::new(None));
+
+    security_key_attempt(
+        Verified(flags.clone()),
+        &SecurityKeySigner {
+            key: key.clone(),
+            flags: 0x05,
+            counter: 42,
+        },
+    )
+    .await?
+    .to::<userauth::Success>()?;
+    assert_eq!(
+        flags.lock().unwrap().take(),
+        Some(handler::publickey::SecurityKey {
+            user_presence: true,
+            user_verification: true,
+            counter: 42,
+        })
+    );
+
+    security_key_attempt(
+        Verified(flags.clone()),
+        &SecurityKeySigner {
+            key,
+            flags: 0x01,
+            counter: 43,
+        },
+    )
+    .await?
+    .to::<userauth::Failure>()?;
+
+    Ok(())
+}
+
+/// A mechanism establishing the context in two tokens, whose _MIC_ is the message itself.
+struct Mirror(Vec<Vec<u8>>);
+
+impl handler::gssapi::Gssapi for Mirror {
+    fn mechanisms(&self) -> &[Vec<u8>] {
+        &self.0
+    }
+
+    async fn start(&mut self, _: &handler::AuthContext<'_>, _: String, _: &[u8]) -> bool {
+        true
+    }
+
+    async fn accept(
+        &mut self,
+        _: &handler::AuthContext<'_>,
+        token: &[u8],
+    ) -> handler::gssapi::Step {
+        match token {
+            b"hello" => handler::gssapi::Step::Continue(b"world".to_vec()),
+            b"again" => handler::gssapi::Step::Complete(None),
+            _ => handler::gssapi::Step::Failure(None),
+        }
+    }
+
+    async fn verify(
+        &mut self,
+        _: &handler::AuthContext<'_>,
+        _: String,
+        message: &[u8],
+        mic: &[u8],
+    ) -> handler::gssapi::Response {
+        if message == mic {
+            handler::gssapi::Response::Accept
+        } else {
+            handler::gssapi::Response::Reject
+        }
+    }
+}
+
+#[tokio::test]
+async fn gssapi_exchanges_are_verified() -> Result<(), Box<dyn std::error::Error>> {
+    use assh::Error;
+    use ssh_packet::{
+        arch::ascii,
+        trans::{ServiceAccept, ServiceRequest},
+        userauth,
+    };
+
+    fn string(buffer: &mut Vec<u8>, data: &[u8]) {
+        buffer.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        buffer.extend_from_slice(data);
+    }
+    fn message(number: u8, data: &[u8]) -> ssh_packet::Packet {
+        let mut payload = vec![number];
+        string(&mut payload, data);
+
+        ssh_packet::Packet { payload }
+    }
+    fn request(username: &str) -> ssh_packet::Packet {
+        let mut payload = vec![50];
+        for string_ in [username, "dummy-service@assh.rs", "gssapi-with-mic"] {
+            string(&mut payload, string_.as_bytes());
+        }
+        payload.extend_from_slice(&2u32.to_be_bytes());
+        string(&mut payload, b"\x06\x01\x00");
+        string(&mut payload, b"\x06\x01\x2a");
+
+        ssh_packet::Packet { payload }
+    }
+
+    let duplex = tokio::io::duplex(ssh_packet::PACKET_MAX_SIZE * 16);
+
+    let cookie = cookie::Cookie::default();
+
+    let (server, client) = tokio::join!(
+        async {
+            let server = Server {
+                keys: vec![ssh_key::private::PrivateKey::random(
+                    &mut rand::thread_rng(),
+                    ssh_key::Algorithm::Ed25519,
+                )
+                .unwrap()],
+                ..Default::default()
+            };
+            let server = assh::Session::new(BufStream::new(duplex.0).compat(), server).await?;
+
+            server
+                .handle(
+                    handler::Auth::new(cookie.clone())
+                        .gssapi(Mirror(vec![b"\x06\x01\x2a".to_vec()])),
+                )
+                .await
+        },
+        async {
+            let client = Client::default();
+            let mut client = assh::Session::new(BufStream::new(duplex.1).compat(), client).await?;
+
+            client
+                .send(&ServiceRequest {
+                    service_name: ascii!("ssh-userauth"),
+                })
+                .await?;
+            client.recv().await?.to::<ServiceAccept>()?;
+
+            let mut outcomes = Vec::new();
+            for username in ["mallory", "user"] {
+                client.send(request(username)).await?;
+                assert_eq!(
+                    client.recv().await?.payload,
+                    message(60, b"\x06\x01\x2a").payload
+                );
+
+                client.send(message(61, b"hello")).await?;
+                assert_eq!(client.recv().await?.payload, message(61, b"world").payload);
+                client.send(message(61, b"again")).await?;
+
+                // The _MIC_ of `mallory` is computed for another user.
+                let mut mic = Vec::new();
+                string(&mut mic, client.session_id().unwrap_or_default());
+                mic.push(50);
+                string(&mut mic, b"user");
+                string(&mut mic, b"dummy-service@assh.rs");
+                string(&mut mic, b"gssapi-with-mic");
+                client.send(message(66, &mic)).await?;
+
+                let packet = client.recv().await?;
+                outcomes.push(
+                    packet.to::<userauth::Failure>().is_err()
+                        && packet.to::<userauth::Success>().is_ok(),
+                );
+            }
+
+            Ok::<_, Error>(outcomes)
+        },
+    );
+    assert_eq!(client?, [false, true]);
+    server?;
+
+    assert!(cookie.is_flagged());
+
+    Ok(())
+}
+
+/// A `keyboard-interactive` handler sending instructions first, then asking for the answer.
+struct Rounds;
+
+impl handler::keyboard_interactive::KeyboardInteractive for Rounds {
+    async fn start(
+        &mut self,
+        _: &handler::AuthContext<'_>,
+        _: String,
+        _: String,
+    ) -> handler::keyboard_interactive::Response {
+        handler::keyboard_interactive::Response::Challenge(
+            handler::keyboard_interactive::Challenge {
+                instruction: "Think about it.".into(),
+                ..Default::default()
+            },
+        )
+    }
+
+    async fn respond(
+        &mut self,
+        _: &handler::AuthContext<'_>,
+        _: String,
+        responses: Vec<String>,
+    ) -> handler::keyboard_interactive::Response {
+        use handler::keyboard_interactive::{Challenge, Prompt, Response};
+
+        match responses.as_slice() {
+            [] => Response::Challenge(Challenge {
+                name: "Question".into(),
+                prompts: vec![Prompt {
+                    prompt: "Answer: ".into(),
+                    echo: true,
+                }],
+                ..Default::default()
+            }),
+            [answer] if answer == "42" => Response::Accept,
+            _ => Response::Reject,
+        }
+    }
+}
+
+#[tokio::test]
+async fn clients_answer_keyboard_interactive_challenges() -> Result<(), Box<dyn std::error::Error>>
+{
+    let duplex = tokio::io::duplex(ssh_packet::PACKET_MAX_SIZE * 16);
+
+    let cookie0 = cookie::Cookie::default();
+    let cookie1 = cookie::Cookie::default();
+    let challenges = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+
+    tokio::try_join!(
+        async {
+            let server = Server {
+                keys: vec![ssh_key::private::PrivateKey::random(
+                    &mut rand::thread_rng(),
+                    ssh_key::Algorithm::Ed25519,
+                )
+                .unwrap()],
+                ..Default::default()
+            };
+            let server = assh::Session::new(BufStream::new(duplex.0).compat(), server).await?;
+
+            server
+                .handle(handler::Auth::new(cookie0.clone()).keyboard_interactive(Rounds))
+                .await
+        },
+        async {
+            let client = Client::default();
+            let client = assh::Session::new(BufStream::new(duplex.1).compat(), client).await?;
+
+            let challenges = challenges.clone();
+            client
+                .request(
+                    request::Auth::new("user", cookie1.clone()).keyboard_interactive(
+                        move |challenge| {
+                            challenges.lock().unwrap().push(challenge.clone());
+
+                            // Answer even the challenge without prompts, which is to be ignored.
+                            vec!["42".into()]
+                        },
+                    ),
+                )
+                .await
+        },
+    )?;
+
+    assert!(cookie0.is_flagged());
+    assert!(cookie1.is_flagged());
+
+    let challenges = challenges.lock().unwrap();
+    assert_eq!(challenges.len(), 2);
+    assert_eq!(challenges[0].instruction, "Think about it.");
+    assert!(challenges[0].prompts.is_empty());
+    assert_eq!(challenges[1].name, "Question");
+    assert_eq!(
+        challenges[1].prompts,
+        [request::Prompt {
+            prompt: "Answer: ".into(),
+            echo: true,
+        }]
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn clients_continue_after_partial_success() -> Result<(), Box<dyn std::error::Error>> {
+    use std::sync::{Arc, Mutex};
+
+    use assh_auth::handler::{audit::Outcome, Method};
+
+    let duplex = tokio::io::duplex(ssh_packet::PACKET_MAX_SIZE * 16);
+    let first =
+        ssh_key::private::PrivateKey::random(&mut rand::thread_rng(), ssh_key::Algorithm::Ed25519)?;
+    let second =
+        ssh_key::private::PrivateKey::random(&mut rand::thread_rng(), ssh_key::Algorithm::Ed25519)?;
+    let events = Arc::new(Mutex::new(Vec::new()));
+
+    let cookie0 = cookie::Cookie::default();
+    let cookie1 = cookie::Cookie::default();
+
+    tokio::try_join!(
+        async {
+            let server = Server {
+                keys: vec![ssh_key::private::PrivateKey::random(
+                    &mut rand::thread_rng(),
+                    ssh_key::Algorithm::Ed25519,
+                )
+                .unwrap()],
+                ..Default::default()
+            };
+            let server = assh::Session::new(BufStream::new(duplex.0).compat(), server).await?;
+
+            server
+                .handle(
+                    handler::Auth::new(cookie0.clone())
+                        .publickey(|_, _| handler::publickey::Response::Accept)
+                        .keyboard_interactive(Rounds)
+                        .required_methods([Method::Publickey, Method::KeyboardInteractive])
+                        .audit({
+                            let events = events.clone();
+
+                            move |event| events.lock().unwrap().push(event)
+                        }),
+                )
+                .await
+        },
+        async {
+            let client = Client::default();
+            let client = assh::Session::new(BufStream::new(duplex.1).compat(), client).await?;
+
+            client
+                .request(
+                    request::Auth::new("user", cookie1.clone())
+                        .publickey(first.clone())
+                        .publickey(second.clone())
+                        .keyboard_interactive(|_| vec!["42".into()]),
+                )
+                .await
+        },
+    )?;
+
+    assert!(cookie0.is_flagged() && cookie1.is_flagged());
+
+    let events = events.lock().unwrap();
+    let outcomes = events
+        .iter()
+        .map(|event| (event.method.as_str(), event.outcome))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        outcomes,
+        [
+            ("none", Outcome::Failure),
+            ("publickey", Outcome::Continue),
+            ("publickey", Outcome::Partial),
+            ("keyboard-interactive", Outcome::Continue),
+            ("keyboard-interactive", Outcome::Continue),
+            ("keyboard-interactive", Outcome::Success)
+        ]
+    );
+
+    // The second key is not attempted, as the `publickey` method is already satisfied.
+    assert!(events
+        .iter()
+        .filter_map(|event| event.key.as_ref())
+        .all(|key| key.fingerprint == Some(first.public_key().fingerprint(Default::default()))));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn partial_success_is_reported_when_exhausted() -> Result<(), Box<dyn std::error::Error>> {
+    use assh_auth::handler::Method;
+
+    let duplex = tokio::io::duplex(ssh_packet::PACKET_MAX_SIZE * 16);
+    let key =
+        ssh_key::private::PrivateKey::random(&mut rand::thread_rng(), ssh_key::Algorithm::Ed25519)?;
+
+    let (server, client) = tokio::join!(
+        async {
+            let server = Server {
+                keys: vec![ssh_key::private::PrivateKey::random(
+                    &mut rand::thread_rng(),
+                    ssh_key::Algorithm::Ed25519,
+                )
+                .unwrap()],
+                ..Default::default()
+            };
+            let server = assh::Session::new(BufStream::new(duplex.0).compat(), server).await?;
+
+            server
+                .handle(
+                    handler::Auth::new(cookie::Cookie::default())
+                        .publickey(|_, _| handler::publickey::Response::Accept)
+                        .password(|_, _, _| handler::password::Response::Accept)
+                        .required_methods([Method::Publickey, Method::Password]),
+                )
+                .await
+        },
+        async {
+            let client = Client::default();
+            let client = assh::Session::new(BufStream::new(duplex.1).compat(), client).await?;
+
+            client
+                .request(request::Auth::new("user", cookie::Cookie::default()).publickey(key))
+                .await
+        },
+    );
+
+    assert!(server.is_err());
+
+    let err = client.unwrap_err().to_string();
+    assert!(
+        err.contains("after the partial success of `publickey`"),
+        "{err}"
+    );
+
+    Ok(())
+}
+
+/// A `password` handler requiring a change of the `old` password.
+fn expiring(
+    changes: std::sync::Arc<std::sync::Mutex<Vec<Option<String>>>>,
+) -> impl handler::password::Password {
+    move |_, password: String, new: Option<String>| {
+        changes.lock().unwrap().push(new.clone());
+
+        match (password.as_str(), new.as_deref()) {
+            ("old", None) => handler::password::Response::PasswordExpired {
+                prompt: "Your password has expired".into(),
+            },
+            ("old", Some("new")) => handler::password::Response::Accept,
+            _ => handler::password::Response::Reject,
+        }
+    }
+}
+
+#[tokio::test]
+async fn clients_change_expired_passwords() -> Result<(), Box<dyn std::error::Error>> {
+    let duplex = tokio::io::duplex(ssh_packet::PACKET_MAX_SIZE * 16);
+
+    let cookie0 = cookie::Cookie::default();
+    let cookie1 = cookie::Cookie::default();
+    let changes = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let prompts = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+
+    tokio::try_join!(
+        async {
+            let server = Server {
+                keys: vec![ssh_key::private::PrivateKey::random(
+                    &mut rand::thread_rng(),
+                    ssh_key::Algorithm::Ed25519,
+                )
+                .unwrap()],
+                ..Default::default()
+            };
+            let server = assh::Session::new(BufStream::new(duplex.0).compat(), server).await?;
+
+            server
+                .handle(handler::Auth::new(cookie0.clone()).password(expiring(changes.clone())))
+                .await
+        },
+        async {
+            let client = Client::default();
+            let client = assh::Session::new(BufStream::new(duplex.1).compat(), client).await?;
+
+            let prompts = prompts.clone();
+            client
+                .request(
+                    request::Auth::new("user", cookie1.clone())
+                        .password("old")
+                        .password_change(move |prompt| {
+                            prompts.lock().unwrap().push(prompt.to_owned());
+
+                            Some("new".into())
+                        }),
+                )
+                .await
+        },
+    )?;
+
+    assert!(cookie0.is_flagged());
+    assert!(cookie1.is_flagged());
+    assert_eq!(*changes.lock().unwrap(), [None, Some("new".into())]);
+    assert_eq!(*prompts.lock().unwrap(), ["Your password has expired"]);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn declined_password_changes_continue() -> Result<(), Box<dyn std::error::Error>> {
+    use assh::{error::DisconnectedError, Error};
+    use ssh_packet::trans::DisconnectReason;
+
+    let duplex = tokio::io::duplex(ssh_packet::PACKET_MAX_SIZE * 16);
+
+    let changes = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+
+    let (server, client) = tokio::join!(
+        async {
+            let server = Server {
+                keys: vec![ssh_key::private::PrivateKey::random(
+                    &mut rand::thread_rng(),
+                    ssh_key::Algorithm::Ed25519,
+                )
+                .unwrap()],
+                ..Default::default()
+            };
+            let server = assh::Session::new(BufStream::new(duplex.0).compat(), server).await?;
+
+            server
+                .handle(
+                    handler::Auth::new(cookie::Cookie::default())
+                        .password(expiring(changes.clone())),
+                )
+                .await
+        },
+        async {
+            let client = Client::default();
+            let client = assh::Session::new(BufStream::new(duplex.1).compat(), client).await?;
+
+            client
+                .request(
+                    request::Auth::new("user", cookie::Cookie::default())
+                        .password("old")
+                        .password_change(|_| None),
+                )
+                .await
+        },
+    );
+
+    // Without any other method to continue with, the client gives up.
+    assert!(matches!(
+        client,
+        Err(Error::Disconnected(DisconnectedError {
+            reason: DisconnectReason::NoMoreAuthMethodsAvailable,
+            ..
+        }))
+    ));
+    assert!(server.is_err());
+    assert_eq!(*changes.lock().unwrap(), [None]);
+
+    Ok(())
+}
+
+/// A `ssh-agent` listening in a scratch directory, killed when dropped.
+#[cfg(unix)]
+struct SshAgent {
+    dir: std::path::PathBuf,
+    process: std::process::Child,
+}
+
+#[cfg(unix)]
+impl SshAgent {
+    /// Spawn an agent holding freshly generated keys, from each of the `ssh-keygen` arguments.
+    fn spawn(keys: &[&[&str]]) -> Self {
+        use std::process::{Command, Stdio};
+
+        let dir = std::env::temp_dir().join(format!("assh-agent-{:016x}", rand::random::<u64>()));
+        std::fs::create_dir(&dir).unwrap();
+
+        let process = Command::new("ssh-agent")
+            .arg("-D")
+            .arg("-a")
+            .arg(dir.join("socket"))
+            .stdout(Stdio::null())
+            .spawn()
+            .unwrap();
+        let agent = Self { dir, process };
+
+        while !agent.socket().exists() {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+
+        for (index, args) in keys.iter().enumerate() {
+            let path = agent.dir.join(format!("key-{index}"));
+
+            assert!(Command::new("ssh-keygen")
+                .args(["-q", "-N", ""])
+                .args(*args)
+                .arg("-f")
+                .arg(&path)
+                .status()
+                .unwrap()
+                .success());
+            assert!(Command::new("ssh-add")
+                .arg(&path)
+                .env("SSH_AUTH_SOCK", agent.socket())
+                .stderr(Stdio::null())
+                .status()
+                .unwrap()
+                .success());
+        }
+
+        agent
+    }
+
+    fn socket(&self) -> std::path::PathBuf {
+        self.dir.join("socket")
+    }
+}
+
+#[cfg(unix)]
+impl Drop for SshAgent {
+    fn drop(&mut self) {
+        self.process.kill().ok();
+        self.process.wait().ok();
+
+        std::fs::remove_dir_all(&self.dir).ok();
+    }
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn clients_sign_with_the_agent() -> Result<(), Box<dyn std::error::Error>> {
+    use assh::algorithm::HostKeySigner;
+
+    let duplex = tokio::io::duplex(ssh_packet::PACKET_MAX_SIZE * 16);
+
+    let agent = SshAgent::spawn(&[&["-t", "ed25519"], &["-t", "rsa", "-b", "2048"]]);
+    let keys = request::Agent::new(agent.socket()).identities().await?;
+    let rsa = keys
+        .iter()
+        .map(|key| key.public_key().clone())
+        .find(|key| key.algorithm().is_rsa())
+        .unwrap();
+
+    let probe = IdentityProbe::default();
+
+    tokio::try_join!(
+        async {
+            let server = Server {
+                keys: vec![ssh_key::private::PrivateKey::random(
+                    &mut rand::thread_rng(),
+                    ssh_key::Algorithm::Ed25519,
+                )
+                .unwrap()],
+                ..Default::default()
+            };
+            let server = assh::Session::new(BufStream::new(duplex.0).compat(), server).await?;
+
+            // Only accept the _RSA_ key, so that the other one is attempted first or not at all.
+            let accepted = rsa.clone();
+            server
+                .handle(handler::Auth::new(probe.clone()).publickey(
+                    move |_, key: ssh_key::PublicKey| {
+                        if key.key_data() == accepted.key_data() {
+                            handler::publickey::Response::Accept
+                        } else {
+                            handler::publickey::Response::Reject
+                        }
+                    },
+                ))
+                .await
+        },
+        async {
+            let client = Client::default();
+            let client = assh::Session::new(BufStream::new(duplex.1).compat(), client).await?;
+
+            client
+                .request(
+                    request::Auth::new("user", cookie::Cookie::default())
+                        .agent(request::Agent::new(agent.socket())),
+                )
+                .await
+        },
+    )?;
+
+    let identity = probe.0.lock().unwrap().take().unwrap();
+    assert_eq!(
+        identity.public_key.map(|key| key.key_data().clone()),
+        Some(rsa.key_data().clone())
+    );
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn unreachable_agents_are_skipped() -> Result<(), Box<dyn std::error::Error>> {
+    let duplex = tokio::io::duplex(ssh_packet::PACKET_MAX_SIZE * 16);
+
+    let cookie0 = cookie::Cookie::default();
+    let cookie1 = cookie::Cookie::default();
+
+    tokio::try_join!(
+        async {
+            let server = Server {
+                keys: vec![ssh_key::private::PrivateKey::random(
+                    &mut rand::thread_rng(),
+                    ssh_key::Algorithm::Ed25519,
+                )
+                .unwrap()],
+                ..Default::default()
+            };
+            let server = assh::Session::new(BufStream::new(duplex.0).compat(), server).await?;
+
+            server
+                .handle(
+                    handler::Auth::new(cookie0.clone())
+                        .password(|_, _, _| handler::password::Response::Accept)
+                        .publickey(|_, _| handler::publickey::Response::Accept),
+                )
+                .await
+        },
+        async {
+            let client = Client::default();
+            let client = assh::Session::new(BufStream::new(duplex.1).compat(), client).await?;
+
+            client
+                .request(
+                    request::Auth::new("user", cookie1.clone())
+                        .agent(request::Agent::new("/nonexistent/agent.sock"))
+                        .password("hunter2"),
+                )
+                .await
+        },
+    )?;
+
+    assert!(cookie0.is_flagged());
+    assert!(cookie1.is_flagged());
+
+    Ok(())
+}
+
+/// Write a freshly generated _Ed25519_ key encrypted with the `passphrase` to a scratch file.
+fn encrypted_key(passphrase: &str) -> (std::path::PathBuf, ssh_key::PublicKey) {
+    let key =
+        ssh_key::PrivateKey::random(&mut rand::thread_rng(), ssh_key::Algorithm::Ed25519).unwrap();
+    let path = std::env::temp_dir().join(format!("assh-auth-key-{:016x}", rand::random::<u64>()));
+
+    key.encrypt(&mut rand::thread_rng(), passphrase)
+        .unwrap()
+        .write_openssh_file(&path, Default::default())
+        .unwrap();
+
+    (path, key.public_key().clone())
+}
+
+async fn keyfile_attempt(
+    path: std::path::PathBuf,
+    passphrases: Vec<&'static str>,
+    algorithms: Vec<ssh_key::Algorithm>,
+    password: bool,
+) -> (Option<handler::Identity>, usize) {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    let duplex = tokio::io::duplex(ssh_packet::PACKET_MAX_SIZE * 16);
+
+    let probe = IdentityProbe::default();
+    let prompts = Arc::new(AtomicUsize::new(0));
+
+    let _ = tokio::try_join!(
+        async {
+            let server = Server {
+                keys: vec![ssh_key::private::PrivateKey::random(
+                    &mut rand::thread_rng(),
+                    ssh_key::Algorithm::Ed25519,
+                )
+                .unwrap()],
+                ..Default::default()
+            };
+            let server = assh::Session::new(BufStream::new(duplex.0).compat(), server).await?;
+
+            let auth = handler::Auth::new(probe.clone())
+                .publickey(|_, _| handler::publickey::Response::Accept)
+                .publickey_algorithms(algorithms);
+            if password {
+                server
+                    .handle(auth.password(|_, _, _| handler::password::Response::Accept))
+                    .await
+            } else {
+                server.handle(auth).await
+            }
+        },
+        async {
+            let client = Client::default();
+            let client = assh::Session::new(BufStream::new(duplex.1).compat(), client).await?;
+
+            let prompts = prompts.clone();
+            client
+                .request(
+                    request::Auth::new("user", cookie::Cookie::default())
+                        .publickey_from_file(path, move || {
+                            let prompt = prompts.fetch_add(1, Ordering::Relaxed);
+
+                            passphrases
+                                .get(prompt)
+                                .map(|passphrase| passphrase.to_string())
+                        })
+                        .password("hunter2"),
+                )
+                .await
+        },
+    );
+
+    let identity = probe.0.lock().unwrap().take();
+
+    (identity, prompts.load(Ordering::Relaxed))
+}
+
+#[tokio::test]
+async fn encrypted_keys_are_decrypted_when_accepted() -> Result<(), Box<dyn std::error::Error>> {
+    let (path, key) = encrypted_key("correct horse");
+
+    // The passphrase is requested again after a wrong one.
+    let (identity, prompts) = keyfile_attempt(
+        path.clone(),
+        vec!["wrong", "correct horse"],
+        vec![ssh_key::Algorithm::Ed25519],
+        false,
+    )
+    .await;
+    assert_eq!(identity.unwrap().public_key, Some(key));
+    assert_eq!(prompts, 2);
+
+    // The passphrase is not requested for a key the server doesn't accept.
+    let (identity, prompts) =
+        keyfile_attempt(path.clone(), vec!["correct horse"], vec![], true).await;
+    assert_eq!(identity.unwrap().methods, ["password"]);
+    assert_eq!(prompts, 0);
+
+    // The key is skipped after three wrong passphrases.
+    let (identity, prompts) = keyfile_attempt(
+        path.clone(),
+        vec!["wrong", "wrong", "wrong", "correct horse"],
+        vec![ssh_key::Algorithm::Ed25519],
+        false,
+    )
+    .await;
+    assert!(identity.is_none());
+    assert_eq!(prompts, 3);
+
+    std::fs::remove_file(path)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn banners_are_handed_to_clients() -> Result<(), Box<dyn std::error::Error>> {
+    use assh::Error;
+    use ssh_packet::{
+        arch::{ascii, NameList},
+        trans::{ServiceAccept, ServiceRequest},
+        userauth,
+    };
+
+    let duplex = tokio::io::duplex(ssh_packet::PACKET_MAX_SIZE * 16);
+
+    let cookie = cookie::Cookie::default();
+    let banners = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+
+    tokio::try_join!(
+        async {
+            let server = Server {
+                keys: vec![ssh_key::private::PrivateKey::random(
+                    &mut rand::thread_rng(),
+                    ssh_key::Algorithm::Ed25519,
+                )
+                .unwrap()],
+                ..Default::default()
+            };
+            let mut server = assh::Session::new(BufStream::new(duplex.0).compat(), server).await?;
+
+            server.recv().await?.to::<ServiceRequest>()?;
+            server
+                .send(&ServiceAccept {
+                    service_name: ascii!("ssh-userauth"),
+                })
+                .await?;
+
+            let banner = |message: &'static str| userauth::Banner {
+                message: message.into(),
+                language: Default::default(),
+            };
+
+            // Banners both before the first response, and between the attempts.
+            server.recv().await?.to::<userauth::Request>()?;
+            server.send(&banner("Welcome")).await?;
+            server.send(&banner("Be nice")).await?;
+            server
+                .send(&userauth::Failure {
+                    continue_with: NameList::from_iter([ascii!("password")]),
+                    partial_success: false.into(),
+                })
+                .await?;
+
+            server.recv().await?.to::<userauth::Request>()?;
+            server.send(&banner("Almost there")).await?;
+            server.send(&userauth::Success).await?;
+
+            Ok::<_, Error>(())
+        },
+        async {
+            let client = Client::default();
+            let client = assh::Session::new(BufStream::new(duplex.1).compat(), client).await?;
+
+            let banners = banners.clone();
+            client
+                .request(
+                    request::Auth::new("user", cookie.clone())
+                        .password("hunter2")
+                        .on_banner(move |message| banners.lock().unwrap().push(message.to_owned())),
+                )
+                .await
+        },
+    )?;
+
+    assert!(cookie.is_flagged());
+    assert_eq!(
+        *banners.lock().unwrap(),
+        ["Welcome", "Be nice", "Almost there"]
+    );
+
+    Ok(())
+}
@@ -0,0 +1,70 @@
+use std::time::{Duration, SystemTime};
+
+use assh_auth::handler::keyboard_interactive::Totp;
+
+const SECRET: &[u8] = b"12345678901234567890";
+
+fn at(seconds: u64) -> SystemTime {
+    SystemTime::UNIX_EPOCH + Duration::from_secs(seconds)
+}
+
+#[test]
+fn codes_match_the_test_vectors() {
+    // The `SHA1` test vectors from RFC6238 appendix B.
+    let totp = Totp::new().user("alice", SECRET).digits(8);
+
+    for (time, code) in [
+        (59, "94287082"),
+        (1111111109, "07081804"),
+        (1111111111, "14050471"),
+        (1234567890, "89005924"),
+        (2000000000, "69279037"),
+        (20000000000, "65353130"),
+    ] {
+        assert_eq!(totp.generate("alice", at(time)).as_deref(), Some(code));
+    }
+}
+
+#[test]
+fn codes_are_verified_within_the_window() {
+    let totp = Totp::new().user("alice", SECRET);
+    let code = totp.generate("alice", at(1_000_000_020)).unwrap();
+
+    assert!(!Totp::new()
+        .user("alice", SECRET)
+        .verify("alice", &code, at(1_000_000_080)));
+    assert!(totp.verify("alice", &code, at(1_000_000_040)));
+
+    assert!(!totp.verify("bob", &code, at(1_000_000_020)));
+    assert!(!totp.verify("alice", "000000", at(1_000_000_020)));
+}
+
+#[test]
+fn codes_are_not_replayed() {
+    let totp = Totp::new().user("alice", SECRET);
+    let shared = totp.clone();
+
+    let first = totp.generate("alice", at(1_000_000_020)).unwrap();
+    let previous = totp.generate("alice", at(1_000_000_000)).unwrap();
+    let next = totp.generate("alice", at(1_000_000_050)).unwrap();
+
+    assert!(totp.verify("alice", &first, at(1_000_000_020)));
+    assert!(!shared.verify("alice", &first, at(1_000_000_025)));
+    assert!(!totp.verify("alice", &previous, at(1_000_000_025)));
+    assert!(totp.verify("alice", &next, at(1_000_000_050)));
+}
+
+#[test]
+fn drift_is_tracked() {
+    let totp = Totp::new().user("alice", SECRET).window(1).drift(2);
+
+    // The device runs a time-step ahead, which is then compensated for.
+    let ahead = totp.generate("alice", at(1_000_000_050)).unwrap();
+    assert!(totp.verify("alice", &ahead, at(1_000_000_020)));
+
+    let further = totp.generate("alice", at(1_000_000_110)).unwrap();
+    assert!(totp.verify("alice", &further, at(1_000_000_050)));
+
+    let untracked = Totp::new().user("alice", SECRET);
+    assert!(!untracked.verify("alice", &further, at(1_000_000_050)));
+}
@@ -74,6 +74,15 @@ impl<'s, IO: Pipe, S: Side> Request<'s, IO, S> {
         Ok(())
     }
 
+    /// Whether the peer expects a response to the channel request.
+    pub fn want_reply(&self) -> bool {
+        *self
+            .inner
+            .as_ref()
+            .expect("Inner value has been dropped before the outer structure")
+            .want_reply
+    }
+
     /// Access the _context_ of the channel request.
     pub fn cx(&self) -> &connect::ChannelRequestContext {
         &self
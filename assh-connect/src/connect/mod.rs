@@ -34,6 +34,12 @@ where
         }
     }
 
+    /// Access the [`assh::Extensions`] attached to the session by the previous services,
+    /// such as the identity of the authenticated user.
+    pub fn extensions(&self) -> &assh::Extensions {
+        &self.mux.extensions
+    }
+
     /// Iterate over the incoming _global requests_.
     pub fn global_requests(
         &self,
@@ -72,6 +72,15 @@ impl<'s, IO: Pipe, S: Side> GlobalRequest<'s, IO, S> {
         Ok(())
     }
 
+    /// Whether the peer expects a response to the global request.
+    pub fn want_reply(&self) -> bool {
+        *self
+            .inner
+            .as_ref()
+            .expect("Inner value has been dropped before the outer structure")
+            .want_reply
+    }
+
     /// Access the _context_ of the global request.
     pub fn cx(&self) -> &connect::GlobalRequestContext {
         &self
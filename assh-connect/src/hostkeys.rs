@@ -0,0 +1,357 @@
+//! The _host key rotation_ extension, announcing all the host keys of the _server_
+//! with the `hostkeys-00@openssh.com` global request, and proving their possession with the
+//! `hostkeys-prove-00@openssh.com` global request, as described in OpenSSH's `PROTOCOL`.
+
+use assh::{
+    side::{
+        client::Client,
+        server::{PrivateKey, Server},
+        Side,
+    },
+    Pipe,
+};
+use futures::FutureExt;
+use signature::{SignatureEncoding, Signer, Verifier};
+use ssh_key::{Algorithm, PublicKey, Signature};
+use ssh_packet::{
+    arch::{ascii, Ascii, Bool, Bytes},
+    binrw::{self, BinWrite},
+    connect, Packet,
+};
+
+use crate::{
+    mux::{Interest, Mux},
+    Connect, Error, Result,
+};
+
+/// The name of the _global request_ announcing the host keys.
+const HOSTKEYS: Ascii<'static> = ascii!("hostkeys-00@openssh.com");
+
+/// The name of the _global request_ proving the possession of the host keys.
+const HOSTKEYS_PROVE: Ascii<'static> = ascii!("hostkeys-prove-00@openssh.com");
+
+/// The callback receiving the host keys announced by the _server_.
+type Callback = Box<dyn Fn(Vec<PublicKey>) + Send + Sync>;
+
+/// The outcome of proving the possession of a host key by the _server_.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Proof {
+    /// The _server_ signed the challenge with this key.
+    Verified(PublicKey),
+
+    /// The _server_ failed or refused to sign the challenge with this key.
+    Failed(PublicKey),
+}
+
+/// The state of the extension, shared with the [`Mux`].
+#[derive(Default)]
+pub(crate) struct State {
+    /// The callback registered by the _client_.
+    callback: Option<Callback>,
+
+    /// The keys announced by the _server_, to answer the proofs.
+    keys: Vec<PrivateKey>,
+}
+
+/// The `hostkeys-00@openssh.com` global request.
+#[binrw::binrw]
+#[derive(Debug)]
+#[brw(big, magic = 80_u8)]
+struct Announcement {
+    #[br(assert(kind == HOSTKEYS))]
+    #[bw(calc = HOSTKEYS)]
+    kind: Ascii<'static>,
+
+    #[br(assert(!*want_reply))]
+    #[bw(calc = false.into())]
+    want_reply: Bool,
+
+    #[br(parse_with = binrw::helpers::until_eof)]
+    blobs: Vec<Bytes<'static>>,
+}
+
+/// The `hostkeys-prove-00@openssh.com` global request.
+#[binrw::binrw]
+#[derive(Debug)]
+#[brw(big, magic = 80_u8)]
+struct ProveRequest {
+    #[br(assert(kind == HOSTKEYS_PROVE))]
+    #[bw(calc = HOSTKEYS_PROVE)]
+    kind: Ascii<'static>,
+
+    #[br(assert(*want_reply))]
+    #[bw(calc = true.into())]
+    want_reply: Bool,
+
+    #[br(parse_with = binrw::helpers::until_eof)]
+    blobs: Vec<Bytes<'static>>,
+}
+
+/// The `SSH_MSG_REQUEST_SUCCESS` message in response to a `hostkeys-prove-00@openssh.com` request,
+/// with a signature for each of the requested keys in the same order.
+#[binrw::binrw]
+#[derive(Debug)]
+#[brw(big, magic = 81_u8)]
+struct ProveResponse {
+    #[br(parse_with = binrw::helpers::until_eof)]
+    signatures: Vec<Bytes<'static>>,
+}
+
+/// The data signed to prove the possession of the `host_key`, bound to the session.
+#[binrw::binwrite]
+#[derive(Debug)]
+#[bw(big)]
+struct Challenge<'b> {
+    #[bw(calc = HOSTKEYS_PROVE)]
+    kind: Ascii<'static>,
+
+    session_id: Bytes<'b>,
+    host_key: Bytes<'b>,
+}
+
+impl Challenge<'_> {
+    fn to_vec(&self) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        self.write(&mut std::io::Cursor::new(&mut buffer))
+            .expect("The binrw structure serialization failed");
+
+        buffer
+    }
+}
+
+impl Announcement {
+    /// Decode the announced keys, skipping the malformed and unknown ones as OpenSSH does.
+    fn keys(&self) -> Vec<PublicKey> {
+        self.blobs
+            .iter()
+            .filter_map(|blob| match PublicKey::from_bytes(blob) {
+                Ok(key) if !matches!(key.algorithm(), Algorithm::Other(_)) => Some(key),
+                Ok(key) => {
+                    tracing::debug!(
+                        "Skipped announced host key of unknown type `{}`",
+                        key.algorithm()
+                    );
+
+                    None
+                }
+                Err(err) => {
+                    tracing::debug!("Skipped malformed announced host key: {err}");
+
+                    None
+                }
+            })
+            .collect()
+    }
+}
+
+/// Handle the `packet` if it is related to the extension, regardless of the registered interests.
+pub(crate) fn intercept<IO: Pipe, S: Side>(mux: &Mux<IO, S>, packet: &Packet) -> bool {
+    if let Ok(announcement) = packet.to::<Announcement>() {
+        let state = mux
+            .hostkeys
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+
+        match &state.callback {
+            Some(callback) => {
+                let keys = announcement.keys();
+                tracing::debug!("Received an announcement of {} host keys", keys.len());
+
+                callback(keys)
+            }
+            None => tracing::debug!("Ignored an unhandled host keys announcement"),
+        }
+
+        true
+    } else if let Ok(request) = packet.to::<ProveRequest>() {
+        let state = mux
+            .hostkeys
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+
+        // The whole request is refused if any of the keys is unknown, as OpenSSH does.
+        let signatures = request
+            .blobs
+            .iter()
+            .map(|blob| {
+                let key = state.keys.iter().find(|key| {
+                    key.public_key()
+                        .to_bytes()
+                        .is_ok_and(|known| known == blob.as_ref())
+                })?;
+                let challenge = Challenge {
+                    session_id: mux.session_id.as_slice().into(),
+                    host_key: blob.as_borrow(),
+                };
+
+                let signature: Signature = key.try_sign(&challenge.to_vec()).ok()?;
+                Some(signature.to_vec().into())
+            })
+            .collect::<Option<Vec<_>>>();
+
+        tracing::debug!(
+            "Received a request to prove {} host keys, {}",
+            request.blobs.len(),
+            if signatures.is_some() {
+                "answered"
+            } else {
+                "refused"
+            },
+        );
+
+        match signatures {
+            Some(signatures) => mux.feed(&ProveResponse { signatures }),
+            None => mux.feed(&connect::RequestFailure),
+        }
+
+        true
+    } else {
+        false
+    }
+}
+
+impl<IO: Pipe> Connect<IO, Server> {
+    /// Announce all the host `keys` of the _server_ to the _client_, usually the ones configured
+    /// in the [`Server::keys`], and keep them to answer the proofs requested by the _client_.
+    pub async fn announce_host_keys(&self, keys: &[PrivateKey]) -> Result<()> {
+        let blobs = keys
+            .iter()
+            .map(|key| Ok(key.public_key().to_bytes()?.into()))
+            .collect::<Result<_, assh::Error>>()?;
+
+        self.mux
+            .hostkeys
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .keys = keys.to_vec();
+        self.mux.send(&Announcement { blobs }).await?;
+
+        Ok(())
+    }
+}
+
+impl<IO: Pipe> Connect<IO, Client> {
+    /// Register the `callback` receiving the host keys announced by the _server_,
+    /// to let the application learn them ahead of a rotation.
+    ///
+    /// The announcements are handled while polling the other facilities of the [`Connect`],
+    /// and are ignored if no callback is registered.
+    pub fn on_host_keys(&self, callback: impl Fn(Vec<PublicKey>) + Send + Sync + 'static) {
+        self.mux
+            .hostkeys
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .callback = Some(Box::new(callback));
+    }
+
+    /// Request the _server_ to prove the possession of the announced `keys`,
+    /// by signing a challenge bound to the session with each of them.
+    ///
+    /// Each of the keys is reported as a [`Proof`], in the same order,
+    /// and all of them fail if the _server_ refuses the request.
+    pub async fn prove_host_keys(&self, keys: &[PublicKey]) -> Result<Vec<Proof>> {
+        let interest = Interest::GlobalResponse;
+        let _unregister_on_drop = self.mux.register_scoped(interest);
+
+        let blobs = keys
+            .iter()
+            .map(|key| Ok(key.to_bytes()?.into()))
+            .collect::<Result<Vec<Bytes>, assh::Error>>()?;
+
+        self.mux
+            .send(&ProveRequest {
+                blobs: blobs.clone(),
+            })
+            .await?;
+
+        #[binrw::binrw]
+        #[br(little)]
+        enum Response {
+            Success(ProveResponse),
+            Failure(connect::RequestFailure),
+        }
+
+        let signatures =
+            futures::future::poll_fn(|cx| self.mux.poll_interest::<Response>(cx, &interest))
+                .map(|polled| match polled.transpose()? {
+                    Some(Response::Success(message)) => Ok(message.signatures),
+                    Some(Response::Failure(_)) => Ok(Vec::new()),
+                    _ => Err(Error::SessionClosed),
+                })
+                .await?;
+
+        Ok(keys
+            .iter()
+            .zip(&blobs)
+            .enumerate()
+            .map(|(index, (key, blob))| {
+                let challenge = Challenge {
+                    session_id: self.mux.session_id.as_slice().into(),
+                    host_key: blob.as_borrow(),
+                };
+
+                let verified = signatures
+                    .get(index)
+                    .and_then(|signature| Signature::try_from(signature.as_ref()).ok())
+                    .is_some_and(|signature| {
+                        Verifier::verify(key, &challenge.to_vec(), &signature).is_ok()
+                    });
+
+                if verified {
+                    Proof::Verified(key.clone())
+                } else {
+                    tracing::warn!(
+                        "The server failed to prove the possession of the host key `{}`",
+                        key.fingerprint(Default::default())
+                    );
+
+                    Proof::Failed(key.clone())
+                }
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ssh_packet::IntoPacket;
+
+    use super::*;
+
+    fn string(data: &[u8]) -> Vec<u8> {
+        [&(data.len() as u32).to_be_bytes()[..], data].concat()
+    }
+
+    #[test]
+    fn unknown_and_malformed_keys_are_skipped() -> Result<(), Box<dyn std::error::Error>> {
+        let key = PrivateKey::random(&mut rand::thread_rng(), Algorithm::Ed25519)?;
+        let unknown = [string(b"unknown@example.com"), string(b"opaque")].concat();
+
+        let announcement = (&Announcement {
+            blobs: vec![
+                key.public_key().to_bytes()?.into(),
+                unknown.into(),
+                b"garbage".to_vec().into(),
+            ],
+        })
+            .into_packet()
+            .to::<Announcement>()?;
+
+        assert_eq!(announcement.keys(), vec![key.public_key().clone()]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn prove_request_requires_a_reply() {
+        let request = [
+            &[80][..],
+            &string(HOSTKEYS_PROVE.as_bytes()),
+            &[0],
+            &string(b"blob"),
+        ]
+        .concat();
+
+        assert!(Packet { payload: request }.to::<ProveRequest>().is_err());
+    }
+}
@@ -25,9 +25,12 @@ mod mux;
 pub mod channel;
 pub mod channel_open;
 pub mod global_request;
+pub mod hostkeys;
 
 mod connect;
 pub use connect::{Connect, Service};
 
+mod relay;
+
 mod error;
 pub use error::{Error, Result};
@@ -1,7 +1,12 @@
-use assh::{side::Side, Pipe, Session};
+use assh::{side::Side, Extensions, Pipe, Session};
 use dashmap::DashMap;
 use futures::{lock::Mutex, task, FutureExt};
-use ssh_packet::{binrw, connect, IntoPacket, Packet};
+use ssh_packet::{
+    binrw::{self, meta::ReadMagic},
+    connect, IntoPacket, Packet,
+};
+
+use crate::hostkeys;
 
 mod interest;
 pub use interest::Interest;
@@ -18,7 +23,11 @@ pub struct Mux<IO: Pipe, S: Side> {
     queue: flume::Sender<Packet>,
     poller: Mutex<Poller<IO, S>>,
     interests: DashMap<Interest, task::AtomicWaker>,
+    unimplemented: DashMap<Interest, assh::Error>,
     pub(crate) channels: Slots<u32, CHANNEL_MAX_COUNT>,
+    pub(crate) hostkeys: std::sync::Mutex<hostkeys::State>,
+    pub(crate) session_id: Vec<u8>,
+    pub(crate) extensions: Extensions,
 }
 
 impl<IO, S> From<Session<IO, S>> for Mux<IO, S>
@@ -26,14 +35,20 @@ where
     IO: Pipe,
     S: Side,
 {
-    fn from(session: Session<IO, S>) -> Self {
+    fn from(mut session: Session<IO, S>) -> Self {
+        let session_id = session.session_id().unwrap_or_default().to_vec();
+        let extensions = std::mem::take(session.extensions_mut());
         let (poller, queue) = Poller::new(session);
 
         Self {
             queue,
             poller: poller.into(),
             interests: Default::default(),
+            unimplemented: Default::default(),
             channels: Default::default(),
+            hostkeys: Default::default(),
+            session_id,
+            extensions,
         }
     }
 }
@@ -68,6 +83,8 @@ where
         if let Some((interest, waker)) = self.interests.remove(interest) {
             tracing::trace!("Unregistered interest for `{interest:?}`");
 
+            self.unimplemented.remove(&interest);
+
             // Wake unregistered tasks to signal them to finish.
             waker.wake();
         }
@@ -109,8 +126,40 @@ where
             return task::Poll::Ready(None);
         }
 
+        if let Some((_, err)) = self.unimplemented.remove(interest) {
+            return task::Poll::Ready(Some(Err(err)));
+        }
+
         let mut poller = futures::ready!(self.poller.lock().poll_unpin(cx));
-        let buffer = futures::ready!(poller.poll_peek(cx))?;
+        let buffer = match futures::ready!(poller.poll_peek(cx)) {
+            Err(
+                err @ assh::Error::Unimplemented {
+                    message, channel, ..
+                },
+            ) => {
+                // Fail the task awaiting the response to the message the peer does not implement.
+                match self.awaiting(message, channel) {
+                    Some(awaiting) if &awaiting == interest => {
+                        return task::Poll::Ready(Some(Err(err)));
+                    }
+                    Some(awaiting) if self.interests.contains_key(&awaiting) => {
+                        tracing::trace!(
+                            "{interest:?} != {awaiting:?}: Storing error and waking task"
+                        );
+
+                        self.unimplemented.insert(awaiting, err);
+                        if let Some(waker) = self.interests.get(&awaiting).as_deref() {
+                            waker.wake();
+                        }
+                    }
+                    _ => tracing::debug!("{err}, while no task awaits its response"),
+                }
+
+                cx.waker().wake_by_ref();
+                return task::Poll::Pending;
+            }
+            buffer => buffer?,
+        };
 
         match buffer.take() {
             None => {
@@ -124,6 +173,12 @@ where
                 task::Poll::Ready(None)
             }
             Some(packet) => {
+                // Host keys related requests are consumed regardless of the registered interests.
+                if hostkeys::intercept(self, &packet) {
+                    cx.waker().wake_by_ref();
+                    return task::Poll::Pending;
+                }
+
                 let Some(packet_interest) = Interest::parse(&packet) else {
                     return task::Poll::Ready(Some(Err(assh::Error::UnexpectedMessage)));
                 };
@@ -193,6 +248,21 @@ where
         }
     }
 
+    /// The interest awaiting the response to the `message`, referring to the `channel` if any.
+    fn awaiting(&self, message: u8, channel: Option<u32>) -> Option<Interest> {
+        if message == connect::GlobalRequest::MAGIC {
+            Some(Interest::GlobalResponse)
+        } else if message == connect::ChannelOpen::MAGIC {
+            channel.map(Interest::ChannelOpenResponse)
+        } else if message == connect::ChannelRequest::MAGIC {
+            channel
+                .and_then(|remote| self.channels.position(&remote))
+                .map(|local| Interest::ChannelResponse(local as u32))
+        } else {
+            None
+        }
+    }
+
     pub fn feed(&self, item: impl IntoPacket) {
         self.queue.send(item.into_packet()).ok();
     }
@@ -50,6 +50,20 @@ impl<T, const N: usize> Slots<T, N> {
             .and_then(Weak::upgrade)
             .map(|pointer| Lease { index, pointer })
     }
+
+    pub fn position(&self, value: &T) -> Option<usize>
+    where
+        T: PartialEq,
+    {
+        self.inner
+            .read()
+            .expect("This `Slots`'s lock has been poisonned")
+            .iter()
+            .position(|slot| {
+                slot.upgrade()
+                    .is_some_and(|pointer| pointer.as_ref().as_ref() == Some(value))
+            })
+    }
 }
 
 pub struct Reservation<'s, T, const N: usize> {
@@ -197,6 +211,18 @@ mod tests {
         )
     }
 
+    #[test]
+    fn lease_position_matches() {
+        let slots = Slots::<usize, 4>::new();
+
+        let _one = slots.insert(1);
+        let _two = slots.reserve();
+        let three = slots.insert(3);
+
+        assert_eq!(slots.position(&3), three.map(|lease| lease.index()));
+        assert_eq!(slots.position(&2), None);
+    }
+
     #[test]
     fn out_of_bound_lease() {
         let slots = Slots::<(), 4>::new();
@@ -0,0 +1,181 @@
+//! Relaying of the _connect_ layer between two sessions, such as for a bastion.
+
+use std::num::NonZeroU32;
+
+use assh::{side::Side, Pipe};
+use futures::{AsyncWriteExt, FutureExt, TryStreamExt};
+
+use crate::{
+    channel::{request, Channel},
+    channel_open::{self, ChannelOpen, ChannelOpenFailureReason},
+    global_request::{self, GlobalRequest},
+    Connect, Error, Result,
+};
+
+/// The _extended data_ stream of the standard error, as per RFC4254 §5.2.
+const STDERR: NonZeroU32 = NonZeroU32::MIN;
+
+impl<IO, S> Connect<IO, S>
+where
+    IO: Pipe,
+    S: Side,
+{
+    /// Relay the _global requests_ and _channels_ opened by the peer of this session to the
+    /// `other` session and conversely, until either session ends, such as for a bastion
+    /// terminating the session of a _client_ and opening another one to the actual _server_.
+    ///
+    /// The channels are relayed with their _data_, _standard error_, _end-of-file_ and _requests_,
+    /// each side receiving the data only as fast as the other side consumes it, and are closed
+    /// on both sides as soon as either peer closes them. The responses to the requests are relayed
+    /// back to the requesting peer, but the _host key rotation_ requests are specific to each
+    /// session and not relayed.
+    pub async fn relay<OIO, OS>(&self, other: &Connect<OIO, OS>) -> Result<()>
+    where
+        OIO: Pipe,
+        OS: Side,
+    {
+        futures::select! {
+            result = forward(self, other).fuse() => result,
+            result = forward(other, self).fuse() => result,
+        }
+    }
+}
+
+/// Relay the _global requests_ and _channels_ opened by the peer of `from` to `to`, until `from` ends.
+async fn forward<FIO, FS, TIO, TS>(from: &Connect<FIO, FS>, to: &Connect<TIO, TS>) -> Result<()>
+where
+    FIO: Pipe,
+    FS: Side,
+    TIO: Pipe,
+    TS: Side,
+{
+    futures::try_join!(
+        from.global_requests()
+            .try_for_each(|request| forward_global_request(request, to)),
+        from.channel_opens()
+            .try_for_each_concurrent(None, |open| forward_channel(open, to)),
+    )?;
+
+    Ok(())
+}
+
+async fn forward_global_request<FIO, FS, TIO, TS>(
+    request: GlobalRequest<'_, FIO, FS>,
+    to: &Connect<TIO, TS>,
+) -> Result<()>
+where
+    FIO: Pipe,
+    FS: Side,
+    TIO: Pipe,
+    TS: Side,
+{
+    if !request.want_reply() {
+        to.global_request(request.cx().clone()).await?;
+
+        return request.accept(0).await;
+    }
+
+    match to.global_request_wait(request.cx().clone()).await? {
+        global_request::Response::Success(bound_port) => {
+            request.accept(bound_port.unwrap_or_default()).await
+        }
+        global_request::Response::Failure => request.reject().await,
+    }
+}
+
+async fn forward_channel<FIO, FS, TIO, TS>(
+    open: ChannelOpen<'_, FIO, FS>,
+    to: &Connect<TIO, TS>,
+) -> Result<()>
+where
+    FIO: Pipe,
+    FS: Side,
+    TIO: Pipe,
+    TS: Side,
+{
+    let response = match to.channel_open(open.cx().clone()).await {
+        Err(Error::TooManyChannels) => {
+            return open
+                .reject(
+                    ChannelOpenFailureReason::ResourceShortage,
+                    "Too many channels opened on the relayed session",
+                )
+                .await
+        }
+        response => response?,
+    };
+
+    match response {
+        channel_open::Response::Success(outbound) => {
+            let inbound = open.accept().await?;
+
+            // The first peer to close it's channel ends the relay, closing both channels on drop.
+            futures::select! {
+                result = pipe(&inbound, &outbound).fuse() => result,
+                result = pipe(&outbound, &inbound).fuse() => result,
+            }
+        }
+        channel_open::Response::Failure {
+            reason,
+            description,
+        } => open.reject(reason, description).await,
+    }
+}
+
+/// Relay the data and requests received on `from` to `to`, until the peer closes `from`.
+async fn pipe<FIO, FS, TIO, TS>(
+    from: &Channel<'_, FIO, FS>,
+    to: &Channel<'_, TIO, TS>,
+) -> Result<()>
+where
+    FIO: Pipe,
+    FS: Side,
+    TIO: Pipe,
+    TS: Side,
+{
+    // The readers are made right away, since the data received without a reader is dropped.
+    let (mut data, mut stderr) = (from.as_reader(), from.as_reader_ext(STDERR));
+    let (mut data_out, mut stderr_out) = (to.as_writer(), to.as_writer_ext(STDERR));
+
+    let streams = async {
+        futures::try_join!(
+            futures::io::copy(&mut data, &mut data_out),
+            futures::io::copy(&mut stderr, &mut stderr_out),
+        )
+        .map_err(assh::Error::from)?;
+
+        data_out.flush().await.map_err(assh::Error::from)?;
+        stderr_out.flush().await.map_err(assh::Error::from)?;
+
+        to.eof().await
+    };
+    let requests = from
+        .requests()
+        .try_for_each(|request| forward_channel_request(request, to));
+
+    futures::try_join!(streams, requests)?;
+
+    Ok(())
+}
+
+async fn forward_channel_request<FIO, FS, TIO, TS>(
+    request: request::Request<'_, FIO, FS>,
+    to: &Channel<'_, TIO, TS>,
+) -> Result<()>
+where
+    FIO: Pipe,
+    FS: Side,
+    TIO: Pipe,
+    TS: Side,
+{
+    if !request.want_reply() {
+        to.request(request.cx().clone()).await?;
+
+        return request.accept().await;
+    }
+
+    match to.request_wait(request.cx().clone()).await? {
+        request::Response::Success => request.accept().await,
+        request::Response::Failure => request.reject().await,
+    }
+}
@@ -0,0 +1,160 @@
+use std::sync::{Arc, Mutex};
+
+use assh::{
+    algorithm::Key,
+    side::{
+        client::Client,
+        server::{PrivateKey, Server},
+    },
+};
+use assh_connect::{
+    channel_open::{self, ChannelOpenContext},
+    hostkeys::Proof,
+};
+
+use async_compat::CompatExt;
+use futures::TryStreamExt;
+use tokio::io::BufStream;
+
+#[tokio::test]
+async fn announced_host_keys_are_received() -> Result<(), eyre::Error> {
+    let duplex = tokio::io::duplex(ssh_packet::PACKET_MAX_SIZE * 16);
+    let keys = vec![
+        PrivateKey::random(&mut rand::thread_rng(), Key::Ed25519)?,
+        PrivateKey::random(
+            &mut rand::thread_rng(),
+            Key::Ecdsa {
+                curve: ssh_key::EcdsaCurve::NistP256,
+            },
+        )?,
+    ];
+    let expected = keys
+        .iter()
+        .map(|key| key.public_key().clone())
+        .collect::<Vec<_>>();
+
+    let announced = Arc::new(Mutex::new(None));
+
+    tokio::try_join!(
+        async {
+            let server = Server {
+                keys: keys.clone(),
+                ..Default::default()
+            };
+            let server = assh::Session::new(BufStream::new(duplex.0).compat(), server).await?;
+
+            let connect = server.handle(assh_connect::Service).await?;
+            connect.announce_host_keys(&keys).await?;
+
+            // Wait for the client to open a channel, after it received the announcement.
+            connect
+                .channel_opens()
+                .try_next()
+                .await?
+                .expect("Disconnected before opening at least one channel")
+                .accept()
+                .await?;
+
+            Ok::<_, eyre::Error>(())
+        },
+        async {
+            let client =
+                assh::Session::new(BufStream::new(duplex.1).compat(), Client::default()).await?;
+
+            let connect = client.request(assh_connect::Service).await?;
+            let sink = announced.clone();
+            connect.on_host_keys(move |keys| {
+                *sink.lock().unwrap() = Some(keys);
+            });
+
+            let channel_open::Response::Success(_) =
+                connect.channel_open(ChannelOpenContext::Session).await?
+            else {
+                panic!("Channel opening rejected server-side")
+            };
+
+            Ok(())
+        },
+    )?;
+
+    assert_eq!(announced.lock().unwrap().take(), Some(expected));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn announced_host_keys_are_proven() -> Result<(), eyre::Error> {
+    let duplex = tokio::io::duplex(ssh_packet::PACKET_MAX_SIZE * 16);
+    let keys = vec![
+        PrivateKey::random(&mut rand::thread_rng(), Key::Ed25519)?,
+        PrivateKey::random(
+            &mut rand::thread_rng(),
+            Key::Ecdsa {
+                curve: ssh_key::EcdsaCurve::NistP384,
+            },
+        )?,
+    ];
+    let announced = keys
+        .iter()
+        .map(|key| key.public_key().clone())
+        .collect::<Vec<_>>();
+    let foreign = PrivateKey::random(&mut rand::thread_rng(), Key::Ed25519)?
+        .public_key()
+        .clone();
+
+    tokio::try_join!(
+        async {
+            let server = Server {
+                keys: keys.clone(),
+                ..Default::default()
+            };
+            let server = assh::Session::new(BufStream::new(duplex.0).compat(), server).await?;
+
+            let connect = server.handle(assh_connect::Service).await?;
+            connect.announce_host_keys(&keys).await?;
+
+            // The proofs are answered while waiting for the client to open a channel.
+            connect
+                .channel_opens()
+                .try_next()
+                .await?
+                .expect("Disconnected before opening at least one channel")
+                .accept()
+                .await?;
+
+            Ok::<_, eyre::Error>(())
+        },
+        async {
+            let client =
+                assh::Session::new(BufStream::new(duplex.1).compat(), Client::default()).await?;
+            let connect = client.request(assh_connect::Service).await?;
+
+            assert_eq!(
+                connect.prove_host_keys(&announced).await?,
+                announced
+                    .iter()
+                    .cloned()
+                    .map(Proof::Verified)
+                    .collect::<Vec<_>>()
+            );
+
+            // The request is refused as a whole when a key is unknown to the server.
+            assert_eq!(
+                connect
+                    .prove_host_keys(&[announced[0].clone(), foreign.clone()])
+                    .await?,
+                vec![Proof::Failed(announced[0].clone()), Proof::Failed(foreign)]
+            );
+
+            let channel_open::Response::Success(_) =
+                connect.channel_open(ChannelOpenContext::Session).await?
+            else {
+                panic!("Channel opening rejected server-side")
+            };
+
+            Ok(())
+        },
+    )?;
+
+    Ok(())
+}
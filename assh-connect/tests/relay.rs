@@ -0,0 +1,146 @@
+use std::num::NonZeroU32;
+
+use assh::{
+    algorithm::Key,
+    side::{
+        client::Client,
+        server::{PrivateKey, Server},
+    },
+};
+use assh_connect::{
+    channel::request::{self, ChannelRequestContext},
+    channel_open::{self, ChannelOpenContext},
+    global_request::{self, GlobalRequestContext},
+};
+
+use async_compat::{Compat, CompatExt};
+use futures::{AsyncReadExt, AsyncWriteExt, TryStreamExt};
+use tokio::io::{BufStream, DuplexStream};
+
+type IO = Compat<BufStream<DuplexStream>>;
+
+const STDERR: NonZeroU32 = NonZeroU32::MIN;
+
+fn server() -> Result<Server, eyre::Error> {
+    Ok(Server {
+        keys: vec![PrivateKey::random(&mut rand::thread_rng(), Key::Ed25519)?],
+        ..Default::default()
+    })
+}
+
+fn io(stream: DuplexStream) -> IO {
+    BufStream::new(stream).compat()
+}
+
+#[tokio::test]
+async fn channels_and_requests_are_relayed() -> Result<(), eyre::Error> {
+    tracing_subscriber::fmt()
+        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
+        .try_init()
+        .ok();
+
+    let front = tokio::io::duplex(ssh_packet::PACKET_MAX_SIZE * 16);
+    let back = tokio::io::duplex(ssh_packet::PACKET_MAX_SIZE * 16);
+
+    tokio::try_join!(
+        async {
+            let server = assh::Session::new(io(back.0), server()?).await?;
+            let connect = server.handle(assh_connect::Service).await?;
+
+            let mut global_requests = connect.global_requests();
+            let request = global_requests
+                .try_next()
+                .await?
+                .expect("Disconnected before sending a global request");
+            assert!(request.want_reply());
+            request.reject().await?;
+
+            let channel = connect
+                .channel_opens()
+                .try_next()
+                .await?
+                .expect("Disconnected before opening at least one channel")
+                .accept()
+                .await?;
+
+            let request = channel
+                .requests()
+                .try_next()
+                .await?
+                .expect("Channel closed before sending a request");
+            assert!(
+                matches!(request.cx(), ChannelRequestContext::Exec { command } if command.as_ref() == b"echo")
+            );
+            request.accept().await?;
+
+            let mut stderr = channel.as_writer_ext(STDERR);
+            stderr.write_all(b"echoing").await?;
+            stderr.flush().await?;
+
+            futures::io::copy(&mut channel.as_reader(), &mut channel.as_writer()).await?;
+            channel.eof().await?;
+
+            Ok::<_, eyre::Error>(())
+        },
+        async {
+            let bastion = assh::Session::new(io(front.0), server()?).await?;
+            let bastion = bastion.handle(assh_connect::Service).await?;
+
+            let upstream = assh::Session::new(io(back.1), Client::default()).await?;
+            let upstream = upstream.request(assh_connect::Service).await?;
+
+            bastion.relay(&upstream).await?;
+
+            Ok(())
+        },
+        async {
+            let client = assh::Session::new(io(front.1), Client::default()).await?;
+            let connect = client.request(assh_connect::Service).await?;
+
+            assert!(matches!(
+                connect
+                    .global_request_wait(GlobalRequestContext::TcpipForward {
+                        bind_address: b"localhost".to_vec().into(),
+                        bind_port: 0,
+                    })
+                    .await?,
+                global_request::Response::Failure
+            ));
+
+            let channel_open::Response::Success(channel) =
+                connect.channel_open(ChannelOpenContext::Session).await?
+            else {
+                panic!("Channel opening rejected through the relay")
+            };
+            let (mut stdout, mut stderr) = (channel.as_reader(), channel.as_reader_ext(STDERR));
+
+            assert_eq!(
+                channel
+                    .request_wait(ChannelRequestContext::Exec {
+                        command: b"echo".to_vec().into(),
+                    })
+                    .await?,
+                request::Response::Success
+            );
+
+            let sent = rand::random::<[u8; 8192]>();
+            let mut writer = channel.as_writer();
+            writer.write_all(&sent).await?;
+            writer.flush().await?;
+            channel.eof().await?;
+
+            let (mut received, mut errors) = (Vec::new(), Vec::new());
+            futures::try_join!(
+                stdout.read_to_end(&mut received),
+                stderr.read_to_end(&mut errors)
+            )?;
+
+            assert_eq!(received, sent);
+            assert_eq!(errors, b"echoing");
+
+            Ok(())
+        },
+    )?;
+
+    Ok(())
+}
@@ -0,0 +1,84 @@
+use async_std::{
+    net::{TcpListener, TcpStream},
+    task,
+};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use futures::io::BufReader;
+
+use assh::{
+    side::{
+        client::{Algorithms, Client},
+        server::Server,
+    },
+    Result, Session,
+};
+use ssh_packet::trans::Ignore;
+
+/// Size of the payload sent for each iteration.
+const SIZE: usize = 32 * 1024;
+
+/// Connect a _client_ session to a _server_ session discarding all the received packets.
+async fn connect(cipher: &str, mac: &str) -> Result<Session<BufReader<TcpStream>, Client>> {
+    let socket = TcpListener::bind(("127.0.0.1", 0)).await?;
+    let addr = socket.local_addr()?;
+
+    task::spawn(async move {
+        let (stream, _) = socket.accept().await?;
+
+        let server = Server {
+            keys: vec![ssh_key::PrivateKey::random(
+                &mut rand::thread_rng(),
+                ssh_key::Algorithm::Ed25519,
+            )
+            .expect("Unable to generate a server key")],
+            ..Default::default()
+        };
+
+        // The _ignore_ messages are consumed by the session, so this never yields a packet.
+        Session::new(BufReader::new(stream), server)
+            .await?
+            .recv()
+            .await
+    });
+
+    Session::new(
+        BufReader::new(TcpStream::connect(addr).await?),
+        Client {
+            algorithms: Algorithms {
+                ciphers: vec![cipher.parse().expect("Unknown cipher")],
+                macs: vec![mac.parse().expect("Unknown mac")],
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    )
+    .await
+}
+
+fn throughput(c: &mut Criterion) {
+    let mut group = c.benchmark_group("throughput");
+    group.throughput(Throughput::Bytes(SIZE as u64));
+
+    let message = Ignore {
+        data: vec![0x42; SIZE].into(),
+    };
+
+    for (cipher, mac) in [
+        ("aes256-ctr", "hmac-sha2-256"),
+        ("aes256-ctr", "hmac-sha2-256-etm@openssh.com"),
+        ("aes256-gcm@openssh.com", "hmac-sha2-256"),
+        ("chacha20-poly1305@openssh.com", "hmac-sha2-256"),
+    ] {
+        let mut session = task::block_on(connect(cipher, mac)).expect("Unable to connect");
+
+        group.bench_function(
+            BenchmarkId::from_parameter(format!("{cipher}+{mac}")),
+            |b| b.iter(|| task::block_on(session.send(&message)).expect("Unable to send")),
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, throughput);
+criterion_main!(benches);
@@ -0,0 +1,258 @@
+//! Keys held by a local `ssh-agent`, signing over it's unix socket,
+//! as described in `draft-miller-ssh-agent`.
+//!
+//! The keys sign the key-exchanges as host keys of the _server_,
+//! or the authentication requests of the _client_.
+
+use std::{
+    io::{Read, Write},
+    os::unix::net::UnixStream,
+    path::PathBuf,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use ssh_encoding::{Decode, Encode};
+use ssh_key::{HashAlg, PublicKey, Signature};
+
+use crate::{
+    algorithm::{HostKeySigner, Key, SignFuture},
+    Error, Result,
+};
+
+const SSH_AGENT_FAILURE: u8 = 5;
+const SSH_AGENTC_REQUEST_IDENTITIES: u8 = 11;
+const SSH_AGENT_IDENTITIES_ANSWER: u8 = 12;
+const SSH_AGENTC_SIGN_REQUEST: u8 = 13;
+const SSH_AGENT_SIGN_RESPONSE: u8 = 14;
+
+const SSH_AGENT_RSA_SHA2_256: u32 = 2;
+const SSH_AGENT_RSA_SHA2_512: u32 = 4;
+
+/// The maximum length of a response from the agent, as OpenSSH does.
+const MAX_MESSAGE_SIZE: usize = 256 * 1024;
+
+/// The connection to the agent, shared by all it's keys and only re-dialed after a failure.
+#[derive(Debug)]
+struct Connection {
+    path: PathBuf,
+    stream: Mutex<Option<UnixStream>>,
+}
+
+impl Connection {
+    /// Send the `message` to the agent and wait for it's response, blocking the current thread.
+    fn request(&self, message: &[u8]) -> Result<Vec<u8>> {
+        let mut stream = self.stream.lock().unwrap_or_else(PoisonError::into_inner);
+
+        let response = (|| {
+            let stream = match &mut *stream {
+                Some(stream) => stream,
+                none => none.insert(UnixStream::connect(&self.path)?),
+            };
+
+            stream.write_all(&(message.len() as u32).to_be_bytes())?;
+            stream.write_all(message)?;
+
+            let mut length = [0; 4];
+            stream.read_exact(&mut length)?;
+
+            let length = u32::from_be_bytes(length) as usize;
+            if length > MAX_MESSAGE_SIZE {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    "the response exceeds the maximum message size",
+                ));
+            }
+
+            let mut response = vec![0; length];
+            stream.read_exact(&mut response)?;
+
+            Ok(response)
+        })();
+
+        // Drop the connection after a failure, to dial the agent again on the next request.
+        if response.is_err() {
+            *stream = None;
+        }
+
+        response.map_err(|source| Error::Agent {
+            path: self.path.clone(),
+            source,
+        })
+    }
+
+    /// Send the `message` to the agent from a thread-pool, without blocking the executor.
+    async fn send(self: &Arc<Self>, message: Vec<u8>) -> Result<Vec<u8>> {
+        let connection = self.clone();
+
+        blocking::unblock(move || connection.request(&message)).await
+    }
+}
+
+/// A local `ssh-agent`, holding the host keys of the _server_ or the user keys of the _client_.
+///
+/// The agent is dialed on the first request, and the connection is then kept
+/// and reused across the signatures of all the sessions using it's keys.
+#[derive(Debug, Clone)]
+pub struct Agent {
+    connection: Arc<Connection>,
+}
+
+impl Agent {
+    /// Use the agent listening on the unix socket at `path`.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            connection: Arc::new(Connection {
+                path: path.into(),
+                stream: Default::default(),
+            }),
+        }
+    }
+
+    /// Use the agent listening on the unix socket from the `SSH_AUTH_SOCK` environment variable.
+    pub fn from_env() -> Result<Self> {
+        std::env::var_os("SSH_AUTH_SOCK")
+            .map(Self::new)
+            .ok_or(Error::Config(
+                "The `SSH_AUTH_SOCK` environment variable is not set",
+            ))
+    }
+
+    /// List the keys held by the agent, to be registered with [`crate::side::server::Server::host_key_signer`].
+    ///
+    /// The certificates and the keys of unsupported types are skipped.
+    pub async fn identities(&self) -> Result<Vec<AgentKey>> {
+        let response = self
+            .connection
+            .send(vec![SSH_AGENTC_REQUEST_IDENTITIES])
+            .await?;
+
+        let mut reader = response.as_slice();
+        let identities = (|| {
+            if u8::decode(&mut reader)? != SSH_AGENT_IDENTITIES_ANSWER {
+                return Ok(None);
+            }
+
+            (0..u32::decode(&mut reader)?)
+                .map(|_| {
+                    Ok((
+                        Vec::<u8>::decode(&mut reader)?,
+                        String::decode(&mut reader)?,
+                    ))
+                })
+                .collect::<ssh_encoding::Result<Vec<_>>>()
+                .map(Some)
+        })()
+        .ok()
+        .flatten()
+        .ok_or(Error::AgentFailure)?;
+
+        Ok(identities
+            .into_iter()
+            .filter_map(|(blob, comment)| match PublicKey::from_bytes(&blob) {
+                Ok(mut key) => {
+                    key.set_comment(comment);
+
+                    Some(AgentKey::new(self.connection.clone(), key))
+                }
+                Err(err) => {
+                    tracing::debug!("Skipped the agent identity `{comment}`: {err}");
+
+                    None
+                }
+            })
+            .collect())
+    }
+}
+
+/// A key held by an [`Agent`], signing through it.
+#[derive(Debug, Clone)]
+pub struct AgentKey {
+    connection: Arc<Connection>,
+    key: PublicKey,
+    algorithm: Key,
+}
+
+impl AgentKey {
+    fn new(connection: Arc<Connection>, key: PublicKey) -> Self {
+        let algorithm = match key.algorithm() {
+            Key::Rsa { .. } => Key::Rsa {
+                hash: Some(HashAlg::Sha512),
+            },
+            algorithm => algorithm,
+        };
+
+        Self {
+            connection,
+            key,
+            algorithm,
+        }
+    }
+
+    /// Set the hash of the signatures produced by an _RSA_ key, defaults to `rsa-sha2-512`,
+    /// this has no effect on the other types of keys.
+    pub fn rsa_hash(mut self, hash: HashAlg) -> Self {
+        if let Key::Rsa { .. } = self.algorithm {
+            self.algorithm = Key::Rsa { hash: Some(hash) };
+        }
+
+        self
+    }
+
+    /// The flags of the signature request, selecting the hash for _RSA_ keys.
+    fn flags(&self) -> u32 {
+        match self.algorithm {
+            Key::Rsa {
+                hash: Some(HashAlg::Sha256),
+            } => SSH_AGENT_RSA_SHA2_256,
+            Key::Rsa {
+                hash: Some(HashAlg::Sha512),
+            } => SSH_AGENT_RSA_SHA2_512,
+            _ => 0,
+        }
+    }
+}
+
+impl HostKeySigner for AgentKey {
+    fn public_key(&self) -> &PublicKey {
+        &self.key
+    }
+
+    fn algorithm(&self) -> Key {
+        self.algorithm.clone()
+    }
+
+    fn sign<'a>(&'a self, data: &'a [u8]) -> SignFuture<'a> {
+        Box::pin(async move {
+            let mut message = vec![SSH_AGENTC_SIGN_REQUEST];
+            self.key.to_bytes()?.encode(&mut message)?;
+            data.encode(&mut message)?;
+            self.flags().encode(&mut message)?;
+
+            let response = self.connection.send(message).await?;
+
+            let mut reader = response.as_slice();
+            let signature = match u8::decode(&mut reader) {
+                Ok(SSH_AGENT_SIGN_RESPONSE) => Vec::<u8>::decode(&mut reader)
+                    .ok()
+                    .and_then(|signature| Signature::try_from(signature.as_slice()).ok()),
+                Ok(SSH_AGENT_FAILURE) => {
+                    tracing::warn!(
+                        "The agent refused to sign with the key `{}`",
+                        self.key.fingerprint(Default::default())
+                    );
+
+                    None
+                }
+                _ => None,
+            }
+            .ok_or(Error::AgentFailure)?;
+
+            // The agent may silently ignore the flags, and sign with another algorithm.
+            if signature.algorithm() != self.algorithm {
+                return Err(Error::AgentFailure);
+            }
+
+            Ok(signature)
+        })
+    }
+}
@@ -1,6 +1,25 @@
-use aes_gcm::Tag;
+use std::sync::Arc;
+
+#[cfg(feature = "aes-gcm")]
+use aes_gcm::aead::AeadInPlace;
+#[cfg(feature = "chacha20-poly1305")]
+use chacha20::{
+    cipher::{KeyIvInit, StreamCipher, StreamCipherSeek},
+    ChaCha20Legacy,
+};
+#[cfg(any(feature = "aes-gcm", feature = "chacha20-poly1305"))]
+use cipher::KeyInit;
+#[cfg(feature = "chacha20-poly1305")]
+use poly1305::Poly1305;
 use ssh_packet::{arch::NameList, trans::KexInit};
 use strum::{AsRefStr, EnumString};
+#[cfg(feature = "chacha20-poly1305")]
+use subtle::ConstantTimeEq;
+#[cfg(feature = "aes-gcm")]
+use zeroize::Zeroize;
+use zeroize::ZeroizeOnDrop;
+#[cfg(feature = "chacha20-poly1305")]
+use zeroize::Zeroizing;
 
 use crate::{
     side::{client::Client, server::Server},
@@ -9,11 +28,96 @@ use crate::{
 
 use super::Negociate;
 
-// TODO: (optimization) Get rid of this Box<dyn> altogether.
-pub type CipherState = Box<dyn std::any::Any + Send + Sync>;
+/// Negociate the cipher algorithm of the side `S`, either from the built-in ones or from the `custom` ones.
+pub(crate) fn negociate<S>(
+    clientkex: &KexInit,
+    serverkex: &KexInit,
+    custom: &[Arc<dyn CipherAlgorithm>],
+) -> Result<Arc<dyn CipherAlgorithm>>
+where
+    Cipher: Negociate<S>,
+{
+    let (client, server) = (
+        <Cipher as Negociate<S>>::field(clientkex),
+        <Cipher as Negociate<S>>::field(serverkex),
+    );
+    let err = || <Cipher as Negociate<S>>::err(client.0.to_string(), server.0.to_string());
+
+    let name = client.preferred_in(server).ok_or_else(err)?;
+
+    match name.parse::<Cipher>() {
+        Ok(cipher) => Ok(Arc::new(cipher)),
+        Err(_) => custom
+            .iter()
+            .find(|cipher| cipher.name() == &*name)
+            .cloned()
+            .ok_or_else(err),
+    }
+}
+
+/// A cipher algorithm, implemented by the built-in [`Cipher`] methods,
+/// and which can be implemented to provide custom methods to the _sessions_.
+pub trait CipherAlgorithm: std::fmt::Debug + Send + Sync + 'static {
+    /// The name of the algorithm, as advertised in the [`KexInit`].
+    fn name(&self) -> &str;
+
+    /// The size of the cipher blocks, to which the packets are aligned.
+    fn block_size(&self) -> usize;
+
+    /// The size of the _key_ to be derived from the key-exchange.
+    fn key_size(&self) -> usize;
+
+    /// The size of the _initialization vector_ to be derived from the key-exchange.
+    fn iv_size(&self) -> usize;
+
+    /// The size of the authentication tag appended to the packets by _AEAD_ ciphers,
+    /// which is zero for the ciphers relying on the _hmac_ to authenticate the packets.
+    fn tag_size(&self) -> usize {
+        0
+    }
+
+    /// Whether the cipher is an _AEAD_, authenticating the packets in place of the _hmac_.
+    fn is_aead(&self) -> bool {
+        self.tag_size() > 0
+    }
+
+    /// Construct the [`CipherState`] encrypting the packets sent after the key-exchange.
+    fn encryptor(&self, key: &[u8], iv: &[u8]) -> Box<dyn CipherState>;
+
+    /// Construct the [`CipherState`] decrypting the packets received after the key-exchange.
+    fn decryptor(&self, key: &[u8], iv: &[u8]) -> Box<dyn CipherState>;
+}
+
+/// The instance of a [`CipherAlgorithm`] for one direction of the stream, constructed once
+/// per key-exchange and carrying the evolving counter, chaining block or nonce across packets.
+pub trait CipherState: std::fmt::Debug + Send + Sync {
+    /// Encrypt the `buffer` in-place, which is a multiple of the block size.
+    fn encrypt(&mut self, buffer: &mut [u8]) -> Result<()>;
+
+    /// Decrypt the `buffer` in-place, which is a multiple of the block size.
+    fn decrypt(&mut self, buffer: &mut [u8]) -> Result<()>;
+
+    /// Decrypt the `length` prefix of a packet for _AEAD_ ciphers, as it is needed before opening it.
+    fn decrypt_length(&mut self, _seq: u32, _length: &mut [u8; 4]) -> Result<()> {
+        Ok(())
+    }
+
+    /// Encrypt the `buffer` prefixed with it's length using an _AEAD_ cipher, and return the authentication tag.
+    fn seal(&mut self, _seq: u32, _buffer: &mut [u8]) -> Result<Vec<u8>> {
+        Err(Error::Cipher)
+    }
+
+    /// Verify the `tag` of the `buffer` prefixed with it's encrypted length using an _AEAD_ cipher,
+    /// and decrypt it in-place, leaving the length prefix untouched.
+    fn open(&mut self, _seq: u32, _buffer: &mut [u8], _tag: &[u8]) -> Result<()> {
+        Err(Error::Cipher)
+    }
+}
 
 impl Negociate<Client> for Cipher {
-    const ERR: Error = Error::NoCommonCipher;
+    fn err(client: String, server: String) -> Error {
+        Error::NoCommonCipher { client, server }
+    }
 
     fn field<'f>(kex: &'f KexInit) -> &'f NameList<'f> {
         &kex.encryption_algorithms_client_to_server
@@ -21,51 +125,71 @@ impl Negociate<Client> for Cipher {
 }
 
 impl Negociate<Server> for Cipher {
-    const ERR: Error = Error::NoCommonCipher;
+    fn err(client: String, server: String) -> Error {
+        Error::NoCommonCipher { client, server }
+    }
 
     fn field<'f>(kex: &'f KexInit) -> &'f NameList<'f> {
         &kex.encryption_algorithms_server_to_client
     }
 }
 
-// TODO: (feature) Implement the latest and safest ciphers (`chacha20-poly1305@openssh.com`, `aes256-gcm@openssh.com`, `aes128-gcm@openssh.com`).
-
 /// SSH cipher algorithms.
 #[non_exhaustive]
 #[derive(Debug, Clone, Default, PartialEq, EnumString, AsRefStr)]
 #[strum(serialize_all = "kebab-case")]
 pub enum Cipher {
-    // /// ChaCha20-Poly1305.
-    // #[strum(serialize = "chacha20-poly1305@openssh.com")]
-    // ChaCha20Poly1305,
-
-    // /// AES-256 in Galois/Counter Mode (GCM).
-    // #[strum(serialize = "aes256-gcm@openssh.com")]
-    // Aes256Gcm,
-
-    // /// AES-128 in Galois/Counter Mode (GCM).
-    // #[strum(serialize = "aes128-gcm@openssh.com")]
-    // Aes128Gcm,
-    //
+    /// ChaCha20-Poly1305, with the packet length encrypted separately.
+    #[cfg(feature = "chacha20-poly1305")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "chacha20-poly1305")))]
+    #[strum(serialize = "chacha20-poly1305@openssh.com")]
+    ChaCha20Poly1305,
+
+    /// AES-256 in Galois/Counter Mode (GCM).
+    #[cfg(feature = "aes-gcm")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "aes-gcm")))]
+    #[strum(serialize = "aes256-gcm@openssh.com")]
+    Aes256Gcm,
+
+    /// AES-128 in Galois/Counter Mode (GCM).
+    #[cfg(feature = "aes-gcm")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "aes-gcm")))]
+    #[strum(serialize = "aes128-gcm@openssh.com")]
+    Aes128Gcm,
+
     /// AES-256 in counter (CTR) mode.
+    #[cfg(feature = "aes-ctr")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "aes-ctr")))]
     Aes256Ctr,
 
     /// AES-192 in counter (CTR) mode.
+    #[cfg(feature = "aes-ctr")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "aes-ctr")))]
     Aes192Ctr,
 
     /// AES-128 in counter (CTR) mode.
+    #[cfg(feature = "aes-ctr")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "aes-ctr")))]
     Aes128Ctr,
 
     /// AES-256 in cipher block chaining (CBC) mode.
+    #[cfg(feature = "aes-cbc")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "aes-cbc")))]
     Aes256Cbc,
 
     /// AES-192 in cipher block chaining (CBC) mode.
+    #[cfg(feature = "aes-cbc")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "aes-cbc")))]
     Aes192Cbc,
 
     /// AES-128 in cipher block chaining (CBC) mode.
+    #[cfg(feature = "aes-cbc")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "aes-cbc")))]
     Aes128Cbc,
 
     /// TripleDES in cipher block chaining (CBC) mode.
+    #[cfg(feature = "tdes-cbc")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "tdes-cbc")))]
     #[strum(serialize = "3des-cbc")]
     TDesCbc,
 
@@ -74,42 +198,147 @@ pub enum Cipher {
     None,
 }
 
-impl Cipher {
-    /// This method is a hack to solve deduplication of the enum
-    /// variants and to store the cipher states inside a dynamically
-    /// typed `Box<dyn std::any::Any>`.
-    fn state<'s, T: cipher::KeyIvInit + Send + Sync + 'static>(
-        state: &'s mut Option<CipherState>,
-        key: &[u8],
-        iv: &[u8],
-    ) -> &'s mut T {
-        state
-            .get_or_insert_with(|| {
-                Box::new(T::new_from_slices(key, iv).expect("Key derivation failed horribly"))
-            })
-            .downcast_mut()
-            .expect("State changed in the meanwhile")
-    }
-
-    fn ctr<C: ctr::cipher::StreamCipher>(cipher: &mut C, buffer: &mut [u8]) -> Result<Option<Tag>> {
-        cipher
-            .try_apply_keystream(buffer)
+/// The [`CipherState`] of the built-in [`Cipher`]s.
+enum State {
+    #[cfg(feature = "aes-ctr")]
+    Aes256Ctr(ctr::Ctr128BE<aes::Aes256>),
+    #[cfg(feature = "aes-ctr")]
+    Aes192Ctr(ctr::Ctr128BE<aes::Aes192>),
+    #[cfg(feature = "aes-ctr")]
+    Aes128Ctr(ctr::Ctr128BE<aes::Aes128>),
+    #[cfg(feature = "aes-cbc")]
+    Aes256CbcEncryptor(cbc::Encryptor<aes::Aes256>),
+    #[cfg(feature = "aes-cbc")]
+    Aes192CbcEncryptor(cbc::Encryptor<aes::Aes192>),
+    #[cfg(feature = "aes-cbc")]
+    Aes128CbcEncryptor(cbc::Encryptor<aes::Aes128>),
+    #[cfg(feature = "tdes-cbc")]
+    TDesCbcEncryptor(cbc::Encryptor<des::TdesEde3>),
+    #[cfg(feature = "aes-cbc")]
+    Aes256CbcDecryptor(cbc::Decryptor<aes::Aes256>),
+    #[cfg(feature = "aes-cbc")]
+    Aes192CbcDecryptor(cbc::Decryptor<aes::Aes192>),
+    #[cfg(feature = "aes-cbc")]
+    Aes128CbcDecryptor(cbc::Decryptor<aes::Aes128>),
+    #[cfg(feature = "tdes-cbc")]
+    TDesCbcDecryptor(cbc::Decryptor<des::TdesEde3>),
+    #[cfg(feature = "aes-gcm")]
+    Aes256Gcm(Gcm<aes_gcm::Aes256Gcm>),
+    #[cfg(feature = "aes-gcm")]
+    Aes128Gcm(Gcm<aes_gcm::Aes128Gcm>),
+    /// The _ChaCha20_ ciphers are keyed per packet from the sequence number.
+    #[cfg(feature = "chacha20-poly1305")]
+    ChaCha20Poly1305(Zeroizing<Vec<u8>>),
+    None,
+}
+
+/// All the cipher states wipe their keys and counters when dropped,
+/// with the `zeroize` features of the underlying crates.
+impl ZeroizeOnDrop for State {}
+
+impl std::fmt::Debug for State {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("State { .. }")
+    }
+}
+
+/// Whether the [`State`] is constructed for encryption or decryption,
+/// which only matters to the _CBC_ mode.
+#[derive(Clone, Copy, PartialEq)]
+enum Mode {
+    Encrypt,
+    Decrypt,
+}
+
+/// The state of an _AES-GCM_ cipher, the `nonce` carrying the invocation counter across packets.
+#[cfg(feature = "aes-gcm")]
+struct Gcm<C> {
+    cipher: C,
+    nonce: [u8; 12],
+}
+
+#[cfg(feature = "aes-gcm")]
+impl<C> Drop for Gcm<C> {
+    fn drop(&mut self) {
+        self.nonce.zeroize();
+    }
+}
+
+#[cfg(feature = "aes-gcm")]
+impl<C: AeadInPlace + KeyInit> Gcm<C> {
+    fn new(key: &[u8], iv: &[u8]) -> Self {
+        Self {
+            cipher: C::new_from_slice(key).expect("Key derivation failed horribly"),
+            nonce: iv.try_into().expect("Key derivation failed horribly"),
+        }
+    }
+
+    /// Increment the invocation counter, in the last 8 bytes of the nonce.
+    fn increment(&mut self) {
+        let mut counter = [0u8; 8];
+        counter.copy_from_slice(&self.nonce[4..]);
+
+        self.nonce[4..].copy_from_slice(&u64::from_be_bytes(counter).wrapping_add(1).to_be_bytes());
+    }
+
+    /// The packet length is left in clear, but authenticated as additional data.
+    fn seal(&mut self, buffer: &mut [u8]) -> Result<Vec<u8>> {
+        let (length, buffer) = buffer.split_at_mut(4);
+
+        let tag = self
+            .cipher
+            .encrypt_in_place_detached(self.nonce.as_ref().into(), length, buffer)
             .map_err(|_| Error::Cipher)?;
+        self.increment();
 
-        Ok(None)
+        Ok(tag.to_vec())
     }
 
-    pub(crate) fn encrypt(
-        &mut self,
-        state: &mut Option<CipherState>,
-        key: &[u8],
-        iv: &[u8],
-        buffer: &mut [u8],
-    ) -> Result<Option<Tag>> {
-        fn cbc<C: cbc::cipher::BlockEncryptMut>(
-            cipher: &mut C,
-            buffer: &mut [u8],
-        ) -> Result<Option<Tag>> {
+    fn open(&mut self, buffer: &mut [u8], tag: &[u8]) -> Result<()> {
+        let (length, buffer) = buffer.split_at_mut(4);
+
+        self.cipher
+            .decrypt_in_place_detached(self.nonce.as_ref().into(), length, buffer, tag.into())
+            .map_err(|_| digest::MacError)?;
+        self.increment();
+
+        Ok(())
+    }
+}
+
+impl State {
+    #[cfg(feature = "chacha20-poly1305")]
+    fn chacha(key: &[u8], seq: u32) -> Result<ChaCha20Legacy> {
+        ChaCha20Legacy::new_from_slices(key, &u64::from(seq).to_be_bytes())
+            .map_err(|_| Error::Cipher)
+    }
+
+    /// Compute the _Poly1305_ tag of the `buffer`, keyed from the first block of the `cipher`.
+    #[cfg(feature = "chacha20-poly1305")]
+    fn poly1305(cipher: &mut ChaCha20Legacy, buffer: &[u8]) -> poly1305::Tag {
+        let mut key = Zeroizing::new([0u8; 32]);
+        cipher.apply_keystream(key.as_mut());
+
+        Poly1305::new(key.as_ref().into()).compute_unpadded(buffer)
+    }
+
+    #[cfg(feature = "aes-ctr")]
+    fn ctr<C: ctr::cipher::StreamCipher>(cipher: &mut C, buffer: &mut [u8]) -> Result<()> {
+        cipher
+            .try_apply_keystream(buffer)
+            .map_err(|_| Error::Cipher)
+    }
+}
+
+// The parameters are unused when the build includes only AEAD ciphers.
+#[cfg_attr(
+    not(any(feature = "aes-ctr", feature = "aes-cbc", feature = "tdes-cbc")),
+    allow(unused_variables)
+)]
+impl CipherState for State {
+    fn encrypt(&mut self, buffer: &mut [u8]) -> Result<()> {
+        #[cfg(any(feature = "aes-cbc", feature = "tdes-cbc"))]
+        fn cbc<C: cbc::cipher::BlockEncryptMut>(cipher: &mut C, buffer: &mut [u8]) -> Result<()> {
             use cbc::cipher::inout;
 
             let data = inout::InOutBufReserved::from_mut_slice(buffer, buffer.len())
@@ -124,53 +353,34 @@ impl Cipher {
                 cipher.encrypt_block_inout_mut(block);
             }
 
-            Ok(None)
+            Ok(())
         }
 
         match self {
-            Self::Aes256Ctr => Self::ctr(
-                Self::state::<ctr::Ctr128BE<aes::Aes256>>(state, key, iv),
-                buffer,
-            ),
-            Self::Aes192Ctr => Self::ctr(
-                Self::state::<ctr::Ctr128BE<aes::Aes192>>(state, key, iv),
-                buffer,
-            ),
-            Self::Aes128Ctr => Self::ctr(
-                Self::state::<ctr::Ctr128BE<aes::Aes128>>(state, key, iv),
-                buffer,
-            ),
-            Self::Aes256Cbc => cbc(
-                Self::state::<cbc::Encryptor<aes::Aes256>>(state, key, iv),
-                buffer,
-            ),
-            Self::Aes192Cbc => cbc(
-                Self::state::<cbc::Encryptor<aes::Aes192>>(state, key, iv),
-                buffer,
-            ),
-            Self::Aes128Cbc => cbc(
-                Self::state::<cbc::Encryptor<aes::Aes128>>(state, key, iv),
-                buffer,
-            ),
-            Self::TDesCbc => cbc(
-                Self::state::<cbc::Encryptor<des::TdesEde3>>(state, key, iv),
-                buffer,
-            ),
-            Self::None => Ok(None),
+            #[cfg(feature = "aes-ctr")]
+            Self::Aes256Ctr(cipher) => Self::ctr(cipher, buffer),
+            #[cfg(feature = "aes-ctr")]
+            Self::Aes192Ctr(cipher) => Self::ctr(cipher, buffer),
+            #[cfg(feature = "aes-ctr")]
+            Self::Aes128Ctr(cipher) => Self::ctr(cipher, buffer),
+            #[cfg(feature = "aes-cbc")]
+            Self::Aes256CbcEncryptor(cipher) => cbc(cipher, buffer),
+            #[cfg(feature = "aes-cbc")]
+            Self::Aes192CbcEncryptor(cipher) => cbc(cipher, buffer),
+            #[cfg(feature = "aes-cbc")]
+            Self::Aes128CbcEncryptor(cipher) => cbc(cipher, buffer),
+            #[cfg(feature = "tdes-cbc")]
+            Self::TDesCbcEncryptor(cipher) => cbc(cipher, buffer),
+            Self::None => Ok(()),
+            // AEAD ciphers are handled through `CipherState::seal` instead.
+            #[allow(unreachable_patterns)]
+            _ => Err(Error::Cipher),
         }
     }
 
-    pub(crate) fn decrypt(
-        &mut self,
-        state: &mut Option<CipherState>,
-        key: &[u8],
-        iv: &[u8],
-        buffer: &mut [u8],
-    ) -> Result<Option<Tag>> {
-        fn cbc<C: cbc::cipher::BlockDecryptMut>(
-            cipher: &mut C,
-            buffer: &mut [u8],
-        ) -> Result<Option<Tag>> {
+    fn decrypt(&mut self, buffer: &mut [u8]) -> Result<()> {
+        #[cfg(any(feature = "aes-cbc", feature = "tdes-cbc"))]
+        fn cbc<C: cbc::cipher::BlockDecryptMut>(cipher: &mut C, buffer: &mut [u8]) -> Result<()> {
             use cbc::cipher::inout;
 
             let data = inout::InOutBufReserved::from_mut_slice(buffer, buffer.len())
@@ -185,78 +395,220 @@ impl Cipher {
                 cipher.decrypt_block_inout_mut(block);
             }
 
-            Ok(None)
+            Ok(())
         }
 
         match self {
             // In CTR mode, encryption and decrytion are the same
-            Self::Aes256Ctr | Self::Aes192Ctr | Self::Aes128Ctr => {
-                self.encrypt(state, key, iv, buffer)
+            #[cfg(feature = "aes-ctr")]
+            Self::Aes256Ctr(cipher) => Self::ctr(cipher, buffer),
+            #[cfg(feature = "aes-ctr")]
+            Self::Aes192Ctr(cipher) => Self::ctr(cipher, buffer),
+            #[cfg(feature = "aes-ctr")]
+            Self::Aes128Ctr(cipher) => Self::ctr(cipher, buffer),
+            #[cfg(feature = "aes-cbc")]
+            Self::Aes256CbcDecryptor(cipher) => cbc(cipher, buffer),
+            #[cfg(feature = "aes-cbc")]
+            Self::Aes192CbcDecryptor(cipher) => cbc(cipher, buffer),
+            #[cfg(feature = "aes-cbc")]
+            Self::Aes128CbcDecryptor(cipher) => cbc(cipher, buffer),
+            #[cfg(feature = "tdes-cbc")]
+            Self::TDesCbcDecryptor(cipher) => cbc(cipher, buffer),
+            Self::None => Ok(()),
+            // AEAD ciphers are handled through `CipherState::open` instead.
+            #[allow(unreachable_patterns)]
+            _ => Err(Error::Cipher),
+        }
+    }
+
+    fn decrypt_length(&mut self, seq: u32, length: &mut [u8; 4]) -> Result<()> {
+        match self {
+            #[cfg(feature = "chacha20-poly1305")]
+            Self::ChaCha20Poly1305(key) => {
+                Self::chacha(&key[32..], seq)?.apply_keystream(length);
+
+                Ok(())
             }
-            Self::Aes256Cbc => cbc(
-                Self::state::<cbc::Decryptor<aes::Aes256>>(state, key, iv),
-                buffer,
-            ),
-            Self::Aes192Cbc => cbc(
-                Self::state::<cbc::Decryptor<aes::Aes192>>(state, key, iv),
-                buffer,
-            ),
-            Self::Aes128Cbc => cbc(
-                Self::state::<cbc::Decryptor<aes::Aes128>>(state, key, iv),
-                buffer,
-            ),
-            Self::TDesCbc => cbc(
-                Self::state::<cbc::Decryptor<des::TdesEde3>>(state, key, iv),
-                buffer,
-            ),
-            Self::None => Ok(None),
+            _ => Ok(()),
         }
     }
 
-    pub(crate) fn block_size(&self) -> usize {
+    fn seal(&mut self, seq: u32, buffer: &mut [u8]) -> Result<Vec<u8>> {
         match self {
-            Self::None | Self::TDesCbc { .. } => 8,
-            Self::Aes128Cbc { .. }
-            | Self::Aes192Cbc { .. }
-            | Self::Aes256Cbc { .. }
-            | Self::Aes128Ctr { .. }
-            | Self::Aes192Ctr { .. }
-            | Self::Aes256Ctr { .. } => 16,
+            #[cfg(feature = "aes-gcm")]
+            Self::Aes256Gcm(cipher) => cipher.seal(buffer),
+            #[cfg(feature = "aes-gcm")]
+            Self::Aes128Gcm(cipher) => cipher.seal(buffer),
+            #[cfg(feature = "chacha20-poly1305")]
+            Self::ChaCha20Poly1305(key) => {
+                let (main, header) = key.split_at(32);
+                Self::chacha(header, seq)?.apply_keystream(&mut buffer[..4]);
+
+                let mut cipher = Self::chacha(main, seq)?;
+                cipher.seek(64);
+                cipher.apply_keystream(&mut buffer[4..]);
+                cipher.seek(0);
+
+                Ok(Self::poly1305(&mut cipher, buffer).to_vec())
+            }
+            _ => Err(Error::Cipher),
         }
     }
 
-    pub(crate) fn key_size(&self) -> usize {
+    fn open(&mut self, seq: u32, buffer: &mut [u8], tag: &[u8]) -> Result<()> {
+        match self {
+            #[cfg(feature = "aes-gcm")]
+            Self::Aes256Gcm(cipher) => cipher.open(buffer, tag),
+            #[cfg(feature = "aes-gcm")]
+            Self::Aes128Gcm(cipher) => cipher.open(buffer, tag),
+            #[cfg(feature = "chacha20-poly1305")]
+            Self::ChaCha20Poly1305(key) => {
+                let mut cipher = Self::chacha(&key[..32], seq)?;
+
+                if !bool::from(Self::poly1305(&mut cipher, buffer).as_slice().ct_eq(tag)) {
+                    return Err(digest::MacError.into());
+                }
+
+                cipher.seek(64);
+                cipher.apply_keystream(&mut buffer[4..]);
+
+                Ok(())
+            }
+            _ => Err(Error::Cipher),
+        }
+    }
+}
+
+impl Cipher {
+    /// Construct the [`State`] from the derived `key` and `iv`, which is done only once
+    /// per key-exchange, the state being then reused for all the packets of the direction.
+    #[cfg_attr(
+        not(any(
+            feature = "aes-ctr",
+            feature = "aes-cbc",
+            feature = "tdes-cbc",
+            feature = "aes-gcm"
+        )),
+        allow(unused_variables)
+    )]
+    fn init(&self, mode: Mode, key: &[u8], iv: &[u8]) -> State {
+        #[cfg(any(feature = "aes-ctr", feature = "aes-cbc", feature = "tdes-cbc"))]
+        fn new<T: cipher::KeyIvInit>(key: &[u8], iv: &[u8]) -> T {
+            T::new_from_slices(key, iv).expect("Key derivation failed horribly")
+        }
+
+        match (self, mode) {
+            #[cfg(feature = "aes-ctr")]
+            (Self::Aes256Ctr, _) => State::Aes256Ctr(new(key, iv)),
+            #[cfg(feature = "aes-ctr")]
+            (Self::Aes192Ctr, _) => State::Aes192Ctr(new(key, iv)),
+            #[cfg(feature = "aes-ctr")]
+            (Self::Aes128Ctr, _) => State::Aes128Ctr(new(key, iv)),
+            #[cfg(feature = "aes-cbc")]
+            (Self::Aes256Cbc, Mode::Encrypt) => State::Aes256CbcEncryptor(new(key, iv)),
+            #[cfg(feature = "aes-cbc")]
+            (Self::Aes192Cbc, Mode::Encrypt) => State::Aes192CbcEncryptor(new(key, iv)),
+            #[cfg(feature = "aes-cbc")]
+            (Self::Aes128Cbc, Mode::Encrypt) => State::Aes128CbcEncryptor(new(key, iv)),
+            #[cfg(feature = "tdes-cbc")]
+            (Self::TDesCbc, Mode::Encrypt) => State::TDesCbcEncryptor(new(key, iv)),
+            #[cfg(feature = "aes-cbc")]
+            (Self::Aes256Cbc, Mode::Decrypt) => State::Aes256CbcDecryptor(new(key, iv)),
+            #[cfg(feature = "aes-cbc")]
+            (Self::Aes192Cbc, Mode::Decrypt) => State::Aes192CbcDecryptor(new(key, iv)),
+            #[cfg(feature = "aes-cbc")]
+            (Self::Aes128Cbc, Mode::Decrypt) => State::Aes128CbcDecryptor(new(key, iv)),
+            #[cfg(feature = "tdes-cbc")]
+            (Self::TDesCbc, Mode::Decrypt) => State::TDesCbcDecryptor(new(key, iv)),
+            #[cfg(feature = "aes-gcm")]
+            (Self::Aes256Gcm, _) => State::Aes256Gcm(Gcm::new(key, iv)),
+            #[cfg(feature = "aes-gcm")]
+            (Self::Aes128Gcm, _) => State::Aes128Gcm(Gcm::new(key, iv)),
+            #[cfg(feature = "chacha20-poly1305")]
+            (Self::ChaCha20Poly1305, _) => State::ChaCha20Poly1305(Zeroizing::new(key.to_vec())),
+            (Self::None, _) => State::None,
+        }
+    }
+}
+
+impl CipherAlgorithm for Cipher {
+    fn name(&self) -> &str {
+        self.as_ref()
+    }
+
+    fn block_size(&self) -> usize {
+        match self {
+            Self::None => 8,
+            #[cfg(feature = "tdes-cbc")]
+            Self::TDesCbc => 8,
+            #[cfg(feature = "chacha20-poly1305")]
+            Self::ChaCha20Poly1305 => 8,
+            #[cfg(feature = "aes-cbc")]
+            Self::Aes128Cbc | Self::Aes192Cbc | Self::Aes256Cbc => 16,
+            #[cfg(feature = "aes-ctr")]
+            Self::Aes128Ctr | Self::Aes192Ctr | Self::Aes256Ctr => 16,
+            #[cfg(feature = "aes-gcm")]
+            Self::Aes128Gcm | Self::Aes256Gcm => 16,
+        }
+    }
+
+    fn key_size(&self) -> usize {
         match self {
             Self::None => 0,
-            Self::Aes128Cbc { .. } | Self::Aes128Ctr { .. } => 16,
-            Self::TDesCbc { .. } | Self::Aes192Cbc { .. } | Self::Aes192Ctr { .. } => 24,
-            Self::Aes256Cbc { .. } | Self::Aes256Ctr { .. } => 32,
+            #[cfg(feature = "aes-cbc")]
+            Self::Aes128Cbc => 16,
+            #[cfg(feature = "aes-cbc")]
+            Self::Aes192Cbc => 24,
+            #[cfg(feature = "aes-cbc")]
+            Self::Aes256Cbc => 32,
+            #[cfg(feature = "aes-ctr")]
+            Self::Aes128Ctr => 16,
+            #[cfg(feature = "aes-ctr")]
+            Self::Aes192Ctr => 24,
+            #[cfg(feature = "aes-ctr")]
+            Self::Aes256Ctr => 32,
+            #[cfg(feature = "aes-gcm")]
+            Self::Aes128Gcm => 16,
+            #[cfg(feature = "aes-gcm")]
+            Self::Aes256Gcm => 32,
+            #[cfg(feature = "tdes-cbc")]
+            Self::TDesCbc => 24,
+            #[cfg(feature = "chacha20-poly1305")]
+            Self::ChaCha20Poly1305 => 64,
         }
     }
 
-    pub(crate) fn iv_size(&self) -> usize {
+    fn iv_size(&self) -> usize {
         match self {
             Self::None => 0,
-            Self::TDesCbc { .. } => 8,
-            Self::Aes128Cbc { .. }
-            | Self::Aes192Cbc { .. }
-            | Self::Aes256Cbc { .. }
-            | Self::Aes128Ctr { .. }
-            | Self::Aes192Ctr { .. }
-            | Self::Aes256Ctr { .. } => 16,
+            #[cfg(feature = "chacha20-poly1305")]
+            Self::ChaCha20Poly1305 => 0,
+            #[cfg(feature = "tdes-cbc")]
+            Self::TDesCbc => 8,
+            #[cfg(feature = "aes-gcm")]
+            Self::Aes128Gcm | Self::Aes256Gcm => 12,
+            #[cfg(feature = "aes-cbc")]
+            Self::Aes128Cbc | Self::Aes192Cbc | Self::Aes256Cbc => 16,
+            #[cfg(feature = "aes-ctr")]
+            Self::Aes128Ctr | Self::Aes192Ctr | Self::Aes256Ctr => 16,
+        }
+    }
+
+    fn tag_size(&self) -> usize {
+        match self {
+            #[cfg(feature = "chacha20-poly1305")]
+            Self::ChaCha20Poly1305 => 16,
+            #[cfg(feature = "aes-gcm")]
+            Self::Aes256Gcm | Self::Aes128Gcm => 16,
+            _ => 0,
         }
     }
 
-    // pub(crate) fn has_tag(&self) -> bool {
-    //     match self {
-    //         Self::None
-    //         | Self::TDesCbc { .. }
-    //         | Self::Aes128Cbc { .. }
-    //         | Self::Aes192Cbc { .. }
-    //         | Self::Aes256Cbc { .. }
-    //         | Self::Aes128Ctr { .. }
-    //         | Self::Aes192Ctr { .. }
-    //         | Self::Aes256Ctr { .. } => false,
-    //     }
-    // }
+    fn encryptor(&self, key: &[u8], iv: &[u8]) -> Box<dyn CipherState> {
+        Box::new(self.init(Mode::Encrypt, key, iv))
+    }
+
+    fn decryptor(&self, key: &[u8], iv: &[u8]) -> Box<dyn CipherState> {
+        Box::new(self.init(Mode::Decrypt, key, iv))
+    }
 }
@@ -1,5 +1,5 @@
-use std::io::{Read, Write};
-
+#[cfg(feature = "zlib")]
+use flate2::{Compression, FlushCompress, FlushDecompress};
 use ssh_packet::{arch::NameList, trans::KexInit};
 use strum::{AsRefStr, EnumString};
 
@@ -11,7 +11,9 @@ use crate::{
 use super::Negociate;
 
 impl Negociate<Client> for Compress {
-    const ERR: Error = Error::NoCommonCompression;
+    fn err(client: String, server: String) -> Error {
+        Error::NoCommonCompression { client, server }
+    }
 
     fn field<'f>(kex: &'f KexInit) -> &'f NameList<'f> {
         &kex.compression_algorithms_client_to_server
@@ -19,25 +21,29 @@ impl Negociate<Client> for Compress {
 }
 
 impl Negociate<Server> for Compress {
-    const ERR: Error = Error::NoCommonCompression;
+    fn err(client: String, server: String) -> Error {
+        Error::NoCommonCompression { client, server }
+    }
 
     fn field<'f>(kex: &'f KexInit) -> &'f NameList<'f> {
         &kex.compression_algorithms_server_to_client
     }
 }
 
-// TODO: (compliance) Fix compression algorithms, not working right now.
-
 /// SSH compression algorithms.
 #[non_exhaustive]
 #[derive(Debug, Clone, Default, PartialEq, EnumString, AsRefStr)]
 #[strum(serialize_all = "kebab-case")]
 pub enum Compress {
-    /// zlib compression (OpenSSH mode).
+    /// zlib compression (OpenSSH mode), delayed until the user is authenticated.
+    #[cfg(feature = "zlib")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "zlib")))]
     #[strum(serialize = "zlib@openssh.com")]
     ZlibOpenssh,
 
     /// zlib compression.
+    #[cfg(feature = "zlib")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "zlib")))]
     Zlib,
 
     /// No compression algorithm.
@@ -45,33 +51,150 @@ pub enum Compress {
     None,
 }
 
-impl Compress {
-    pub(crate) fn decompress(&self, buf: Vec<u8>) -> Result<Vec<u8>> {
-        match self {
-            Self::ZlibOpenssh | Self::Zlib => {
-                let mut buffer = Vec::with_capacity(buf.len());
-                let decoder = libflate::zlib::Decoder::new(std::io::Cursor::new(buf))?;
+/// The state of the _zlib_ stream, spanning across all the packets of one direction.
+#[derive(Debug)]
+pub(crate) struct CompressState {
+    #[cfg_attr(not(feature = "zlib"), allow(dead_code))]
+    level: u32,
+    #[cfg(feature = "zlib")]
+    deflate: Option<flate2::Compress>,
+    #[cfg(feature = "zlib")]
+    inflate: Option<flate2::Decompress>,
+}
 
-                decoder
-                    .take(ssh_packet::PACKET_MAX_SIZE as u64)
-                    .read_to_end(&mut buffer)?;
+impl Default for CompressState {
+    fn default() -> Self {
+        Self {
+            level: DEFAULT_LEVEL,
+            #[cfg(feature = "zlib")]
+            deflate: None,
+            #[cfg(feature = "zlib")]
+            inflate: None,
+        }
+    }
+}
+
+/// The default compression level, balanced between speed and ratio as the one of _zlib_.
+const DEFAULT_LEVEL: u32 = 6;
 
-                Ok(buffer)
+impl CompressState {
+    /// Set the compression `level`, clamped from 1 (fastest) to 9 (best).
+    pub(crate) fn with_level(&mut self, level: u32) {
+        self.level = level.clamp(1, 9);
+    }
+
+    /// Re-initialize the contexts to a blank dictionary, keeping their allocations,
+    /// since RFC4253 §6.2 mandates the compression context to be initialized after each key-exchange.
+    pub(crate) fn reset(&mut self) {
+        #[cfg(feature = "zlib")]
+        if let Some(deflate) = &mut self.deflate {
+            deflate.reset();
+        }
+        #[cfg(feature = "zlib")]
+        if let Some(inflate) = &mut self.inflate {
+            inflate.reset(true);
+        }
+    }
+
+    /// Take over the contexts of the `previous` state, after having [`CompressState::reset`] them.
+    #[cfg_attr(not(feature = "zlib"), allow(unused_mut))]
+    pub(crate) fn inherit(&mut self, mut previous: Self) {
+        previous.reset();
+
+        #[cfg(feature = "zlib")]
+        {
+            if previous.level == self.level {
+                self.deflate = previous.deflate;
             }
-            Self::None => Ok(buf),
+            self.inflate = previous.inflate;
         }
     }
+}
 
-    pub(crate) fn compress(&self, buf: &[u8]) -> Result<Vec<u8>> {
+impl Compress {
+    /// Whether the compression only starts once the user is authenticated.
+    pub(crate) fn is_delayed(&self) -> bool {
         match self {
+            #[cfg(feature = "zlib")]
+            Self::ZlibOpenssh => true,
+            _ => false,
+        }
+    }
+
+    #[cfg_attr(not(feature = "zlib"), allow(unused_variables))]
+    pub(crate) fn decompress(&self, state: &mut CompressState, buf: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            #[cfg(feature = "zlib")]
             Self::ZlibOpenssh | Self::Zlib => {
-                let mut encoder = libflate::zlib::Encoder::new(Vec::with_capacity(buf.len()))?;
+                let inflate = state
+                    .inflate
+                    .get_or_insert_with(|| flate2::Decompress::new(true));
+
+                let mut buffer = Vec::with_capacity(buf.len() * 2);
+                let mut consumed = 0;
+
+                // Loop until the input is consumed and the output buffer is left with spare space,
+                // otherwise some decompressed data could still be pending in the stream.
+                loop {
+                    let total = inflate.total_in();
+                    inflate
+                        .decompress_vec(&buf[consumed..], &mut buffer, FlushDecompress::Sync)
+                        .map_err(|_| Error::Compression)?;
+                    consumed += (inflate.total_in() - total) as usize;
+
+                    if consumed == buf.len() && buffer.len() < buffer.capacity() {
+                        break Ok(buffer);
+                    }
+                    if buffer.len() > ssh_packet::PACKET_MAX_SIZE {
+                        break Err(Error::Compression);
+                    }
+
+                    buffer.reserve(buffer.capacity());
+                }
+            }
+            Self::None => Ok(buf.to_vec()),
+        }
+    }
 
-                encoder.write_all(buf)?;
+    /// Compress the `buf`, appending the result to the `out` buffer.
+    #[cfg_attr(not(feature = "zlib"), allow(unused_variables))]
+    pub(crate) fn compress(
+        &self,
+        state: &mut CompressState,
+        buf: &[u8],
+        out: &mut Vec<u8>,
+    ) -> Result<()> {
+        match self {
+            #[cfg(feature = "zlib")]
+            Self::ZlibOpenssh | Self::Zlib => {
+                let deflate = state.deflate.get_or_insert_with(|| {
+                    flate2::Compress::new(Compression::new(state.level), true)
+                });
+
+                out.reserve(buf.len() + 64);
+                let mut consumed = 0;
+
+                // Each packet is flushed to a byte boundary, so the peer is able to decompress it
+                // without waiting for the following ones.
+                loop {
+                    let total = deflate.total_in();
+                    deflate
+                        .compress_vec(&buf[consumed..], out, FlushCompress::Partial)
+                        .map_err(|_| Error::Compression)?;
+                    consumed += (deflate.total_in() - total) as usize;
+
+                    if consumed == buf.len() && out.len() < out.capacity() {
+                        break Ok(());
+                    }
+
+                    out.reserve(out.capacity());
+                }
+            }
+            Self::None => {
+                out.extend_from_slice(buf);
 
-                Ok(encoder.finish().into_result()?)
+                Ok(())
             }
-            Self::None => Ok(buf.into()),
         }
     }
 }
@@ -1,9 +1,15 @@
+#[cfg(any(feature = "hmac-sha2", feature = "hmac-sha1", feature = "hmac-md5"))]
 use digest::OutputSizeUser;
+#[cfg(feature = "hmac-md5")]
 use md5::Md5;
+#[cfg(feature = "hmac-sha1")]
 use sha1::Sha1;
+#[cfg(feature = "hmac-sha2")]
 use sha2::{Sha256, Sha512};
 use ssh_packet::{arch::NameList, trans::KexInit};
 use strum::{AsRefStr, EnumString};
+#[cfg(feature = "umac")]
+use subtle::ConstantTimeEq;
 
 use crate::{
     side::{client::Client, server::Server},
@@ -12,8 +18,13 @@ use crate::{
 
 use super::Negociate;
 
+#[cfg(feature = "umac")]
+mod umac;
+
 impl Negociate<Client> for Hmac {
-    const ERR: Error = Error::NoCommonHmac;
+    fn err(client: String, server: String) -> Error {
+        Error::NoCommonHmac { client, server }
+    }
 
     fn field<'f>(kex: &'f KexInit) -> &'f NameList<'f> {
         &kex.mac_algorithms_client_to_server
@@ -21,7 +32,9 @@ impl Negociate<Client> for Hmac {
 }
 
 impl Negociate<Server> for Hmac {
-    const ERR: Error = Error::NoCommonHmac;
+    fn err(client: String, server: String) -> Error {
+        Error::NoCommonHmac { client, server }
+    }
 
     fn field<'f>(kex: &'f KexInit) -> &'f NameList<'f> {
         &kex.mac_algorithms_server_to_client
@@ -34,33 +47,67 @@ impl Negociate<Server> for Hmac {
 #[strum(serialize_all = "kebab-case")]
 pub enum Hmac {
     /// HMAC with sha-2-512 digest on encrypted message.
+    #[cfg(feature = "hmac-sha2")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "hmac-sha2")))]
     #[strum(serialize = "hmac-sha2-512-etm@openssh.com")]
     HmacSha512ETM,
 
     /// HMAC with sha-2-256 digest on encrypted message.
+    #[cfg(feature = "hmac-sha2")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "hmac-sha2")))]
     #[strum(serialize = "hmac-sha2-256-etm@openssh.com")]
     HmacSha256ETM,
 
     /// HMAC with sha-2-512 digest.
+    #[cfg(feature = "hmac-sha2")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "hmac-sha2")))]
     #[strum(serialize = "hmac-sha2-512")]
     HmacSha512,
 
     /// HMAC with sha-2-256 digest.
+    #[cfg(feature = "hmac-sha2")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "hmac-sha2")))]
     #[strum(serialize = "hmac-sha2-256")]
     HmacSha256,
 
+    /// UMAC with a 128-bit tag.
+    #[cfg(feature = "umac")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "umac")))]
+    #[strum(serialize = "umac-128@openssh.com")]
+    Umac128,
+
+    /// UMAC with a 64-bit tag.
+    #[cfg(feature = "umac")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "umac")))]
+    #[strum(serialize = "umac-64@openssh.com")]
+    Umac64,
+
     /// HMAC with sha-1 digest on encrypted message.
+    #[cfg(feature = "hmac-sha1")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "hmac-sha1")))]
     #[strum(serialize = "hmac-sha1-etm@openssh.com")]
     HmacSha1ETM,
 
     /// HMAC with sha-1 digest.
+    #[cfg(feature = "hmac-sha1")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "hmac-sha1")))]
     HmacSha1,
 
+    /// HMAC with sha-1 digest, truncated to 96 bits.
+    #[cfg(feature = "hmac-sha1")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "hmac-sha1")))]
+    #[strum(serialize = "hmac-sha1-96")]
+    HmacSha196,
+
     /// HMAC with md5 digest on encrypted message.
+    #[cfg(feature = "hmac-md5")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "hmac-md5")))]
     #[strum(serialize = "hmac-md5-etm@openssh.com")]
     HmacMd5ETM,
 
     /// HMAC with md5 digest.
+    #[cfg(feature = "hmac-md5")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "hmac-md5")))]
     HmacMd5,
 
     /// No HMAC algorithm.
@@ -69,6 +116,28 @@ pub enum Hmac {
 }
 
 impl Hmac {
+    /// The size of the integrity key, which is the digest size even for truncated variants.
+    pub(crate) fn key_size(&self) -> usize {
+        match self {
+            #[cfg(feature = "hmac-sha1")]
+            Self::HmacSha196 => Sha1::output_size(),
+            #[cfg(feature = "umac")]
+            Self::Umac128 | Self::Umac64 => umac::KEY_SIZE,
+            _ => ssh_packet::Mac::size(self),
+        }
+    }
+
+    /// Verify the `mac` of the packet in `buf`, the comparison being done in constant time.
+    // The parameters are unused when the build includes no mac algorithms.
+    #[cfg_attr(
+        not(any(
+            feature = "hmac-sha2",
+            feature = "umac",
+            feature = "hmac-sha1",
+            feature = "hmac-md5"
+        )),
+        allow(unused_variables)
+    )]
     pub(crate) fn verify(
         &self,
         seq: u32,
@@ -76,6 +145,7 @@ impl Hmac {
         key: &[u8],
         mac: &[u8],
     ) -> Result<(), digest::MacError> {
+        #[cfg(any(feature = "hmac-sha2", feature = "hmac-sha1", feature = "hmac-md5"))]
         fn verify<D: digest::Mac + digest::KeyInit>(
             seq: u32,
             buf: &[u8],
@@ -86,23 +156,47 @@ impl Hmac {
                 .expect("Key derivation failed horribly")
                 .chain_update(seq.to_be_bytes())
                 .chain_update(buf)
-                .verify(mac.into())
+                .verify_truncated_left(mac)
         }
 
         match self {
+            #[cfg(feature = "hmac-sha2")]
             Self::HmacSha512ETM | Self::HmacSha512 => {
                 verify::<hmac::Hmac<Sha512>>(seq, buf, key, mac)
             }
+            #[cfg(feature = "hmac-sha2")]
             Self::HmacSha256ETM | Self::HmacSha256 => {
                 verify::<hmac::Hmac<Sha256>>(seq, buf, key, mac)
             }
-            Self::HmacSha1ETM | Self::HmacSha1 => verify::<hmac::Hmac<Sha1>>(seq, buf, key, mac),
+            #[cfg(feature = "hmac-sha1")]
+            Self::HmacSha1ETM | Self::HmacSha1 | Self::HmacSha196 => {
+                verify::<hmac::Hmac<Sha1>>(seq, buf, key, mac)
+            }
+            #[cfg(feature = "hmac-md5")]
             Self::HmacMd5ETM | Self::HmacMd5 => verify::<hmac::Hmac<Md5>>(seq, buf, key, mac),
+            #[cfg(feature = "umac")]
+            Self::Umac128 | Self::Umac64 => {
+                if bool::from(self.sign(seq, buf, key).ct_eq(mac)) {
+                    Ok(())
+                } else {
+                    Err(digest::MacError)
+                }
+            }
             Self::None => Ok(()),
         }
     }
 
+    #[cfg_attr(
+        not(any(
+            feature = "hmac-sha2",
+            feature = "umac",
+            feature = "hmac-sha1",
+            feature = "hmac-md5"
+        )),
+        allow(unused_variables)
+    )]
     pub(crate) fn sign(&self, seq: u32, buf: &[u8], key: &[u8]) -> Vec<u8> {
+        #[cfg(any(feature = "hmac-sha2", feature = "hmac-sha1", feature = "hmac-md5"))]
         fn sign<D: digest::Mac + digest::KeyInit>(seq: u32, buf: &[u8], key: &[u8]) -> Vec<u8> {
             <D as digest::Mac>::new_from_slice(key)
                 .expect("Key derivation failed horribly")
@@ -114,30 +208,72 @@ impl Hmac {
         }
 
         match self {
+            #[cfg(feature = "hmac-sha2")]
             Self::HmacSha512ETM | Self::HmacSha512 => sign::<hmac::Hmac<Sha512>>(seq, buf, key),
+            #[cfg(feature = "hmac-sha2")]
             Self::HmacSha256ETM | Self::HmacSha256 => sign::<hmac::Hmac<Sha256>>(seq, buf, key),
+            #[cfg(feature = "hmac-sha1")]
             Self::HmacSha1ETM | Self::HmacSha1 => sign::<hmac::Hmac<Sha1>>(seq, buf, key),
+            #[cfg(feature = "hmac-sha1")]
+            Self::HmacSha196 => {
+                let mut mac = sign::<hmac::Hmac<Sha1>>(seq, buf, key);
+                mac.truncate(ssh_packet::Mac::size(self));
+
+                mac
+            }
+            #[cfg(feature = "hmac-md5")]
             Self::HmacMd5ETM | Self::HmacMd5 => sign::<hmac::Hmac<Md5>>(seq, buf, key),
+            #[cfg(feature = "umac")]
+            Self::Umac128 | Self::Umac64 => umac::umac(
+                key,
+                &u64::from(seq).to_be_bytes(),
+                buf,
+                ssh_packet::Mac::size(self),
+            ),
             Self::None => Default::default(),
         }
     }
+
+    /// Whether the algorithm relies on _SHA-1_.
+    pub(crate) fn is_sha1(&self) -> bool {
+        match self {
+            #[cfg(feature = "hmac-sha1")]
+            Self::HmacSha1ETM | Self::HmacSha1 | Self::HmacSha196 => true,
+            _ => false,
+        }
+    }
 }
 
 impl ssh_packet::Mac for Hmac {
     fn size(&self) -> usize {
         match self {
+            #[cfg(feature = "hmac-sha2")]
             Self::HmacSha512ETM | Self::HmacSha512 => Sha512::output_size(),
+            #[cfg(feature = "hmac-sha2")]
             Self::HmacSha256ETM | Self::HmacSha256 => Sha256::output_size(),
+            #[cfg(feature = "hmac-sha1")]
             Self::HmacSha1ETM | Self::HmacSha1 => Sha1::output_size(),
+            #[cfg(feature = "hmac-sha1")]
+            Self::HmacSha196 => 12,
+            #[cfg(feature = "umac")]
+            Self::Umac128 => 16,
+            #[cfg(feature = "umac")]
+            Self::Umac64 => 8,
+            #[cfg(feature = "hmac-md5")]
             Self::HmacMd5ETM | Self::HmacMd5 => Md5::output_size(),
             Self::None => 0,
         }
     }
 
     fn etm(&self) -> bool {
-        matches!(
-            self,
-            Self::HmacSha512ETM | Self::HmacSha256ETM | Self::HmacSha1ETM | Self::HmacMd5ETM
-        )
+        match self {
+            #[cfg(feature = "hmac-sha2")]
+            Self::HmacSha512ETM | Self::HmacSha256ETM => true,
+            #[cfg(feature = "hmac-sha1")]
+            Self::HmacSha1ETM => true,
+            #[cfg(feature = "hmac-md5")]
+            Self::HmacMd5ETM => true,
+            _ => false,
+        }
     }
 }
@@ -0,0 +1,228 @@
+//! The _UMAC_ message authentication code, see <https://datatracker.ietf.org/doc/html/rfc4418>.
+
+use aes::{
+    cipher::{BlockEncrypt, KeyInit},
+    Aes128,
+};
+
+/// Size of the _UMAC_ key, which is an _AES-128_ key.
+pub const KEY_SIZE: usize = 16;
+
+/// Size of the chunks hashed by the first layer, and of the first layer key.
+const L1_KEY_SIZE: usize = 1024;
+
+/// The prime `2^64 - 59`, for the second layer hashing.
+const P64: u64 = 0xFFFF_FFFF_FFFF_FFC5;
+
+/// The prime `2^36 - 5`, for the third layer hashing.
+const P36: u64 = 0x0000_000F_FFFF_FFFB;
+
+/// Compute the _UMAC_ tag of `taglen` bytes of the `message` with the `nonce`.
+pub fn umac(key: &[u8], nonce: &[u8], message: &[u8], taglen: usize) -> Vec<u8> {
+    let cipher = Aes128::new_from_slice(key).expect("Key derivation failed horribly");
+
+    uhash(&cipher, message, taglen)
+        .into_iter()
+        .zip(pdf(&cipher, nonce, taglen))
+        .map(|(hash, pad)| hash ^ pad)
+        .collect()
+}
+
+/// Derive `len` bytes of key material for the `index`, using _AES-128_ in counter mode.
+fn kdf(cipher: &Aes128, index: u64, len: usize) -> Vec<u8> {
+    let mut derived = Vec::with_capacity(len.div_ceil(16) * 16);
+
+    for counter in 1..=len.div_ceil(16) as u64 {
+        let mut block = [0u8; 16];
+        block[..8].copy_from_slice(&index.to_be_bytes());
+        block[8..].copy_from_slice(&counter.to_be_bytes());
+
+        cipher.encrypt_block((&mut block).into());
+        derived.extend_from_slice(&block);
+    }
+
+    derived.truncate(len);
+    derived
+}
+
+/// The pad-derivation function, to be xored with the hashed message.
+fn pdf(cipher: &Aes128, nonce: &[u8], taglen: usize) -> Vec<u8> {
+    let mut block = [0u8; 16];
+    block[..nonce.len()].copy_from_slice(nonce);
+
+    // Small tags are extracted from the block depending on the nonce low bits.
+    let index = if taglen < 16 {
+        let index = nonce[nonce.len() - 1] as usize % (16 / taglen);
+        block[nonce.len() - 1] ^= index as u8;
+
+        index
+    } else {
+        0
+    };
+
+    let key = kdf(cipher, 0, KEY_SIZE);
+    Aes128::new_from_slice(&key)
+        .expect("Key derivation failed horribly")
+        .encrypt_block((&mut block).into());
+
+    block[index * taglen..(index + 1) * taglen].to_vec()
+}
+
+/// The universal hash function, made of three layers.
+fn uhash(cipher: &Aes128, message: &[u8], taglen: usize) -> Vec<u8> {
+    let iters = taglen / 4;
+
+    let l1key = kdf(cipher, 1, L1_KEY_SIZE + (iters - 1) * 16);
+    let l2key = kdf(cipher, 2, iters * 24);
+    let l3key1 = kdf(cipher, 3, iters * 64);
+    let l3key2 = kdf(cipher, 4, iters * 4);
+
+    (0..iters)
+        .flat_map(|i| {
+            let a = l1(&l1key[i * 16..i * 16 + L1_KEY_SIZE], message);
+            let b = if message.len() <= L1_KEY_SIZE {
+                a[0]
+            } else {
+                l2(&l2key[i * 24..(i + 1) * 24], &a)
+            };
+
+            l3(
+                &l3key1[i * 64..(i + 1) * 64],
+                &l3key2[i * 4..(i + 1) * 4],
+                b,
+            )
+            .to_be_bytes()
+        })
+        .collect()
+}
+
+/// The `NH` hash of the `message`, of which the length is a multiple of 32 bytes.
+fn nh(key: &[u8], message: &[u8]) -> u64 {
+    let word = |bytes: &[u8], i: usize| {
+        [
+            bytes[i * 4],
+            bytes[i * 4 + 1],
+            bytes[i * 4 + 2],
+            bytes[i * 4 + 3],
+        ]
+    };
+
+    message
+        .chunks_exact(32)
+        .zip(key.chunks_exact(32))
+        .fold(0u64, |y, (m, k)| {
+            (0..4).fold(y, |y, i| {
+                let a = u32::from_le_bytes(word(m, i)).wrapping_add(u32::from_be_bytes(word(k, i)));
+                let b = u32::from_le_bytes(word(m, i + 4))
+                    .wrapping_add(u32::from_be_bytes(word(k, i + 4)));
+
+                y.wrapping_add(u64::from(a) * u64::from(b))
+            })
+        })
+}
+
+/// The first layer, hashing each 1024 bytes chunk of the `message` with `NH`.
+fn l1(key: &[u8], message: &[u8]) -> Vec<u64> {
+    let chunks = if message.is_empty() {
+        vec![message]
+    } else {
+        message.chunks(L1_KEY_SIZE).collect()
+    };
+
+    chunks
+        .into_iter()
+        .map(|chunk| {
+            let mut padded = chunk.to_vec();
+            padded.resize(chunk.len().div_ceil(32).max(1) * 32, 0);
+
+            nh(key, &padded).wrapping_add(chunk.len() as u64 * 8)
+        })
+        .collect()
+}
+
+/// The second layer, reducing the hashed chunks with a polynomial hash.
+///
+/// As packets are way smaller than `2^24` bytes, only the 64-bit polynomial is needed here.
+fn l2(key: &[u8], words: &[u64]) -> u64 {
+    let mut k = [0u8; 8];
+    k.copy_from_slice(&key[..8]);
+    let k = u128::from(u64::from_be_bytes(k) & 0x01FF_FFFF_01FF_FFFF);
+
+    let poly = |y: u64, m: u64| ((k * u128::from(y) + u128::from(m)) % u128::from(P64)) as u64;
+
+    words.iter().fold(1, |y, &m| {
+        if m >= 0xFFFF_FFFF_0000_0000 {
+            poly(poly(y, P64 - 1), m - (u64::MAX - P64 + 1))
+        } else {
+            poly(y, m)
+        }
+    })
+}
+
+/// The third layer, hashing the 128-bit value whose high half is zero, and the `m` low half.
+fn l3(key1: &[u8], key2: &[u8], m: u64) -> u32 {
+    let y = (0..4).fold(0u64, |y, i| {
+        let mut k = [0u8; 8];
+        k.copy_from_slice(&key1[(4 + i) * 8..(5 + i) * 8]);
+
+        y + ((m >> (48 - 16 * i)) & 0xFFFF) * (u64::from_be_bytes(k) % P36)
+    });
+
+    (y % P36) as u32 ^ u32::from_be_bytes([key2[0], key2[1], key2[2], key2[3]])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &[u8] = b"abcdefghijklmnop";
+    const NONCE: &[u8] = b"bcdefghi";
+
+    fn hex(bytes: Vec<u8>) -> String {
+        use std::fmt::Write;
+
+        bytes.iter().fold(String::new(), |mut hex, byte| {
+            write!(hex, "{byte:02X}").expect("Writing to a `String` can't fail");
+            hex
+        })
+    }
+
+    #[test]
+    fn rfc4418_vectors() {
+        for (message, umac64, umac128) in [
+            (
+                b"".to_vec(),
+                "6E155FAD26900BE1",
+                "32FEDB100C79AD58F07FF7643CC60465",
+            ),
+            (
+                b"aaa".to_vec(),
+                "44B5CB542F220104",
+                "185E4FE905CBA7BD85E4C2DC3D117D8D",
+            ),
+            (
+                vec![b'a'; 1 << 10],
+                "26BF2F5D60118BD9",
+                "7A54ABE04AF82D60FB298C3CBD195BCB",
+            ),
+            (
+                vec![b'a'; 1 << 15],
+                "27F8EF643B0D118D",
+                "7B136BD911E4B734286EF2BE501F2C3C",
+            ),
+            (
+                b"abc".to_vec(),
+                "D4D7B9F6BD4FBFCF",
+                "883C3D4B97A61976FFCF232308CBA5A5",
+            ),
+            (
+                b"abc".repeat(500),
+                "D4CF26DDEFD5C01A",
+                "8824A260C53C66A36C9260A62CB83AA1",
+            ),
+        ] {
+            assert_eq!(hex(umac(KEY, NONCE, &message, 8)), umac64);
+            assert_eq!(hex(umac(KEY, NONCE, &message, 16)), umac128);
+        }
+    }
+}
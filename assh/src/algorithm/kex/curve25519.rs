@@ -1,19 +1,24 @@
 use digest::{Digest, FixedOutputReset};
 use secrecy::{ExposeSecret, SecretBox};
-use signature::{SignatureEncoding, Signer, Verifier};
-use ssh_key::{PrivateKey, Signature};
+use signature::{SignatureEncoding, Verifier};
+use ssh_key::Signature;
 use ssh_packet::{
     arch::MpInt,
     crypto::exchange,
     trans::{KexEcdhInit, KexEcdhReply},
 };
+use zeroize::Zeroizing;
 
-use crate::{stream::Stream, Error, Pipe, Result};
+use crate::{
+    algorithm::{HostKey, HostKeySigner},
+    stream::KexStream,
+    Error, Result,
+};
 
-use super::{KexMeta, Keys, Transport};
+use super::{trimmed, KexMeta, Keys, Transport};
 
 pub async fn as_client<H: Digest + FixedOutputReset>(
-    stream: &mut Stream<impl Pipe>,
+    stream: &mut KexStream<'_>,
     client: KexMeta<'_>,
     server: KexMeta<'_>,
 ) -> Result<(Transport, Transport)> {
@@ -32,22 +37,29 @@ pub async fn as_client<H: Digest + FixedOutputReset>(
     );
 
     let secret = e_c.diffie_hellman(&q_s);
-    let secret = SecretBox::new(MpInt::positive(secret.as_bytes()).into());
-
-    let k_s = ssh_key::PublicKey::from_bytes(&ecdh.k_s)?;
-    let hash = exchange::Ecdh {
-        v_c: client.id.to_string().into_bytes().into(),
-        v_s: server.id.to_string().into_bytes().into(),
-        i_c: (client.kexinit).into(),
-        i_s: (server.kexinit).into(),
-        k_s: ecdh.k_s,
-        q_c: q_c.as_ref().into(),
-        q_s: q_s.as_ref().into(),
-        k: secret.expose_secret().as_borrow(),
-    }
-    .hash::<H>();
-
-    Verifier::verify(&k_s, &hash, &Signature::try_from(ecdh.signature.as_ref())?)?;
+    let secret = SecretBox::new(MpInt::positive(trimmed(secret.as_bytes())).into());
+
+    let k_s = HostKey::from_bytes(&ecdh.k_s)?;
+    let hash = Zeroizing::new(
+        exchange::Ecdh {
+            v_c: client.id.to_string().into_bytes().into(),
+            v_s: server.id.to_string().into_bytes().into(),
+            i_c: (client.kexinit).into(),
+            i_s: (server.kexinit).into(),
+            k_s: ecdh.k_s,
+            q_c: q_c.as_ref().into(),
+            q_s: q_s.as_ref().into(),
+            k: secret.expose_secret().as_borrow(),
+        }
+        .hash::<H>(),
+    );
+
+    Verifier::verify(
+        &k_s.public_key(),
+        &hash,
+        &Signature::try_from(ecdh.signature.as_ref())?,
+    )?;
+    stream.with_host_key(k_s);
 
     let session_id = stream.with_session(&hash);
 
@@ -55,7 +67,7 @@ pub async fn as_client<H: Digest + FixedOutputReset>(
         secret.expose_secret(),
         &hash,
         session_id,
-        &client.cipher,
+        &*client.cipher,
         &client.hmac,
     );
     let client = client.into_transport(keys);
@@ -64,7 +76,7 @@ pub async fn as_client<H: Digest + FixedOutputReset>(
         secret.expose_secret(),
         &hash,
         session_id,
-        &server.cipher,
+        &*server.cipher,
         &server.hmac,
     );
     let server = server.into_transport(keys);
@@ -73,10 +85,11 @@ pub async fn as_client<H: Digest + FixedOutputReset>(
 }
 
 pub async fn as_server<H: Digest + FixedOutputReset>(
-    stream: &mut Stream<impl Pipe>,
+    stream: &mut KexStream<'_>,
     client: KexMeta<'_>,
     server: KexMeta<'_>,
-    key: &PrivateKey,
+    key: &dyn HostKeySigner,
+    host_key: &HostKey,
 ) -> Result<(Transport, Transport)> {
     let ecdh: KexEcdhInit = stream.recv().await?.to()?;
 
@@ -88,23 +101,25 @@ pub async fn as_server<H: Digest + FixedOutputReset>(
     );
 
     let secret = e_s.diffie_hellman(&q_c);
-    let secret = SecretBox::new(MpInt::positive(secret.as_bytes()).into());
+    let secret = SecretBox::new(MpInt::positive(trimmed(secret.as_bytes())).into());
 
-    let k_s = key.public_key().to_bytes()?;
+    let k_s = host_key.to_bytes()?;
 
-    let hash = exchange::Ecdh {
-        v_c: client.id.to_string().into_bytes().into(),
-        v_s: server.id.to_string().into_bytes().into(),
-        i_c: (client.kexinit).into(),
-        i_s: (server.kexinit).into(),
-        k_s: k_s.as_slice().into(),
-        q_c: q_c.as_ref().into(),
-        q_s: q_s.as_ref().into(),
-        k: secret.expose_secret().as_borrow(),
-    }
-    .hash::<H>();
+    let hash = Zeroizing::new(
+        exchange::Ecdh {
+            v_c: client.id.to_string().into_bytes().into(),
+            v_s: server.id.to_string().into_bytes().into(),
+            i_c: (client.kexinit).into(),
+            i_s: (server.kexinit).into(),
+            k_s: k_s.as_slice().into(),
+            q_c: q_c.as_ref().into(),
+            q_s: q_s.as_ref().into(),
+            k: secret.expose_secret().as_borrow(),
+        }
+        .hash::<H>(),
+    );
 
-    let signature = Signer::sign(key, &hash);
+    let signature = key.sign(&hash).await?;
 
     stream
         .send(&KexEcdhReply {
@@ -120,7 +135,7 @@ pub async fn as_server<H: Digest + FixedOutputReset>(
         secret.expose_secret(),
         &hash,
         session_id,
-        &client.cipher,
+        &*client.cipher,
         &client.hmac,
     );
     let client = client.into_transport(keys);
@@ -129,7 +144,7 @@ pub async fn as_server<H: Digest + FixedOutputReset>(
         secret.expose_secret(),
         &hash,
         session_id,
-        &server.cipher,
+        &*server.cipher,
         &server.hmac,
     );
     let server = server.into_transport(keys);
@@ -0,0 +1,158 @@
+use digest::{Digest, FixedOutputReset};
+use rand::RngCore;
+use secrecy::{ExposeSecret, SecretBox};
+use signature::{SignatureEncoding, Verifier};
+use ssh_key::Signature;
+use ssh_packet::{
+    arch::MpInt,
+    crypto::exchange,
+    trans::{KexEcdhInit, KexEcdhReply},
+};
+use zeroize::Zeroizing;
+
+use crate::{
+    algorithm::{HostKey, HostKeySigner},
+    stream::KexStream,
+    Error, Result,
+};
+
+use super::{trimmed, KexMeta, Keys, Transport};
+
+/// Generate a random ephemeral secret for the exchange.
+fn secret() -> Result<x448::Secret> {
+    let mut bytes = Zeroizing::new([0u8; 56]);
+    rand::thread_rng().fill_bytes(bytes.as_mut());
+
+    x448::Secret::from_bytes(bytes.as_ref()).ok_or(Error::KexError)
+}
+
+pub async fn as_client<H: Digest + FixedOutputReset>(
+    stream: &mut KexStream<'_>,
+    client: KexMeta<'_>,
+    server: KexMeta<'_>,
+) -> Result<(Transport, Transport)> {
+    let e_c = secret()?;
+    let q_c = x448::PublicKey::from(&e_c);
+
+    stream
+        .send(&KexEcdhInit {
+            q_c: q_c.as_bytes().as_slice().into(),
+        })
+        .await?;
+
+    let ecdh: KexEcdhReply = stream.recv().await?.to()?;
+    let q_s = x448::PublicKey::from_bytes(&ecdh.q_s).ok_or(Error::KexError)?;
+
+    let secret = e_c.as_diffie_hellman(&q_s).ok_or(Error::KexError)?;
+    let secret = SecretBox::new(MpInt::positive(trimmed(secret.as_bytes())).into());
+
+    let k_s = HostKey::from_bytes(&ecdh.k_s)?;
+    let hash = Zeroizing::new(
+        exchange::Ecdh {
+            v_c: client.id.to_string().into_bytes().into(),
+            v_s: server.id.to_string().into_bytes().into(),
+            i_c: (client.kexinit).into(),
+            i_s: (server.kexinit).into(),
+            k_s: ecdh.k_s,
+            q_c: q_c.as_bytes().as_slice().into(),
+            q_s: q_s.as_bytes().as_slice().into(),
+            k: secret.expose_secret().as_borrow(),
+        }
+        .hash::<H>(),
+    );
+
+    Verifier::verify(
+        &k_s.public_key(),
+        &hash,
+        &Signature::try_from(ecdh.signature.as_ref())?,
+    )?;
+    stream.with_host_key(k_s);
+
+    let session_id = stream.with_session(&hash);
+
+    let keys = Keys::as_client::<H>(
+        secret.expose_secret(),
+        &hash,
+        session_id,
+        &*client.cipher,
+        &client.hmac,
+    );
+    let client = client.into_transport(keys);
+
+    let keys = Keys::as_server::<H>(
+        secret.expose_secret(),
+        &hash,
+        session_id,
+        &*server.cipher,
+        &server.hmac,
+    );
+    let server = server.into_transport(keys);
+
+    Ok((client, server))
+}
+
+pub async fn as_server<H: Digest + FixedOutputReset>(
+    stream: &mut KexStream<'_>,
+    client: KexMeta<'_>,
+    server: KexMeta<'_>,
+    key: &dyn HostKeySigner,
+    host_key: &HostKey,
+) -> Result<(Transport, Transport)> {
+    let ecdh: KexEcdhInit = stream.recv().await?.to()?;
+
+    let e_s = secret()?;
+    let q_s = x448::PublicKey::from(&e_s);
+
+    let q_c = x448::PublicKey::from_bytes(&ecdh.q_c).ok_or(Error::KexError)?;
+
+    let secret = e_s.as_diffie_hellman(&q_c).ok_or(Error::KexError)?;
+    let secret = SecretBox::new(MpInt::positive(trimmed(secret.as_bytes())).into());
+
+    let k_s = host_key.to_bytes()?;
+
+    let hash = Zeroizing::new(
+        exchange::Ecdh {
+            v_c: client.id.to_string().into_bytes().into(),
+            v_s: server.id.to_string().into_bytes().into(),
+            i_c: (client.kexinit).into(),
+            i_s: (server.kexinit).into(),
+            k_s: k_s.as_slice().into(),
+            q_c: q_c.as_bytes().as_slice().into(),
+            q_s: q_s.as_bytes().as_slice().into(),
+            k: secret.expose_secret().as_borrow(),
+        }
+        .hash::<H>(),
+    );
+
+    let signature = key.sign(&hash).await?;
+
+    stream
+        .send(&KexEcdhReply {
+            k_s: k_s.into(),
+            q_s: q_s.as_bytes().as_slice().into(),
+            signature: signature.to_vec().into(),
+        })
+        .await?;
+
+    let session_id = stream.with_session(&hash);
+
+    let keys = Keys::as_client::<H>(
+        secret.expose_secret(),
+        &hash,
+        session_id,
+        &*client.cipher,
+        &client.hmac,
+    );
+    let client = client.into_transport(keys);
+
+    let keys = Keys::as_server::<H>(
+        secret.expose_secret(),
+        &hash,
+        session_id,
+        &*server.cipher,
+        &server.hmac,
+    );
+    let server = server.into_transport(keys);
+
+    Ok((client, server))
+}
@@ -0,0 +1,226 @@
+use digest::{Digest, FixedOutputReset};
+use num_bigint_dig::BigUint;
+use rand::RngCore;
+use secrecy::{ExposeSecret, SecretBox};
+use signature::{SignatureEncoding, Verifier};
+use ssh_key::Signature;
+use ssh_packet::{
+    arch::MpInt,
+    crypto::exchange,
+    trans::{KexEcdhInit, KexEcdhReply},
+};
+use zeroize::Zeroizing;
+
+use crate::{
+    algorithm::{HostKey, HostKeySigner},
+    stream::KexStream,
+    Error, Result,
+};
+
+use super::{KexMeta, Keys, Transport};
+
+// NOTE: The `SSH_MSG_KEXDH_INIT` and `SSH_MSG_KEXDH_REPLY` messages share their
+// identifiers and layout with the ECDH ones, the `e` and `f` values being
+// transmitted as an `mpint`, which is encoded as a `string` on the wire.
+
+/// A finite-field Diffie-Hellman group, with it's generator.
+pub struct Group {
+    prime: &'static str,
+    generator: u32,
+}
+
+/// The _Oakley Group 2_, see <https://datatracker.ietf.org/doc/html/rfc2409#section-6.2>.
+pub const GROUP1: Group = Group {
+    prime: "\
+        FFFFFFFFFFFFFFFFC90FDAA22168C234C4C6628B80DC1CD129024E088A67CC74\
+        020BBEA63B139B22514A08798E3404DDEF9519B3CD3A431B302B0A6DF25F1437\
+        4FE1356D6D51C245E485B576625E7EC6F44C42E9A637ED6B0BFF5CB6F406B7ED\
+        EE386BFB5A899FA5AE9F24117C4B1FE649286651ECE65381FFFFFFFFFFFFFFFF\
+    ",
+    generator: 2,
+};
+
+/// The _Oakley Group 14_, see <https://datatracker.ietf.org/doc/html/rfc3526#section-3>.
+pub const GROUP14: Group = Group {
+    prime: "\
+        FFFFFFFFFFFFFFFFC90FDAA22168C234C4C6628B80DC1CD129024E088A67CC74\
+        020BBEA63B139B22514A08798E3404DDEF9519B3CD3A431B302B0A6DF25F1437\
+        4FE1356D6D51C245E485B576625E7EC6F44C42E9A637ED6B0BFF5CB6F406B7ED\
+        EE386BFB5A899FA5AE9F24117C4B1FE649286651ECE45B3DC2007CB8A163BF05\
+        98DA48361C55D39A69163FA8FD24CF5F83655D23DCA3AD961C62F356208552BB\
+        9ED529077096966D670C354E4ABC9804F1746C08CA18217C32905E462E36CE3B\
+        E39E772C180E86039B2783A2EC07A28FB5C55DF06F4C52C9DE2BCBF695581718\
+        3995497CEA956AE515D2261898FA051015728E5A8AACAA68FFFFFFFFFFFFFFFF\
+    ",
+    generator: 2,
+};
+
+impl Group {
+    fn prime(&self) -> BigUint {
+        BigUint::parse_bytes(self.prime.as_bytes(), 16).expect("Invalid group prime")
+    }
+
+    /// Generate a random ephemeral secret `x` in `[2, p - 2]`, and the public value `g^x mod p`.
+    fn keypair(&self) -> (Zeroizing<BigUint>, BigUint) {
+        let prime = self.prime();
+
+        let mut bytes = Zeroizing::new(vec![0u8; prime.bits().div_ceil(8)]);
+        rand::thread_rng().fill_bytes(&mut bytes);
+
+        let x = Zeroizing::new(BigUint::from_bytes_be(&bytes) % (&prime - 3u32) + 2u32);
+        let e = BigUint::from(self.generator).modpow(&x, &prime);
+
+        (x, e)
+    }
+
+    /// Compute the shared secret from the peer's public value, ensuring it lies in `[2, p - 2]`.
+    fn shared(&self, x: &BigUint, peer: &[u8]) -> Result<Zeroizing<Vec<u8>>> {
+        let prime = self.prime();
+
+        // Refuse negative `mpint` values, encoded with their high-bit set.
+        if peer.first().is_some_and(|byte| byte & 0x80 != 0) {
+            return Err(Error::KexError);
+        }
+
+        let f = BigUint::from_bytes_be(peer);
+        if f < BigUint::from(2u32) || f > &prime - 2u32 {
+            return Err(Error::KexError);
+        }
+
+        let secret = Zeroizing::new(f.modpow(x, &prime));
+
+        Ok(Zeroizing::new(secret.to_bytes_be()))
+    }
+}
+
+pub async fn as_client<H: Digest + FixedOutputReset>(
+    stream: &mut KexStream<'_>,
+    client: KexMeta<'_>,
+    server: KexMeta<'_>,
+    group: &Group,
+) -> Result<(Transport, Transport)> {
+    let (x, e) = group.keypair();
+    let e = e.to_bytes_be();
+    let e = MpInt::positive(&e);
+
+    stream
+        .send(&KexEcdhInit {
+            q_c: e.as_ref().into(),
+        })
+        .await?;
+
+    let dh: KexEcdhReply = stream.recv().await?.to()?;
+
+    let secret = group.shared(&x, &dh.q_s)?;
+    let secret = SecretBox::new(MpInt::positive(&secret).into());
+
+    let k_s = HostKey::from_bytes(&dh.k_s)?;
+    let hash = Zeroizing::new(
+        exchange::Ecdh {
+            v_c: client.id.to_string().into_bytes().into(),
+            v_s: server.id.to_string().into_bytes().into(),
+            i_c: (client.kexinit).into(),
+            i_s: (server.kexinit).into(),
+            k_s: dh.k_s,
+            q_c: e.as_ref().into(),
+            q_s: dh.q_s,
+            k: secret.expose_secret().as_borrow(),
+        }
+        .hash::<H>(),
+    );
+
+    Verifier::verify(
+        &k_s.public_key(),
+        &hash,
+        &Signature::try_from(dh.signature.as_ref())?,
+    )?;
+    stream.with_host_key(k_s);
+
+    let session_id = stream.with_session(&hash);
+
+    let keys = Keys::as_client::<H>(
+        secret.expose_secret(),
+        &hash,
+        session_id,
+        &*client.cipher,
+        &client.hmac,
+    );
+    let client = client.into_transport(keys);
+
+    let keys = Keys::as_server::<H>(
+        secret.expose_secret(),
+        &hash,
+        session_id,
+        &*server.cipher,
+        &server.hmac,
+    );
+    let server = server.into_transport(keys);
+
+    Ok((client, server))
+}
+
+pub async fn as_server<H: Digest + FixedOutputReset>(
+    stream: &mut KexStream<'_>,
+    client: KexMeta<'_>,
+    server: KexMeta<'_>,
+    key: &dyn HostKeySigner,
+    host_key: &HostKey,
+    group: &Group,
+) -> Result<(Transport, Transport)> {
+    let dh: KexEcdhInit = stream.recv().await?.to()?;
+
+    let (y, f) = group.keypair();
+    let f = f.to_bytes_be();
+    let f = MpInt::positive(&f);
+
+    let secret = group.shared(&y, &dh.q_c)?;
+    let secret = SecretBox::new(MpInt::positive(&secret).into());
+
+    let k_s = host_key.to_bytes()?;
+
+    let hash = Zeroizing::new(
+        exchange::Ecdh {
+            v_c: client.id.to_string().into_bytes().into(),
+            v_s: server.id.to_string().into_bytes().into(),
+            i_c: (client.kexinit).into(),
+            i_s: (server.kexinit).into(),
+            k_s: k_s.as_slice().into(),
+            q_c: dh.q_c,
+            q_s: f.as_ref().into(),
+            k: secret.expose_secret().as_borrow(),
+        }
+        .hash::<H>(),
+    );
+
+    let signature = key.sign(&hash).await?;
+
+    stream
+        .send(&KexEcdhReply {
+            k_s: k_s.into(),
+            q_s: f.as_ref().into(),
+            signature: signature.to_vec().into(),
+        })
+        .await?;
+
+    let session_id = stream.with_session(&hash);
+
+    let keys = Keys::as_client::<H>(
+        secret.expose_secret(),
+        &hash,
+        session_id,
+        &*client.cipher,
+        &client.hmac,
+    );
+    let client = client.into_transport(keys);
+
+    let keys = Keys::as_server::<H>(
+        secret.expose_secret(),
+        &hash,
+        session_id,
+        &*server.cipher,
+        &server.hmac,
+    );
+    let server = server.into_transport(keys);
+
+    Ok((client, server))
+}
@@ -0,0 +1,166 @@
+use digest::{Digest, FixedOutputReset};
+use elliptic_curve::{
+    ecdh::EphemeralSecret,
+    sec1::{FromEncodedPoint, ModulusSize, ToEncodedPoint},
+    AffinePoint, CurveArithmetic, FieldBytesSize, PublicKey,
+};
+use secrecy::{ExposeSecret, SecretBox};
+use signature::{SignatureEncoding, Verifier};
+use ssh_key::Signature;
+use ssh_packet::{
+    arch::MpInt,
+    crypto::exchange,
+    trans::{KexEcdhInit, KexEcdhReply},
+};
+use zeroize::Zeroizing;
+
+use crate::{
+    algorithm::{HostKey, HostKeySigner},
+    stream::KexStream,
+    Error, Result,
+};
+
+use super::{trimmed, KexMeta, Keys, Transport};
+
+pub async fn as_client<C, H>(
+    stream: &mut KexStream<'_>,
+    client: KexMeta<'_>,
+    server: KexMeta<'_>,
+) -> Result<(Transport, Transport)>
+where
+    C: CurveArithmetic,
+    FieldBytesSize<C>: ModulusSize,
+    AffinePoint<C>: FromEncodedPoint<C> + ToEncodedPoint<C>,
+    H: Digest + FixedOutputReset,
+{
+    let e_c = EphemeralSecret::<C>::random(&mut rand::thread_rng());
+    let q_c = e_c.public_key().to_encoded_point(false);
+
+    stream
+        .send(&KexEcdhInit {
+            q_c: q_c.as_bytes().into(),
+        })
+        .await?;
+
+    let ecdh: KexEcdhReply = stream.recv().await?.to()?;
+    let q_s = PublicKey::<C>::from_sec1_bytes(&ecdh.q_s).map_err(|_| Error::KexError)?;
+
+    let secret = e_c.diffie_hellman(&q_s);
+    let secret = SecretBox::new(MpInt::positive(trimmed(secret.raw_secret_bytes())).into());
+
+    let k_s = HostKey::from_bytes(&ecdh.k_s)?;
+    let hash = Zeroizing::new(
+        exchange::Ecdh {
+            v_c: client.id.to_string().into_bytes().into(),
+            v_s: server.id.to_string().into_bytes().into(),
+            i_c: (client.kexinit).into(),
+            i_s: (server.kexinit).into(),
+            k_s: ecdh.k_s,
+            q_c: q_c.as_bytes().into(),
+            q_s: ecdh.q_s,
+            k: secret.expose_secret().as_borrow(),
+        }
+        .hash::<H>(),
+    );
+
+    Verifier::verify(
+        &k_s.public_key(),
+        &hash,
+        &Signature::try_from(ecdh.signature.as_ref())?,
+    )?;
+    stream.with_host_key(k_s);
+
+    let session_id = stream.with_session(&hash);
+
+    let keys = Keys::as_client::<H>(
+        secret.expose_secret(),
+        &hash,
+        session_id,
+        &*client.cipher,
+        &client.hmac,
+    );
+    let client = client.into_transport(keys);
+
+    let keys = Keys::as_server::<H>(
+        secret.expose_secret(),
+        &hash,
+        session_id,
+        &*server.cipher,
+        &server.hmac,
+    );
+    let server = server.into_transport(keys);
+
+    Ok((client, server))
+}
+
+pub async fn as_server<C, H>(
+    stream: &mut KexStream<'_>,
+    client: KexMeta<'_>,
+    server: KexMeta<'_>,
+    key: &dyn HostKeySigner,
+    host_key: &HostKey,
+) -> Result<(Transport, Transport)>
+where
+    C: CurveArithmetic,
+    FieldBytesSize<C>: ModulusSize,
+    AffinePoint<C>: FromEncodedPoint<C> + ToEncodedPoint<C>,
+    H: Digest + FixedOutputReset,
+{
+    let ecdh: KexEcdhInit = stream.recv().await?.to()?;
+
+    let e_s = EphemeralSecret::<C>::random(&mut rand::thread_rng());
+    let q_s = e_s.public_key().to_encoded_point(false);
+
+    let q_c = PublicKey::<C>::from_sec1_bytes(&ecdh.q_c).map_err(|_| Error::KexError)?;
+
+    let secret = e_s.diffie_hellman(&q_c);
+    let secret = SecretBox::new(MpInt::positive(trimmed(secret.raw_secret_bytes())).into());
+
+    let k_s = host_key.to_bytes()?;
+
+    let hash = Zeroizing::new(
+        exchange::Ecdh {
+            v_c: client.id.to_string().into_bytes().into(),
+            v_s: server.id.to_string().into_bytes().into(),
+            i_c: (client.kexinit).into(),
+            i_s: (server.kexinit).into(),
+            k_s: k_s.as_slice().into(),
+            q_c: ecdh.q_c,
+            q_s: q_s.as_bytes().into(),
+            k: secret.expose_secret().as_borrow(),
+        }
+        .hash::<H>(),
+    );
+
+    let signature = key.sign(&hash).await?;
+
+    stream
+        .send(&KexEcdhReply {
+            k_s: k_s.into(),
+            q_s: q_s.as_bytes().into(),
+            signature: signature.to_vec().into(),
+        })
+        .await?;
+
+    let session_id = stream.with_session(&hash);
+
+    let keys = Keys::as_client::<H>(
+        secret.expose_secret(),
+        &hash,
+        session_id,
+        &*client.cipher,
+        &client.hmac,
+    );
+    let client = client.into_transport(keys);
+
+    let keys = Keys::as_server::<H>(
+        secret.expose_secret(),
+        &hash,
+        session_id,
+        &*server.cipher,
+        &server.hmac,
+    );
+    let server = server.into_transport(keys);
+
+    Ok((client, server))
+}
@@ -1,40 +1,67 @@
-use std::any::TypeId;
+use std::{any::TypeId, sync::Arc};
 
 use ssh_packet::{trans::KexInit, Id};
 
 use crate::{
-    algorithm::{Cipher, Compress, Hmac, Negociate},
+    algorithm::{cipher, Cipher, CipherAlgorithm, Compress, Hmac, Negociate},
     side::{client::Client, server::Server, Side},
     stream::{Keys, Transport},
     Result,
 };
 
+/// The negociated algorithms and exchanged data for one side of the key-exchange.
 pub struct KexMeta<'k> {
+    /// The [`Id`] of this side.
     pub id: &'k Id,
 
+    /// The negociated _compression_ algorithm for this side.
     pub compress: Compress,
-    pub cipher: Cipher,
+
+    /// The negociated _encryption_ algorithm for this side.
+    pub cipher: Arc<dyn CipherAlgorithm>,
+
+    /// The negociated _hmac_ algorithm for this side.
     pub hmac: Hmac,
 
+    /// The [`KexInit`] sent by this side.
     pub kexinit: &'k KexInit<'k>,
 }
 
 impl<'k> KexMeta<'k> {
-    pub fn new<S: Side>(
+    pub(crate) fn new<S: Side>(
         id: &'k Id,
         clientkex: &'k KexInit<'k>,
         serverkex: &'k KexInit<'k>,
+        custom: &[Arc<dyn CipherAlgorithm>],
     ) -> Result<Self>
     where
         Compress: Negociate<S>,
         Cipher: Negociate<S>,
         Hmac: Negociate<S>,
     {
+        let cipher = cipher::negociate::<S>(clientkex, serverkex, custom)?;
+
+        // AEAD ciphers authenticate the packets by themselves, the hmac is then ignored.
+        let hmac = if cipher.is_aead() {
+            Hmac::None
+        } else {
+            <Hmac as Negociate<S>>::negociate(clientkex, serverkex)?
+        };
+
+        if cipher.name() == Cipher::None.as_ref() {
+            tracing::warn!("The `none` cipher has been negociated, packets will be sent in clear");
+        }
+        if hmac == Hmac::None && !cipher.is_aead() {
+            tracing::warn!(
+                "The `none` hmac has been negociated, packets will not be authenticated"
+            );
+        }
+
         Ok(Self {
             id,
             compress: <Compress as Negociate<S>>::negociate(clientkex, serverkex)?,
-            cipher: <Cipher as Negociate<S>>::negociate(clientkex, serverkex)?,
-            hmac: <Hmac as Negociate<S>>::negociate(clientkex, serverkex)?,
+            cipher,
+            hmac,
             kexinit: if TypeId::of::<S>() == TypeId::of::<Client>() {
                 clientkex
             } else if TypeId::of::<S>() == TypeId::of::<Server>() {
@@ -45,6 +72,7 @@ impl<'k> KexMeta<'k> {
         })
     }
 
+    /// Combine the negociated algorithms with the derived `keys` into a [`Transport`].
     pub fn into_transport(self, keys: Keys) -> Transport {
         let Self {
             compress,
@@ -54,11 +82,79 @@ impl<'k> KexMeta<'k> {
         } = self;
 
         Transport {
+            delayed: compress.is_delayed(),
             compress,
             cipher,
             hmac,
-            state: None,
             chain: keys,
+            ..Default::default()
         }
     }
 }
+
+#[cfg(all(test, feature = "aes-gcm", feature = "aes-ctr", feature = "hmac-sha2"))]
+mod tests {
+    use ssh_packet::arch::NameList;
+
+    use super::*;
+    use crate::Error;
+
+    fn kexinit<'k>(ciphers: [&'k str; 2], macs: [&'k str; 2]) -> KexInit<'k> {
+        KexInit {
+            cookie: Default::default(),
+            kex_algorithms: NameList::from_iter(["curve25519-sha256"]),
+            server_host_key_algorithms: NameList::from_iter(["ssh-ed25519"]),
+            encryption_algorithms_client_to_server: NameList::from_iter([ciphers[0]]),
+            encryption_algorithms_server_to_client: NameList::from_iter([ciphers[1]]),
+            mac_algorithms_client_to_server: NameList::from_iter([macs[0]]),
+            mac_algorithms_server_to_client: NameList::from_iter([macs[1]]),
+            compression_algorithms_client_to_server: NameList::from_iter(["none"]),
+            compression_algorithms_server_to_client: NameList::from_iter(["none"]),
+            languages_client_to_server: Default::default(),
+            languages_server_to_client: Default::default(),
+            first_kex_packet_follows: false.into(),
+        }
+    }
+
+    #[test]
+    fn hmac_is_not_negociated_for_aead_directions() -> Result<()> {
+        let id = Id::v2("test", None::<&str>);
+        let clientkex = kexinit(
+            ["aes256-gcm@openssh.com", "aes256-ctr"],
+            ["vendor-mac@example.com", "hmac-sha2-256"],
+        );
+        let serverkex = kexinit(
+            ["aes256-gcm@openssh.com", "aes256-ctr"],
+            ["hmac-sha2-512", "hmac-sha2-256"],
+        );
+
+        let client = KexMeta::new::<Client>(&id, &clientkex, &serverkex, &[])?;
+        assert_eq!(client.cipher.name(), "aes256-gcm@openssh.com");
+        assert_eq!(client.hmac, Hmac::None);
+
+        let server = KexMeta::new::<Server>(&id, &clientkex, &serverkex, &[])?;
+        assert_eq!(server.cipher.name(), "aes256-ctr");
+        assert_eq!(server.hmac, Hmac::HmacSha256);
+
+        Ok(())
+    }
+
+    #[test]
+    fn hmac_is_negociated_for_non_aead_directions() {
+        let id = Id::v2("test", None::<&str>);
+        let clientkex = kexinit(
+            ["aes256-gcm@openssh.com", "aes256-ctr"],
+            ["hmac-sha2-256", "vendor-mac@example.com"],
+        );
+        let serverkex = kexinit(
+            ["aes256-gcm@openssh.com", "aes256-ctr"],
+            ["hmac-sha2-256", "hmac-sha2-512"],
+        );
+
+        assert!(KexMeta::new::<Client>(&id, &clientkex, &serverkex, &[]).is_ok());
+        assert!(matches!(
+            KexMeta::new::<Server>(&id, &clientkex, &serverkex, &[]),
+            Err(Error::NoCommonHmac { .. })
+        ));
+    }
+}
@@ -1,30 +1,124 @@
-use ssh_key::PrivateKey;
+//! Key-exchange algorithms, and the [`KexAlgorithm`] trait to extend them.
+
+use std::{future::Future, pin::Pin, sync::Arc};
+
 use ssh_packet::{arch::NameList, trans::KexInit};
 use strum::{AsRefStr, EnumString};
 
 use crate::{
-    stream::{Keys, Stream, Transport, TransportPair},
-    Error, Pipe, Result,
+    algorithm::{HostKey, HostKeySigner},
+    Error, Result,
 };
 
-use super::Negociate;
-
-// TODO: (reliability) Investigate the randomly-occuring `invalid signature` occuring against OpenSSH.
+#[doc(no_inline)]
+pub use crate::stream::{KexStream, Keys, Transport, TransportPair};
 
 mod meta;
 pub use meta::KexMeta;
 
+#[cfg(feature = "curve25519")]
 mod curve25519;
 
-impl Negociate for Kex {
-    const ERR: Error = Error::NoCommonKex;
+#[cfg(feature = "curve448")]
+mod curve448;
+
+#[cfg(feature = "ecdh-nistp")]
+mod ecdh;
+
+#[cfg(feature = "sntrup761")]
+mod sntrup761x25519;
+
+#[cfg(feature = "legacy-kex")]
+mod dh;
+
+#[cfg(not(any(
+    feature = "curve25519",
+    feature = "curve448",
+    feature = "ecdh-nistp",
+    feature = "sntrup761",
+    feature = "legacy-kex"
+)))]
+compile_error!(
+    "At least one of the key-exchange features of `assh` is required, such as `curve25519`."
+);
+
+/// Marker advertised by the _client_ to signal support for the _strict key-exchange_.
+pub(crate) const KEX_STRICT_CLIENT: &str = "kex-strict-c-v00@openssh.com";
+
+/// Marker advertised by the _server_ to signal support for the _strict key-exchange_.
+pub(crate) const KEX_STRICT_SERVER: &str = "kex-strict-s-v00@openssh.com";
+
+/// Marker advertised by the _client_ to signal support for the extension negotiation, as described in RFC8308.
+pub(crate) const EXT_INFO_CLIENT: &str = "ext-info-c";
+
+/// The future returned by the [`KexAlgorithm`] methods.
+pub type KexFuture<'a> = Pin<Box<dyn Future<Output = Result<TransportPair>> + Send + Sync + 'a>>;
+
+/// A key-exchange algorithm, implemented by the built-in [`Kex`] methods,
+/// and which can be implemented to provide custom methods to the _sessions_.
+pub trait KexAlgorithm: std::fmt::Debug + Send + Sync + 'static {
+    /// The name of the algorithm, as advertised in the [`KexInit`].
+    fn name(&self) -> &str;
+
+    /// Perform the key-exchange from the _client_ side, registering the server's
+    /// verified host key with [`KexStream::with_host_key`].
+    fn as_client<'a>(
+        &'a self,
+        stream: &'a mut KexStream<'_>,
+        client: KexMeta<'a>,
+        server: KexMeta<'a>,
+    ) -> KexFuture<'a>;
+
+    /// Perform the key-exchange from the _server_ side, presenting the `host_key`
+    /// and signing the exchange with it's private `key`.
+    fn as_server<'a>(
+        &'a self,
+        stream: &'a mut KexStream<'_>,
+        client: KexMeta<'a>,
+        server: KexMeta<'a>,
+        key: &'a dyn HostKeySigner,
+        host_key: &'a HostKey,
+    ) -> KexFuture<'a>;
+}
+
+/// Strip the leading zeroes of the shared secret to encode it as a proper `mpint`.
+#[cfg(any(feature = "curve25519", feature = "curve448", feature = "ecdh-nistp"))]
+fn trimmed(secret: &[u8]) -> &[u8] {
+    let start = secret
+        .iter()
+        .position(|byte| *byte != 0)
+        .unwrap_or(secret.len());
+
+    &secret[start..]
+}
+
+/// Whether the `name` is a marker advertised in the kex algorithms, rather than an actual algorithm.
+pub(crate) fn is_marker(name: &str) -> bool {
+    name == KEX_STRICT_CLIENT || name == KEX_STRICT_SERVER || name == EXT_INFO_CLIENT
+}
+
+/// Negociate the key-exchange algorithm, either from the built-in ones or from the `custom` ones.
+pub(crate) fn negociate(
+    clientkex: &KexInit,
+    serverkex: &KexInit,
+    custom: &[Arc<dyn KexAlgorithm>],
+) -> Result<Arc<dyn KexAlgorithm>> {
+    let name = clientkex
+        .kex_algorithms
+        .preferred_in(&serverkex.kex_algorithms)
+        .ok_or(Error::NoCommonKex)?;
 
-    fn field<'f>(kex: &'f KexInit) -> &'f NameList<'f> {
-        &kex.kex_algorithms
+    match name.parse::<Kex>() {
+        Ok(kex) => Ok(Arc::new(kex)),
+        Err(_) => custom
+            .iter()
+            .find(|kex| kex.name() == &*name)
+            .cloned()
+            .ok_or(Error::NoCommonKex),
     }
 }
 
-// TODO: (feature) Implement the following legacy key-exchange methods (`diffie-hellman-group14-sha256`, `diffie-hellman-group14-sha1`, `diffie-hellman-group1-sha1`).
+// TODO: (feature) Implement the `diffie-hellman-group14-sha256` legacy key-exchange method.
 
 /// SSH key-exchange algorithms.
 #[non_exhaustive]
@@ -32,54 +126,205 @@ impl Negociate for Kex {
 #[strum(serialize_all = "kebab-case")]
 pub enum Kex {
     /// Curve25519 ECDH with sha-2-256 digest.
+    #[cfg(feature = "curve25519")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "curve25519")))]
     Curve25519Sha256,
 
     /// Curve25519 ECDH with sha-2-256 digest (pre-RFC 8731).
+    #[cfg(feature = "curve25519")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "curve25519")))]
     #[strum(serialize = "curve25519-sha256@libssh.org")]
     Curve25519Sha256Libssh,
-    //
-    // DiffieHellmanGroup14Sha256,
-    //
-    // DiffieHellmanGroup14Sha1,
-    //
-    // DiffieHellmanGroup1Sha1,
+
+    /// Curve448 ECDH with sha-2-512 digest.
+    #[cfg(feature = "curve448")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "curve448")))]
+    Curve448Sha512,
+
+    /// NIST P-256 ECDH with sha-2-256 digest.
+    #[cfg(feature = "ecdh-nistp")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "ecdh-nistp")))]
+    #[strum(serialize = "ecdh-sha2-nistp256")]
+    EcdhSha2Nistp256,
+
+    /// NIST P-384 ECDH with sha-2-384 digest.
+    #[cfg(feature = "ecdh-nistp")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "ecdh-nistp")))]
+    #[strum(serialize = "ecdh-sha2-nistp384")]
+    EcdhSha2Nistp384,
+
+    /// NIST P-521 ECDH with sha-2-512 digest.
+    #[cfg(feature = "ecdh-nistp")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "ecdh-nistp")))]
+    #[strum(serialize = "ecdh-sha2-nistp521")]
+    EcdhSha2Nistp521,
+
+    /// Streamlined NTRU Prime 761 and Curve25519 hybrid key-exchange with sha-2-512 digest.
+    #[cfg(feature = "sntrup761")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "sntrup761")))]
+    #[strum(serialize = "sntrup761x25519-sha512@openssh.com")]
+    Sntrup761X25519Sha512,
+
+    /// Diffie-Hellman key-exchange over the 2048-bit MODP group with sha-1 digest.
+    ///
+    /// **Deprecated**: this method relies on _SHA-1_ and is considered weak,
+    /// it is never advertised unless explicitly enabled.
+    #[cfg(feature = "legacy-kex")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "legacy-kex")))]
+    DiffieHellmanGroup14Sha1,
+
+    /// Diffie-Hellman key-exchange over the 1024-bit Oakley group with sha-1 digest.
+    ///
+    /// **Deprecated**: this method relies on _SHA-1_ and a 1024-bit group, and is considered broken,
+    /// it is never advertised unless explicitly enabled.
+    #[cfg(feature = "legacy-kex")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "legacy-kex")))]
+    DiffieHellmanGroup1Sha1,
 }
 
 impl Kex {
-    pub(crate) async fn as_client(
-        &self,
-        stream: &mut Stream<impl Pipe>,
-        client: KexMeta<'_>,
-        server: KexMeta<'_>,
-    ) -> Result<TransportPair> {
-        let (client, server) = match self {
-            Self::Curve25519Sha256 | Self::Curve25519Sha256Libssh => {
-                curve25519::as_client::<sha2::Sha256>(stream, client, server).await?
-            }
+    /// Whether both peers advertised the _strict key-exchange_ markers for their respective side.
+    pub(crate) fn is_strict(kexinit: &KexInit, peerkexinit: &KexInit) -> bool {
+        let has = |kexinit: &KexInit, marker: &str| {
+            kexinit
+                .kex_algorithms
+                .into_iter()
+                .any(|name| &*name == marker)
         };
 
-        Ok(TransportPair {
-            tx: client,
-            rx: server,
+        (has(kexinit, KEX_STRICT_CLIENT) && has(peerkexinit, KEX_STRICT_SERVER))
+            || (has(kexinit, KEX_STRICT_SERVER) && has(peerkexinit, KEX_STRICT_CLIENT))
+    }
+
+    /// Whether the peer's guessed algorithms matches ours, in which case
+    /// the key-exchange packet following it's [`KexInit`] is to be kept.
+    pub(crate) fn is_guessed(kexinit: &KexInit, peerkexinit: &KexInit) -> bool {
+        let first = |names: &NameList| names.into_iter().next().map(|name| name.into_string());
+
+        first(&kexinit.kex_algorithms) == first(&peerkexinit.kex_algorithms)
+            && first(&kexinit.server_host_key_algorithms)
+                == first(&peerkexinit.server_host_key_algorithms)
+    }
+}
+
+impl KexAlgorithm for Kex {
+    fn name(&self) -> &str {
+        self.as_ref()
+    }
+
+    fn as_client<'a>(
+        &'a self,
+        stream: &'a mut KexStream<'_>,
+        client: KexMeta<'a>,
+        server: KexMeta<'a>,
+    ) -> KexFuture<'a> {
+        Box::pin(async move {
+            let (client, server) = match self {
+                #[cfg(feature = "curve25519")]
+                Self::Curve25519Sha256 | Self::Curve25519Sha256Libssh => {
+                    curve25519::as_client::<sha2::Sha256>(stream, client, server).await?
+                }
+                #[cfg(feature = "curve448")]
+                Self::Curve448Sha512 => {
+                    curve448::as_client::<sha2::Sha512>(stream, client, server).await?
+                }
+                #[cfg(feature = "ecdh-nistp")]
+                Self::EcdhSha2Nistp256 => {
+                    ecdh::as_client::<p256::NistP256, sha2::Sha256>(stream, client, server).await?
+                }
+                #[cfg(feature = "ecdh-nistp")]
+                Self::EcdhSha2Nistp384 => {
+                    ecdh::as_client::<p384::NistP384, sha2::Sha384>(stream, client, server).await?
+                }
+                #[cfg(feature = "ecdh-nistp")]
+                Self::EcdhSha2Nistp521 => {
+                    ecdh::as_client::<p521::NistP521, sha2::Sha512>(stream, client, server).await?
+                }
+                #[cfg(feature = "sntrup761")]
+                Self::Sntrup761X25519Sha512 => {
+                    sntrup761x25519::as_client::<sha2::Sha512>(stream, client, server).await?
+                }
+                #[cfg(feature = "legacy-kex")]
+                Self::DiffieHellmanGroup14Sha1 => {
+                    dh::as_client::<sha1::Sha1>(stream, client, server, &dh::GROUP14).await?
+                }
+                #[cfg(feature = "legacy-kex")]
+                Self::DiffieHellmanGroup1Sha1 => {
+                    dh::as_client::<sha1::Sha1>(stream, client, server, &dh::GROUP1).await?
+                }
+            };
+
+            Ok(TransportPair {
+                tx: client,
+                rx: server,
+            })
         })
     }
 
-    pub(crate) async fn as_server(
-        &self,
-        stream: &mut Stream<impl Pipe>,
-        client: KexMeta<'_>,
-        server: KexMeta<'_>,
-        key: &PrivateKey,
-    ) -> Result<TransportPair> {
-        let (client, server) = match self {
-            Self::Curve25519Sha256 | Self::Curve25519Sha256Libssh => {
-                curve25519::as_server::<sha2::Sha256>(stream, client, server, key).await?
-            }
-        };
+    fn as_server<'a>(
+        &'a self,
+        stream: &'a mut KexStream<'_>,
+        client: KexMeta<'a>,
+        server: KexMeta<'a>,
+        key: &'a dyn HostKeySigner,
+        host_key: &'a HostKey,
+    ) -> KexFuture<'a> {
+        Box::pin(async move {
+            let (client, server) = match self {
+                #[cfg(feature = "curve25519")]
+                Self::Curve25519Sha256 | Self::Curve25519Sha256Libssh => {
+                    curve25519::as_server::<sha2::Sha256>(stream, client, server, key, host_key)
+                        .await?
+                }
+                #[cfg(feature = "curve448")]
+                Self::Curve448Sha512 => {
+                    curve448::as_server::<sha2::Sha512>(stream, client, server, key, host_key)
+                        .await?
+                }
+                #[cfg(feature = "ecdh-nistp")]
+                Self::EcdhSha2Nistp256 => {
+                    ecdh::as_server::<p256::NistP256, sha2::Sha256>(
+                        stream, client, server, key, host_key,
+                    )
+                    .await?
+                }
+                #[cfg(feature = "ecdh-nistp")]
+                Self::EcdhSha2Nistp384 => {
+                    ecdh::as_server::<p384::NistP384, sha2::Sha384>(
+                        stream, client, server, key, host_key,
+                    )
+                    .await?
+                }
+                #[cfg(feature = "ecdh-nistp")]
+                Self::EcdhSha2Nistp521 => {
+                    ecdh::as_server::<p521::NistP521, sha2::Sha512>(
+                        stream, client, server, key, host_key,
+                    )
+                    .await?
+                }
+                #[cfg(feature = "sntrup761")]
+                Self::Sntrup761X25519Sha512 => {
+                    sntrup761x25519::as_server::<sha2::Sha512>(
+                        stream, client, server, key, host_key,
+                    )
+                    .await?
+                }
+                #[cfg(feature = "legacy-kex")]
+                Self::DiffieHellmanGroup14Sha1 => {
+                    dh::as_server::<sha1::Sha1>(stream, client, server, key, host_key, &dh::GROUP14)
+                        .await?
+                }
+                #[cfg(feature = "legacy-kex")]
+                Self::DiffieHellmanGroup1Sha1 => {
+                    dh::as_server::<sha1::Sha1>(stream, client, server, key, host_key, &dh::GROUP1)
+                        .await?
+                }
+            };
 
-        Ok(TransportPair {
-            tx: server,
-            rx: client,
+            Ok(TransportPair {
+                tx: server,
+                rx: client,
+            })
         })
     }
 }
@@ -0,0 +1,191 @@
+use digest::{Digest, FixedOutputReset};
+use rand::RngCore;
+use secrecy::{ExposeSecret, SecretBox};
+use signature::{SignatureEncoding, Verifier};
+use ssh_key::Signature;
+use ssh_packet::{
+    arch::MpInt,
+    crypto::exchange,
+    trans::{KexEcdhInit, KexEcdhReply},
+};
+use zeroize::Zeroizing;
+
+use crate::{
+    algorithm::{HostKey, HostKeySigner},
+    stream::KexStream,
+    Error, Result,
+};
+
+use super::{KexMeta, Keys, Transport};
+
+/// Generate a random 32-byte seed for the deterministic `sntrup761` primitives.
+fn seed() -> Zeroizing<[u8; 32]> {
+    let mut seed = Zeroizing::new([0u8; 32]);
+    rand::thread_rng().fill_bytes(seed.as_mut());
+
+    seed
+}
+
+/// Combine both shared secrets, which are then encoded as a `string` rather than an `mpint`.
+fn combine<H: Digest>(kem: &[u8], ecdh: &[u8]) -> SecretBox<MpInt<'static>> {
+    let secret = Zeroizing::new(H::new().chain_update(kem).chain_update(ecdh).finalize());
+
+    SecretBox::new(MpInt::from_bytes(secret.to_vec()).into())
+}
+
+pub async fn as_client<H: Digest + FixedOutputReset>(
+    stream: &mut KexStream<'_>,
+    client: KexMeta<'_>,
+    server: KexMeta<'_>,
+) -> Result<(Transport, Transport)> {
+    let (ek_c, dk_c) = sntrup761::generate_key_from_seed(*seed());
+    let e_c = x25519_dalek::EphemeralSecret::random_from_rng(rand::thread_rng());
+
+    let q_c = [
+        ek_c.as_ref(),
+        x25519_dalek::PublicKey::from(&e_c).as_bytes(),
+    ]
+    .concat();
+
+    stream
+        .send(&KexEcdhInit {
+            q_c: q_c.as_slice().into(),
+        })
+        .await?;
+
+    let ecdh: KexEcdhReply = stream.recv().await?.to()?;
+    if ecdh.q_s.len() != sntrup761::CIPHERTEXT_SIZE + 32 {
+        return Err(Error::KexError);
+    }
+
+    let (ciphertext, q_s) = ecdh.q_s.split_at(sntrup761::CIPHERTEXT_SIZE);
+    let ciphertext = sntrup761::Ciphertext::try_from(ciphertext).map_err(|_| Error::KexError)?;
+    let q_s =
+        x25519_dalek::PublicKey::from(<[u8; 32]>::try_from(q_s).map_err(|_| Error::KexError)?);
+
+    let kem = dk_c.decapsulate(&ciphertext);
+    let secret = e_c.diffie_hellman(&q_s);
+    let secret = combine::<H>(kem.as_ref(), secret.as_bytes());
+
+    let k_s = HostKey::from_bytes(&ecdh.k_s)?;
+    let hash = Zeroizing::new(
+        exchange::Ecdh {
+            v_c: client.id.to_string().into_bytes().into(),
+            v_s: server.id.to_string().into_bytes().into(),
+            i_c: (client.kexinit).into(),
+            i_s: (server.kexinit).into(),
+            k_s: ecdh.k_s,
+            q_c: q_c.as_slice().into(),
+            q_s: ecdh.q_s,
+            k: secret.expose_secret().as_borrow(),
+        }
+        .hash::<H>(),
+    );
+
+    Verifier::verify(
+        &k_s.public_key(),
+        &hash,
+        &Signature::try_from(ecdh.signature.as_ref())?,
+    )?;
+    stream.with_host_key(k_s);
+
+    let session_id = stream.with_session(&hash);
+
+    let keys = Keys::as_client::<H>(
+        secret.expose_secret(),
+        &hash,
+        session_id,
+        &*client.cipher,
+        &client.hmac,
+    );
+    let client = client.into_transport(keys);
+
+    let keys = Keys::as_server::<H>(
+        secret.expose_secret(),
+        &hash,
+        session_id,
+        &*server.cipher,
+        &server.hmac,
+    );
+    let server = server.into_transport(keys);
+
+    Ok((client, server))
+}
+
+pub async fn as_server<H: Digest + FixedOutputReset>(
+    stream: &mut KexStream<'_>,
+    client: KexMeta<'_>,
+    server: KexMeta<'_>,
+    key: &dyn HostKeySigner,
+    host_key: &HostKey,
+) -> Result<(Transport, Transport)> {
+    let ecdh: KexEcdhInit = stream.recv().await?.to()?;
+    if ecdh.q_c.len() != sntrup761::PUBLIC_KEY_SIZE + 32 {
+        return Err(Error::KexError);
+    }
+
+    let (ek_c, q_c) = ecdh.q_c.split_at(sntrup761::PUBLIC_KEY_SIZE);
+    let ek_c = sntrup761::EncapsulationKey::try_from(ek_c).map_err(|_| Error::KexError)?;
+    let q_c =
+        x25519_dalek::PublicKey::from(<[u8; 32]>::try_from(q_c).map_err(|_| Error::KexError)?);
+
+    let (ciphertext, kem) = ek_c.encapsulate_deterministic(*seed());
+    let e_s = x25519_dalek::EphemeralSecret::random_from_rng(rand::thread_rng());
+
+    let q_s = [
+        ciphertext.as_ref(),
+        x25519_dalek::PublicKey::from(&e_s).as_bytes(),
+    ]
+    .concat();
+
+    let secret = e_s.diffie_hellman(&q_c);
+    let secret = combine::<H>(kem.as_ref(), secret.as_bytes());
+
+    let k_s = host_key.to_bytes()?;
+
+    let hash = Zeroizing::new(
+        exchange::Ecdh {
+            v_c: client.id.to_string().into_bytes().into(),
+            v_s: server.id.to_string().into_bytes().into(),
+            i_c: (client.kexinit).into(),
+            i_s: (server.kexinit).into(),
+            k_s: k_s.as_slice().into(),
+            q_c: ecdh.q_c,
+            q_s: q_s.as_slice().into(),
+            k: secret.expose_secret().as_borrow(),
+        }
+        .hash::<H>(),
+    );
+
+    let signature = key.sign(&hash).await?;
+
+    stream
+        .send(&KexEcdhReply {
+            k_s: k_s.into(),
+            q_s: q_s.into(),
+            signature: signature.to_vec().into(),
+        })
+        .await?;
+
+    let session_id = stream.with_session(&hash);
+
+    let keys = Keys::as_client::<H>(
+        secret.expose_secret(),
+        &hash,
+        session_id,
+        &*client.cipher,
+        &client.hmac,
+    );
+    let client = client.into_transport(keys);
+
+    let keys = Keys::as_server::<H>(
+        secret.expose_secret(),
+        &hash,
+        session_id,
+        &*server.cipher,
+        &server.hmac,
+    );
+    let server = server.into_transport(keys);
+
+    Ok((client, server))
+}
@@ -1,14 +1,123 @@
+use std::{future::Future, pin::Pin};
+
 pub use ssh_key::Algorithm as Key;
-use ssh_packet::{arch::NameList, trans::KexInit};
+use ssh_key::{Certificate, HashAlg, PrivateKey, PublicKey, Signature};
+use ssh_packet::trans::KexInit;
+
+use crate::{Error, Result};
+
+/// Negociate the name of the host key algorithm, reporting the offers of both sides on failure.
+pub(crate) fn negociate_host_key(clientkex: &KexInit, serverkex: &KexInit) -> Result<String> {
+    fn offered(kex: &KexInit) -> String {
+        kex.server_host_key_algorithms
+            .into_iter()
+            .map(|name| name.to_string())
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    clientkex
+        .server_host_key_algorithms
+        .preferred_in(&serverkex.server_host_key_algorithms)
+        .map(|name| name.to_string())
+        .ok_or_else(|| Error::NoCommonKey {
+            client: offered(clientkex),
+            server: offered(serverkex),
+        })
+}
+
+/// The suffix of the host key algorithms presenting an OpenSSH certificate.
+const CERTIFICATE_SUFFIX: &str = "-cert-v01@openssh.com";
 
-use crate::Error;
+/// The name of the host key algorithm presenting a certificate of a `key`,
+/// as described in OpenSSH's `PROTOCOL.certkeys`.
+pub(crate) fn certificate_name(key: &Key) -> String {
+    match key {
+        Key::Rsa {
+            hash: Some(HashAlg::Sha512),
+        } => format!("rsa-sha2-512{CERTIFICATE_SUFFIX}"),
+        Key::Rsa {
+            hash: Some(HashAlg::Sha256),
+        } => format!("rsa-sha2-256{CERTIFICATE_SUFFIX}"),
+        key => key.to_certificate_type(),
+    }
+}
 
-use super::Negociate;
+/// The host key presented by the _server_ during the key-exchange.
+#[derive(Debug, Clone)]
+pub enum HostKey {
+    /// A plain public key.
+    Key(PublicKey),
+
+    /// A public key certified by a _certificate authority_.
+    Certificate(Box<Certificate>),
+}
+
+impl HostKey {
+    /// Decode the host key from its wire-format `blob`.
+    pub fn from_bytes(blob: &[u8]) -> Result<Self> {
+        let name = blob
+            .get(..4)
+            .map(|len| u32::from_be_bytes([len[0], len[1], len[2], len[3]]) as usize)
+            .and_then(|len| blob.get(4..4 + len))
+            .ok_or(Error::KexError)?;
+
+        if name.ends_with(CERTIFICATE_SUFFIX.as_bytes()) {
+            Ok(Self::Certificate(Certificate::from_bytes(blob)?.into()))
+        } else {
+            Ok(Self::Key(PublicKey::from_bytes(blob)?))
+        }
+    }
 
-impl Negociate for Key {
-    const ERR: Error = Error::NoCommonKey;
+    /// Encode the host key to its wire-format blob.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        Ok(match self {
+            Self::Key(key) => key.to_bytes()?,
+            Self::Certificate(certificate) => certificate.to_bytes()?,
+        })
+    }
+
+    /// The public key signing the exchange, which is the certified key for certificates.
+    pub fn public_key(&self) -> PublicKey {
+        match self {
+            Self::Key(key) => key.clone(),
+            Self::Certificate(certificate) => certificate.public_key().clone().into(),
+        }
+    }
+}
+
+/// The future returned by the [`HostKeySigner`] methods.
+pub type SignFuture<'a> = Pin<Box<dyn Future<Output = Result<Signature>> + Send + Sync + 'a>>;
+
+/// A host key of the _server_ signing the key-exchanges, which can be implemented to keep
+/// the private key out of memory, such as in an HSM or behind a remote KMS.
+pub trait HostKeySigner: std::fmt::Debug + Send + Sync + 'static {
+    /// The public key, presented to the _client_.
+    fn public_key(&self) -> &PublicKey;
+
+    /// The host key algorithm of the produced signatures.
+    fn algorithm(&self) -> Key;
+
+    /// Sign the `data` with the private key, using the [`HostKeySigner::algorithm`].
+    fn sign<'a>(&'a self, data: &'a [u8]) -> SignFuture<'a>;
+}
+
+impl HostKeySigner for PrivateKey {
+    fn public_key(&self) -> &PublicKey {
+        PrivateKey::public_key(self)
+    }
+
+    fn algorithm(&self) -> Key {
+        // The _RSA_ keys are only able to produce `rsa-sha2-512` signatures.
+        match PrivateKey::algorithm(self) {
+            Key::Rsa { .. } => Key::Rsa {
+                hash: Some(HashAlg::Sha512),
+            },
+            algorithm => algorithm,
+        }
+    }
 
-    fn field<'f>(kex: &'f KexInit) -> &'f NameList<'f> {
-        &kex.server_host_key_algorithms
+    fn sign<'a>(&'a self, data: &'a [u8]) -> SignFuture<'a> {
+        Box::pin(async move { Ok(signature::Signer::try_sign(self, data)?) })
     }
 }
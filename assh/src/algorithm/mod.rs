@@ -1,4 +1,10 @@
 //! Supported algorithms for **compression**, **encryption**, **integrity** and **key-exchange**.
+//!
+//! Each family of algorithms is gated behind a cargo feature, all enabled by default but `legacy-kex`,
+//! and the `minimal-profile` feature only enables `curve25519` and `chacha20-poly1305` when combined with
+//! `default-features = false`. The default [`side::client::Algorithms`](crate::side::client::Algorithms) and
+//! [`side::server::Algorithms`](crate::side::server::Algorithms) advertise every compiled-in algorithm,
+//! so prefer setting them explicitly if another crate of the dependency graph could enable more features.
 
 // TODO: (feature) Gate insecure algorithms behind an `insecure` feature flag.
 
@@ -9,32 +15,41 @@ use ssh_packet::{arch::NameList, trans::KexInit};
 use crate::{Error, Result};
 
 pub(crate) trait Negociate<S = ()>: Sized + FromStr {
-    const ERR: Error;
+    /// The negociation error, reporting the algorithms offered by the _client_ and the _server_.
+    fn err(client: String, server: String) -> Error;
 
     fn field<'f>(kex: &'f KexInit) -> &'f NameList<'f>;
 
     fn negociate(clientkex: &KexInit, serverkex: &KexInit) -> Result<Self> {
+        let err = || {
+            Self::err(
+                Self::field(clientkex).0.to_string(),
+                Self::field(serverkex).0.to_string(),
+            )
+        };
+
         Self::field(clientkex)
             .preferred_in(Self::field(serverkex))
-            .ok_or(Self::ERR)?
+            .ok_or_else(err)?
             .parse()
-            .map_err(|_| Self::ERR)
+            .map_err(|_| err())
     }
 }
 
 mod cipher;
-pub use cipher::Cipher;
-pub(super) use cipher::CipherState;
+pub use cipher::{Cipher, CipherAlgorithm, CipherState};
 
 mod compress;
 pub use compress::Compress;
+pub(super) use compress::CompressState;
 
 mod hmac;
 pub use hmac::Hmac;
 
-mod kex;
-pub use kex::Kex;
-pub(super) use kex::KexMeta;
+pub mod kex;
+pub use kex::{Kex, KexAlgorithm};
+pub(super) use kex::{KexMeta, EXT_INFO_CLIENT, KEX_STRICT_CLIENT, KEX_STRICT_SERVER};
 
 mod key;
-pub use key::Key;
+pub(crate) use key::{certificate_name, negociate_host_key};
+pub use key::{HostKey, HostKeySigner, Key, SignFuture};
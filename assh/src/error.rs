@@ -36,6 +36,14 @@ pub enum Error {
     #[error(transparent)]
     Id(#[from] ssh_packet::Error),
 
+    /// The identification string received from the peer exceeds the maximum length.
+    #[error("Peer sent an identification string exceeding {0} bytes")]
+    IdTooLong(usize),
+
+    /// The protocol version of the identification string received from the peer is not supported.
+    #[error("Peer uses the unsupported protocol version `{0}`")]
+    ProtocolVersionUnsupported(String),
+
     /// I/O Error.
     #[error(transparent)]
     Io(#[from] std::io::Error),
@@ -48,42 +56,170 @@ pub enum Error {
     #[error(transparent)]
     Key(#[from] ssh_key::Error),
 
-    /// Packet integrity error.
+    /// SSH wire encoding error.
     #[error(transparent)]
-    Integrity(#[from] digest::MacError),
+    Encoding(#[from] ssh_encoding::Error),
+
+    /// The authentication code of a received packet did not match, the packet may have been tampered with.
+    #[error("The packet authentication code did not match")]
+    MacMismatch(#[from] digest::MacError),
 
     /// Signature error during the key-exchange.
     #[error(transparent)]
     Signature(#[from] signature::Error),
 
+    /// The session configuration is invalid.
+    #[error("Invalid session configuration: {0}")]
+    Config(&'static str),
+
     /// No common kex algorithm found between both sides.
     #[error("Unable to negociate a common kex algorithm")]
     NoCommonKex,
 
     /// No common key algorithm found between both sides.
-    #[error("Unable to negociate a common host key algorithm")]
-    NoCommonKey,
+    #[error("Unable to negociate a common host key algorithm, the client offered `{client}` while the server offered `{server}`")]
+    NoCommonKey {
+        /// The host key algorithms offered by the _client_.
+        client: String,
+
+        /// The host key algorithms offered by the _server_.
+        server: String,
+    },
 
     /// No common cipher algorithm found between both sides.
-    #[error("Unable to negociate a common encryption algorithm")]
-    NoCommonCipher,
+    #[error("Unable to negociate a common encryption algorithm, the client offered `{client}` while the server offered `{server}`")]
+    NoCommonCipher {
+        /// The encryption algorithms offered by the _client_.
+        client: String,
+
+        /// The encryption algorithms offered by the _server_.
+        server: String,
+    },
 
     /// No common hmac algorithm found between both sides.
-    #[error("Unable to negociate a common HMAC algorithm")]
-    NoCommonHmac,
+    #[error("Unable to negociate a common HMAC algorithm, the client offered `{client}` while the server offered `{server}`")]
+    NoCommonHmac {
+        /// The hmac algorithms offered by the _client_.
+        client: String,
+
+        /// The hmac algorithms offered by the _server_.
+        server: String,
+    },
 
     /// No common compression algorithm found between both sides.
-    #[error("Unable to negociate a common compression algorithm")]
-    NoCommonCompression,
+    #[error("Unable to negociate a common compression algorithm, the client offered `{client}` while the server offered `{server}`")]
+    NoCommonCompression {
+        /// The compression algorithms offered by the _client_.
+        client: String,
+
+        /// The compression algorithms offered by the _server_.
+        server: String,
+    },
 
     /// Protocol error in the key-exchange.
     #[error("Error in the kex-exchange algorithm")]
     KexError,
 
+    /// The key-exchange did not complete in the allowed time.
+    #[error("The key-exchange did not complete in the allowed time")]
+    KexTimeout,
+
+    /// The authentication did not complete in the allowed time.
+    #[error("The authentication did not complete in the allowed time")]
+    AuthTimeout,
+
+    /// The host key presented by the server has been rejected by the client.
+    #[error("The server host key `{0}` has been rejected")]
+    HostKeyRejected(ssh_key::Fingerprint),
+
+    /// The host key presented by the server is forbidden by the client's host key policy.
+    #[error(
+        "The server host key `{algorithm}` of {bits} bits is forbidden by the host key policy"
+    )]
+    WeakHostKey {
+        /// The negociated host key algorithm.
+        algorithm: String,
+
+        /// The size of the host key in bits.
+        bits: usize,
+    },
+
+    /// The host certificate presented by the server is not signed by a trusted authority.
+    #[error("The server host certificate is not signed by a trusted authority")]
+    CertificateUntrusted,
+
+    /// The host certificate presented by the server is not a _host_ certificate.
+    #[error("The server host certificate is not a host certificate")]
+    CertificateType,
+
+    /// The host certificate presented by the server is expired or not yet valid.
+    #[error("The server host certificate is outside of it's validity period")]
+    CertificateExpired,
+
+    /// The host certificate presented by the server is not valid for the server's hostname.
+    #[error("The server host certificate is not valid for this hostname")]
+    CertificatePrincipal,
+
+    /// The private key file is not in the OpenSSH format.
+    #[error("The private key is not in the OpenSSH format")]
+    KeyFormat,
+
+    /// The private key file is encrypted with an unsupported cipher.
+    #[error("The private key is encrypted with the unsupported `{0}` cipher")]
+    KeyCipher(String),
+
+    /// The private key file is encrypted, and the passphrase is missing or wrong.
+    #[error("The private key passphrase is missing or wrong")]
+    KeyPassphrase,
+
+    /// The private key file is corrupted.
+    #[error("The private key is corrupted: {0}")]
+    KeyCorrupt(ssh_key::Error),
+
+    /// The `ssh-agent` holding the keys is unreachable.
+    #[error("Unable to communicate with the ssh-agent at `{}`: {source}", path.display())]
+    Agent {
+        /// The path to the unix socket of the agent.
+        path: std::path::PathBuf,
+
+        /// The underlying I/O error.
+        source: std::io::Error,
+    },
+
+    /// The `ssh-agent` holding the keys refused or failed the request.
+    #[error("The ssh-agent refused or failed the request")]
+    AgentFailure,
+
     /// Error while encrypting or decrypting messages.
     #[error("The cipher ended up in an error")]
     Cipher,
 
+    /// The padding of a received packet does not follow the protocol rules.
+    #[error("Peer sent a packet with an invalid padding")]
+    Padding,
+
+    /// The length declared by a received packet exceeds the configured maximum.
+    #[error("Peer sent a packet exceeding the maximum length, with {0} bytes")]
+    PacketTooLarge(usize),
+
+    /// Error while compressing or decompressing messages.
+    #[error("The compression ended up in an error")]
+    Compression,
+
+    /// The peer does not implement a message we sent and awaited a response to.
+    #[error("Peer does not implement the `{}` message sent as packet #{seq}", message_name(*message))]
+    Unimplemented {
+        /// The sequence number of the packet.
+        seq: u32,
+
+        /// The message number of the packet.
+        message: u8,
+
+        /// The channel referred to by the message for the _connect_ layer messages, which is
+        /// the sender channel of a channel open, and the recipient channel of a channel request.
+        channel: Option<u32>,
+    },
+
     /// The message received was unexpected in the current context.
     #[error("Peer sent a message that made no sense in the current context")]
     UnexpectedMessage,
@@ -95,3 +231,10 @@ pub enum Error {
 
 /// A handy [`std::result::Result`] type alias bounding the [`enum@Error`] struct as `E`.
 pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// The name of the `message` number, or the number itself if unknown.
+fn message_name(message: u8) -> std::borrow::Cow<'static, str> {
+    crate::stream::message_name(message)
+        .map(Into::into)
+        .unwrap_or_else(|| format!("^{message:#x}").into())
+}
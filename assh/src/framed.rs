@@ -0,0 +1,205 @@
+//! A [`Session`] framed as a [`Stream`] and a [`Sink`] of packets.
+
+use std::pin::Pin;
+
+use futures::{future::BoxFuture, task, FutureExt, Sink, Stream};
+use ssh_packet::Packet;
+
+use crate::{side::Side, Error, Pipe, Result, Session};
+
+type SendFut<IO, S> = BoxFuture<'static, (Result<()>, Box<Session<IO, S>>)>;
+type RecvFut<IO, S> = BoxFuture<'static, (Result<Packet>, Box<Session<IO, S>>)>;
+
+enum State<IO: Pipe, S: Side> {
+    /// Idling and waiting for tasks.
+    Idle(Option<Box<Session<IO, S>>>),
+
+    /// Polling to send a packet.
+    Sending(SendFut<IO, S>),
+
+    /// Polling to recv a packet.
+    Recving(RecvFut<IO, S>),
+}
+
+/// A [`Session`] framed as a [`Stream`] of the received _packets_ and a [`Sink`] of the _packets_ to send,
+/// with the key-exchanges and the transport messages handled underneath as in [`Session::recv`] and [`Session::send`].
+///
+/// The packet being received is kept across the polls, so dropping the [`Stream::poll_next`] future,
+/// such as in a [`futures::select`] call, never loses data. The stream ends once the session is disconnected,
+/// and the errors while sending are returned by whichever of the stream or the sink polls the session next.
+pub struct Framed<IO: Pipe, S: Side> {
+    state: State<IO, S>,
+
+    /// The outcome of the last receive, awaiting to be yielded by the stream.
+    buffer: Option<Result<Packet>>,
+}
+
+impl<IO, S> Framed<IO, S>
+where
+    IO: Pipe,
+    S: Side,
+{
+    /// Frame the `session` as a [`Stream`] and a [`Sink`] of packets.
+    pub fn new(session: Session<IO, S>) -> Self {
+        Self {
+            state: State::Idle(Some(session.into())),
+            buffer: None,
+        }
+    }
+
+    /// Wait for the pending operation to complete and recover the [`Session`],
+    /// the packet received but not yet yielded being received first by [`Session::recv`],
+    /// or the error received but not yet yielded being returned.
+    pub async fn into_inner(mut self) -> Result<Session<IO, S>> {
+        futures::future::poll_fn(|cx| self.poll_idle(cx)).await?;
+
+        let State::Idle(Some(mut session)) = self.state else {
+            unreachable!("the session is idle once the pending operation completed")
+        };
+
+        if let Some(result) = self.buffer.take() {
+            let packet = result?;
+
+            if let Ok(stream) = session.connected_mut() {
+                stream.defer(packet)?;
+            }
+        }
+
+        Ok(*session)
+    }
+
+    /// Drive the pending operation to completion, buffering the received packet if any.
+    fn poll_idle(&mut self, cx: &mut task::Context<'_>) -> task::Poll<Result<()>> {
+        match &mut self.state {
+            State::Idle(_) => task::Poll::Ready(Ok(())),
+
+            State::Sending(fut) => {
+                let (result, session) = futures::ready!(fut.poll_unpin(cx));
+                self.state = State::Idle(Some(session));
+
+                task::Poll::Ready(result)
+            }
+
+            State::Recving(fut) => {
+                let (result, session) = futures::ready!(fut.poll_unpin(cx));
+                self.state = State::Idle(Some(session));
+                self.buffer = Some(result);
+
+                task::Poll::Ready(Ok(()))
+            }
+        }
+    }
+}
+
+impl<IO, S> From<Session<IO, S>> for Framed<IO, S>
+where
+    IO: Pipe,
+    S: Side,
+{
+    fn from(session: Session<IO, S>) -> Self {
+        Self::new(session)
+    }
+}
+
+impl<IO, S> Stream for Framed<IO, S>
+where
+    IO: Pipe,
+    S: Side,
+{
+    type Item = Result<Packet>;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+    ) -> task::Poll<Option<Self::Item>> {
+        let this = &mut *self;
+
+        if this.buffer.is_none() {
+            futures::ready!(this.poll_idle(cx))?;
+        }
+
+        if let Some(result) = this.buffer.take() {
+            return task::Poll::Ready(match result {
+                Err(Error::Disconnected(_)) => None,
+                other => Some(other),
+            });
+        }
+
+        let State::Idle(session) = &mut this.state else {
+            unreachable!("the session is idle once the pending operation completed")
+        };
+        let Some(mut session) = session.take() else {
+            unreachable!("the idle session is only taken to start an operation")
+        };
+
+        // NOTE: The packet is only received once data is available, to never hold the session
+        // in a pending receive while the sink has packets to send.
+        match session.poll_readable(cx) {
+            task::Poll::Ready(Err(Error::Disconnected(_))) => {
+                this.state = State::Idle(Some(session));
+
+                task::Poll::Ready(None)
+            }
+            task::Poll::Ready(_) => {
+                this.state = State::Recving(async move { (session.recv().await, session) }.boxed());
+
+                cx.waker().wake_by_ref();
+                task::Poll::Pending
+            }
+            task::Poll::Pending => {
+                this.state = State::Idle(Some(session));
+
+                task::Poll::Pending
+            }
+        }
+    }
+}
+
+impl<IO, S> Sink<Packet> for Framed<IO, S>
+where
+    IO: Pipe,
+    S: Side,
+{
+    type Error = Error;
+
+    fn poll_ready(
+        mut self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+    ) -> task::Poll<Result<(), Self::Error>> {
+        self.poll_idle(cx)
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, item: Packet) -> Result<(), Self::Error> {
+        let State::Idle(session) = &mut self.state else {
+            unreachable!("`Sink::poll_ready` must return `Ready` before `Sink::start_send`")
+        };
+        let Some(mut session) = session.take() else {
+            unreachable!("the idle session is only taken to start an operation")
+        };
+
+        self.state = State::Sending(async move { (session.send(item).await, session) }.boxed());
+
+        Ok(())
+    }
+
+    fn poll_flush(
+        mut self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+    ) -> task::Poll<Result<(), Self::Error>> {
+        // NOTE: The packets are flushed by `Session::send`, so only the pending send is awaited,
+        // leaving a pending receive to the stream.
+        match self.state {
+            State::Sending(_) => self.poll_idle(cx),
+            _ => task::Poll::Ready(Ok(())),
+        }
+    }
+
+    fn poll_close(
+        self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+    ) -> task::Poll<Result<(), Self::Error>> {
+        // NOTE: The session is disconnected when dropped, or explicitly
+        // with `Session::disconnect` after `Framed::into_inner`.
+        self.poll_flush(cx)
+    }
+}
@@ -0,0 +1,14 @@
+//! Hooks into the lifecycle of a [`Session`](crate::Session).
+
+use crate::error::DisconnectedError;
+
+/// A _layer_ attached to a [`Session`](crate::Session) with [`Session::with_layer`](crate::Session::with_layer),
+/// notified of the events of the session, such as to log them or to collect metrics.
+pub trait Layer: Send + Sync + 'static {
+    /// Called once the session has been disconnected, by either side, before the stream is torn down.
+    ///
+    /// The [`DisconnectedError::by`] field tells which side sent the disconnect message.
+    fn on_disconnect(&mut self, disconnected: &DisconnectedError) {
+        let _ = disconnected;
+    }
+}
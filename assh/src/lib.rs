@@ -26,11 +26,22 @@
 mod stream;
 
 pub mod algorithm;
+pub mod layer;
 pub mod service;
 pub mod side;
 
+#[cfg(unix)]
+pub mod agent;
+
 pub mod error;
 pub use error::{Error, Result};
 
 mod session;
 pub use session::{Pipe, Session};
+
+mod framed;
+pub use framed::Framed;
+
+mod extensions;
+pub use extensions::Extensions;
+pub use stream::{PaddingMode, TransportStats, TransportStatsPair};
@@ -1,11 +1,15 @@
 //! Service handling and requesting facilities.
 
-use futures::Future;
-use ssh_packet::arch::Ascii;
+use std::{convert::Infallible, marker::PhantomData};
 
-use crate::{side::Side, Pipe, Session};
+use futures::{future::BoxFuture, Future, FutureExt};
+use ssh_packet::{
+    arch::{Ascii, Utf8},
+    trans::DisconnectReason,
+    IntoPacket, Packet,
+};
 
-// TODO: (feature) Handle multiple services at once ?
+use crate::{error::DisconnectedError, side::Side, Error, Extensions, Pipe, Result, Session};
 
 /// A _service handler_ in the transport protocol.
 pub trait Handler {
@@ -27,6 +31,122 @@ pub trait Handler {
         S: Side;
 }
 
+/// A set of _service handlers_, dispatching the service requests on their name,
+/// implemented for any [`Handler`] and for the tuples of up to four [`Handler`]s.
+pub trait Handlers {
+    /// The errorneous outcome of the [`Handlers`].
+    type Err: From<crate::Error>;
+    /// The successful outcome of the [`Handlers`].
+    type Ok<IO: Pipe, S: Side>;
+
+    /// Whether one of the handlers handles the service named `service_name`.
+    fn handles(&self, service_name: &str) -> bool;
+
+    /// Dispatch the request for the service named `service_name` to the handler handling it,
+    /// disconnecting with [`DisconnectReason::ServiceNotAvailable`] if none does.
+    fn dispatch<IO, S>(
+        &mut self,
+        service_name: &str,
+        session: Session<IO, S>,
+    ) -> impl Future<Output = Result<Self::Ok<IO, S>, Self::Err>>
+    where
+        IO: Pipe,
+        S: Side;
+}
+
+impl<H: Handler> Handlers for H {
+    type Err = H::Err;
+    type Ok<IO: Pipe, S: Side> = H::Ok<IO, S>;
+
+    fn handles(&self, service_name: &str) -> bool {
+        service_name == &*H::SERVICE_NAME
+    }
+
+    async fn dispatch<IO, S>(
+        &mut self,
+        service_name: &str,
+        mut session: Session<IO, S>,
+    ) -> Result<Self::Ok<IO, S>, Self::Err>
+    where
+        IO: Pipe,
+        S: Side,
+    {
+        if self.handles(service_name) {
+            self.on_request(session).await
+        } else {
+            Err(unavailable(&mut session).await.into())
+        }
+    }
+}
+
+/// The successful outcome of the [`Handlers`] of a tuple, from the handler which served the request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Dispatched<A, B, C = Infallible, D = Infallible> {
+    /// The outcome of the first handler.
+    First(A),
+    /// The outcome of the second handler.
+    Second(B),
+    /// The outcome of the third handler.
+    Third(C),
+    /// The outcome of the fourth handler.
+    Fourth(D),
+}
+
+macro_rules! handlers {
+    ($first:tt $First:ident $FirstVariant:ident $(, $index:tt $Handler:ident $Variant:ident)+) => {
+        impl<$First, $($Handler),+> Handlers for ($First, $($Handler),+)
+        where
+            $First: Handler,
+            $($Handler: Handler<Err = $First::Err>),+
+        {
+            type Err = $First::Err;
+            type Ok<IO: Pipe, S: Side> =
+                Dispatched<<$First as Handler>::Ok<IO, S> $(, <$Handler as Handler>::Ok<IO, S>)+>;
+
+            fn handles(&self, service_name: &str) -> bool {
+                service_name == &*$First::SERVICE_NAME
+                    $(|| service_name == &*$Handler::SERVICE_NAME)+
+            }
+
+            async fn dispatch<IO, S>(
+                &mut self,
+                service_name: &str,
+                mut session: Session<IO, S>,
+            ) -> Result<Self::Ok<IO, S>, Self::Err>
+            where
+                IO: Pipe,
+                S: Side,
+            {
+                if service_name == &*$First::SERVICE_NAME {
+                    return self.$first.on_request(session).await.map(Dispatched::$FirstVariant);
+                }
+                $(
+                    if service_name == &*$Handler::SERVICE_NAME {
+                        return self.$index.on_request(session).await.map(Dispatched::$Variant);
+                    }
+                )+
+
+                Err(unavailable(&mut session).await.into())
+            }
+        }
+    };
+}
+
+handlers!(0 A First, 1 B Second);
+handlers!(0 A First, 1 B Second, 2 C Third);
+handlers!(0 A First, 1 B Second, 2 C Third, 3 D Fourth);
+
+/// Disconnect the `session` from the peer, as the requested service is unknown.
+async fn unavailable<IO: Pipe, S: Side>(session: &mut Session<IO, S>) -> Error {
+    session
+        .disconnect(
+            DisconnectReason::ServiceNotAvailable,
+            "Requested service is unknown",
+        )
+        .await
+        .into()
+}
+
 /// A _service request_ in the transport protocol.
 pub trait Request {
     /// The errorneous outcome of the [`Request`].
@@ -46,3 +166,201 @@ pub trait Request {
         IO: Pipe,
         S: Side;
 }
+
+/// A marker type naming the service of a [`handler_fn`] or a [`request_fn`].
+pub trait ServiceName {
+    /// The service _identifier_.
+    const SERVICE_NAME: Ascii<'static>;
+}
+
+/// The object-safe subset of the [`Session`] methods, to erase it's [`Pipe`] and [`Side`].
+trait Erased: Send + Sync {
+    fn send(&mut self, packet: Packet) -> BoxFuture<'_, Result<()>>;
+
+    fn recv(&mut self) -> BoxFuture<'_, Result<Packet>>;
+
+    fn disconnect(
+        &mut self,
+        reason: DisconnectReason,
+        description: String,
+    ) -> BoxFuture<'_, DisconnectedError>;
+
+    fn extensions(&self) -> &Extensions;
+
+    fn extensions_mut(&mut self) -> &mut Extensions;
+}
+
+impl<IO: Pipe, S: Side> Erased for Session<IO, S> {
+    fn send(&mut self, packet: Packet) -> BoxFuture<'_, Result<()>> {
+        Session::send(self, packet).boxed()
+    }
+
+    fn recv(&mut self) -> BoxFuture<'_, Result<Packet>> {
+        Session::recv(self).boxed()
+    }
+
+    fn disconnect(
+        &mut self,
+        reason: DisconnectReason,
+        description: String,
+    ) -> BoxFuture<'_, DisconnectedError> {
+        Session::disconnect(self, reason, description).boxed()
+    }
+
+    fn extensions(&self) -> &Extensions {
+        Session::extensions(self)
+    }
+
+    fn extensions_mut(&mut self) -> &mut Extensions {
+        Session::extensions_mut(self)
+    }
+}
+
+/// A [`Session`] with it's [`Pipe`] and [`Side`] erased, handed to the closures of [`handler_fn`] and [`request_fn`].
+pub struct DynSession(Box<dyn Erased>);
+
+impl DynSession {
+    /// Erase the [`Pipe`] and [`Side`] of the `session`.
+    pub fn new<IO: Pipe, S: Side>(session: Session<IO, S>) -> Self {
+        Self(Box::new(session))
+    }
+
+    /// Send a _packet_ to the connected peer, as [`Session::send`].
+    pub async fn send(&mut self, message: impl IntoPacket) -> Result<()> {
+        self.0.send(message.into_packet()).await
+    }
+
+    /// Receive a _packet_ from the connected peer, as [`Session::recv`].
+    pub async fn recv(&mut self) -> Result<Packet> {
+        self.0.recv().await
+    }
+
+    /// Send a _disconnect message_ to the peer and shutdown the session, as [`Session::disconnect`].
+    pub async fn disconnect(
+        &mut self,
+        reason: DisconnectReason,
+        description: impl Into<Utf8<'_>>,
+    ) -> DisconnectedError {
+        self.0
+            .disconnect(reason, description.into().into_string())
+            .await
+    }
+
+    /// Access the [`Extensions`] attached to the session by the services.
+    pub fn extensions(&self) -> &Extensions {
+        self.0.extensions()
+    }
+
+    /// Access mutably the [`Extensions`] attached to the session by the services.
+    pub fn extensions_mut(&mut self) -> &mut Extensions {
+        self.0.extensions_mut()
+    }
+}
+
+impl std::fmt::Debug for DynSession {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DynSession").finish_non_exhaustive()
+    }
+}
+
+/// A [`Handler`] of the service named by `N`, from a closure, see [`handler_fn`].
+#[derive(Clone)]
+pub struct HandlerFn<N, F> {
+    name: PhantomData<N>,
+    callback: F,
+}
+
+impl<N: ServiceName, F> std::fmt::Debug for HandlerFn<N, F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HandlerFn")
+            .field("service_name", &N::SERVICE_NAME)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Create a [`Handler`] of the service named by the `name` marker,
+/// calling the `callback` with the [`DynSession`] when the peer requests it.
+pub fn handler_fn<N, F, Fut, T, E>(_name: N, callback: F) -> HandlerFn<N, F>
+where
+    N: ServiceName,
+    F: FnMut(DynSession) -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+    E: From<Error>,
+{
+    HandlerFn {
+        name: PhantomData,
+        callback,
+    }
+}
+
+impl<N, F, Fut, T, E> Handler for HandlerFn<N, F>
+where
+    N: ServiceName,
+    F: FnMut(DynSession) -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+    E: From<Error>,
+{
+    type Err = E;
+    type Ok<IO: Pipe, S: Side> = T;
+
+    const SERVICE_NAME: Ascii<'static> = N::SERVICE_NAME;
+
+    async fn on_request<IO, S>(&mut self, session: Session<IO, S>) -> Result<T, E>
+    where
+        IO: Pipe,
+        S: Side,
+    {
+        (self.callback)(DynSession::new(session)).await
+    }
+}
+
+/// A [`Request`] of the service named by `N`, from a closure, see [`request_fn`].
+#[derive(Clone)]
+pub struct RequestFn<N, F> {
+    name: PhantomData<N>,
+    callback: F,
+}
+
+impl<N: ServiceName, F> std::fmt::Debug for RequestFn<N, F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RequestFn")
+            .field("service_name", &N::SERVICE_NAME)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Create a [`Request`] of the service named by the `name` marker,
+/// calling the `callback` with the [`DynSession`] when the peer accepts it.
+pub fn request_fn<N, F, Fut, T, E>(_name: N, callback: F) -> RequestFn<N, F>
+where
+    N: ServiceName,
+    F: FnMut(DynSession) -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+    E: From<Error>,
+{
+    RequestFn {
+        name: PhantomData,
+        callback,
+    }
+}
+
+impl<N, F, Fut, T, E> Request for RequestFn<N, F>
+where
+    N: ServiceName,
+    F: FnMut(DynSession) -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+    E: From<Error>,
+{
+    type Err = E;
+    type Ok<IO: Pipe, S: Side> = T;
+
+    const SERVICE_NAME: Ascii<'static> = N::SERVICE_NAME;
+
+    async fn on_accept<IO, S>(&mut self, session: Session<IO, S>) -> Result<T, E>
+    where
+        IO: Pipe,
+        S: Side,
+    {
+        (self.callback)(DynSession::new(session)).await
+    }
+}
@@ -1,5 +1,4 @@
-use either::Either;
-use futures::{AsyncBufRead, AsyncWrite, AsyncWriteExt};
+use futures::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use futures_time::future::FutureExt;
 use ssh_packet::{
     arch::Utf8,
@@ -11,14 +10,25 @@ use ssh_packet::{
 };
 
 use crate::{
+    algorithm::{kex, Cipher, CipherAlgorithm, HostKey},
     error::{DisconnectedBy, DisconnectedError, Error, Result},
+    layer::Layer,
     service,
-    side::Side,
-    stream::Stream,
+    side::{ext_info, Side},
+    stream::{NegociatedAlgorithms, Stream, TransportStatsPair},
+    Extensions,
 };
 
-// TODO: (feature) Handle extension negotiation described in RFC8308.
-// TODO: (reliability) Fix out-of-band rekeying, it expects the packet right away while we are not sure the peer is that fast.
+// TODO: (feature) Handle the extensions described in RFC8308 other than `server-sig-algs`.
+
+/// The maximum length of the identification string, including the `CR LF`, as per RFC4253 §4.2.
+const ID_MAX_LEN: usize = 255;
+
+/// The maximum amount of lines the peer may send before it's identification string.
+const ID_MAX_LINES: usize = 1024;
+
+/// The protocol versions compatible with SSH-2.0, as per RFC4253 §5.1.
+const PROTOCOL_VERSIONS: &[&str] = &["2.0", "1.99"];
 
 /// A trait alias for something _pipe-alike_, implementing [`AsyncBufRead`] and [`AsyncWrite`].
 pub trait Pipe: AsyncBufRead + AsyncWrite + Unpin + Send + Sync + 'static {}
@@ -26,10 +36,16 @@ impl<T: AsyncBufRead + AsyncWrite + Unpin + Send + Sync + 'static> Pipe for T {}
 
 /// A session wrapping a `stream` to handle **key-exchange** and **[`SSH-TRANS`]** layer messages.
 pub struct Session<IO: Pipe, S: Side> {
-    stream: Either<Stream<IO>, DisconnectedError>,
+    /// The stream, only taken away by [`Self::into_inner`].
+    stream: Option<Stream<IO>>,
+    disconnected: Option<DisconnectedError>,
     config: S,
 
+    id: Id,
     peer_id: Id,
+    server_sig_algs: Option<Vec<String>>,
+    extensions: Extensions,
+    layers: Vec<Box<dyn Layer>>,
 }
 
 impl<IO, S> Session<IO, S>
@@ -40,46 +56,263 @@ where
     /// Create a new [`Session`] from a [`Pipe`] stream,
     /// and some configuration.
     pub async fn new(mut stream: IO, config: S) -> Result<Self> {
+        Self::check(&config)?;
+
         config.id().to_writer(&mut stream).await?;
         stream.flush().await?;
 
-        let peer_id = Id::from_reader(&mut stream)
+        let peer_id = Self::read_id(&mut stream)
             .timeout(config.timeout())
             .await??;
 
-        let stream = Stream::new(stream, config.timeout());
+        let id = config.id().clone();
+
+        Self::new_with_ids(stream, config, id, peer_id)
+    }
+
+    /// Create a new [`Session`] from a [`Pipe`] stream on which the identification strings
+    /// have already been exchanged, such as by a proxy peeking at them, and some configuration.
+    ///
+    /// The exchange starts right away with the key-exchange, with the `id` sent by this side
+    /// in place of the configured one, and the `peer_id` received from the peer,
+    /// which must match the strings exchanged on the wire to produce the same exchange hash as the peer.
+    pub fn new_with_ids(stream: IO, config: S, id: Id, peer_id: Id) -> Result<Self> {
+        Self::check(&config)?;
+
+        let stream = Stream::new(
+            stream,
+            config.timeout(),
+            config.rekey_bytes(),
+            config.rekey_interval(),
+            config.max_packet_size(),
+            config.buffer_size(),
+        );
 
         tracing::debug!("Session started with peer `{peer_id}`");
 
         Ok(Self {
-            stream: Either::Left(stream),
+            stream: Some(stream),
+            disconnected: None,
             config,
+            id,
             peer_id,
+            server_sig_algs: None,
+            extensions: Default::default(),
+            layers: Default::default(),
         })
     }
 
+    /// Attach the `layer` to the session, notified of it's events after the layers attached before it.
+    pub fn with_layer(mut self, layer: impl Layer) -> Self {
+        self.layers.push(Box::new(layer));
+
+        self
+    }
+
+    /// Read the peer's [`Id`], skipping the lines sent beforehand,
+    /// which are bounded in length and may end with a bare `LF`.
+    async fn read_id(stream: &mut IO) -> Result<Id> {
+        let mut line = Vec::with_capacity(ID_MAX_LEN);
+
+        for _ in 0..=ID_MAX_LINES {
+            line.clear();
+            (&mut *stream)
+                .take(ID_MAX_LEN as u64)
+                .read_until(b'\n', &mut line)
+                .await?;
+
+            match line.strip_suffix(b"\n") {
+                Some(text) if text.starts_with(b"SSH-") => {
+                    return Self::parse_id(text.strip_suffix(b"\r").unwrap_or(text))
+                }
+                Some(_) => continue,
+                None if line.len() == ID_MAX_LEN => return Err(Error::IdTooLong(ID_MAX_LEN)),
+                None => return Err(ssh_packet::Error::UnexpectedEof.into()),
+            }
+        }
+
+        Err(ssh_packet::Error::BadIdentifer(String::from_utf8_lossy(&line).into_owned()).into())
+    }
+
+    /// Parse the peer's [`Id`] from the `text` of its line, without the line ending.
+    fn parse_id(text: &[u8]) -> Result<Id> {
+        let bad = || ssh_packet::Error::BadIdentifer(String::from_utf8_lossy(text).into_owned());
+
+        if text.iter().any(u8::is_ascii_control) {
+            return Err(bad().into());
+        }
+
+        let id: Id = std::str::from_utf8(text).map_err(|_| bad())?.parse()?;
+        if !PROTOCOL_VERSIONS.contains(&id.protoversion.as_str()) {
+            return Err(Error::ProtocolVersionUnsupported(id.protoversion));
+        }
+
+        Ok(id)
+    }
+
+    /// Validate the algorithms advertised by the `config`, which need at least one algorithm
+    /// per name-list, except for the _hmac_ when all the ciphers authenticate the packets by themselves.
+    fn check(config: &S) -> Result<()> {
+        let kexinit = config.kexinit();
+
+        if !kexinit
+            .kex_algorithms
+            .into_iter()
+            .any(|name| !kex::is_marker(&name))
+        {
+            return Err(Error::Config("no key-exchange algorithm enabled"));
+        }
+
+        let ciphers = &kexinit.encryption_algorithms_client_to_server;
+        if ciphers.into_iter().next().is_none() {
+            return Err(Error::Config("no encryption algorithm enabled"));
+        }
+
+        let aead = ciphers
+            .into_iter()
+            .all(|name| name.parse::<Cipher>().is_ok_and(|cipher| cipher.is_aead()));
+        if !aead
+            && kexinit
+                .mac_algorithms_client_to_server
+                .into_iter()
+                .next()
+                .is_none()
+        {
+            return Err(Error::Config(
+                "no hmac algorithm enabled, while some ciphers require one",
+            ));
+        }
+
+        if kexinit
+            .compression_algorithms_client_to_server
+            .into_iter()
+            .next()
+            .is_none()
+            || kexinit
+                .compression_algorithms_server_to_client
+                .into_iter()
+                .next()
+                .is_none()
+        {
+            return Err(Error::Config("no compression algorithm enabled"));
+        }
+
+        Ok(())
+    }
+
+    /// Access the [`Id`] sent to the peer, either the configured one or the one provided to [`Self::new_with_ids`].
+    pub fn id(&self) -> &Id {
+        &self.id
+    }
+
     /// Access the [`Id`] of the connected peer.
     pub fn peer_id(&self) -> &Id {
         &self.peer_id
     }
 
+    /// Access the names of the signature algorithms accepted by the peer for the `publickey` authentication,
+    /// as advertised in it's `server-sig-algs` extension, or `None` if it didn't advertise them.
+    pub fn server_sig_algs(&self) -> Option<&[String]> {
+        self.server_sig_algs.as_deref()
+    }
+
+    /// Access the [`Extensions`] attached to the session by the services.
+    pub fn extensions(&self) -> &Extensions {
+        &self.extensions
+    }
+
+    /// Access mutably the [`Extensions`] attached to the session by the services.
+    pub fn extensions_mut(&mut self) -> &mut Extensions {
+        &mut self.extensions
+    }
+
     /// Access initial exchange hash.
     pub fn session_id(&self) -> Option<&[u8]> {
-        self.stream.as_ref().left().and_then(Stream::session_id)
+        self.connected().and_then(Stream::session_id)
+    }
+
+    /// Access the names of the algorithms negociated during the last key-exchange,
+    /// or `None` if no key-exchange completed or the session has been disconnected.
+    pub fn algorithms(&self) -> Option<&NegociatedAlgorithms> {
+        self.connected().and_then(Stream::algorithms)
+    }
+
+    /// Access the host key which authenticated the _server_ during the last key-exchange,
+    /// either verified by the _client_ or presented by the _server_, along with
+    /// the negociated [`NegociatedAlgorithms::host_key`] algorithm.
+    pub fn server_host_key(&self) -> Option<&HostKey> {
+        self.connected().and_then(Stream::server_host_key)
+    }
+
+    /// Take a snapshot of the traffic statistics for both directions and of the key-exchanges,
+    /// cumulative across re-keys, or `None` if the session has been disconnected.
+    pub fn stats(&self) -> Option<TransportStatsPair> {
+        self.connected().map(Stream::stats)
+    }
+
+    /// Consume the session to recover the underlying [`Pipe`] stream, along with the packets
+    /// received from the peer but not yet returned by [`Self::recv`], and the [`DisconnectedError`]
+    /// if the session has been disconnected.
+    ///
+    /// The bytes buffered by the [`Pipe`] itself are left untouched in it, but a packet partially
+    /// read by a cancelled [`Self::recv`] is lost, as it can't be decrypted without its remainder.
+    ///
+    /// The session is not disconnected from the peer if it's still connected.
+    pub fn into_inner(mut self) -> (IO, Vec<Packet>, Option<DisconnectedError>) {
+        let Some((stream, pending)) = self.stream.take().map(Stream::into_inner) else {
+            unreachable!("the stream is only taken away when consuming the session");
+        };
+
+        (stream, pending, self.disconnected.take())
+    }
+
+    /// Access the stream, if the session is still connected.
+    fn connected(&self) -> Option<&Stream<IO>> {
+        self.stream.as_ref().filter(|_| self.disconnected.is_none())
+    }
+
+    /// Access mutably the stream, or the error the session has been disconnected with.
+    pub(crate) fn connected_mut(&mut self) -> Result<&mut Stream<IO>> {
+        Ok(Self::connected_fields(
+            &mut self.stream,
+            &self.disconnected,
+        )?)
+    }
+
+    /// Access mutably the `stream` with the `disconnected` state, borrowing only those fields.
+    fn connected_fields<'s>(
+        stream: &'s mut Option<Stream<IO>>,
+        disconnected: &Option<DisconnectedError>,
+    ) -> Result<&'s mut Stream<IO>, DisconnectedError> {
+        match (stream, disconnected) {
+            (_, Some(err)) => Err(err.clone()),
+            (Some(stream), None) => Ok(stream),
+            (None, None) => {
+                unreachable!("the stream is only taken away when consuming the session")
+            }
+        }
     }
 
     /// Waits until the [`Session`] becomes readable,
     /// mainly to be used with [`Session::recv`] in [`futures::select`],
     /// since the `recv` method is **not cancel-safe**.
     pub async fn readable(&mut self) -> Result<()> {
-        let stream = match &mut self.stream {
-            Either::Left(stream) => stream,
-            Either::Right(err) => return Err(err.clone().into()),
-        };
+        let stream = self.connected_mut()?;
 
         stream.fill_buf().await
     }
 
+    /// Poll the [`Session`] to become readable, as [`Session::readable`] does.
+    pub(crate) fn poll_readable(
+        &mut self,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<()>> {
+        match self.connected_mut() {
+            Ok(stream) => stream.poll_fill_buf(cx),
+            Err(err) => std::task::Poll::Ready(Err(err)),
+        }
+    }
+
     /// Receive a _packet_ from the connected peer.
     ///
     /// # Cancel safety
@@ -87,23 +320,24 @@ where
     /// some data may be partially received.
     pub async fn recv(&mut self) -> Result<Packet> {
         loop {
-            let stream = match &mut self.stream {
-                Either::Left(stream) => stream,
-                Either::Right(err) => return Err(err.clone().into()),
-            };
+            let stream = self.connected_mut()?;
 
-            if stream.is_rekeyable() || stream.peek().await?.to::<KexInit>().is_ok() {
-                if let Err(err) = self.config.kex(stream, &self.peer_id).await {
-                    return Err(self
-                        .disconnect(DisconnectReason::KeyExchangeFailed, err.to_string())
-                        .await
-                        .into());
-                }
+            let rekey = stream.is_rekeyable()
+                || match stream.peek().await {
+                    Ok(packet) => packet.to::<KexInit>().is_ok(),
+                    Err(err) => return Err(self.malformed(err).await),
+                };
+
+            if rekey {
+                self.kex().await?;
 
                 continue;
             }
 
-            let packet = stream.recv().await?;
+            let packet = match stream.recv().await {
+                Ok(packet) => packet,
+                Err(err) => return Err(self.malformed(err).await),
+            };
 
             if let Ok(Disconnect {
                 reason,
@@ -113,7 +347,7 @@ where
             {
                 tracing::info!("Peer disconnected with `{reason:?}`: {description}");
 
-                self.stream = Either::Right(DisconnectedError {
+                self.disconnected(DisconnectedError {
                     by: DisconnectedBy::Them,
                     reason,
                     description: description.into_string(),
@@ -124,29 +358,100 @@ where
                 tracing::debug!("Received an 'unimplemented' message about packet #{seq}",);
             } else if let Ok(Debug { message, .. }) = packet.to() {
                 tracing::debug!("Received a 'debug' message: {message}");
+            } else if let Some(extensions) = ext_info::decode(&packet) {
+                tracing::debug!("Received an 'ext-info' message with {extensions:?}");
+
+                if let Some((_, algorithms)) = extensions
+                    .into_iter()
+                    .find(|(name, _)| name == ext_info::SERVER_SIG_ALGS)
+                {
+                    self.server_sig_algs = Some(algorithms.split(',').map(Into::into).collect());
+                }
             } else {
                 break Ok(packet);
             }
         }
     }
 
+    /// Disconnect from the peer if the `err` is caused by a malformed or tampered packet.
+    async fn malformed(&mut self, err: Error) -> Error {
+        let reason = match err {
+            Error::MacMismatch(_) => DisconnectReason::MacError,
+            Error::Padding | Error::PacketTooLarge(_) => DisconnectReason::ProtocolError,
+            err => return err,
+        };
+
+        self.disconnect(reason, err.to_string()).await.into()
+    }
+
+    /// Waits until the [`Session`] becomes writable, mainly to be used with [`Session::send`]
+    /// in [`futures::select`], since the `send` method is **not cancel-safe**.
+    ///
+    /// This performs the key-exchange if one is due, since sending would wait for it,
+    /// and waits for the underlying writer to accept more data.
+    ///
+    /// # Cancel safety
+    /// This method is cancel-safe, unless a key-exchange is due and gets interrupted,
+    /// in which case the session can't be used afterwards.
+    pub async fn writable(&mut self) -> Result<()> {
+        let stream = self.connected_mut()?;
+        if stream.is_rekeyable() || stream.is_kex_pending() {
+            self.kex().await?;
+        }
+
+        self.connected_mut()?.flush().await
+    }
+
     /// Send a _packet_ to the connected peer.
+    ///
+    /// # Cancel safety
+    /// This method is **not cancel-safe**, if used within a [`futures::select`] call,
+    /// some data may be partially sent.
     pub async fn send(&mut self, message: impl IntoPacket) -> Result<()> {
-        let stream = match &mut self.stream {
-            Either::Left(stream) => stream,
-            Either::Right(err) => return Err(err.clone().into()),
-        };
+        let stream = self.connected_mut()?;
+        if stream.is_rekeyable() || stream.is_kex_pending() {
+            self.kex().await?;
+        }
 
-        if stream.is_rekeyable() {
-            if let Err(err) = self.config.kex(stream, &self.peer_id).await {
-                return Err(self
-                    .disconnect(DisconnectReason::KeyExchangeFailed, err.to_string())
-                    .await
-                    .into());
-            }
+        self.connected_mut()?.send(message).await
+    }
+
+    /// Initiate a _key re-exchange_ with the peer, regardless of the rekeying thresholds.
+    ///
+    /// The _session identifier_ remains the one derived from the initial exchange.
+    pub async fn rekey(&mut self) -> Result<()> {
+        self.kex().await
+    }
+
+    /// Activate the delayed compression (`zlib@openssh.com`) on both directions,
+    /// to be called by the authentication service once the user is authenticated.
+    pub fn activate_compression(&mut self) {
+        if let Ok(stream) = self.connected_mut() {
+            stream.with_compression();
+        }
+    }
+
+    /// Perform the key-exchange, and disconnect from the peer on failure.
+    async fn kex(&mut self) -> Result<()> {
+        let stream = Self::connected_fields(&mut self.stream, &self.disconnected)?;
+
+        if let Err(err) = self.config.kex(stream, &self.id, &self.peer_id).await {
+            let reason = match err {
+                Error::KexTimeout | Error::PacketTooLarge(_) | Error::Padding => {
+                    DisconnectReason::ProtocolError
+                }
+                Error::HostKeyRejected(_)
+                | Error::CertificateUntrusted
+                | Error::CertificateType
+                | Error::CertificateExpired
+                | Error::CertificatePrincipal => DisconnectReason::HostKeyNotVerifiable,
+                _ => DisconnectReason::KeyExchangeFailed,
+            };
+
+            return Err(self.disconnect(reason, err.to_string()).await.into());
         }
 
-        stream.send(message).await
+        Ok(())
     }
 
     /// Send a _disconnect message_ to the peer and shutdown the session.
@@ -155,9 +460,9 @@ where
         reason: DisconnectReason,
         description: impl Into<Utf8<'_>>,
     ) -> DisconnectedError {
-        let stream = match &mut self.stream {
-            Either::Left(stream) => stream,
-            Either::Right(err) => return err.clone(),
+        let stream = match Self::connected_fields(&mut self.stream, &self.disconnected) {
+            Ok(stream) => stream,
+            Err(err) => return err,
         };
 
         let message = Disconnect {
@@ -174,23 +479,35 @@ where
             reason: message.reason,
             description: message.description.into_string(),
         };
-        self.stream = Either::Right(err.clone());
+        self.disconnected(err.clone());
 
         err
     }
 
-    /// Handle a _service_ for the peer.
+    /// Mark the session as disconnected with `err`, and notify the layers.
+    fn disconnected(&mut self, err: DisconnectedError) {
+        for layer in &mut self.layers {
+            layer.on_disconnect(&err);
+        }
+
+        self.disconnected = Some(err);
+    }
+
+    /// Handle a _service_ for the peer, or one of multiple services with a tuple of [`service::Handler`]s.
     pub async fn handle<H>(mut self, mut service: H) -> Result<H::Ok<IO, S>, H::Err>
     where
-        H: service::Handler,
+        H: service::Handlers,
     {
         let packet = self.recv().await?;
 
         if let Ok(ServiceRequest { service_name }) = packet.to() {
-            if service_name == H::SERVICE_NAME {
-                self.send(&ServiceAccept { service_name }).await?;
+            if service.handles(&service_name) {
+                self.send(&ServiceAccept {
+                    service_name: service_name.as_borrow(),
+                })
+                .await?;
 
-                service.on_request(self).await
+                service.dispatch(&service_name, self).await
             } else {
                 Err(Error::from(
                     self.disconnect(
@@ -256,6 +573,10 @@ where
     S: Side,
 {
     fn drop(&mut self) {
+        if self.stream.is_none() {
+            return;
+        }
+
         // TODO: (reliability) Find out:
         // 1. if this blocking call is an issue;
         // 2. how to have a generic way to trigger an async task regardless of the executor
@@ -274,7 +595,86 @@ mod tests {
     use crate::side::{client::Client, server::Server};
 
     use async_std::net::TcpStream;
-    use futures::io::BufReader;
+    use futures::io::{BufReader, Cursor};
+    use rstest::rstest;
+
+    type CursorSession = Session<Cursor<Vec<u8>>, Client>;
+
+    #[rstest]
+    #[case(b"SSH-2.0-billsSSH_3.6.3q3\r\n", "2.0")]
+    #[case(b"SSH-2.0-billsSSH_3.6.3q3\n", "2.0")]
+    #[case(b"SSH-1.99-billsSSH_3.6.3q3\r\n", "1.99")]
+    #[case(b"SSH-2.0-billsSSH_3.6.3q3 with-comment\r\n", "2.0")]
+    #[case(b"banner line\r\nanother one\nSSH-2.0-billsSSH_3.6.3q3\r\n", "2.0")]
+    #[async_std::test]
+    async fn id_is_accepted(#[case] wire: &[u8], #[case] protoversion: &str) -> Result<()> {
+        let id = CursorSession::read_id(&mut Cursor::new(wire.to_vec())).await?;
+
+        assert_eq!(id.protoversion, protoversion);
+        assert_eq!(id.softwareversion, "billsSSH_3.6.3q3");
+
+        Ok(())
+    }
+
+    #[rstest]
+    #[case(b"SSH-1.5-billsSSH_3.6.3q3\r\n")]
+    #[case(b"SSH-1.0-billsSSH_3.6.3q3\r\n")]
+    #[case(b"SSH-3.0-billsSSH_3.6.3q3\r\n")]
+    #[async_std::test]
+    async fn id_with_unsupported_version_is_refused(#[case] wire: &[u8]) {
+        assert!(matches!(
+            CursorSession::read_id(&mut Cursor::new(wire.to_vec())).await,
+            Err(Error::ProtocolVersionUnsupported(_))
+        ));
+    }
+
+    #[rstest]
+    #[case(b"SSH-2.0-bills\0SSH\r\n")]
+    #[case(b"SSH-2.0-bills\x1bSSH\r\n")]
+    #[case(b"SSH-2.0-bills\rSSH\r\n")]
+    #[case(b"SSH-2.0-billsSSH\r\r\n")]
+    #[case(b"SSH-2.0-bills\xffSSH\r\n")]
+    #[case(b"SSH-2.0-\r\n")]
+    #[async_std::test]
+    async fn malformed_id_is_refused(#[case] wire: &[u8]) {
+        assert!(matches!(
+            CursorSession::read_id(&mut Cursor::new(wire.to_vec())).await,
+            Err(Error::Id(ssh_packet::Error::BadIdentifer(_)))
+        ));
+    }
+
+    #[rstest]
+    #[case(b"")]
+    #[case(b"SSH-2.0-billsSSH_3.6.3q3")]
+    #[case(b"banner line\r\n")]
+    #[async_std::test]
+    async fn truncated_id_is_refused(#[case] wire: &[u8]) {
+        assert!(matches!(
+            CursorSession::read_id(&mut Cursor::new(wire.to_vec())).await,
+            Err(Error::Id(ssh_packet::Error::UnexpectedEof))
+        ));
+    }
+
+    #[rstest]
+    #[case(format!("SSH-2.0-{}\r\n", "A".repeat(ID_MAX_LEN)))]
+    #[case(format!("{}\r\nSSH-2.0-billsSSH_3.6.3q3\r\n", "A".repeat(ID_MAX_LEN)))]
+    #[async_std::test]
+    async fn overlong_id_is_refused(#[case] wire: String) {
+        assert!(matches!(
+            CursorSession::read_id(&mut Cursor::new(wire.into_bytes())).await,
+            Err(Error::IdTooLong(ID_MAX_LEN))
+        ));
+    }
+
+    #[async_std::test]
+    async fn endless_lines_before_id_are_refused() {
+        let wire = "banner\r\n".repeat(ID_MAX_LINES + 1) + "SSH-2.0-billsSSH_3.6.3q3\r\n";
+
+        assert!(matches!(
+            CursorSession::read_id(&mut Cursor::new(wire.into_bytes())).await,
+            Err(Error::Id(ssh_packet::Error::BadIdentifer(_)))
+        ));
+    }
 
     #[test]
     fn assert_session_is_send() {
@@ -1,22 +1,70 @@
 //! Client-[`Side`] implementation of the _session_.
 
-use std::time::Duration;
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
 
 use futures_time::time::Duration as Timeout;
 use rand::RngCore;
-use ssh_packet::{arch::NameList, trans::KexInit};
+use ssh_key::{certificate::CertType, Certificate, HashAlg, PublicKey};
+use ssh_packet::{arch::NameList, trans::KexInit, Packet};
 
 use super::{server::Server, Side};
 use crate::{
-    algorithm::{Cipher, Compress, Hmac, Kex, KexMeta, Key, Negociate},
-    stream::{Stream, TransportPair},
-    Pipe, Result,
+    algorithm::{
+        certificate_name,
+        kex::{self, KexStream},
+        negociate_host_key, Cipher, CipherAlgorithm, Compress, Hmac, HostKey, Kex, KexAlgorithm,
+        KexMeta, Key, EXT_INFO_CLIENT, KEX_STRICT_CLIENT,
+    },
+    stream::{NegociatedAlgorithms, PaddingMode, Stream, TransportPair},
+    Error, Pipe, Result,
 };
 
 #[doc(no_inline)]
 pub use ssh_packet::Id;
 
-// TODO: (compliance) Hostkey verification in client key-exchange.
+mod known_hosts;
+pub use known_hosts::{HostStatus, KnownHosts};
+
+mod pinned;
+pub use pinned::PinnedFingerprints;
+
+mod policy;
+pub use policy::HostKeyPolicy;
+
+mod tofu;
+pub use tofu::{TofuPolicy, TrustOnFirstUse};
+
+/// The future returned by the [`HostKeyVerifier::verify`] method.
+pub type HostKeyFuture<'a> = Pin<Box<dyn Future<Output = bool> + Send + Sync + 'a>>;
+
+/// A verifier deciding whether the host key presented by the _server_ is trusted,
+/// implemented for `Fn(PublicKey, Id) -> impl Future<Output = bool>` closures.
+pub trait HostKeyVerifier: Send + Sync + 'static {
+    /// Whether the host `key` presented by the _server_ identified by `peer_id` is trusted,
+    /// which is asked on each key-exchange, in case the key changed in the meantime.
+    fn verify<'a>(&'a self, key: &'a PublicKey, peer_id: &'a Id) -> HostKeyFuture<'a>;
+}
+
+impl<F, Fut> HostKeyVerifier for F
+where
+    F: Fn(PublicKey, Id) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = bool> + Send + Sync + 'static,
+{
+    fn verify<'a>(&'a self, key: &'a PublicKey, peer_id: &'a Id) -> HostKeyFuture<'a> {
+        Box::pin(self(key.clone(), peer_id.clone()))
+    }
+}
+
+impl std::fmt::Debug for dyn HostKeyVerifier {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HostKeyVerifier").finish_non_exhaustive()
+    }
+}
 
 /// A _client_-side session configuration.
 #[derive(Debug, Clone)]
@@ -27,6 +75,53 @@ pub struct Client {
     /// Timeout for sending and receiving packets.
     pub timeout: Duration,
 
+    /// Timeout for a whole key-exchange to complete.
+    pub kex_timeout: Duration,
+
+    /// Re-key after this amount of bytes have been exchanged,
+    /// defaults to 1GiB as recommended per the RFC.
+    pub rekey_bytes: u64,
+
+    /// Re-key after this amount of time has elapsed since the last exchange,
+    /// defaults to 1 hour as recommended per the RFC.
+    pub rekey_interval: Duration,
+
+    /// Disconnect the peer when it sends a packet declaring a greater length than this,
+    /// defaults to 256KiB as OpenSSH does.
+    pub max_packet_size: usize,
+
+    /// The capacity retained by the packet buffers of each direction, which grow up to
+    /// [`Self::max_packet_size`] to fit a larger packet and shrink back to this afterwards,
+    /// so that idle sessions don't hold onto the peak-sized buffers. A size greater than
+    /// the `max_packet_size` never shrinks them, sparing the reallocations to bulk transfers.
+    /// Defaults to 36KiB, fitting the 35000 bytes packets every peer must handle as per RFC4253 §6.1.
+    pub buffer_size: usize,
+
+    /// The _zlib_ compression level for the sent packets,
+    /// from 1 (fastest) to 9 (best), defaults to 6.
+    pub compression_level: u32,
+
+    /// How the padding of the sent packets is filled, defaults to random bytes.
+    pub padding: PaddingMode,
+
+    /// The verifier of the _server_'s host key, any key is trusted if unset.
+    pub host_key_verifier: Option<Arc<dyn HostKeyVerifier>>,
+
+    /// The _certificate authorities_ trusted to sign the _server_'s host key, in which case
+    /// the certificate host key algorithms are advertised first, and valid certificates are
+    /// trusted without asking the [`HostKeyVerifier`].
+    pub certificate_authorities: Vec<PublicKey>,
+
+    /// The names of the host key algorithms advertised to the _server_, in order of preference,
+    /// overriding the ones derived from the enabled [`Algorithms::keys`] if set.
+    pub host_key_algorithms: Option<Vec<String>>,
+
+    /// The minimum strength required from the _server_'s host key.
+    pub host_key_policy: HostKeyPolicy,
+
+    /// The hostname of the _server_, matched against the principals of it's host certificate.
+    pub hostname: Option<String>,
+
     /// The algorithms enabled for this _client_ session.
     pub algorithms: Algorithms,
 }
@@ -43,6 +138,18 @@ impl Default for Client {
                 None::<&str>,
             ),
             timeout: Duration::from_secs(120),
+            kex_timeout: Duration::from_secs(60),
+            rekey_bytes: 0x40000000,
+            rekey_interval: Duration::from_secs(3600),
+            max_packet_size: 0x40000,
+            buffer_size: 0x9000,
+            compression_level: 6,
+            padding: Default::default(),
+            host_key_verifier: None,
+            certificate_authorities: Default::default(),
+            host_key_algorithms: None,
+            host_key_policy: Default::default(),
+            hostname: None,
             algorithms: Default::default(),
         }
     }
@@ -54,30 +161,44 @@ pub struct Algorithms {
     /// Enabled algorithms for _key-exchange_.
     pub kexs: Vec<Kex>,
 
+    /// Additional custom algorithms for _key-exchange_, advertised after the built-in ones.
+    pub custom_kexs: Vec<Arc<dyn KexAlgorithm>>,
+
     /// Enabled algorithms for _server key signature_.
     pub keys: Vec<Key>,
 
     /// Enabled algorithms for _encryption & decryption_.
     pub ciphers: Vec<Cipher>,
 
+    /// Additional custom algorithms for _encryption & decryption_, advertised after the built-in ones.
+    pub custom_ciphers: Vec<Arc<dyn CipherAlgorithm>>,
+
     /// Enabled algorithms for _hmac_.
     pub macs: Vec<Hmac>,
 
-    /// Enabled algorithms for _compression_.
-    pub compressions: Vec<Compress>,
+    /// Enabled algorithms for _compression_ of the packets sent by the _client_.
+    pub compressions_client_to_server: Vec<Compress>,
+
+    /// Enabled algorithms for _compression_ of the packets sent by the _server_.
+    pub compressions_server_to_client: Vec<Compress>,
 }
 
 impl Default for Algorithms {
     fn default() -> Self {
         let super::server::Algorithms {
             kexs,
+            custom_kexs,
             ciphers,
+            custom_ciphers,
             macs,
-            compressions,
+            compressions_client_to_server,
+            compressions_server_to_client,
+            server_sig_algs: _,
         } = Default::default();
 
         Self {
             kexs,
+            custom_kexs,
             keys: vec![
                 Key::Ed25519,
                 Key::Ecdsa {
@@ -96,12 +217,221 @@ impl Default for Algorithms {
                 Key::Dsa,
             ],
             ciphers,
+            custom_ciphers,
             macs,
-            compressions,
+            compressions_client_to_server,
+            compressions_server_to_client,
         }
     }
 }
 
+impl Client {
+    /// Set the enabled algorithms for _key-exchange_, in order of preference.
+    pub fn kexs(mut self, kexs: &[Kex]) -> Self {
+        self.algorithms.kexs = kexs.to_vec();
+
+        self
+    }
+
+    /// Enable the legacy _SHA-1_ Diffie-Hellman algorithms for _key-exchange_,
+    /// with the lowest preference.
+    ///
+    /// **Deprecated**: these methods are considered weak, and should only be
+    /// enabled to talk to peers supporting nothing else.
+    #[cfg(feature = "legacy-kex")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "legacy-kex")))]
+    pub fn legacy_kexs(mut self) -> Self {
+        self.algorithms
+            .kexs
+            .extend([Kex::DiffieHellmanGroup14Sha1, Kex::DiffieHellmanGroup1Sha1]);
+
+        self
+    }
+
+    /// Remove the _SHA-1_ based algorithms from the enabled _hmac_ algorithms.
+    pub fn without_sha1_macs(mut self) -> Self {
+        self.algorithms.macs.retain(|mac| !mac.is_sha1());
+
+        self
+    }
+
+    /// Enable the `none` algorithm for _encryption & decryption_, with the lowest preference.
+    ///
+    /// **Insecure**: the packets are then sent in clear, this should only be
+    /// enabled for debugging or on links which are already secured.
+    pub fn none_cipher(mut self) -> Self {
+        self.algorithms.ciphers.push(Cipher::None);
+
+        self
+    }
+
+    /// Enable the `none` algorithm for _hmac_, with the lowest preference.
+    ///
+    /// **Insecure**: the packets are then not authenticated, this should only be
+    /// enabled for debugging or on links which are already secured.
+    pub fn none_mac(mut self) -> Self {
+        self.algorithms.macs.push(Hmac::None);
+
+        self
+    }
+
+    /// Set the enabled algorithms for _compression_ in both directions, in order of preference.
+    pub fn compressions(mut self, compressions: &[Compress]) -> Self {
+        self.algorithms.compressions_client_to_server = compressions.to_vec();
+        self.algorithms.compressions_server_to_client = compressions.to_vec();
+
+        self
+    }
+
+    /// Set the enabled algorithms for _compression_ of the packets sent by the _client_, in order of preference.
+    pub fn compressions_client_to_server(mut self, compressions: &[Compress]) -> Self {
+        self.algorithms.compressions_client_to_server = compressions.to_vec();
+
+        self
+    }
+
+    /// Set the enabled algorithms for _compression_ of the packets sent by the _server_, in order of preference.
+    pub fn compressions_server_to_client(mut self, compressions: &[Compress]) -> Self {
+        self.algorithms.compressions_server_to_client = compressions.to_vec();
+
+        self
+    }
+
+    /// Set the enabled algorithms for _encryption & decryption_, in order of preference,
+    /// the custom ones being still advertised after them.
+    pub fn ciphers(mut self, ciphers: &[Cipher]) -> Self {
+        self.algorithms.ciphers = ciphers.to_vec();
+
+        self
+    }
+
+    /// Set the enabled algorithms for _hmac_, in order of preference,
+    /// which may be empty if only _AEAD_ ciphers are enabled.
+    pub fn macs(mut self, macs: &[Hmac]) -> Self {
+        self.algorithms.macs = macs.to_vec();
+
+        self
+    }
+
+    /// Register an additional custom algorithm for _key-exchange_.
+    pub fn kex_algorithm(mut self, kex: impl KexAlgorithm) -> Self {
+        self.algorithms.custom_kexs.push(Arc::new(kex));
+
+        self
+    }
+
+    /// The names of the enabled algorithms for _encryption & decryption_, custom ones last.
+    fn cipher_names(&self) -> impl Iterator<Item = &str> {
+        self.algorithms.ciphers.iter().map(Cipher::as_ref).chain(
+            self.algorithms
+                .custom_ciphers
+                .iter()
+                .map(|cipher| cipher.name()),
+        )
+    }
+
+    /// Register an additional custom algorithm for _encryption & decryption_.
+    pub fn cipher_algorithm(mut self, cipher: impl CipherAlgorithm) -> Self {
+        self.algorithms.custom_ciphers.push(Arc::new(cipher));
+
+        self
+    }
+
+    /// Trust the _certificate authority_ `key` to sign the _server_'s host certificate.
+    pub fn certificate_authority(mut self, key: PublicKey) -> Self {
+        self.certificate_authorities.push(key);
+
+        self
+    }
+
+    /// Set the `hostname` of the _server_, to validate the principals of it's host certificate.
+    pub fn hostname(mut self, hostname: impl Into<String>) -> Self {
+        self.hostname = Some(hostname.into());
+
+        self
+    }
+
+    /// Set the names of the host key algorithms advertised to the _server_, in order of preference,
+    /// as OpenSSH's `HostKeyAlgorithms` option, including the certificate ones
+    /// (such as `ssh-ed25519-cert-v01@openssh.com`).
+    pub fn host_key_algorithms(mut self, names: &[&str]) -> Self {
+        self.host_key_algorithms = Some(names.iter().map(|name| name.to_string()).collect());
+
+        self
+    }
+
+    /// Set the minimum strength required from the _server_'s host key.
+    pub fn host_key_policy(mut self, policy: HostKeyPolicy) -> Self {
+        self.host_key_policy = policy;
+
+        self
+    }
+
+    /// The names of the enabled algorithms for _server key signature_, either the configured ones,
+    /// or the derived ones with the certificate ones first if any _certificate authority_ is trusted.
+    fn keys(&self) -> Vec<String> {
+        if let Some(names) = &self.host_key_algorithms {
+            return names.clone();
+        }
+
+        let certificates = self
+            .algorithms
+            .keys
+            .iter()
+            .filter(|_| !self.certificate_authorities.is_empty())
+            .map(certificate_name);
+
+        certificates
+            .chain(self.algorithms.keys.iter().map(Key::to_string))
+            .collect()
+    }
+
+    /// Validate the host `certificate` presented by the _server_,
+    /// as described in OpenSSH's `PROTOCOL.certkeys`.
+    fn validate(&self, certificate: &Certificate) -> Result<()> {
+        if !self
+            .certificate_authorities
+            .iter()
+            .any(|ca| ca.key_data() == certificate.signature_key())
+            || !certificate.critical_options().is_empty()
+        {
+            return Err(Error::CertificateUntrusted);
+        }
+        if certificate.cert_type() != CertType::Host {
+            return Err(Error::CertificateType);
+        }
+
+        let now = SystemTime::now();
+        if now < certificate.valid_after_time() || now >= certificate.valid_before_time() {
+            return Err(Error::CertificateExpired);
+        }
+
+        // An empty list of principals makes the certificate valid for any host.
+        let principals = certificate.valid_principals();
+        if !principals.is_empty()
+            && !self.hostname.as_ref().is_some_and(|hostname| {
+                principals.iter().any(|principal| {
+                    known_hosts::glob(&principal.to_lowercase(), &hostname.to_lowercase())
+                })
+            })
+        {
+            return Err(Error::CertificatePrincipal);
+        }
+
+        let fingerprint = certificate.signature_key().fingerprint(HashAlg::Sha256);
+        certificate
+            .validate([&fingerprint])
+            .map_err(|_| Error::CertificateUntrusted)
+    }
+
+    /// Set the verifier of the _server_'s host key, aborting the key-exchange when it is rejected.
+    pub fn host_key_verifier(mut self, verifier: impl HostKeyVerifier) -> Self {
+        self.host_key_verifier = Some(Arc::new(verifier));
+
+        self
+    }
+}
+
 impl Side for Client {
     fn id(&self) -> &Id {
         &self.id
@@ -111,23 +441,50 @@ impl Side for Client {
         self.timeout.into()
     }
 
+    fn kex_timeout(&self) -> Timeout {
+        self.kex_timeout.into()
+    }
+
+    fn rekey_bytes(&self) -> u64 {
+        self.rekey_bytes
+    }
+
+    fn rekey_interval(&self) -> Duration {
+        self.rekey_interval
+    }
+
+    fn max_packet_size(&self) -> usize {
+        self.max_packet_size
+    }
+
+    fn buffer_size(&self) -> usize {
+        self.buffer_size
+    }
+
     fn kexinit(&self) -> KexInit {
         let mut cookie = [0u8; 16];
         rand::thread_rng().fill_bytes(&mut cookie);
 
         KexInit {
             cookie,
-            kex_algorithms: NameList::from_iter(&self.algorithms.kexs),
-            server_host_key_algorithms: NameList::from_iter(&self.algorithms.keys),
-            encryption_algorithms_client_to_server: NameList::from_iter(&self.algorithms.ciphers),
-            encryption_algorithms_server_to_client: NameList::from_iter(&self.algorithms.ciphers),
+            kex_algorithms: NameList::from_iter(
+                self.algorithms
+                    .kexs
+                    .iter()
+                    .map(Kex::as_ref)
+                    .chain(self.algorithms.custom_kexs.iter().map(|kex| kex.name()))
+                    .chain([KEX_STRICT_CLIENT, EXT_INFO_CLIENT]),
+            ),
+            server_host_key_algorithms: NameList::from_iter(self.keys()),
+            encryption_algorithms_client_to_server: NameList::from_iter(self.cipher_names()),
+            encryption_algorithms_server_to_client: NameList::from_iter(self.cipher_names()),
             mac_algorithms_client_to_server: NameList::from_iter(&self.algorithms.macs),
             mac_algorithms_server_to_client: NameList::from_iter(&self.algorithms.macs),
             compression_algorithms_client_to_server: NameList::from_iter(
-                &self.algorithms.compressions,
+                &self.algorithms.compressions_client_to_server,
             ),
             compression_algorithms_server_to_client: NameList::from_iter(
-                &self.algorithms.compressions,
+                &self.algorithms.compressions_server_to_client,
             ),
             languages_client_to_server: Default::default(),
             languages_server_to_client: Default::default(),
@@ -135,18 +492,55 @@ impl Side for Client {
         }
     }
 
+    fn ext_info(&self, _: &KexInit) -> Result<Option<Packet>> {
+        Ok(None)
+    }
+
     async fn exchange(
         &self,
         stream: &mut Stream<impl Pipe>,
         kexinit: KexInit<'_>,
         peerkexinit: KexInit<'_>,
+        id: &Id,
         peer_id: &Id,
     ) -> Result<TransportPair> {
-        let client = KexMeta::new::<Client>(self.id(), &kexinit, &peerkexinit)?;
-        let server = KexMeta::new::<Server>(peer_id, &kexinit, &peerkexinit)?;
+        let custom = &self.algorithms.custom_ciphers;
+        let client = KexMeta::new::<Client>(id, &kexinit, &peerkexinit, custom)?;
+        let server = KexMeta::new::<Server>(peer_id, &kexinit, &peerkexinit, custom)?;
+
+        let alg = negociate_host_key(&kexinit, &peerkexinit)?;
+        let kex = kex::negociate(&kexinit, &peerkexinit, &self.algorithms.custom_kexs)?;
+        let mut transport = kex
+            .as_client(&mut KexStream::from(&mut *stream), client, server)
+            .await?;
+
+        // The host key is verified before the `NewKeys` are sent, to abort the exchange otherwise.
+        let host_key = stream.take_host_key();
+        if let Some(host_key) = &host_key {
+            self.host_key_policy.check(&alg, &host_key.public_key())?;
+        }
+        match &host_key {
+            Some(HostKey::Certificate(certificate)) => self.validate(certificate)?,
+            Some(HostKey::Key(key)) => {
+                if let Some(verifier) = &self.host_key_verifier {
+                    if !verifier.verify(key, peer_id).await {
+                        return Err(Error::HostKeyRejected(key.fingerprint(HashAlg::Sha256)));
+                    }
+                }
+            }
+            None if self.host_key_verifier.is_some() => return Err(Error::KexError),
+            None => (),
+        }
+
+        transport.tx.with_compression_level(self.compression_level);
+        transport.tx.with_padding(self.padding);
+
+        stream.with_algorithms(NegociatedAlgorithms {
+            kex: kex.name().into(),
+            host_key: alg,
+        });
+        stream.with_server_host_key(host_key);
 
-        Kex::negociate(&kexinit, &peerkexinit)?
-            .as_client(stream, client, server)
-            .await
+        Ok(transport)
     }
 }
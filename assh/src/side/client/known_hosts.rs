@@ -0,0 +1,202 @@
+//! Verification of the _server_'s host key against an OpenSSH `known_hosts` file.
+
+use std::path::Path;
+
+use hmac::{Hmac, Mac};
+use ssh_key::{
+    known_hosts::{Entry, HostPatterns, Marker},
+    PublicKey,
+};
+
+use super::HostKeyVerifier;
+use crate::Result;
+
+/// The default port of the SSH protocol, which is omitted from the host names.
+const DEFAULT_PORT: u16 = 22;
+
+/// The outcome of looking up a host key in the [`KnownHosts`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HostStatus {
+    /// The key is known for this host.
+    Match,
+
+    /// Another key of the same algorithm is known for this host, the key may have **changed**.
+    Mismatch,
+
+    /// The key has been marked as `@revoked` for this host.
+    Revoked,
+
+    /// No key of the same algorithm is known for this host.
+    Unknown,
+}
+
+/// The entries of an OpenSSH `known_hosts` file, as described in `sshd(8)`.
+///
+/// Plain, wildcard, negated and hashed (`|1|`) host patterns are supported,
+/// as well as the `@revoked` marker, while the `@cert-authority` entries are
+/// ignored since the _certificate authorities_ are configured on the [`super::Client`].
+#[derive(Debug, Clone, Default)]
+pub struct KnownHosts {
+    entries: Vec<Entry>,
+}
+
+impl KnownHosts {
+    /// Parse the `known_hosts` file at `path`.
+    pub fn read_file(path: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self::parse(&std::fs::read_to_string(path)?))
+    }
+
+    /// Parse the `input` in the `known_hosts` format, skipping the malformed lines as OpenSSH does.
+    pub fn parse(input: &str) -> Self {
+        let entries = ssh_key::known_hosts::KnownHosts::new(input)
+            .enumerate()
+            .filter_map(|(line, entry)| {
+                entry
+                    .inspect_err(|err| {
+                        tracing::warn!("Skipped malformed `known_hosts` entry #{line}: {err}")
+                    })
+                    .ok()
+            })
+            .collect();
+
+        Self { entries }
+    }
+
+    /// Access the parsed entries.
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    /// Look up the `key` presented by the `host` listening on `port`.
+    pub fn check(&self, host: &str, port: u16, key: &PublicKey) -> HostStatus {
+        let name = host_name(host, port);
+
+        let mut status = HostStatus::Unknown;
+        for entry in self
+            .entries
+            .iter()
+            .filter(|entry| matches(entry.host_patterns(), &name))
+        {
+            let known = entry.public_key();
+
+            match entry.marker() {
+                Some(Marker::Revoked) if known.key_data() == key.key_data() => {
+                    return HostStatus::Revoked
+                }
+                Some(_) => (),
+                None if known.key_data() == key.key_data() => status = HostStatus::Match,
+                None if known.algorithm() == key.algorithm() && status != HostStatus::Match => {
+                    status = HostStatus::Mismatch
+                }
+                None => (),
+            }
+        }
+
+        status
+    }
+
+    /// Make a [`HostKeyVerifier`] only trusting the keys which [`HostStatus::Match`] for the `host` and `port`.
+    pub fn verifier(self, host: impl Into<String>, port: u16) -> impl HostKeyVerifier {
+        let host = host.into();
+
+        move |key: PublicKey, _| {
+            let status = self.check(&host, port, &key);
+            if status != HostStatus::Match {
+                tracing::warn!("Host key verification for `{host}:{port}` failed with {status:?}");
+            }
+
+            async move { status == HostStatus::Match }
+        }
+    }
+}
+
+/// The `name` of the `host` listening on `port`, as written in the `known_hosts` files.
+pub(super) fn host_name(host: &str, port: u16) -> String {
+    if port == DEFAULT_PORT {
+        host.to_lowercase()
+    } else {
+        format!("[{}]:{port}", host.to_lowercase())
+    }
+}
+
+/// Hash the host `name` with the `salt`, as done with OpenSSH's `HashKnownHosts` option.
+pub(super) fn hashed(salt: &[u8], name: &str) -> Option<Hmac<sha1::Sha1>> {
+    let mut mac = Hmac::<sha1::Sha1>::new_from_slice(salt).ok()?;
+    mac.update(name.as_bytes());
+
+    Some(mac)
+}
+
+/// Whether the host `name` matches the `patterns`, and none of the negated ones.
+fn matches(patterns: &HostPatterns, name: &str) -> bool {
+    match patterns {
+        HostPatterns::Patterns(patterns) => {
+            let mut matched = false;
+
+            for pattern in patterns {
+                match pattern.strip_prefix('!') {
+                    Some(pattern) if glob(&pattern.to_lowercase(), name) => return false,
+                    Some(_) => (),
+                    None => matched |= glob(&pattern.to_lowercase(), name),
+                }
+            }
+
+            matched
+        }
+        HostPatterns::HashedName { salt, hash } => {
+            hashed(salt, name).is_some_and(|mac| mac.verify_slice(hash).is_ok())
+        }
+    }
+}
+
+/// Match the `text` against the `pattern`, where `*` matches any sequence and `?` any character.
+pub(super) fn glob(pattern: &str, text: &str) -> bool {
+    let (pattern, text) = (pattern.as_bytes(), text.as_bytes());
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == b'?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((bp, bt)) => {
+                    backtrack = Some((bp, bt + 1));
+                    p = bp + 1;
+                    t = bt + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case("example.com", "example.com", true)]
+    #[case("*.example.com", "host.example.com", true)]
+    #[case("*.example.com", "example.com", false)]
+    #[case("host?.example.com", "host1.example.com", true)]
+    #[case("host?.example.com", "host.example.com", false)]
+    #[case("*", "anything", true)]
+    #[case("a*b*c", "aXXbYYc", true)]
+    #[case("a*b*c", "aXXbYY", false)]
+    #[case("[*.example.com]:2222", "[host.example.com]:2222", true)]
+    #[case("[*.example.com]:2222", "[host.example.com]:22", false)]
+    fn glob_matches(#[case] pattern: &str, #[case] text: &str, #[case] expected: bool) {
+        assert_eq!(glob(pattern, text), expected);
+    }
+}
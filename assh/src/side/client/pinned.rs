@@ -0,0 +1,72 @@
+//! Verification of the _server_'s host key against pinned fingerprints.
+
+use std::str::FromStr;
+
+use ssh_key::{Fingerprint, PublicKey};
+
+use super::{HostKeyFuture, HostKeyVerifier, Id};
+use crate::Result;
+
+/// A [`HostKeyVerifier`] only trusting the host keys matching one of the pinned fingerprints,
+/// as printed by `ssh-keygen -lf` (`SHA256:...`).
+#[derive(Debug, Clone)]
+pub struct PinnedFingerprints {
+    fingerprints: Vec<Fingerprint>,
+}
+
+impl PinnedFingerprints {
+    /// Pin the `fingerprints`, failing if any of them is malformed.
+    pub fn new<'f>(fingerprints: impl IntoIterator<Item = &'f str>) -> Result<Self> {
+        Ok(Self {
+            fingerprints: fingerprints
+                .into_iter()
+                .map(Fingerprint::from_str)
+                .collect::<Result<_, _>>()?,
+        })
+    }
+
+    /// Whether the `key` matches exactly one of the pinned fingerprints.
+    pub fn is_pinned(&self, key: &PublicKey) -> bool {
+        self.fingerprints
+            .iter()
+            .any(|fingerprint| key.fingerprint(fingerprint.algorithm()) == *fingerprint)
+    }
+}
+
+impl HostKeyVerifier for PinnedFingerprints {
+    fn verify<'a>(&'a self, key: &'a PublicKey, _: &'a Id) -> HostKeyFuture<'a> {
+        let pinned = self.is_pinned(key);
+
+        Box::pin(async move { pinned })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &str =
+        "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIKGBCON+qDxtSr/HvfFwadbKAvxlf6jPUcIHjYyVeeIV";
+
+    #[test]
+    fn pinned_fingerprint_is_trusted() -> Result<()> {
+        let key = PublicKey::from_openssh(KEY)?;
+
+        // As printed by `ssh-keygen -lf` for the key.
+        assert!(
+            PinnedFingerprints::new(["SHA256:+VdMSprho3utB0AIXEvpTs/GCiW8cUAGYrCdiEAKhDA"])?
+                .is_pinned(&key)
+        );
+        assert!(
+            !PinnedFingerprints::new(["SHA256:47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU"])?
+                .is_pinned(&key)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn malformed_fingerprint_is_refused() {
+        assert!(PinnedFingerprints::new(["MD5:definitely-not-base64"]).is_err());
+    }
+}
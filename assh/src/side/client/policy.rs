@@ -0,0 +1,75 @@
+//! The minimum strength policy of the _server_'s host key.
+
+use ssh_key::{public::KeyData, EcdsaCurve, Mpint, PublicKey};
+
+use crate::{
+    algorithm::{certificate_name, Key},
+    Error, Result,
+};
+
+/// The minimum strength required from the _server_'s host key, evaluated on each key-exchange
+/// before the key is verified, failing with [`Error::WeakHostKey`] if it's not satisfied.
+///
+/// To deliberately allow weaker keys for some hosts, use a distinct policy when connecting to them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HostKeyPolicy {
+    /// The minimum size of the _RSA_ keys in bits, defaults to 1024 as OpenSSH's `RequiredRSASize`.
+    pub min_rsa_bits: usize,
+
+    /// Whether the _DSA_ keys are allowed, defaults to `true`.
+    pub allow_dsa: bool,
+
+    /// Whether the _SHA-1_ based signatures, such as `ssh-rsa`, are allowed, defaults to `true`.
+    pub allow_sha1_signatures: bool,
+}
+
+impl Default for HostKeyPolicy {
+    fn default() -> Self {
+        Self {
+            min_rsa_bits: 1024,
+            allow_dsa: true,
+            allow_sha1_signatures: true,
+        }
+    }
+}
+
+/// The size in bits of the positive integer.
+fn bits(mpint: &Mpint) -> usize {
+    match mpint.as_positive_bytes() {
+        Some([first, rest @ ..]) => rest.len() * 8 + (8 - first.leading_zeros() as usize),
+        _ => 0,
+    }
+}
+
+impl HostKeyPolicy {
+    /// Check the host `key` of the _server_, signing with the negociated `algorithm`.
+    pub(super) fn check(&self, algorithm: &str, key: &PublicKey) -> Result<()> {
+        let (allowed, bits) = match key.key_data() {
+            KeyData::Rsa(rsa) => (bits(&rsa.n) >= self.min_rsa_bits, bits(&rsa.n)),
+            KeyData::Dsa(dsa) => (self.allow_dsa, bits(&dsa.p)),
+            KeyData::Ecdsa(ecdsa) => (
+                true,
+                match ecdsa.curve() {
+                    EcdsaCurve::NistP256 => 256,
+                    EcdsaCurve::NistP384 => 384,
+                    EcdsaCurve::NistP521 => 521,
+                },
+            ),
+            KeyData::Ed25519(_) => (true, 256),
+            _ => (true, 0),
+        };
+
+        let sha1 = [Key::Rsa { hash: None }, Key::Dsa]
+            .iter()
+            .any(|key| algorithm == key.as_str() || algorithm == certificate_name(key));
+
+        if allowed && (self.allow_sha1_signatures || !sha1) {
+            Ok(())
+        } else {
+            Err(Error::WeakHostKey {
+                algorithm: algorithm.into(),
+                bits,
+            })
+        }
+    }
+}
@@ -0,0 +1,293 @@
+//! _Trust on first use_ of the _server_'s host key, persisting the accepted keys
+//! to an OpenSSH `known_hosts` file.
+
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use hmac::Mac;
+use rand::RngCore;
+use ssh_key::{known_hosts::HostPatterns, HashAlg, PublicKey};
+
+use super::{
+    known_hosts::{hashed, host_name},
+    HostKeyFuture, HostKeyVerifier, HostStatus, Id, KnownHosts,
+};
+use crate::Result;
+
+/// The policy applied to the hosts absent from the `known_hosts` file.
+///
+/// Whatever the policy, a key which does not match the known one for the host is **rejected**.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum TofuPolicy {
+    /// Reject the unknown hosts, as OpenSSH's `StrictHostKeyChecking=yes`.
+    #[default]
+    Strict,
+
+    /// Accept the unknown hosts and persist their key,
+    /// as OpenSSH's `StrictHostKeyChecking=accept-new`.
+    AcceptNew,
+
+    /// Accept the unknown hosts without persisting their key.
+    AcceptAny,
+}
+
+/// A [`HostKeyVerifier`] checking the host key against a `known_hosts` file,
+/// and trusting the unknown hosts on first use according to the [`TofuPolicy`].
+#[derive(Debug, Clone)]
+pub struct TrustOnFirstUse {
+    path: PathBuf,
+    host: String,
+    port: u16,
+    policy: TofuPolicy,
+    hash: bool,
+    confirm: Option<Arc<dyn HostKeyVerifier>>,
+}
+
+impl TrustOnFirstUse {
+    /// Verify the keys of the `host` listening on `port` against the `known_hosts` file at `path`,
+    /// which is created when persisting the first key if missing, and locked with a sibling `.lock` file while updated.
+    pub fn new(path: impl Into<PathBuf>, host: impl Into<String>, port: u16) -> Self {
+        Self {
+            path: path.into(),
+            host: host.into(),
+            port,
+            policy: Default::default(),
+            hash: false,
+            confirm: None,
+        }
+    }
+
+    /// Set the policy applied to the unknown hosts.
+    pub fn policy(mut self, policy: TofuPolicy) -> Self {
+        self.policy = policy;
+
+        self
+    }
+
+    /// Hash the host names of the persisted entries with a fresh salt,
+    /// as OpenSSH's `HashKnownHosts=yes`.
+    pub fn hashed(mut self) -> Self {
+        self.hash = true;
+
+        self
+    }
+
+    /// Ask the `confirm` callback before accepting an unknown host,
+    /// to let interactive programs prompt the user with the key's fingerprint.
+    pub fn confirm(mut self, confirm: impl HostKeyVerifier) -> Self {
+        self.confirm = Some(Arc::new(confirm));
+
+        self
+    }
+
+    /// Format the `known_hosts` line for the `key` of the host.
+    fn line(&self, key: &PublicKey) -> Result<String> {
+        let name = host_name(&self.host, self.port);
+
+        let patterns = if self.hash {
+            let mut salt = vec![0; 20];
+            rand::thread_rng().fill_bytes(&mut salt);
+
+            let hash = hashed(&salt, &name)
+                .map(|mac| mac.finalize().into_bytes().into())
+                .unwrap_or_default();
+
+            HostPatterns::HashedName { salt, hash }
+        } else {
+            HostPatterns::Patterns(vec![name])
+        };
+
+        let mut key = key.clone();
+        key.set_comment("");
+
+        Ok(format!("{} {}", patterns.to_string(), key.to_openssh()?))
+    }
+
+    async fn check(&self, key: &PublicKey, peer_id: &Id) -> bool {
+        let path = self.path.clone();
+        let known = match blocking::unblock(move || KnownHosts::read_file(path)).await {
+            Ok(known) => known,
+            Err(crate::Error::Io(err)) if err.kind() == std::io::ErrorKind::NotFound => {
+                Default::default()
+            }
+            Err(err) => {
+                tracing::warn!("Unable to read `{}`: {err}", self.path.display());
+
+                return false;
+            }
+        };
+
+        let fingerprint = key.fingerprint(HashAlg::Sha256);
+        match known.check(&self.host, self.port, key) {
+            HostStatus::Match => return true,
+            status @ (HostStatus::Mismatch | HostStatus::Revoked) => {
+                tracing::warn!(
+                    "Host key `{fingerprint}` for `{}:{}` failed with {status:?}",
+                    self.host,
+                    self.port
+                );
+
+                return false;
+            }
+            HostStatus::Unknown if self.policy == TofuPolicy::Strict => {
+                tracing::warn!(
+                    "Host key `{fingerprint}` for `{}:{}` is unknown",
+                    self.host,
+                    self.port
+                );
+
+                return false;
+            }
+            HostStatus::Unknown => (),
+        }
+
+        if let Some(confirm) = &self.confirm {
+            if !confirm.verify(key, peer_id).await {
+                return false;
+            }
+        }
+
+        if self.policy == TofuPolicy::AcceptNew {
+            match self.line(key) {
+                Ok(line) => {
+                    let (path, host, port, key) =
+                        (self.path.clone(), self.host.clone(), self.port, key.clone());
+
+                    match blocking::unblock(move || append(&path, &line, &host, port, &key)).await {
+                        Ok(true) => tracing::info!(
+                            "Permanently added `{fingerprint}` for `{}:{}` to `{}`",
+                            self.host,
+                            self.port,
+                            self.path.display()
+                        ),
+                        Ok(false) => tracing::debug!(
+                            "Host key `{fingerprint}` for `{}:{}` was concurrently added to `{}`",
+                            self.host,
+                            self.port,
+                            self.path.display()
+                        ),
+                        Err(err) => {
+                            tracing::warn!("Unable to persist to `{}`: {err}", self.path.display())
+                        }
+                    }
+                }
+                Err(err) => tracing::warn!("Unable to format the host key `{fingerprint}`: {err}"),
+            }
+        }
+
+        true
+    }
+}
+
+impl HostKeyVerifier for TrustOnFirstUse {
+    fn verify<'a>(&'a self, key: &'a PublicKey, peer_id: &'a Id) -> HostKeyFuture<'a> {
+        Box::pin(self.check(key, peer_id))
+    }
+}
+
+/// Append the `line` for the `key` of the `host` to the `known_hosts` file at `path`, unless another process
+/// persisted the key meanwhile, returning whether the line was appended.
+///
+/// The file is locked for the whole read-modify-write, and atomically replaced with an updated copy
+/// so concurrent readers never observe a partial write.
+fn append(
+    path: &Path,
+    line: &str,
+    host: &str,
+    port: u16,
+    key: &PublicKey,
+) -> std::io::Result<bool> {
+    let _lock = Lock::acquire(path)?;
+
+    let (mut contents, permissions) = match std::fs::read_to_string(path) {
+        Ok(contents) => (contents, Some(std::fs::metadata(path)?.permissions())),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => (String::new(), None),
+        Err(err) => return Err(err),
+    };
+
+    if KnownHosts::parse(&contents).check(host, port, key) == HostStatus::Match {
+        return Ok(false);
+    }
+
+    if !contents.is_empty() && !contents.ends_with('\n') {
+        contents.push('\n');
+    }
+    contents.push_str(line);
+    contents.push('\n');
+
+    let temporary = sibling(path, |name| {
+        format!(".{name}.{:016x}.tmp", rand::random::<u64>())
+    });
+    let written = (|| {
+        let mut file = std::fs::File::create(&temporary)?;
+        if let Some(permissions) = permissions {
+            file.set_permissions(permissions)?;
+        }
+
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()?;
+
+        std::fs::rename(&temporary, path)
+    })();
+
+    if written.is_err() {
+        let _ = std::fs::remove_file(&temporary);
+    }
+
+    written.map(|()| true)
+}
+
+/// An exclusive lock on a `known_hosts` file, held as long as the sibling `.lock` file exists.
+struct Lock(PathBuf);
+
+impl Lock {
+    /// The maximum duration to wait for another process to release the lock.
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// Create the lock file of the `path`, waiting for it to be released if it already exists.
+    fn acquire(path: &Path) -> std::io::Result<Self> {
+        let lock = sibling(path, |name| format!("{name}.lock"));
+        let deadline = Instant::now() + Self::TIMEOUT;
+
+        loop {
+            match std::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&lock)
+            {
+                Ok(_) => break Ok(Self(lock)),
+                Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => {
+                    if Instant::now() >= deadline {
+                        break Err(std::io::Error::new(
+                            std::io::ErrorKind::TimedOut,
+                            format!("the lock `{}` is still held", lock.display()),
+                        ));
+                    }
+
+                    std::thread::sleep(Duration::from_millis(10));
+                }
+                Err(err) => break Err(err),
+            }
+        }
+    }
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// A path alongside the `path`, named from it's file name.
+fn sibling(path: &Path, name: impl FnOnce(&str) -> String) -> PathBuf {
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    path.with_file_name(name(&file_name))
+}
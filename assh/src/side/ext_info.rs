@@ -0,0 +1,42 @@
+//! The `SSH_MSG_EXT_INFO` message of the extension negotiation, as described in RFC8308.
+
+use ssh_encoding::{Decode, Encode};
+use ssh_packet::Packet;
+
+/// The `SSH_MSG_EXT_INFO` message number.
+const SSH_MSG_EXT_INFO: u8 = 7;
+
+/// The extension listing the signature algorithms accepted by the _server_ for the `publickey` method.
+pub(crate) const SERVER_SIG_ALGS: &str = "server-sig-algs";
+
+/// Build the `SSH_MSG_EXT_INFO` message with the `extensions`, as their names and values.
+pub(crate) fn encode<'e>(
+    extensions: impl IntoIterator<Item = (&'e str, String)>,
+) -> ssh_encoding::Result<Packet> {
+    let extensions = extensions.into_iter().collect::<Vec<_>>();
+
+    let mut payload = vec![SSH_MSG_EXT_INFO];
+    extensions.len().encode(&mut payload)?;
+    for (name, value) in extensions {
+        name.encode(&mut payload)?;
+        value.encode(&mut payload)?;
+    }
+
+    Ok(Packet { payload })
+}
+
+/// Parse the extensions of the `packet`, if it's a well-formed `SSH_MSG_EXT_INFO` message.
+pub(crate) fn decode(packet: &Packet) -> Option<Vec<(String, String)>> {
+    let (&SSH_MSG_EXT_INFO, mut buffer) = packet.payload.split_first()? else {
+        return None;
+    };
+
+    (0..u32::decode(&mut buffer).ok()?)
+        .map(|_| {
+            Some((
+                String::decode(&mut buffer).ok()?,
+                String::from_utf8_lossy(&Vec::<u8>::decode(&mut buffer).ok()?).into_owned(),
+            ))
+        })
+        .collect()
+}
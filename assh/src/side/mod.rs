@@ -1,17 +1,23 @@
 //! Session's [`Side`]s, either [`Client`] or [`Server`].
 
 use futures::Future;
-use futures_time::time::Duration;
+use futures_time::{future::FutureExt, time::Duration};
 use ssh_packet::{
-    trans::{KexInit, NewKeys},
-    Id,
+    trans::{Debug, Ignore, KexInit, NewKeys, Unimplemented},
+    Id, Packet,
 };
 
 use crate::{
-    stream::{Stream, TransportPair},
-    Pipe, Result,
+    algorithm::Kex,
+    stream::{is_kex_message, Stream, TransportPair},
+    Error, Pipe, Result,
 };
 
+pub(crate) mod ext_info;
+
+/// The message numbers specific to the key-exchange methods, as per RFC4250 §4.1.2.
+const KEX_METHOD_MESSAGES: std::ops::RangeInclusive<u8> = 30..=49;
+
 pub mod client;
 use client::Client;
 
@@ -34,48 +40,158 @@ pub trait Side: private::Sealed + Send + Sync + Unpin + 'static {
     /// Get the _timeout_ for this session.
     fn timeout(&self) -> Duration;
 
+    /// Get the _timeout_ for a whole key-exchange to complete.
+    fn kex_timeout(&self) -> Duration;
+
+    /// Get the amount of exchanged bytes after which the keys are re-exchanged.
+    fn rekey_bytes(&self) -> u64;
+
+    /// Get the elapsed time after which the keys are re-exchanged.
+    fn rekey_interval(&self) -> std::time::Duration;
+
+    /// Get the maximum length of the received packets, past which the peer is disconnected.
+    fn max_packet_size(&self) -> usize;
+
+    /// Get the capacity the packet buffers shrink back to after a larger packet.
+    fn buffer_size(&self) -> usize;
+
     /// Generate a [`KexInit`] message from the config.
     fn kexinit(&self) -> KexInit;
 
-    /// Exchange the keys from the config.
+    /// Generate the `SSH_MSG_EXT_INFO` message sent after the initial key-exchange,
+    /// if the peer signaled it's support in the `peerkexinit`.
+    fn ext_info(&self, peerkexinit: &KexInit) -> Result<Option<Packet>>;
+
+    /// Exchange the keys from the config, between this side identified by `id` and the peer identified by `peer_id`.
     fn exchange(
         &self,
         stream: &mut Stream<impl Pipe>,
         kexinit: KexInit,
         peerkexinit: KexInit,
+        id: &Id,
         peer_id: &Id,
     ) -> impl Future<Output = Result<TransportPair>> + Send + Sync;
 
-    /// Perform the key-exchange from this side.
+    /// Perform the key-exchange from this side, identified by `id`, with the peer identified by `peer_id`.
     fn kex(
         &self,
         stream: &mut Stream<impl Pipe>,
+        id: &Id,
         peer_id: &Id,
     ) -> impl Future<Output = Result<()>> + Send + Sync {
         async move {
-            tracing::debug!("Starting key-exchange procedure");
-
-            let kexinit = self.kexinit();
-            stream.send(&kexinit).await?;
-
-            // TODO: (compliance) Take care of `KexInit::first_kex_packet_follows` being true.
-
-            let peerkexinit = stream.recv().await?.to::<KexInit>()?;
-
-            let transport = self.exchange(stream, kexinit, peerkexinit, peer_id).await?;
-
-            stream.send(&NewKeys).await?;
-            stream.recv().await?.to::<NewKeys>()?;
-
-            tracing::debug!(
-                "Key exchange success, negociated algorithms:\nrx: {:?}\ntx: {:?}",
-                transport.rx,
-                transport.tx,
-            );
-
-            stream.with_transport(transport);
-
-            Ok(())
+            async move {
+                tracing::debug!("Starting key-exchange procedure");
+
+                let kexinit = self.kexinit();
+                stream.send(&kexinit).await?;
+
+                let mut interleaved = false;
+                let peerkexinit = loop {
+                    let packet = stream.recv().await?;
+
+                    if let Ok(peerkexinit) = packet.to::<KexInit>() {
+                        break peerkexinit;
+                    } else if packet.to::<Ignore>().is_ok()
+                        || packet.to::<Debug>().is_ok()
+                        || packet.to::<Unimplemented>().is_ok()
+                    {
+                        tracing::debug!("Received a non-kex message before the peer's `KexInit`");
+
+                        interleaved = true;
+                    } else if ext_info::decode(&packet).is_some() {
+                        // The peer's extensions may still be in flight when re-keying right away.
+                        tracing::debug!("Discarded the peer's 'ext-info' message during a re-key");
+                    } else if stream.session_id().is_some()
+                        && !packet.payload.first().copied().is_some_and(is_kex_message)
+                    {
+                        // The peer may still be sending before it received our `KexInit` when re-keying,
+                        // while nothing else than the transport messages may precede the initial exchange.
+                        tracing::debug!(
+                            "Deferred a ^{:#x} message received before the peer's `KexInit`",
+                            packet.payload.first().copied().unwrap_or_default()
+                        );
+
+                        interleaved = true;
+                        stream.defer(packet)?;
+                    } else {
+                        return Err(Error::UnexpectedMessage);
+                    }
+                };
+
+                // Strict key-exchange is only negociated during the initial exchange,
+                // where the peer's `KexInit` is required to be the very first packet.
+                if stream.session_id().is_none() && Kex::is_strict(&kexinit, &peerkexinit) {
+                    if interleaved {
+                        return Err(Error::UnexpectedMessage);
+                    }
+
+                    tracing::debug!("Strict key-exchange is in effect for this session");
+
+                    stream.with_strict();
+                }
+
+                // The peer's guessed key-exchange packet is discarded if the guess was wrong,
+                // skipping the transport messages which may precede it.
+                if *peerkexinit.first_kex_packet_follows && !Kex::is_guessed(&kexinit, &peerkexinit)
+                {
+                    loop {
+                        let packet = stream.recv().await?;
+
+                        if packet.to::<Ignore>().is_ok()
+                            || packet.to::<Debug>().is_ok()
+                            || packet.to::<Unimplemented>().is_ok()
+                        {
+                            tracing::debug!(
+                                "Received a transport message before the peer's guessed packet"
+                            );
+                        } else if packet
+                            .payload
+                            .first()
+                            .is_some_and(|message| KEX_METHOD_MESSAGES.contains(message))
+                        {
+                            tracing::debug!(
+                                "Discarded the peer's wrongly guessed key-exchange packet ({} bytes)",
+                                packet.payload.len()
+                            );
+
+                            break;
+                        } else {
+                            return Err(Error::UnexpectedMessage);
+                        }
+                    }
+                }
+
+                // The extensions are only negociated during the initial exchange.
+                let ext_info = match stream.session_id() {
+                    None => self.ext_info(&peerkexinit)?,
+                    Some(_) => None,
+                };
+
+                let transport = self
+                    .exchange(stream, kexinit, peerkexinit, id, peer_id)
+                    .await?;
+
+                stream.send(&NewKeys).await?;
+                stream.recv().await?.to::<NewKeys>()?;
+
+                tracing::debug!(
+                    "Key exchange success, negociated algorithms:\nrx: {:?}\ntx: {:?}",
+                    transport.rx,
+                    transport.tx,
+                );
+
+                stream.with_transport(transport);
+
+                if let Some(packet) = ext_info {
+                    stream.send(packet).await?;
+                }
+
+                stream.flush_queue().await
+            }
+            .timeout(self.kex_timeout())
+            .await
+            .map_err(|_| Error::KexTimeout)?
         }
     }
 }
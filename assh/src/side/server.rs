@@ -1,24 +1,35 @@
 //! Server-[`Side`] implementation of the _session_.
 
-use std::time::Duration;
+use std::{path::Path, sync::Arc, time::Duration};
 
 use futures_time::time::Duration as Timeout;
 use rand::RngCore;
-use ssh_key::Algorithm;
-use ssh_packet::{arch::NameList, trans::KexInit};
+use ssh_packet::{arch::NameList, trans::KexInit, Packet};
 
-use super::{client::Client, Side};
+use super::{client::Client, ext_info, Side};
 use crate::{
-    algorithm::{Cipher, Compress, Hmac, Kex, KexMeta, Negociate},
-    stream::{Stream, TransportPair},
-    Pipe, Result,
+    algorithm::{
+        certificate_name,
+        kex::{self, KexStream},
+        negociate_host_key, Cipher, CipherAlgorithm, Compress, Hmac, HostKey, HostKeySigner, Kex,
+        KexAlgorithm, KexMeta, Key, EXT_INFO_CLIENT, KEX_STRICT_SERVER,
+    },
+    stream::{NegociatedAlgorithms, PaddingMode, Stream, TransportPair},
+    Error, Pipe, Result,
 };
 
 #[doc(no_inline)]
-pub use ssh_key::PrivateKey;
+pub use ssh_key::{Certificate, PrivateKey};
 #[doc(no_inline)]
 pub use ssh_packet::Id;
 
+#[cfg(unix)]
+#[doc(no_inline)]
+pub use crate::agent::{Agent, AgentKey};
+
+mod keys;
+pub use keys::{load_host_key, load_host_keys, LoadedKeys, Passphrase};
+
 /// A _server_-side session configuration.
 #[derive(Debug, Clone)]
 pub struct Server {
@@ -28,9 +39,46 @@ pub struct Server {
     /// Timeout for sending and receiving packets.
     pub timeout: Duration,
 
+    /// Timeout for a whole key-exchange to complete.
+    pub kex_timeout: Duration,
+
+    /// Re-key after this amount of bytes have been exchanged,
+    /// defaults to 1GiB as recommended per the RFC.
+    pub rekey_bytes: u64,
+
+    /// Re-key after this amount of time has elapsed since the last exchange,
+    /// defaults to 1 hour as recommended per the RFC.
+    pub rekey_interval: Duration,
+
+    /// Disconnect the peer when it sends a packet declaring a greater length than this,
+    /// defaults to 256KiB as OpenSSH does.
+    pub max_packet_size: usize,
+
+    /// The capacity retained by the packet buffers of each direction, which grow up to
+    /// [`Self::max_packet_size`] to fit a larger packet and shrink back to this afterwards,
+    /// so that idle sessions don't hold onto the peak-sized buffers. A size greater than
+    /// the `max_packet_size` never shrinks them, sparing the reallocations to bulk transfers.
+    /// Defaults to 36KiB, fitting the 35000 bytes packets every peer must handle as per RFC4253 §6.1.
+    pub buffer_size: usize,
+
+    /// The _zlib_ compression level for the sent packets,
+    /// from 1 (fastest) to 9 (best), defaults to 6.
+    pub compression_level: u32,
+
+    /// How the padding of the sent packets is filled, defaults to random bytes.
+    pub padding: PaddingMode,
+
     /// Server keys for key-exchange signature.
     pub keys: Vec<PrivateKey>,
 
+    /// Additional server keys for key-exchange signature, which private part is kept
+    /// out of memory, such as in an HSM or behind a remote KMS.
+    pub signers: Vec<Arc<dyn HostKeySigner>>,
+
+    /// Certificates of the server keys, presented in place of the plain keys
+    /// to the clients supporting them.
+    pub certificates: Vec<Certificate>,
+
     /// The algorithms enabled for this _server_ session.
     pub algorithms: Algorithms,
 }
@@ -47,7 +95,16 @@ impl Default for Server {
                 None::<&str>,
             ),
             timeout: Duration::from_secs(120),
+            kex_timeout: Duration::from_secs(60),
+            rekey_bytes: 0x40000000,
+            rekey_interval: Duration::from_secs(3600),
+            max_packet_size: 0x40000,
+            buffer_size: 0x9000,
+            compression_level: 6,
+            padding: Default::default(),
             keys: Default::default(),
+            signers: Default::default(),
+            certificates: Default::default(),
             algorithms: Default::default(),
         }
     }
@@ -59,44 +116,317 @@ pub struct Algorithms {
     /// Enabled algorithms for _key-exchange_.
     pub kexs: Vec<Kex>,
 
+    /// Additional custom algorithms for _key-exchange_, advertised after the built-in ones.
+    pub custom_kexs: Vec<Arc<dyn KexAlgorithm>>,
+
     /// Enabled algorithms for _encryption & decryption_.
     pub ciphers: Vec<Cipher>,
 
+    /// Additional custom algorithms for _encryption & decryption_, advertised after the built-in ones.
+    pub custom_ciphers: Vec<Arc<dyn CipherAlgorithm>>,
+
     /// Enabled algorithms for _hmac_.
     pub macs: Vec<Hmac>,
 
-    /// Enabled algorithms for _compression_.
-    pub compressions: Vec<Compress>,
+    /// Enabled algorithms for _compression_ of the packets sent by the _client_.
+    pub compressions_client_to_server: Vec<Compress>,
+
+    /// Enabled algorithms for _compression_ of the packets sent by the _server_.
+    pub compressions_server_to_client: Vec<Compress>,
+
+    /// Signature algorithms accepted for the `publickey` authentication, advertised in the
+    /// `server-sig-algs` extension to the _clients_ supporting the extension negotiation,
+    /// which should match the ones accepted by the authentication service.
+    pub server_sig_algs: Vec<Key>,
 }
 
 impl Default for Algorithms {
     fn default() -> Self {
         Self {
-            kexs: vec![Kex::Curve25519Sha256, Kex::Curve25519Sha256Libssh],
+            kexs: vec![
+                #[cfg(feature = "sntrup761")]
+                Kex::Sntrup761X25519Sha512,
+                #[cfg(feature = "curve25519")]
+                Kex::Curve25519Sha256,
+                #[cfg(feature = "curve25519")]
+                Kex::Curve25519Sha256Libssh,
+                #[cfg(feature = "curve448")]
+                Kex::Curve448Sha512,
+                #[cfg(feature = "ecdh-nistp")]
+                Kex::EcdhSha2Nistp521,
+                #[cfg(feature = "ecdh-nistp")]
+                Kex::EcdhSha2Nistp384,
+                #[cfg(feature = "ecdh-nistp")]
+                Kex::EcdhSha2Nistp256,
+            ],
+            custom_kexs: Default::default(),
             ciphers: vec![
+                #[cfg(feature = "chacha20-poly1305")]
+                Cipher::ChaCha20Poly1305,
+                #[cfg(feature = "aes-gcm")]
+                Cipher::Aes256Gcm,
+                #[cfg(feature = "aes-gcm")]
+                Cipher::Aes128Gcm,
+                #[cfg(feature = "aes-ctr")]
                 Cipher::Aes256Ctr,
+                #[cfg(feature = "aes-ctr")]
                 Cipher::Aes192Ctr,
+                #[cfg(feature = "aes-ctr")]
                 Cipher::Aes128Ctr,
+                #[cfg(feature = "aes-cbc")]
                 Cipher::Aes256Cbc,
+                #[cfg(feature = "aes-cbc")]
                 Cipher::Aes192Cbc,
+                #[cfg(feature = "aes-cbc")]
                 Cipher::Aes128Cbc,
+                #[cfg(feature = "tdes-cbc")]
                 Cipher::TDesCbc,
             ],
+            custom_ciphers: Default::default(),
             macs: vec![
+                #[cfg(feature = "hmac-sha2")]
                 Hmac::HmacSha512ETM,
+                #[cfg(feature = "hmac-sha2")]
                 Hmac::HmacSha256ETM,
+                #[cfg(feature = "hmac-sha2")]
                 Hmac::HmacSha512,
+                #[cfg(feature = "hmac-sha2")]
                 Hmac::HmacSha256,
+                #[cfg(feature = "umac")]
+                Hmac::Umac128,
+                #[cfg(feature = "umac")]
+                Hmac::Umac64,
+                #[cfg(feature = "hmac-sha1")]
                 Hmac::HmacSha1ETM,
+                #[cfg(feature = "hmac-sha1")]
                 Hmac::HmacSha1,
+                #[cfg(feature = "hmac-sha1")]
+                Hmac::HmacSha196,
+                #[cfg(feature = "hmac-md5")]
                 Hmac::HmacMd5ETM,
+                #[cfg(feature = "hmac-md5")]
                 Hmac::HmacMd5,
             ],
-            compressions: vec![Compress::ZlibOpenssh, Compress::Zlib, Compress::None],
+            compressions_client_to_server: vec![
+                #[cfg(feature = "zlib")]
+                Compress::ZlibOpenssh,
+                #[cfg(feature = "zlib")]
+                Compress::Zlib,
+                Compress::None,
+            ],
+            compressions_server_to_client: vec![
+                #[cfg(feature = "zlib")]
+                Compress::ZlibOpenssh,
+                #[cfg(feature = "zlib")]
+                Compress::Zlib,
+                Compress::None,
+            ],
+            server_sig_algs: vec![
+                Key::Ed25519,
+                Key::SkEd25519,
+                Key::Ecdsa {
+                    curve: ssh_key::EcdsaCurve::NistP256,
+                },
+                Key::SkEcdsaSha2NistP256,
+                Key::Ecdsa {
+                    curve: ssh_key::EcdsaCurve::NistP384,
+                },
+                Key::Ecdsa {
+                    curve: ssh_key::EcdsaCurve::NistP521,
+                },
+                Key::Rsa {
+                    hash: Some(ssh_key::HashAlg::Sha512),
+                },
+                Key::Rsa {
+                    hash: Some(ssh_key::HashAlg::Sha256),
+                },
+                Key::Rsa { hash: None },
+                Key::Dsa,
+            ],
         }
     }
 }
 
+impl Server {
+    /// Set the enabled algorithms for _key-exchange_, in order of preference.
+    pub fn kexs(mut self, kexs: &[Kex]) -> Self {
+        self.algorithms.kexs = kexs.to_vec();
+
+        self
+    }
+
+    /// Enable the legacy _SHA-1_ Diffie-Hellman algorithms for _key-exchange_,
+    /// with the lowest preference.
+    ///
+    /// **Deprecated**: these methods are considered weak, and should only be
+    /// enabled to talk to peers supporting nothing else.
+    #[cfg(feature = "legacy-kex")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "legacy-kex")))]
+    pub fn legacy_kexs(mut self) -> Self {
+        self.algorithms
+            .kexs
+            .extend([Kex::DiffieHellmanGroup14Sha1, Kex::DiffieHellmanGroup1Sha1]);
+
+        self
+    }
+
+    /// Remove the _SHA-1_ based algorithms from the enabled _hmac_ algorithms.
+    pub fn without_sha1_macs(mut self) -> Self {
+        self.algorithms.macs.retain(|mac| !mac.is_sha1());
+
+        self
+    }
+
+    /// Enable the `none` algorithm for _encryption & decryption_, with the lowest preference.
+    ///
+    /// **Insecure**: the packets are then sent in clear, this should only be
+    /// enabled for debugging or on links which are already secured.
+    pub fn none_cipher(mut self) -> Self {
+        self.algorithms.ciphers.push(Cipher::None);
+
+        self
+    }
+
+    /// Enable the `none` algorithm for _hmac_, with the lowest preference.
+    ///
+    /// **Insecure**: the packets are then not authenticated, this should only be
+    /// enabled for debugging or on links which are already secured.
+    pub fn none_mac(mut self) -> Self {
+        self.algorithms.macs.push(Hmac::None);
+
+        self
+    }
+
+    /// Set the enabled algorithms for _compression_ in both directions, in order of preference.
+    pub fn compressions(mut self, compressions: &[Compress]) -> Self {
+        self.algorithms.compressions_client_to_server = compressions.to_vec();
+        self.algorithms.compressions_server_to_client = compressions.to_vec();
+
+        self
+    }
+
+    /// Set the enabled algorithms for _compression_ of the packets sent by the _client_, in order of preference.
+    pub fn compressions_client_to_server(mut self, compressions: &[Compress]) -> Self {
+        self.algorithms.compressions_client_to_server = compressions.to_vec();
+
+        self
+    }
+
+    /// Set the enabled algorithms for _compression_ of the packets sent by the _server_, in order of preference.
+    pub fn compressions_server_to_client(mut self, compressions: &[Compress]) -> Self {
+        self.algorithms.compressions_server_to_client = compressions.to_vec();
+
+        self
+    }
+
+    /// Set the enabled algorithms for _encryption & decryption_, in order of preference,
+    /// the custom ones being still advertised after them.
+    pub fn ciphers(mut self, ciphers: &[Cipher]) -> Self {
+        self.algorithms.ciphers = ciphers.to_vec();
+
+        self
+    }
+
+    /// Set the enabled algorithms for _hmac_, in order of preference,
+    /// which may be empty if only _AEAD_ ciphers are enabled.
+    pub fn macs(mut self, macs: &[Hmac]) -> Self {
+        self.algorithms.macs = macs.to_vec();
+
+        self
+    }
+
+    /// Register an additional custom algorithm for _key-exchange_.
+    pub fn kex_algorithm(mut self, kex: impl KexAlgorithm) -> Self {
+        self.algorithms.custom_kexs.push(Arc::new(kex));
+
+        self
+    }
+
+    /// Load an additional server key for key-exchange signature from the OpenSSH-formatted
+    /// private key file at `path`, decrypting it with the `passphrase` if encrypted.
+    pub fn host_key_file(
+        mut self,
+        path: impl AsRef<Path>,
+        passphrase: &Passphrase,
+    ) -> Result<Self> {
+        self.keys.push(load_host_key(path, passphrase)?);
+
+        Ok(self)
+    }
+
+    /// Register an additional server key for key-exchange signature,
+    /// which private part is kept out of memory.
+    pub fn host_key_signer(mut self, signer: impl HostKeySigner) -> Self {
+        self.signers.push(Arc::new(signer));
+
+        self
+    }
+
+    /// The names of the enabled algorithms for _encryption & decryption_, custom ones last.
+    fn cipher_names(&self) -> impl Iterator<Item = &str> {
+        self.algorithms.ciphers.iter().map(Cipher::as_ref).chain(
+            self.algorithms
+                .custom_ciphers
+                .iter()
+                .map(|cipher| cipher.name()),
+        )
+    }
+
+    /// Set the signature algorithms advertised in the `server-sig-algs` extension, in order of preference,
+    /// such as the ones accepted by the authentication service.
+    pub fn server_sig_algs(mut self, algorithms: impl IntoIterator<Item = Key>) -> Self {
+        self.algorithms.server_sig_algs = algorithms.into_iter().collect();
+
+        self
+    }
+
+    /// Register an additional custom algorithm for _encryption & decryption_.
+    pub fn cipher_algorithm(mut self, cipher: impl CipherAlgorithm) -> Self {
+        self.algorithms.custom_ciphers.push(Arc::new(cipher));
+
+        self
+    }
+}
+
+impl Server {
+    /// The certificate of the `key`, if any.
+    fn certificate(&self, key: &dyn HostKeySigner) -> Option<&Certificate> {
+        self.certificates
+            .iter()
+            .find(|certificate| certificate.public_key() == key.public_key().key_data())
+    }
+
+    /// All the host keys, from the [`Server::keys`] then the [`Server::signers`].
+    fn signers(&self) -> impl Iterator<Item = &dyn HostKeySigner> {
+        self.keys
+            .iter()
+            .map(|key| key as &dyn HostKeySigner)
+            .chain(self.signers.iter().map(|signer| &**signer))
+    }
+
+    /// The host key algorithms for each of the keys, with the certificates first.
+    fn host_keys(&self) -> impl Iterator<Item = (String, &dyn HostKeySigner, HostKey)> {
+        let certificates = self.signers().filter_map(|key| {
+            self.certificate(key).map(|certificate| {
+                (
+                    certificate_name(&key.algorithm()),
+                    key,
+                    HostKey::Certificate(certificate.clone().into()),
+                )
+            })
+        });
+        let keys = self.signers().map(|key| {
+            (
+                key.algorithm().to_string(),
+                key,
+                HostKey::Key(key.public_key().clone()),
+            )
+        });
+
+        certificates.chain(keys)
+    }
+}
+
 impl Side for Server {
     fn id(&self) -> &Id {
         &self.id
@@ -106,25 +436,52 @@ impl Side for Server {
         self.timeout.into()
     }
 
+    fn kex_timeout(&self) -> Timeout {
+        self.kex_timeout.into()
+    }
+
+    fn rekey_bytes(&self) -> u64 {
+        self.rekey_bytes
+    }
+
+    fn rekey_interval(&self) -> Duration {
+        self.rekey_interval
+    }
+
+    fn max_packet_size(&self) -> usize {
+        self.max_packet_size
+    }
+
+    fn buffer_size(&self) -> usize {
+        self.buffer_size
+    }
+
     fn kexinit(&self) -> KexInit {
         let mut cookie = [0u8; 16];
         rand::thread_rng().fill_bytes(&mut cookie);
 
         KexInit {
             cookie,
-            kex_algorithms: NameList::from_iter(&self.algorithms.kexs),
+            kex_algorithms: NameList::from_iter(
+                self.algorithms
+                    .kexs
+                    .iter()
+                    .map(Kex::as_ref)
+                    .chain(self.algorithms.custom_kexs.iter().map(|kex| kex.name()))
+                    .chain([KEX_STRICT_SERVER]),
+            ),
             server_host_key_algorithms: NameList::from_iter(
-                self.keys.iter().map(PrivateKey::algorithm),
+                self.host_keys().map(|(name, _, _)| name),
             ),
-            encryption_algorithms_client_to_server: NameList::from_iter(&self.algorithms.ciphers),
-            encryption_algorithms_server_to_client: NameList::from_iter(&self.algorithms.ciphers),
+            encryption_algorithms_client_to_server: NameList::from_iter(self.cipher_names()),
+            encryption_algorithms_server_to_client: NameList::from_iter(self.cipher_names()),
             mac_algorithms_client_to_server: NameList::from_iter(&self.algorithms.macs),
             mac_algorithms_server_to_client: NameList::from_iter(&self.algorithms.macs),
             compression_algorithms_client_to_server: NameList::from_iter(
-                &self.algorithms.compressions,
+                &self.algorithms.compressions_client_to_server,
             ),
             compression_algorithms_server_to_client: NameList::from_iter(
-                &self.algorithms.compressions,
+                &self.algorithms.compressions_server_to_client,
             ),
             languages_client_to_server: NameList::default(),
             languages_server_to_client: NameList::default(),
@@ -132,25 +489,65 @@ impl Side for Server {
         }
     }
 
+    fn ext_info(&self, peerkexinit: &KexInit) -> Result<Option<Packet>> {
+        let supported = peerkexinit
+            .kex_algorithms
+            .into_iter()
+            .any(|name| &*name == EXT_INFO_CLIENT);
+
+        supported
+            .then(|| {
+                ext_info::encode([(
+                    ext_info::SERVER_SIG_ALGS,
+                    self.algorithms
+                        .server_sig_algs
+                        .iter()
+                        .map(Key::as_str)
+                        .collect::<Vec<_>>()
+                        .join(","),
+                )])
+            })
+            .transpose()
+            .map_err(Into::into)
+    }
+
     async fn exchange(
         &self,
         stream: &mut Stream<impl Pipe>,
         kexinit: KexInit<'_>,
         peerkexinit: KexInit<'_>,
+        id: &Id,
         peer_id: &Id,
     ) -> Result<TransportPair> {
-        let client = KexMeta::new::<Client>(peer_id, &peerkexinit, &kexinit)?;
-        let server = KexMeta::new::<Server>(self.id(), &peerkexinit, &kexinit)?;
+        let custom = &self.algorithms.custom_ciphers;
+        let client = KexMeta::new::<Client>(peer_id, &peerkexinit, &kexinit, custom)?;
+        let server = KexMeta::new::<Server>(id, &peerkexinit, &kexinit, custom)?;
 
-        let alg = Algorithm::negociate(&peerkexinit, &kexinit)?;
-        let key = self
-            .keys
-            .iter()
-            .find(|key| key.algorithm() == alg)
-            .expect("Did our KexInit lie to the client ?");
+        let alg = negociate_host_key(&peerkexinit, &kexinit)?;
+        let (_, key, host_key) = self
+            .host_keys()
+            .find(|(name, _, _)| *name == alg)
+            .ok_or(Error::KexError)?;
+
+        let kex = kex::negociate(&peerkexinit, &kexinit, &self.algorithms.custom_kexs)?;
+        let mut transport = kex
+            .as_server(
+                &mut KexStream::from(&mut *stream),
+                client,
+                server,
+                key,
+                &host_key,
+            )
+            .await?;
+        transport.tx.with_compression_level(self.compression_level);
+        transport.tx.with_padding(self.padding);
+
+        stream.with_algorithms(NegociatedAlgorithms {
+            kex: kex.name().into(),
+            host_key: alg,
+        });
+        stream.with_server_host_key(Some(host_key));
 
-        Kex::negociate(&peerkexinit, &kexinit)?
-            .as_server(stream, client, server, key)
-            .await
+        Ok(transport)
     }
 }
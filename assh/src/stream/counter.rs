@@ -4,8 +4,8 @@ use futures::io::{AsyncRead, AsyncWrite};
 
 pub struct IoCounter<C> {
     inner: C,
-    rx: usize,
-    tx: usize,
+    rx: u64,
+    tx: u64,
 }
 
 impl<C> IoCounter<C> {
@@ -17,7 +17,7 @@ impl<C> IoCounter<C> {
         }
     }
 
-    pub fn count(&self) -> usize {
+    pub fn count(&self) -> u64 {
         self.rx + self.tx
     }
 
@@ -25,6 +25,15 @@ impl<C> IoCounter<C> {
         self.rx = 0;
         self.tx = 0;
     }
+
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+
+    #[cfg(test)]
+    pub fn get_ref(&self) -> &C {
+        &self.inner
+    }
 }
 
 impl<C: AsyncRead + Unpin> AsyncRead for IoCounter<C> {
@@ -36,7 +45,7 @@ impl<C: AsyncRead + Unpin> AsyncRead for IoCounter<C> {
         let poll = std::pin::Pin::new(&mut self.inner).poll_read(cx, buf);
 
         if let Poll::Ready(Ok(bytes)) = poll {
-            self.rx += bytes;
+            self.rx += bytes as u64;
         }
 
         poll
@@ -52,7 +61,7 @@ impl<C: AsyncWrite + Unpin> AsyncWrite for IoCounter<C> {
         let poll = std::pin::Pin::new(&mut self.inner).poll_write(cx, buf);
 
         if let Poll::Ready(Ok(bytes)) = poll {
-            self.tx += bytes;
+            self.tx += bytes as u64;
         }
 
         poll
@@ -1,34 +1,36 @@
 use digest::{Digest, FixedOutputReset};
 use secrecy::SecretBox;
-use ssh_packet::Mac;
+use zeroize::{Zeroize, ZeroizeOnDrop, Zeroizing};
 
 use crate::algorithm::Hmac;
 
-use super::algorithm::Cipher;
+use super::algorithm::CipherAlgorithm;
 
+/// The keys derived from the key-exchange's shared secret, for one direction of the stream.
 #[derive(Debug, Default)]
 pub struct Keys {
     /// Cipher _initialization vector_.
-    pub iv: SecretBox<Vec<u8>>,
+    pub(crate) iv: SecretBox<Vec<u8>>,
 
     /// Cipher _key_.
-    pub key: SecretBox<Vec<u8>>,
+    pub(crate) key: SecretBox<Vec<u8>>,
 
     /// Hmac _key_.
-    pub hmac: SecretBox<Vec<u8>>,
+    pub(crate) hmac: SecretBox<Vec<u8>>,
 }
 
 impl Keys {
+    /// Derive the _client-to-server_ keys from the `secret` and exchange `hash`, as digested by `D`.
     pub fn as_client<D: Digest + FixedOutputReset>(
         secret: &impl AsRef<[u8]>,
         hash: &[u8],
         session_id: &[u8],
-        cipher: &Cipher,
+        cipher: &dyn CipherAlgorithm,
         hmac: &Hmac,
     ) -> Self {
         let ivsize = cipher.iv_size();
         let keysize = cipher.key_size();
-        let hmacsize = hmac.size();
+        let hmacsize = hmac.key_size();
 
         Self {
             iv: Self::derive::<D>(secret, hash, b'A', session_id, ivsize),
@@ -37,16 +39,17 @@ impl Keys {
         }
     }
 
+    /// Derive the _server-to-client_ keys from the `secret` and exchange `hash`, as digested by `D`.
     pub fn as_server<D: Digest + FixedOutputReset>(
         secret: &impl AsRef<[u8]>,
         hash: &[u8],
         session_id: &[u8],
-        cipher: &Cipher,
+        cipher: &dyn CipherAlgorithm,
         hmac: &Hmac,
     ) -> Self {
         let ivsize = cipher.iv_size();
         let keysize = cipher.key_size();
-        let hmacsize = hmac.size();
+        let hmacsize = hmac.key_size();
 
         Self {
             iv: Self::derive::<D>(secret, hash, b'B', session_id, ivsize),
@@ -55,6 +58,8 @@ impl Keys {
         }
     }
 
+    /// Derive `size` bytes of key material, extending the digest by iteratively
+    /// hashing `K || H || K1 || K2 ...` when more bytes are needed, as per RFC4253 §7.2.
     fn derive<D: Digest + FixedOutputReset>(
         secret: &impl AsRef<[u8]>,
         hash: &[u8],
@@ -63,6 +68,11 @@ impl Keys {
         size: usize,
     ) -> SecretBox<Vec<u8>> {
         SecretBox::<Vec<u8>>::init_with_mut(|key| {
+            // Reserve the whole buffer upfront, to prevent reallocations from leaving copies behind.
+            key.reserve_exact(
+                size.div_ceil(<D as Digest>::output_size()) * <D as Digest>::output_size(),
+            );
+
             let mut hasher = D::new()
                 .chain_update((secret.as_ref().len() as u32).to_be_bytes())
                 .chain_update(secret)
@@ -70,7 +80,7 @@ impl Keys {
                 .chain_update([kind])
                 .chain_update(session_id);
 
-            key.extend_from_slice(&hasher.finalize_reset());
+            key.extend_from_slice(&Zeroizing::new(hasher.finalize_reset()));
 
             while key.len() < size {
                 hasher = hasher
@@ -79,10 +89,150 @@ impl Keys {
                     .chain_update(hash)
                     .chain_update(key.as_slice());
 
-                key.extend_from_slice(&hasher.finalize_reset());
+                key.extend_from_slice(&Zeroizing::new(hasher.finalize_reset()));
             }
 
             key.truncate(size);
         })
     }
 }
+
+impl Zeroize for Keys {
+    fn zeroize(&mut self) {
+        self.iv.zeroize();
+        self.key.zeroize();
+        self.hmac.zeroize();
+    }
+}
+
+/// All the fields are [`SecretBox`]es, which are wiped when dropped.
+impl ZeroizeOnDrop for Keys {}
+
+#[cfg(test)]
+#[cfg_attr(
+    not(all(feature = "aes-ctr", feature = "hmac-sha2")),
+    allow(unused_imports, dead_code)
+)]
+mod tests {
+    use secrecy::ExposeSecret;
+
+    use super::*;
+    use crate::algorithm::Cipher;
+
+    #[cfg(all(feature = "aes-ctr", feature = "hmac-sha2"))]
+    fn keys() -> Keys {
+        Keys::as_client::<sha2::Sha256>(
+            &[0x42; 32],
+            &[0x13; 32],
+            &[0x37; 32],
+            &Cipher::Aes256Ctr,
+            &Hmac::HmacSha512,
+        )
+    }
+
+    #[test]
+    fn keys_are_zeroize_on_drop() {
+        fn assert<T: Zeroize + ZeroizeOnDrop>() {}
+
+        assert::<Keys>();
+    }
+
+    #[cfg(all(feature = "aes-ctr", feature = "hmac-sha2"))]
+    #[test]
+    fn derived_keys_are_not_reallocated() {
+        let keys = keys();
+
+        assert_eq!(keys.key.expose_secret().len(), 32);
+        assert_eq!(keys.key.expose_secret().capacity(), 32);
+        assert_eq!(keys.hmac.expose_secret().len(), 64);
+        assert_eq!(keys.hmac.expose_secret().capacity(), 64);
+    }
+
+    #[cfg(all(feature = "aes-ctr", feature = "hmac-sha2"))]
+    #[test]
+    fn zeroized_keys_are_wiped() {
+        let mut keys = keys();
+        keys.zeroize();
+
+        assert!(keys.iv.expose_secret().is_empty());
+        assert!(keys.key.expose_secret().is_empty());
+        assert!(keys.hmac.expose_secret().is_empty());
+    }
+
+    /// A naive implementation of the key derivation, following RFC4253 §7.2 to the letter.
+    fn reference<D: Digest>(kind: u8, size: usize) -> Vec<u8> {
+        let secret = [[0, 0, 0, 32].as_slice(), &[0x42; 32]].concat();
+        let hash = [0x13; 32];
+
+        let mut key = D::new()
+            .chain_update(&secret)
+            .chain_update(hash)
+            .chain_update([kind])
+            .chain_update([0x37; 32])
+            .finalize()
+            .to_vec();
+
+        while key.len() < size {
+            let next = D::new()
+                .chain_update(&secret)
+                .chain_update(hash)
+                .chain_update(&key)
+                .finalize();
+
+            key.extend_from_slice(&next);
+        }
+
+        key.truncate(size);
+        key
+    }
+
+    #[cfg(all(feature = "chacha20-poly1305", feature = "hmac-sha2"))]
+    #[test]
+    fn keys_are_extended_past_the_digest_size() {
+        let keys = Keys::as_server::<sha1::Sha1>(
+            &[0x42; 32],
+            &[0x13; 32],
+            &[0x37; 32],
+            &Cipher::ChaCha20Poly1305,
+            &Hmac::HmacSha512,
+        );
+
+        assert_eq!(
+            keys.iv.expose_secret().as_slice(),
+            reference::<sha1::Sha1>(b'B', 0)
+        );
+        assert_eq!(
+            keys.key.expose_secret().as_slice(),
+            reference::<sha1::Sha1>(b'D', 64)
+        );
+        assert_eq!(
+            keys.hmac.expose_secret().as_slice(),
+            reference::<sha1::Sha1>(b'F', 64)
+        );
+    }
+
+    #[cfg(all(feature = "aes-ctr", feature = "hmac-sha1"))]
+    #[test]
+    fn keys_are_truncated_to_the_required_size() {
+        let keys = Keys::as_client::<sha2::Sha512>(
+            &[0x42; 32],
+            &[0x13; 32],
+            &[0x37; 32],
+            &Cipher::Aes128Ctr,
+            &Hmac::HmacSha196,
+        );
+
+        assert_eq!(
+            keys.iv.expose_secret().as_slice(),
+            reference::<sha2::Sha512>(b'A', 16)
+        );
+        assert_eq!(
+            keys.key.expose_secret().as_slice(),
+            reference::<sha2::Sha512>(b'C', 16)
+        );
+        assert_eq!(
+            keys.hmac.expose_secret().as_slice(),
+            reference::<sha2::Sha512>(b'E', 20)
+        );
+    }
+}
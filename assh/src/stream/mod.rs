@@ -1,71 +1,316 @@
 //! Primitives to manipulate binary data to extract and encode
 //! messages from/to a [`Pipe`] stream.
 
+use std::{collections::VecDeque, future::Future, pin::Pin, time::Instant};
+
 use futures::{AsyncBufReadExt, AsyncWriteExt, FutureExt};
 use futures_time::{future::FutureExt as _, time::Duration};
-use ssh_packet::IntoPacket;
+use ssh_packet::{trans::Unimplemented, IntoPacket};
 
-use crate::{algorithm, Pipe, Result};
+use crate::{algorithm, Error, Pipe, Result};
 
 mod counter;
 use counter::IoCounter;
 
 mod transport;
-pub(super) use transport::{Transport, TransportPair};
+pub use transport::{
+    NegociatedAlgorithms, PaddingMode, Transport, TransportPair, TransportStats, TransportStatsPair,
+};
 
 mod keys;
-pub(super) use keys::Keys;
+pub use keys::Keys;
+
+mod trace;
+pub(crate) use trace::message_name;
 
 #[doc(no_inline)]
 pub use ssh_packet::Packet;
 
-/// Re-key after 1GiB of exchanged data as recommended per the RFC.
-const REKEY_BYTES_THRESHOLD: usize = 0x40000000;
+/// Re-key after this amount of packets have been exchanged in either direction,
+/// to never let a sequence number wrap with the same keys, as required per the RFC.
+const REKEY_PACKETS: u32 = u32::MAX - 1024;
+
+/// The message number of the `SSH_MSG_KEXINIT` message.
+const SSH_MSG_KEXINIT: u8 = 20;
+
+/// The message number of the `SSH_MSG_UNIMPLEMENTED` message.
+const SSH_MSG_UNIMPLEMENTED: u8 = 3;
+
+/// The amount of the last sent packets awaiting a response which are remembered,
+/// to correlate the `SSH_MSG_UNIMPLEMENTED` messages of the peer with them.
+const AWAITING_MAX: usize = 32;
+
+/// The maximum amount of packets queued or deferred across a key-exchange, in either direction.
+const QUEUE_MAX: usize = 1024;
+
+/// The maximum size of the payloads queued or deferred across a key-exchange, in either direction,
+/// to bound the memory a peer never completing the exchange can make us hold.
+const QUEUE_MAX_SIZE: usize = 0x800000;
+
+/// Push the `packet` at the back of the `queue`, unless it would exceed the bounds.
+fn enqueue(queue: &mut VecDeque<Packet>, packet: Packet) -> Result<()> {
+    let size = queue
+        .iter()
+        .map(|packet| packet.payload.len())
+        .sum::<usize>();
+
+    if queue.len() >= QUEUE_MAX || size + packet.payload.len() > QUEUE_MAX_SIZE {
+        return Err(Error::UnexpectedMessage);
+    }
+
+    queue.push_back(packet);
+
+    Ok(())
+}
+
+/// Whether the message numbered `number` may be sent during a key-exchange, as per RFC4253 §7.1,
+/// which are the transport layer generic messages and the key-exchange messages.
+pub fn is_kex_message(number: u8) -> bool {
+    matches!(number, 1..=4 | 20..=49)
+}
+
+/// A sent packet for which the sender awaits a response from the peer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Awaiting {
+    /// The sequence number of the packet.
+    seq: u32,
+
+    /// The message number of the packet.
+    message: u8,
+
+    /// The channel referred to by the packet, for the _connect_ layer messages.
+    channel: Option<u32>,
+}
+
+impl Awaiting {
+    /// Inspect the `payload` of the packet sent as `seq`, to know whether a response is awaited,
+    /// which is the case for the service requests, the key-exchange and authentication messages,
+    /// the channel opens and the global and channel requests wanting a reply.
+    fn new(seq: u32, payload: &[u8]) -> Option<Self> {
+        let (&message, _) = payload.split_first()?;
+
+        let uint = |at: usize| {
+            payload
+                .get(at..at + 4)
+                .and_then(|bytes| bytes.try_into().ok())
+                .map(u32::from_be_bytes)
+        };
+        let after_string = |at: usize| uint(at).map(|len| at + 4 + len as usize);
+        let want_reply = |at: Option<usize>| at.and_then(|at| payload.get(at)) == Some(&1);
+
+        let channel = match message {
+            5 | 20 | 30..=49 | 50 | 60..=79 => None,
+            80 if want_reply(after_string(1)) => None,
+            90 => Some(uint(after_string(1)?)?),
+            98 if want_reply(after_string(5)) => Some(uint(1)?),
+            _ => return None,
+        };
+
+        Some(Self {
+            seq,
+            message,
+            channel,
+        })
+    }
+}
+
+/// The progress of the key-exchange on the stream.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum KexState {
+    /// No key-exchange is in progress.
+    #[default]
+    Idle,
+
+    /// The peer sent it's `KexInit`, which we have yet to answer.
+    Pending,
+
+    /// We sent our `KexInit`, and are waiting for the `NewKeys` to complete the exchange.
+    Exchanging,
+}
 
 /// A wrapper around a [`Pipe`] to interface with to the SSH binary protocol.
 pub struct Stream<S> {
     inner: IoCounter<S>,
     timeout: Duration,
 
+    /// Re-key after this amount of bytes have been exchanged.
+    rekey_bytes: u64,
+
+    /// Re-key after this amount of time has elapsed since the last exchange.
+    rekey_interval: std::time::Duration,
+
+    /// Refuse received packets declaring a greater length than this.
+    max_packet_size: usize,
+
+    /// The capacity the packet buffers shrink back to after a larger packet.
+    buffer_size: usize,
+
+    /// The instant of the last key-exchange.
+    exchanged: Instant,
+
+    /// The amount of completed key-exchanges.
+    exchanges: u64,
+
     /// The pair of transport algorithms and keys computed from the key exchange.
     transport: TransportPair,
 
     /// The session identifier derived from the first key exchange.
     session: Option<Vec<u8>>,
 
+    /// The host key presented by the server during the ongoing key exchange.
+    host_key: Option<algorithm::HostKey>,
+
+    /// The host key which authenticated the server during the last key exchange.
+    server_host_key: Option<algorithm::HostKey>,
+
+    /// The algorithms negociated during the last key exchange.
+    algorithms: Option<NegociatedAlgorithms>,
+
+    /// Whether the _strict key-exchange_ extension is in effect.
+    strict: bool,
+
+    /// Whether the delayed compression has been activated.
+    compressing: bool,
+
     /// Sequence number for the `tx` side.
     txseq: u32,
 
     /// Sequence number for the `rx` side.
     rxseq: u32,
 
+    /// Sequence numbers for the `tx` and `rx` sides at the last key-exchange.
+    rekeyed: (u32, u32),
+
     /// A buffer for the `peek` method.
     buffer: Option<Packet>,
+
+    /// The progress of the key-exchange.
+    kex: KexState,
+
+    /// The non-kex packets sent during a key-exchange, to be flushed after the `NewKeys`.
+    queue: VecDeque<Packet>,
+
+    /// The non-kex packets received during a key-exchange, to be received after the `NewKeys`.
+    deferred: VecDeque<Packet>,
+
+    /// The last sent packets awaiting a response from the peer.
+    awaiting: VecDeque<Awaiting>,
 }
 
 impl<S> Stream<S>
 where
     S: Pipe,
 {
-    pub fn new(stream: S, timeout: Duration) -> Self {
+    pub fn new(
+        stream: S,
+        timeout: Duration,
+        rekey_bytes: u64,
+        rekey_interval: std::time::Duration,
+        max_packet_size: usize,
+        buffer_size: usize,
+    ) -> Self {
+        let mut transport = TransportPair::default();
+        transport.tx.with_buffer_size(buffer_size);
+        transport.rx.with_buffer_size(buffer_size);
+
         Self {
             inner: IoCounter::new(stream),
             timeout,
-            transport: Default::default(),
+            rekey_bytes,
+            rekey_interval,
+            max_packet_size,
+            buffer_size,
+            exchanged: Instant::now(),
+            exchanges: 0,
+            transport,
             session: None,
+            host_key: None,
+            server_host_key: None,
+            algorithms: None,
+            strict: false,
+            compressing: false,
             txseq: 0,
             rxseq: 0,
+            rekeyed: (0, 0),
             buffer: None,
+            kex: KexState::Idle,
+            queue: VecDeque::new(),
+            deferred: VecDeque::new(),
+            awaiting: VecDeque::new(),
         }
     }
 
     pub fn is_rekeyable(&self) -> bool {
-        self.session.is_none() || self.inner.count() > REKEY_BYTES_THRESHOLD
+        self.session.is_none()
+            || self.inner.count() > self.rekey_bytes
+            || self.exchanged.elapsed() > self.rekey_interval
+            || self.txseq.wrapping_sub(self.rekeyed.0) > REKEY_PACKETS
+            || self.rxseq.wrapping_sub(self.rekeyed.1) > REKEY_PACKETS
+    }
+
+    /// Whether the peer initiated a key-exchange which we have yet to answer.
+    pub fn is_kex_pending(&self) -> bool {
+        self.kex == KexState::Pending
     }
 
-    pub fn with_transport(&mut self, transport: TransportPair) {
+    /// Defer the non-kex `packet` received during a key-exchange,
+    /// to be received after the exchange completes.
+    pub fn defer(&mut self, packet: Packet) -> Result<()> {
+        enqueue(&mut self.deferred, packet)
+    }
+
+    pub fn with_transport(&mut self, mut transport: TransportPair) {
+        // The statistics are cumulative across key-exchanges.
+        transport.tx.stats = self.transport.tx.stats;
+        transport.rx.stats = self.transport.rx.stats;
+        transport.tx.with_buffer_size(self.buffer_size);
+        transport.rx.with_buffer_size(self.buffer_size);
+
+        // The compression contexts outlive the transports, but start over with a blank dictionary.
+        transport
+            .tx
+            .zlib
+            .inherit(std::mem::take(&mut self.transport.tx.zlib));
+        transport
+            .rx
+            .zlib
+            .inherit(std::mem::take(&mut self.transport.rx.zlib));
+
+        // The superseded keys are wiped as they are dropped here, and the cipher states
+        // are reset since they are constructed anew from the freshly derived keys.
         self.transport = transport;
         self.inner.reset();
+        self.exchanged = Instant::now();
+        self.exchanges += 1;
+        self.kex = KexState::Idle;
+
+        // With strict key-exchange, sequence numbers are reset after each `NewKeys`.
+        if self.strict {
+            self.txseq = 0;
+            self.rxseq = 0;
+            self.awaiting.clear();
+        }
+        self.rekeyed = (self.txseq, self.rxseq);
+
+        // Once activated, the delayed compression starts right away for subsequent exchanges.
+        if self.compressing {
+            self.with_compression();
+        }
+    }
+
+    /// Activate the delayed compression on both directions, once the user is authenticated.
+    pub fn with_compression(&mut self) {
+        self.compressing = true;
+        self.transport.tx.activate_compression();
+        self.transport.rx.activate_compression();
+    }
+
+    pub fn with_strict(&mut self) {
+        self.strict = true;
+    }
+
+    pub fn is_strict(&self) -> bool {
+        self.strict
     }
 
     pub fn with_session(&mut self, session: &[u8]) -> &[u8] {
@@ -76,12 +321,69 @@ where
         self.session.as_deref()
     }
 
+    pub fn with_host_key(&mut self, key: algorithm::HostKey) {
+        self.host_key = Some(key);
+    }
+
+    pub fn take_host_key(&mut self) -> Option<algorithm::HostKey> {
+        self.host_key.take()
+    }
+
+    pub fn with_server_host_key(&mut self, key: Option<algorithm::HostKey>) {
+        self.server_host_key = key;
+    }
+
+    pub fn server_host_key(&self) -> Option<&algorithm::HostKey> {
+        self.server_host_key.as_ref()
+    }
+
+    pub fn with_algorithms(&mut self, algorithms: NegociatedAlgorithms) {
+        self.algorithms = Some(algorithms);
+    }
+
+    pub fn algorithms(&self) -> Option<&NegociatedAlgorithms> {
+        self.algorithms.as_ref()
+    }
+
+    pub fn stats(&self) -> TransportStatsPair {
+        TransportStatsPair {
+            tx: self.transport.tx.stats,
+            rx: self.transport.rx.stats,
+            exchanges: self.exchanges,
+            exchanged: (self.exchanges > 0).then_some(self.exchanged),
+        }
+    }
+
+    /// Consume the stream to recover the underlying [`Pipe`],
+    /// along with the received packets which have not been returned by [`Self::recv`] yet.
+    pub fn into_inner(self) -> (S, Vec<Packet>) {
+        let pending = self.buffer.into_iter().chain(self.deferred).collect();
+
+        (self.inner.into_inner(), pending)
+    }
+
     pub async fn fill_buf(&mut self) -> Result<()> {
         self.inner.fill_buf().await?;
 
         Ok(())
     }
 
+    pub fn poll_fill_buf(
+        &mut self,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<()>> {
+        Pin::new(&mut *self.inner)
+            .poll_fill_buf(cx)
+            .map(|result| result.map(drop).map_err(Error::from))
+    }
+
+    /// Wait for the data written to the stream to be flushed.
+    pub async fn flush(&mut self) -> Result<()> {
+        self.inner.flush().await?;
+
+        Ok(())
+    }
+
     /// Poll the stream to detect whether data is immediately readable.
     pub async fn is_readable(&mut self) -> Result<bool> {
         futures::select_biased! {
@@ -104,48 +406,914 @@ where
     }
 
     /// Receive and decrypt a _packet_ from the peer.
+    ///
+    /// The packets deferred during a key-exchange are received first once it completes.
     pub async fn recv(&mut self) -> Result<Packet> {
-        match self.buffer.take() {
-            Some(packet) => Ok(packet),
-            None => {
-                let packet =
-                    Packet::from_reader(&mut self.inner, &mut self.transport.rx, self.rxseq)
-                        .timeout(self.timeout)
-                        .await??;
-
-                tracing::trace!(
-                    "<~- #{}: ^{:#x} ({} bytes)",
-                    self.rxseq,
-                    packet.payload[0],
-                    packet.payload.len(),
-                );
-
-                self.rxseq = self.rxseq.wrapping_add(1);
-
-                Ok(packet)
+        if let Some(packet) = self.buffer.take() {
+            return Ok(packet);
+        }
+
+        if self.kex != KexState::Exchanging {
+            if let Some(packet) = self.deferred.pop_front() {
+                return Ok(packet);
+            }
+        }
+
+        let packet = self
+            .transport
+            .rx
+            .read(&mut self.inner, self.rxseq, self.max_packet_size)
+            .timeout(self.timeout)
+            .await??;
+
+        // The message number is required, even if the padding left no payload after decompression.
+        let Some(&message) = packet.payload.first() else {
+            return Err(Error::Padding);
+        };
+
+        tracing::trace!("<~- #{}: {}", self.rxseq, trace::Summary(&packet.payload));
+
+        self.rxseq = self.rxseq.wrapping_add(1);
+
+        // With strict key-exchange, nothing but the key-exchange messages may be received
+        // until the initial exchange completes, not even the transport generic messages.
+        if self.strict && self.exchanges == 0 && !(SSH_MSG_KEXINIT..=49).contains(&message) {
+            return Err(Error::UnexpectedMessage);
+        }
+
+        if self.kex == KexState::Idle && message == SSH_MSG_KEXINIT {
+            self.kex = KexState::Pending;
+        }
+
+        if message == SSH_MSG_UNIMPLEMENTED {
+            if let Some(awaiting) = packet
+                .to::<Unimplemented>()
+                .ok()
+                .and_then(|Unimplemented { seq }| self.awaited(seq))
+            {
+                return Err(Error::Unimplemented {
+                    seq: awaiting.seq,
+                    message: awaiting.message,
+                    channel: awaiting.channel,
+                });
+            }
+        }
+
+        Ok(packet)
+    }
+
+    /// Remember the packet sent as `seq` with the `payload`, if a response is awaited for it.
+    fn await_response(&mut self, seq: u32, payload: &[u8]) {
+        if let Some(awaiting) = Awaiting::new(seq, payload) {
+            if self.awaiting.len() == AWAITING_MAX {
+                self.awaiting.pop_front();
             }
+
+            self.awaiting.push_back(awaiting);
         }
     }
 
+    /// Take the sent packet `seq` if a response was awaited for it.
+    fn awaited(&mut self, seq: u32) -> Option<Awaiting> {
+        let position = self
+            .awaiting
+            .iter()
+            .position(|awaiting| awaiting.seq == seq)?;
+
+        self.awaiting.remove(position)
+    }
+
     /// Encrypt and send a _packet_ to the peer.
+    ///
+    /// During a key-exchange, the non-kex packets are queued until [`Self::flush_queue`].
     pub async fn send(&mut self, packet: impl IntoPacket) -> Result<()> {
         let packet = packet.into_packet();
+        let message = packet.payload.first().copied();
 
-        packet
-            .to_writer(&mut self.inner, &mut self.transport.tx, self.txseq)
+        if self.kex == KexState::Exchanging && !message.is_some_and(is_kex_message) {
+            tracing::trace!(
+                "Queued a ^{:#x} packet until the end of the key-exchange",
+                message.unwrap_or_default()
+            );
+
+            return enqueue(&mut self.queue, packet);
+        }
+
+        self.transport
+            .tx
+            .write(&mut self.inner, &packet, self.txseq)
             .timeout(self.timeout)
             .await??;
         self.inner.flush().await?;
 
-        tracing::trace!(
-            "-~> #{}: ^{:#x} ({} bytes)",
-            self.txseq,
-            packet.payload[0],
-            packet.payload.len(),
-        );
+        tracing::trace!("-~> #{}: {}", self.txseq, trace::Summary(&packet.payload));
+
+        self.await_response(self.txseq, &packet.payload);
 
         self.txseq = self.txseq.wrapping_add(1);
 
+        if message == Some(SSH_MSG_KEXINIT) {
+            self.kex = KexState::Exchanging;
+        }
+
+        Ok(())
+    }
+
+    /// Send the packets queued during the last key-exchange, in order.
+    pub async fn flush_queue(&mut self) -> Result<()> {
+        while let Some(packet) = self.queue.pop_front() {
+            self.send(packet).await?;
+        }
+
+        Ok(())
+    }
+}
+
+type Erasure<'s, T> = Pin<Box<dyn Future<Output = Result<T>> + Send + Sync + 's>>;
+
+/// An object-safe interface to a [`Stream`], erasing the underlying [`Pipe`].
+trait Erased: Send + Sync {
+    fn send(&mut self, packet: Packet) -> Erasure<'_, ()>;
+
+    fn recv(&mut self) -> Erasure<'_, Packet>;
+
+    fn with_session(&mut self, session: &[u8]) -> &[u8];
+
+    fn with_host_key(&mut self, key: algorithm::HostKey);
+}
+
+impl<S: Pipe> Erased for Stream<S> {
+    fn send(&mut self, packet: Packet) -> Erasure<'_, ()> {
+        Box::pin(Stream::send(self, packet))
+    }
+
+    fn recv(&mut self) -> Erasure<'_, Packet> {
+        Box::pin(Stream::recv(self))
+    }
+
+    fn with_session(&mut self, session: &[u8]) -> &[u8] {
+        Stream::with_session(self, session)
+    }
+
+    fn with_host_key(&mut self, key: algorithm::HostKey) {
+        Stream::with_host_key(self, key)
+    }
+}
+
+/// A handle to the session's stream, provided to the key-exchange algorithms.
+pub struct KexStream<'s> {
+    inner: &'s mut dyn Erased,
+}
+
+impl<'s, S: Pipe> From<&'s mut Stream<S>> for KexStream<'s> {
+    fn from(stream: &'s mut Stream<S>) -> Self {
+        Self { inner: stream }
+    }
+}
+
+impl KexStream<'_> {
+    /// Encrypt and send a _packet_ to the peer.
+    pub async fn send(&mut self, packet: impl IntoPacket) -> Result<()> {
+        self.inner.send(packet.into_packet()).await
+    }
+
+    /// Receive and decrypt a _packet_ from the peer.
+    pub async fn recv(&mut self) -> Result<Packet> {
+        self.inner.recv().await
+    }
+
+    /// Register the exchange `hash` as the session identifier if this is the initial exchange,
+    /// and return the session identifier.
+    pub fn with_session(&mut self, hash: &[u8]) -> &[u8] {
+        self.inner.with_session(hash)
+    }
+
+    /// Register the host `key` presented by the _server_, once its signature of the exchange
+    /// has been verified, for the _client_ to decide whether to trust it.
+    pub fn with_host_key(&mut self, key: algorithm::HostKey) {
+        self.inner.with_host_key(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use futures::io::Cursor;
+    use rstest::rstest;
+    #[cfg(feature = "aes-ctr")]
+    use secrecy::ExposeSecret;
+    use ssh_packet::trans::Ignore;
+
+    use crate::algorithm::{Cipher, CipherAlgorithm, Hmac};
+
+    const MAX_PACKET_SIZE: usize = 0x40000;
+
+    fn stream(rekey_bytes: u64, rekey_interval: std::time::Duration) -> Stream<Cursor<Vec<u8>>> {
+        let mut stream = Stream::new(
+            Cursor::new(Vec::new()),
+            std::time::Duration::from_secs(1).into(),
+            rekey_bytes,
+            rekey_interval,
+            MAX_PACKET_SIZE,
+            MAX_PACKET_SIZE,
+        );
+        stream.with_session(b"session");
+
+        stream
+    }
+
+    #[async_std::test]
+    async fn rekeyable_after_bytes_threshold() -> Result<()> {
+        let mut stream = stream(1024, std::time::Duration::MAX);
+        assert!(!stream.is_rekeyable());
+
+        stream
+            .send(&Ignore {
+                data: vec![0; 2048].into(),
+            })
+            .await?;
+        assert!(stream.is_rekeyable());
+
+        stream.with_transport(Default::default());
+        assert!(!stream.is_rekeyable());
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn non_kex_packets_are_queued_during_exchange() -> Result<()> {
+        let mut stream = stream(u64::MAX, std::time::Duration::MAX);
+        stream.kex = KexState::Exchanging;
+
+        stream
+            .send(&ssh_packet::trans::ServiceRequest {
+                service_name: ssh_packet::arch::ascii!("ssh-userauth"),
+            })
+            .await?;
+        stream
+            .send(&Ignore {
+                data: vec![].into(),
+            })
+            .await?;
+        assert_eq!((stream.txseq, stream.queue.len()), (1, 1));
+
+        stream.with_transport(Default::default());
+        stream.flush_queue().await?;
+        assert_eq!((stream.txseq, stream.queue.len()), (2, 0));
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn queued_packets_are_bounded() -> Result<()> {
+        let mut stream = stream(u64::MAX, std::time::Duration::MAX);
+        stream.kex = KexState::Exchanging;
+
+        for _ in 0..QUEUE_MAX {
+            stream
+                .send(&ssh_packet::connect::ChannelEof {
+                    recipient_channel: 0,
+                })
+                .await?;
+        }
+
+        assert!(matches!(
+            stream
+                .send(&ssh_packet::connect::ChannelEof {
+                    recipient_channel: 0,
+                })
+                .await,
+            Err(Error::UnexpectedMessage)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn deferred_packets_are_bounded() {
+        let mut stream = stream(u64::MAX, std::time::Duration::MAX);
+        let packet = || {
+            Ignore {
+                data: vec![0; QUEUE_MAX_SIZE / 4].into(),
+            }
+            .into_packet()
+        };
+
+        for _ in 0..3 {
+            assert!(stream.defer(packet()).is_ok());
+        }
+
+        assert!(matches!(
+            stream.defer(packet()),
+            Err(Error::UnexpectedMessage)
+        ));
+    }
+
+    #[async_std::test]
+    async fn rekeyable_after_interval() -> Result<()> {
+        let stream = stream(u64::MAX, std::time::Duration::ZERO);
+
+        assert!(stream.is_rekeyable());
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn rekeyable_before_sequence_wraps() -> Result<()> {
+        let mut stream = stream(u64::MAX, std::time::Duration::MAX);
+        stream.with_transport(Default::default());
+
+        stream.txseq = REKEY_PACKETS;
+        assert!(!stream.is_rekeyable());
+
+        stream
+            .send(&Ignore {
+                data: Default::default(),
+            })
+            .await?;
+        assert!(stream.is_rekeyable());
+
+        stream.with_transport(Default::default());
+        assert!(!stream.is_rekeyable());
+
+        stream.rxseq = REKEY_PACKETS.wrapping_add(1);
+        assert!(stream.is_rekeyable());
+
+        Ok(())
+    }
+
+    #[test]
+    fn transport_secrets_are_zeroize_on_drop() {
+        fn assert<T: zeroize::ZeroizeOnDrop>() {}
+
+        assert::<Keys>();
+        #[cfg(feature = "aes-ctr")]
+        assert::<ctr::Ctr128BE<aes::Aes256>>();
+        #[cfg(feature = "aes-cbc")]
+        assert::<cbc::Encryptor<aes::Aes256>>();
+        #[cfg(feature = "tdes-cbc")]
+        assert::<cbc::Decryptor<des::TdesEde3>>();
+        assert::<zeroize::Zeroizing<Vec<u8>>>();
+    }
+
+    #[async_std::test]
+    async fn never_rekeyable_with_maximum_bytes() -> Result<()> {
+        let mut stream = stream(u64::MAX, std::time::Duration::MAX);
+
+        for _ in 0..16 {
+            stream
+                .send(&Ignore {
+                    data: vec![0; 4096].into(),
+                })
+                .await?;
+        }
+        assert!(!stream.is_rekeyable());
+
+        Ok(())
+    }
+
+    #[rstest]
+    #[case(&[5, 0, 0, 0, 0], Some(None))]
+    #[case(&[80, 0, 0, 0, 1, b'x', 1], Some(None))]
+    #[case(&[80, 0, 0, 0, 1, b'x', 0], None)]
+    #[case(&[90, 0, 0, 0, 1, b'x', 0, 0, 0, 7], Some(Some(7)))]
+    #[case(&[90, 0, 0, 0, 1], None)]
+    #[case(&[98, 0, 0, 0, 3, 0, 0, 0, 1, b'x', 1], Some(Some(3)))]
+    #[case(&[98, 0, 0, 0, 3, 0, 0, 0, 1, b'x', 0], None)]
+    #[case(&[94, 0, 0, 0, 3], None)]
+    #[case(&[], None)]
+    fn awaiting_responses(#[case] payload: &[u8], #[case] expected: Option<Option<u32>>) {
+        assert_eq!(
+            Awaiting::new(0, payload).map(|awaiting| awaiting.channel),
+            expected
+        );
+    }
+
+    #[async_std::test]
+    async fn unimplemented_is_correlated_to_the_sent_packet() -> Result<()> {
+        let mut peer = stream(u64::MAX, std::time::Duration::MAX);
+        peer.send(&Unimplemented { seq: 3 }).await?;
+        peer.send(&Unimplemented { seq: 4 }).await?;
+
+        let (wire, _) = peer.into_inner();
+        let mut stream = Stream::new(
+            Cursor::new(wire.into_inner()),
+            std::time::Duration::from_secs(1).into(),
+            u64::MAX,
+            std::time::Duration::MAX,
+            MAX_PACKET_SIZE,
+            MAX_PACKET_SIZE,
+        );
+        stream.await_response(4, &[90, 0, 0, 0, 1, b'x', 0, 0, 0, 7]);
+
+        assert_eq!(stream.recv().await?.payload[0], SSH_MSG_UNIMPLEMENTED);
+        assert!(matches!(
+            stream.recv().await,
+            Err(Error::Unimplemented {
+                seq: 4,
+                message: 90,
+                channel: Some(7)
+            })
+        ));
+
+        Ok(())
+    }
+
+    fn transport(cipher: &Cipher, hmac: &Hmac) -> Transport {
+        Transport {
+            cipher: std::sync::Arc::new(cipher.clone()),
+            hmac: hmac.clone(),
+            chain: Keys::as_client::<sha2::Sha256>(
+                &[0x42; 32],
+                &[0x13; 32],
+                b"session",
+                cipher,
+                hmac,
+            ),
+            ..Default::default()
+        }
+    }
+
+    /// Send the `messages` through a [`Stream`] using the `cipher`, and return the wire bytes.
+    async fn sealed(cipher: &Cipher, hmac: &Hmac, messages: &[Ignore<'_>]) -> Result<Vec<u8>> {
+        let mut stream = stream(u64::MAX, std::time::Duration::MAX);
+        stream.transport.tx = transport(cipher, hmac);
+
+        for message in messages {
+            stream.send(message).await?;
+        }
+
+        Ok(stream.inner.get_ref().get_ref().clone())
+    }
+
+    #[cfg(all(feature = "aes-ctr", feature = "hmac-sha2"))]
+    #[rstest]
+    #[case(Cipher::Aes128Ctr)]
+    #[case(Cipher::Aes192Ctr)]
+    #[case(Cipher::Aes256Ctr)]
+    #[async_std::test]
+    async fn ctr_round_trip(#[case] cipher: Cipher) -> Result<()> {
+        let messages = [
+            Ignore {
+                data: vec![0x01; 100].into(),
+            },
+            Ignore {
+                data: vec![0x02; 200].into(),
+            },
+        ];
+        let wire = sealed(&cipher, &Hmac::HmacSha256, &messages).await?;
+
+        let mut stream = Stream::new(
+            Cursor::new(wire),
+            std::time::Duration::from_secs(1).into(),
+            u64::MAX,
+            std::time::Duration::MAX,
+            MAX_PACKET_SIZE,
+            MAX_PACKET_SIZE,
+        );
+        stream.transport.rx = transport(&cipher, &Hmac::HmacSha256);
+
+        for message in &messages {
+            let packet = stream.recv().await?;
+
+            assert_eq!(packet.to::<Ignore>()?.data.as_ref(), message.data.as_ref());
+        }
+
+        Ok(())
+    }
+
+    #[cfg(feature = "aes-ctr")]
+    #[rstest]
+    #[case(Cipher::Aes128Ctr)]
+    #[case(Cipher::Aes192Ctr)]
+    #[case(Cipher::Aes256Ctr)]
+    #[async_std::test]
+    async fn ctr_counter_carries_across_packets(#[case] cipher: Cipher) -> Result<()> {
+        let messages = [
+            Ignore {
+                data: vec![0x01; 100].into(),
+            },
+            Ignore {
+                data: vec![0x02; 200].into(),
+            },
+        ];
+        let mut wire = sealed(&cipher, &Hmac::None, &messages).await?;
+
+        // Decrypting the whole wire at once only succeeds if the keystream continued across packets.
+        let keys = transport(&cipher, &Hmac::None).chain;
+        cipher
+            .decryptor(keys.key.expose_secret(), keys.iv.expose_secret())
+            .decrypt(&mut wire)?;
+
+        let length = |buf: &[u8]| u32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]) as usize;
+
+        let second = &wire[4 + length(&wire)..];
+
+        assert_eq!(second.len(), 4 + length(second));
+        assert_eq!(second[5], 2, "Expected an `SSH_MSG_IGNORE` message");
+
+        Ok(())
+    }
+
+    #[cfg(feature = "zlib")]
+    #[async_std::test]
+    async fn delayed_compression_starts_when_activated() -> Result<()> {
+        let message = Ignore {
+            data: b"compressible ".repeat(64).into(),
+        };
+        let delayed = || Transport {
+            compress: algorithm::Compress::ZlibOpenssh,
+            delayed: true,
+            ..Default::default()
+        };
+
+        let mut stream = stream(u64::MAX, std::time::Duration::MAX);
+        stream.transport.tx = delayed();
+
+        stream.send(&message).await?;
+        let before = stream.inner.get_ref().get_ref().len();
+
+        stream.with_compression();
+        stream.send(&message).await?;
+        stream.send(&message).await?;
+
+        let wire = stream.inner.get_ref().get_ref().clone();
+        let (plain, compressed) = wire.split_at(before);
+
+        assert!(plain
+            .windows(message.data.len())
+            .any(|window| window == message.data.as_ref()));
+        assert!(!compressed
+            .windows(b"compressible ".len())
+            .any(|window| window == b"compressible "));
+        assert!(compressed.len() < plain.len());
+
+        let mut stream = Stream::new(
+            Cursor::new(wire),
+            std::time::Duration::from_secs(1).into(),
+            u64::MAX,
+            std::time::Duration::MAX,
+            MAX_PACKET_SIZE,
+            MAX_PACKET_SIZE,
+        );
+        stream.transport.rx = delayed();
+
+        assert_eq!(stream.recv().await?.to::<Ignore>()?.data, message.data);
+        stream.with_compression();
+        assert_eq!(stream.recv().await?.to::<Ignore>()?.data, message.data);
+        assert_eq!(stream.recv().await?.to::<Ignore>()?.data, message.data);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "zlib")]
+    #[async_std::test]
+    async fn stats_are_cumulative_across_rekeys() -> Result<()> {
+        let message = Ignore {
+            data: b"compressible ".repeat(64).into(),
+        };
+        let zlib = || TransportPair {
+            tx: Transport {
+                compress: algorithm::Compress::Zlib,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let mut stream = stream(u64::MAX, std::time::Duration::MAX);
+        assert_eq!(stream.stats().exchanged, None);
+
+        stream.with_transport(zlib());
+        stream.send(&message).await?;
+
+        let first = stream.stats();
+        assert_eq!(first.tx.packets, 1);
+        assert!(first.tx.ratio() < 1.0);
+        assert_eq!(
+            first.tx.wire_bytes,
+            stream.inner.get_ref().get_ref().len() as u64
+        );
+        assert_eq!(first.exchanges, 1);
+
+        stream.with_transport(zlib());
+        stream.send(&message).await?;
+
+        let second = stream.stats();
+        assert_eq!(second.tx.packets, 2);
+        assert_eq!(second.tx.raw_bytes, first.tx.raw_bytes * 2);
+        assert!(second.tx.compressed_bytes > first.tx.compressed_bytes);
+        assert!(second.tx.wire_bytes > first.tx.wire_bytes);
+        assert_eq!(second.rx, TransportStats::default());
+        assert_eq!(second.exchanges, 2);
+        assert!(second.exchanged >= first.exchanged);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "zlib")]
+    #[async_std::test]
+    async fn compression_survives_rekeys_mid_transfer() -> Result<()> {
+        let messages = (0..64u8)
+            .map(|i| Ignore {
+                data: format!("chunk #{i}: ").repeat(128).into_bytes().into(),
+            })
+            .collect::<Vec<_>>();
+        let zlib = || TransportPair {
+            tx: Transport {
+                compress: algorithm::Compress::ZlibOpenssh,
+                delayed: true,
+                ..Default::default()
+            },
+            rx: Transport {
+                compress: algorithm::Compress::ZlibOpenssh,
+                delayed: true,
+                ..Default::default()
+            },
+        };
+
+        let mut sender = stream(u64::MAX, std::time::Duration::MAX);
+        sender.with_transport(zlib());
+        sender.with_compression();
+        for (i, message) in messages.iter().enumerate() {
+            if i % 16 == 8 {
+                sender.with_transport(zlib());
+            }
+            sender.send(message).await?;
+        }
+
+        let wire = sender.inner.get_ref().get_ref().clone();
+        let mut receiver = Stream::new(
+            Cursor::new(wire),
+            std::time::Duration::from_secs(1).into(),
+            u64::MAX,
+            std::time::Duration::MAX,
+            MAX_PACKET_SIZE,
+            MAX_PACKET_SIZE,
+        );
+        receiver.with_transport(zlib());
+        receiver.with_compression();
+        for (i, message) in messages.iter().enumerate() {
+            if i % 16 == 8 {
+                receiver.with_transport(zlib());
+            }
+            assert_eq!(receiver.recv().await?.to::<Ignore>()?.data, message.data);
+        }
+
+        assert_eq!(receiver.stats().exchanges, 5);
+        assert!(receiver.stats().rx.ratio() < 1.0);
+
+        Ok(())
+    }
+
+    #[cfg(any(feature = "aes-ctr", feature = "aes-cbc", feature = "tdes-cbc"))]
+    #[rstest]
+    #[cfg_attr(feature = "aes-ctr", case(Cipher::Aes128Ctr))]
+    #[cfg_attr(feature = "aes-ctr", case(Cipher::Aes256Ctr))]
+    #[cfg_attr(feature = "aes-cbc", case(Cipher::Aes128Cbc))]
+    #[cfg_attr(feature = "aes-cbc", case(Cipher::Aes256Cbc))]
+    #[cfg_attr(feature = "tdes-cbc", case(Cipher::TDesCbc))]
+    #[async_std::test]
+    async fn continuous_stream_is_opened_per_packet(#[case] cipher: Cipher) -> Result<()> {
+        let messages = [
+            Ignore {
+                data: vec![0x01; 100].into(),
+            },
+            Ignore {
+                data: vec![0x02; 200].into(),
+            },
+            Ignore {
+                data: vec![0x03; 300].into(),
+            },
+        ];
+        let wire = sealed(&cipher, &Hmac::None, &messages).await?;
+        let keys = transport(&cipher, &Hmac::None).chain;
+
+        // A conforming peer processes the whole wire as one continuous stream.
+        let mut plain = wire.clone();
+        cipher
+            .decryptor(keys.key.expose_secret(), keys.iv.expose_secret())
+            .decrypt(&mut plain)?;
+
+        let mut reference = plain.clone();
+        cipher
+            .encryptor(keys.key.expose_secret(), keys.iv.expose_secret())
+            .encrypt(&mut reference)?;
+        assert_eq!(reference, wire);
+
+        let mut stream = Stream::new(
+            Cursor::new(reference),
+            std::time::Duration::from_secs(1).into(),
+            u64::MAX,
+            std::time::Duration::MAX,
+            MAX_PACKET_SIZE,
+            MAX_PACKET_SIZE,
+        );
+        stream.transport.rx = transport(&cipher, &Hmac::None);
+
+        for message in &messages {
+            let packet = stream.recv().await?;
+
+            assert_eq!(packet.to::<Ignore>()?.data.as_ref(), message.data.as_ref());
+        }
+
+        Ok(())
+    }
+
+    #[rstest]
+    #[cfg_attr(
+        all(feature = "aes-ctr", feature = "hmac-sha2"),
+        case(Cipher::Aes128Ctr, Hmac::HmacSha256)
+    )]
+    #[cfg_attr(
+        all(feature = "aes-ctr", feature = "hmac-sha2"),
+        case(Cipher::Aes128Ctr, Hmac::HmacSha256ETM)
+    )]
+    #[cfg_attr(
+        all(feature = "aes-ctr", feature = "umac"),
+        case(Cipher::Aes128Ctr, Hmac::Umac64)
+    )]
+    #[cfg_attr(
+        feature = "chacha20-poly1305",
+        case(Cipher::ChaCha20Poly1305, Hmac::None)
+    )]
+    #[cfg_attr(feature = "aes-gcm", case(Cipher::Aes256Gcm, Hmac::None))]
+    #[async_std::test]
+    async fn flipped_bit_is_a_mac_mismatch(
+        #[case] cipher: Cipher,
+        #[case] hmac: Hmac,
+    ) -> Result<()> {
+        let messages = [Ignore {
+            data: vec![0x01; 100].into(),
+        }];
+        let mut wire = sealed(&cipher, &hmac, &messages).await?;
+        wire[32] ^= 0x01;
+
+        let mut stream = Stream::new(
+            Cursor::new(wire),
+            std::time::Duration::from_secs(1).into(),
+            u64::MAX,
+            std::time::Duration::MAX,
+            MAX_PACKET_SIZE,
+            MAX_PACKET_SIZE,
+        );
+        stream.transport.rx = transport(&cipher, &hmac);
+
+        assert!(matches!(
+            stream.recv().await,
+            Err(crate::Error::MacMismatch(_))
+        ));
+
+        Ok(())
+    }
+
+    #[rstest]
+    #[cfg_attr(
+        all(feature = "tdes-cbc", feature = "hmac-md5"),
+        case(Cipher::TDesCbc, Hmac::HmacMd5)
+    )]
+    #[cfg_attr(
+        all(feature = "aes-cbc", feature = "hmac-sha2"),
+        case(Cipher::Aes128Cbc, Hmac::HmacSha256ETM)
+    )]
+    #[cfg_attr(
+        all(feature = "aes-ctr", feature = "hmac-sha2"),
+        case(Cipher::Aes256Ctr, Hmac::HmacSha512)
+    )]
+    #[cfg_attr(feature = "aes-gcm", case(Cipher::Aes256Gcm, Hmac::None))]
+    #[cfg_attr(
+        feature = "chacha20-poly1305",
+        case(Cipher::ChaCha20Poly1305, Hmac::None)
+    )]
+    #[case(Cipher::None, Hmac::None)]
+    fn padding_follows_the_rules(#[case] cipher: Cipher, #[case] hmac: Hmac) {
+        let transport = transport(&cipher, &hmac);
+        let align = cipher.block_size().max(8);
+
+        for payload in 0..=256 {
+            let padding = transport.padding(payload);
+            let len = 1 + payload + padding;
+            let aligned = if cipher.is_aead() || ssh_packet::Mac::etm(&hmac) {
+                len
+            } else {
+                4 + len
+            };
+
+            assert!((4..=255).contains(&padding), "{payload}: {padding}");
+            assert_eq!(aligned % align, 0, "{payload}: {padding}");
+            assert!(4 + len >= 16, "{payload}: {padding}");
+        }
+    }
+
+    #[rstest]
+    #[case(PaddingMode::Zeroes, true)]
+    #[case(PaddingMode::Random, false)]
+    #[async_std::test]
+    async fn padding_is_filled_per_mode(
+        #[case] padding: PaddingMode,
+        #[case] zeroed: bool,
+    ) -> Result<()> {
+        let mut stream = stream(u64::MAX, std::time::Duration::MAX);
+        stream.transport.tx.with_padding(padding);
+
+        stream
+            .send(&Ignore {
+                data: vec![0x01; 100].into(),
+            })
+            .await?;
+
+        let wire = stream.inner.get_ref().get_ref();
+        let padding = &wire[wire.len() - wire[4] as usize..];
+
+        assert_eq!(padding.iter().all(|byte| *byte == 0), zeroed);
+
+        Ok(())
+    }
+
+    #[rstest]
+    #[case(12, 3)]
+    #[case(12, 11)]
+    #[case(12, 12)]
+    #[case(12, 255)]
+    #[case(13, 4)]
+    #[case(0, 0)]
+    #[async_std::test]
+    async fn invalid_padding_is_refused(#[case] len: u32, #[case] padding: u8) -> Result<()> {
+        let mut wire = len.to_be_bytes().to_vec();
+        wire.push(padding);
+        wire.resize(4 + len.max(4) as usize, 0);
+
+        let mut stream = Stream::new(
+            Cursor::new(wire),
+            std::time::Duration::from_secs(1).into(),
+            u64::MAX,
+            std::time::Duration::MAX,
+            MAX_PACKET_SIZE,
+            MAX_PACKET_SIZE,
+        );
+
+        assert!(matches!(stream.recv().await, Err(crate::Error::Padding)));
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn oversized_packet_is_refused_before_allocation() -> Result<()> {
+        let mut wire = 0xFFFFFFF0u32.to_be_bytes().to_vec();
+        wire.resize(8, 0);
+
+        let mut stream = Stream::new(
+            Cursor::new(wire),
+            std::time::Duration::from_secs(1).into(),
+            u64::MAX,
+            std::time::Duration::MAX,
+            MAX_PACKET_SIZE,
+            MAX_PACKET_SIZE,
+        );
+
+        assert!(matches!(
+            stream.recv().await,
+            Err(crate::Error::PacketTooLarge(0xFFFFFFF0))
+        ));
+        assert!(stream.transport.rx.buffer.capacity() < MAX_PACKET_SIZE);
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn buffers_shrink_after_a_large_packet() -> Result<()> {
+        const BUFFER_SIZE: usize = 0x1000;
+
+        let mut sender = Stream::new(
+            Cursor::new(Vec::new()),
+            std::time::Duration::from_secs(1).into(),
+            u64::MAX,
+            std::time::Duration::MAX,
+            MAX_PACKET_SIZE,
+            BUFFER_SIZE,
+        );
+        sender
+            .send(&Ignore {
+                data: vec![0x42; 0x20000].into(),
+            })
+            .await?;
+        assert!(sender.transport.tx.buffer.capacity() <= BUFFER_SIZE);
+
+        let mut receiver = Stream::new(
+            Cursor::new(sender.inner.get_ref().get_ref().clone()),
+            std::time::Duration::from_secs(1).into(),
+            u64::MAX,
+            std::time::Duration::MAX,
+            MAX_PACKET_SIZE,
+            BUFFER_SIZE,
+        );
+        assert_eq!(receiver.recv().await?.payload.len(), 0x20000 + 5);
+        assert!(receiver.transport.rx.buffer.capacity() <= BUFFER_SIZE);
+
+        // The buffer size is kept across key-exchanges.
+        receiver.with_transport(Default::default());
+        assert_eq!(receiver.transport.rx.buffer_size, BUFFER_SIZE);
+
         Ok(())
     }
 }
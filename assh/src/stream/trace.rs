@@ -0,0 +1,149 @@
+//! Tracing of the packets exchanged on a [`super::Stream`], after decryption.
+
+use std::fmt;
+
+/// The maximum amount of bytes of the payload dumped for each packet.
+#[cfg(feature = "trace-packets")]
+const DUMP_LIMIT: usize = 512;
+
+/// Resolve the name of the message numbered `number`, as per RFC4250 §4.1,
+/// or `None` for the unassigned or method-specific numbers which can't be resolved alone.
+pub fn message_name(number: u8) -> Option<&'static str> {
+    Some(match number {
+        1 => "SSH_MSG_DISCONNECT",
+        2 => "SSH_MSG_IGNORE",
+        3 => "SSH_MSG_UNIMPLEMENTED",
+        4 => "SSH_MSG_DEBUG",
+        5 => "SSH_MSG_SERVICE_REQUEST",
+        6 => "SSH_MSG_SERVICE_ACCEPT",
+        7 => "SSH_MSG_EXT_INFO",
+        8 => "SSH_MSG_NEWCOMPRESS",
+        20 => "SSH_MSG_KEXINIT",
+        21 => "SSH_MSG_NEWKEYS",
+        30 => "SSH_MSG_KEXDH_INIT",
+        31 => "SSH_MSG_KEXDH_REPLY",
+        50 => "SSH_MSG_USERAUTH_REQUEST",
+        51 => "SSH_MSG_USERAUTH_FAILURE",
+        52 => "SSH_MSG_USERAUTH_SUCCESS",
+        53 => "SSH_MSG_USERAUTH_BANNER",
+        80 => "SSH_MSG_GLOBAL_REQUEST",
+        81 => "SSH_MSG_REQUEST_SUCCESS",
+        82 => "SSH_MSG_REQUEST_FAILURE",
+        90 => "SSH_MSG_CHANNEL_OPEN",
+        91 => "SSH_MSG_CHANNEL_OPEN_CONFIRMATION",
+        92 => "SSH_MSG_CHANNEL_OPEN_FAILURE",
+        93 => "SSH_MSG_CHANNEL_WINDOW_ADJUST",
+        94 => "SSH_MSG_CHANNEL_DATA",
+        95 => "SSH_MSG_CHANNEL_EXTENDED_DATA",
+        96 => "SSH_MSG_CHANNEL_EOF",
+        97 => "SSH_MSG_CHANNEL_CLOSE",
+        98 => "SSH_MSG_CHANNEL_REQUEST",
+        99 => "SSH_MSG_CHANNEL_SUCCESS",
+        100 => "SSH_MSG_CHANNEL_FAILURE",
+        _ => return None,
+    })
+}
+
+/// Whether the payload of the message numbered `number` may hold secrets
+/// and must be elided from the dumps, which are the key-exchange method-specific messages,
+/// and the authentication requests and responses carrying passwords or answers.
+#[cfg(feature = "trace-packets")]
+fn is_sensitive(number: u8) -> bool {
+    matches!(number, 30..=49 | 50 | 60..=79)
+}
+
+/// A lazily formatted summary of a packet's `payload`.
+pub struct Summary<'p>(pub &'p [u8]);
+
+impl fmt::Display for Summary<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Some(&number) = self.0.first() else {
+            return write!(f, "empty packet (0 bytes)");
+        };
+
+        match message_name(number) {
+            Some(name) => write!(f, "{name} (^{number:#x}, {} bytes)", self.0.len())?,
+            None => write!(f, "^{number:#x} ({} bytes)", self.0.len())?,
+        }
+
+        #[cfg(feature = "trace-packets")]
+        if is_sensitive(number) {
+            write!(f, " [payload elided]")?;
+        } else {
+            write!(f, "{}", Hexdump(&self.0[..self.0.len().min(DUMP_LIMIT)]))?;
+
+            if self.0.len() > DUMP_LIMIT {
+                write!(f, "\n[{} more bytes]", self.0.len() - DUMP_LIMIT)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A lazily formatted hexdump of some bytes, 16 per line, each line prefixed with a newline.
+#[cfg(feature = "trace-packets")]
+struct Hexdump<'b>(&'b [u8]);
+
+#[cfg(feature = "trace-packets")]
+impl fmt::Display for Hexdump<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (idx, line) in self.0.chunks(16).enumerate() {
+            write!(f, "\n{:04x}:", idx * 16)?;
+
+            for byte in line {
+                write!(f, " {byte:02x}")?;
+            }
+            write!(f, "{:width$}  |", "", width = (16 - line.len()) * 3)?;
+
+            for byte in line {
+                let char = if byte.is_ascii_graphic() || *byte == b' ' {
+                    *byte as char
+                } else {
+                    '.'
+                };
+
+                write!(f, "{char}")?;
+            }
+            write!(f, "|")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summary_resolves_the_message_name() {
+        assert!(Summary(&[20, 0, 0])
+            .to_string()
+            .starts_with("SSH_MSG_KEXINIT (^0x14, 3 bytes)"));
+        assert!(Summary(&[42]).to_string().starts_with("^0x2a (1 bytes)"));
+        assert_eq!(Summary(&[]).to_string(), "empty packet (0 bytes)");
+    }
+
+    #[cfg(feature = "trace-packets")]
+    #[test]
+    fn summary_elides_sensitive_payloads() {
+        let summary = Summary(&[50, 0xde, 0xad]).to_string();
+
+        assert!(summary.ends_with("[payload elided]"));
+        assert!(!summary.contains("de ad"));
+    }
+
+    #[cfg(feature = "trace-packets")]
+    #[test]
+    fn summary_bounds_the_hexdump() {
+        let mut payload = vec![b'A'; DUMP_LIMIT * 2];
+        payload[0] = 94;
+
+        let summary = Summary(&payload).to_string();
+
+        assert!(summary.contains("\n0000: 5e 41 41"));
+        assert!(summary.contains("|^AAAAAAAAAAAAAAA|"));
+        assert!(summary.ends_with(&format!("[{DUMP_LIMIT} more bytes]")));
+    }
+}
@@ -1,105 +1,427 @@
-use rand::Rng;
+use std::sync::Arc;
+
+use futures::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use rand::{rngs::StdRng, RngCore, SeedableRng};
 use secrecy::ExposeSecret;
-use ssh_packet::{CipherCore, Mac, OpeningCipher, SealingCipher};
+use ssh_packet::{Mac, Packet, PACKET_MIN_SIZE};
+use zeroize::{Zeroize, Zeroizing};
 
 use crate::{
-    stream::algorithm::{self, Cipher, CipherState},
+    stream::algorithm::{self, Cipher, CipherAlgorithm, CipherState, CompressState},
     Error, Result,
 };
 
 use super::Keys;
 
+/// The minimum size of the padding, as per RFC4253 §6.
+const MIN_PAD_SIZE: usize = 4;
+
+/// The minimum alignment of the packets, as per RFC4253 §6.
+const MIN_ALIGN: usize = 8;
+
+/// The pair of [`Transport`]s resulting from a key-exchange.
 #[derive(Debug, Default)]
 pub struct TransportPair {
+    /// The transport for the packets we send.
     pub tx: Transport,
+
+    /// The transport for the packets we receive.
     pub rx: Transport,
 }
 
-#[derive(Debug, Default)]
-pub struct Transport {
-    pub compress: algorithm::Compress,
-    pub cipher: algorithm::Cipher,
-    pub hmac: algorithm::Hmac,
+/// Cumulative traffic statistics for one direction of the stream, across key-exchanges.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TransportStats {
+    /// The amount of packets.
+    pub packets: u64,
 
-    pub state: Option<CipherState>,
-    pub chain: Keys,
+    /// The amount of payload bytes, prior to compression.
+    pub raw_bytes: u64,
+
+    /// The amount of payload bytes, after compression.
+    pub compressed_bytes: u64,
+
+    /// The amount of bytes on the wire, including the framing and authentication codes.
+    pub wire_bytes: u64,
 }
 
-impl CipherCore for Transport {
-    type Err = Error;
-    type Mac = algorithm::Hmac;
+impl TransportStats {
+    /// The effective compression ratio, as compressed bytes over raw bytes.
+    pub fn ratio(&self) -> f64 {
+        if self.raw_bytes == 0 {
+            1.0
+        } else {
+            self.compressed_bytes as f64 / self.raw_bytes as f64
+        }
+    }
 
-    fn mac(&self) -> &Self::Mac {
-        &self.hmac
+    fn record(&mut self, raw: usize, compressed: usize) {
+        self.packets += 1;
+        self.raw_bytes += raw as u64;
+        self.compressed_bytes += compressed as u64;
     }
+}
+
+/// A snapshot of the [`TransportStats`] for both directions of the stream.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TransportStatsPair {
+    /// The statistics for the packets we send.
+    pub tx: TransportStats,
+
+    /// The statistics for the packets we receive.
+    pub rx: TransportStats,
+
+    /// The amount of completed key-exchanges, including the initial one.
+    pub exchanges: u64,
+
+    /// The instant of the last completed key-exchange, if any.
+    pub exchanged: Option<std::time::Instant>,
+}
+
+/// The names of the algorithms negociated during the last key-exchange.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct NegociatedAlgorithms {
+    /// The _key-exchange_ algorithm.
+    pub kex: String,
+
+    /// The _server key signature_ algorithm.
+    pub host_key: String,
+}
+
+/// How the padding of the sent packets is filled.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PaddingMode {
+    /// Random bytes, as recommended per the RFC.
+    #[default]
+    Random,
+
+    /// Zeroes, sparing the random generation for each packet, which is allowed
+    /// per the RFC as the packets are protected by the _hmac_ or the _AEAD_ cipher.
+    Zeroes,
+}
+
+/// The negociated algorithms and derived keys for one direction of the stream.
+#[derive(Debug)]
+pub struct Transport {
+    pub(crate) compress: algorithm::Compress,
+    pub(crate) cipher: Arc<dyn CipherAlgorithm>,
+    pub(crate) hmac: algorithm::Hmac,
+
+    pub(crate) state: Option<Box<dyn CipherState>>,
+    pub(crate) chain: Keys,
+
+    pub(crate) zlib: CompressState,
+    pub(crate) delayed: bool,
+
+    pub(crate) stats: TransportStats,
+
+    /// The buffer in which packets are assembled and decrypted, reused across packets
+    /// and wiped when dropped since it holds the plaintext of the last one.
+    pub(crate) buffer: Zeroizing<Vec<u8>>,
+
+    /// The capacity the buffer shrinks back to after a larger packet.
+    pub(crate) buffer_size: usize,
 
-    fn block_size(&self) -> usize {
-        self.cipher.block_size()
+    pub(crate) padding: PaddingMode,
+    pub(crate) rng: StdRng,
+}
+
+impl Default for Transport {
+    fn default() -> Self {
+        Self {
+            compress: Default::default(),
+            cipher: Arc::new(Cipher::None),
+            hmac: Default::default(),
+            state: None,
+            chain: Default::default(),
+            zlib: Default::default(),
+            delayed: false,
+            stats: Default::default(),
+            buffer: Default::default(),
+            buffer_size: usize::MAX,
+            padding: Default::default(),
+            rng: StdRng::from_entropy(),
+        }
     }
 }
 
-impl OpeningCipher for Transport {
-    fn decrypt<B: AsMut<[u8]>>(&mut self, mut buf: B) -> Result<(), Self::Err> {
-        if self.cipher != Cipher::None {
-            self.cipher.decrypt(
-                &mut self.state,
-                self.chain.key.expose_secret(),
-                self.chain.iv.expose_secret(),
-                buf.as_mut(),
-            )?;
+impl Transport {
+    /// Set the _zlib_ compression `level` for the packets sent with this transport.
+    pub(crate) fn with_compression_level(&mut self, level: u32) {
+        self.zlib.with_level(level);
+    }
+
+    /// Set how the padding of the packets sent with this transport is filled.
+    pub(crate) fn with_padding(&mut self, padding: PaddingMode) {
+        self.padding = padding;
+    }
+
+    /// Set the capacity the buffer of this transport shrinks back to after a larger packet.
+    pub(crate) fn with_buffer_size(&mut self, size: usize) {
+        self.buffer_size = size;
+    }
+
+    /// Start the delayed compression, if it has been negociated for this transport.
+    pub(crate) fn activate_compression(&mut self) {
+        self.delayed = false;
+    }
+
+    /// Whether this transport uses an _AEAD_ cipher, authenticating the packets in place of the _hmac_.
+    pub(crate) fn is_aead(&self) -> bool {
+        self.cipher.is_aead()
+    }
+
+    /// Whether the packet length is left out of the encrypted and aligned data,
+    /// which is the case with _encrypt-then-mac_ and _AEAD_ ciphers.
+    fn is_length_detached(&self) -> bool {
+        self.is_aead() || self.hmac.etm()
+    }
+
+    /// The alignment of the packets, the cipher's block size or 8 bytes at least.
+    fn align(&self) -> usize {
+        self.cipher.block_size().max(MIN_ALIGN)
+    }
+
+    /// Access the encrypting [`CipherState`], constructing it on the first packet after the key-exchange.
+    fn encryptor(&mut self) -> &mut dyn CipherState {
+        let (cipher, chain) = (&self.cipher, &self.chain);
+
+        self.state
+            .get_or_insert_with(|| {
+                cipher.encryptor(chain.key.expose_secret(), chain.iv.expose_secret())
+            })
+            .as_mut()
+    }
+
+    /// Access the decrypting [`CipherState`], constructing it on the first packet after the key-exchange.
+    fn decryptor(&mut self) -> &mut dyn CipherState {
+        let (cipher, chain) = (&self.cipher, &self.chain);
+
+        self.state
+            .get_or_insert_with(|| {
+                cipher.decryptor(chain.key.expose_secret(), chain.iv.expose_secret())
+            })
+            .as_mut()
+    }
+
+    /// The padding size for a `payload` of the provided size, from the rules of RFC4253 §6.
+    pub(crate) fn padding(&self, payload: usize) -> usize {
+        let align = self.align();
+        let size = if self.is_length_detached() {
+            std::mem::size_of::<u8>() + payload
+        } else {
+            std::mem::size_of::<u32>() + std::mem::size_of::<u8>() + payload
+        };
+
+        let mut padding = align - size % align;
+        if padding < MIN_PAD_SIZE {
+            padding += align;
+        }
+        if size + padding < PACKET_MIN_SIZE {
+            padding += align;
         }
 
-        Ok(())
+        padding
     }
 
-    fn open<B: AsRef<[u8]>>(&mut self, buf: B, mac: Vec<u8>, seq: u32) -> Result<(), Self::Err> {
-        if self.mac().size() > 0 {
-            self.hmac
-                .verify(seq, buf.as_ref(), self.chain.hmac.expose_secret(), &mac)?;
+    /// Extract the payload from the decrypted packet in `buf`, without it's length,
+    /// refusing any padding not following the rules of RFC4253 §6,
+    /// or leaving no payload to hold the message number.
+    fn unpad<'b>(&self, buf: &'b [u8]) -> Result<&'b [u8]> {
+        let (padding, data) = buf.split_first().ok_or(Error::Padding)?;
+        let padding = *padding as usize;
+
+        if padding < MIN_PAD_SIZE || padding >= data.len() {
+            return Err(Error::Padding);
         }
 
+        Ok(&data[..data.len() - padding])
+    }
+
+    /// Compress the payload in `buf`, appending it to the `out` buffer.
+    fn compress(&mut self, buf: &[u8], out: &mut Vec<u8>) -> Result<()> {
+        let len = out.len();
+        if self.delayed {
+            out.extend_from_slice(buf);
+        } else {
+            self.compress.compress(&mut self.zlib, buf, out)?;
+        }
+        self.stats.record(buf.len(), out.len() - len);
+
         Ok(())
     }
 
-    fn decompress(&mut self, buf: Vec<u8>) -> Result<Vec<u8>, Self::Err> {
-        self.compress.decompress(buf)
+    fn decompress(&mut self, buf: &[u8]) -> Result<Vec<u8>> {
+        let decompressed = if self.delayed {
+            buf.to_vec()
+        } else {
+            self.compress.decompress(&mut self.zlib, buf)?
+        };
+        self.stats.record(decompressed.len(), buf.len());
+
+        Ok(decompressed)
     }
-}
 
-impl SealingCipher for Transport {
-    fn compress<B: AsRef<[u8]>>(&mut self, buf: B) -> Result<Vec<u8>, Self::Err> {
-        self.compress.compress(buf.as_ref())
+    /// Read, decrypt and verify a [`Packet`] from the `reader`.
+    pub(crate) async fn read(
+        &mut self,
+        reader: &mut (impl AsyncRead + Unpin),
+        seq: u32,
+        max: usize,
+    ) -> Result<Packet> {
+        // The packet is read and decrypted in place, in the buffer reused across packets.
+        let mut buf = std::mem::take(&mut self.buffer);
+        let packet = self.read_into(reader, seq, max, &mut buf).await;
+        self.buffer = buf;
+        self.shrink();
+
+        packet
     }
 
-    fn pad(&mut self, mut buf: Vec<u8>, padding: u8) -> Result<Vec<u8>, Self::Err> {
-        let mut rng = rand::thread_rng();
+    /// Shrink the buffer back to the configured size after a larger packet,
+    /// wiping it beforehand since the reallocation would leave the plaintext in the freed memory.
+    fn shrink(&mut self) {
+        if self.buffer.capacity() > self.buffer_size {
+            self.buffer.zeroize();
+            self.buffer.shrink_to(self.buffer_size);
+        }
+    }
 
-        // prefix with the size
-        let mut padded = vec![padding];
-        padded.append(&mut buf);
+    async fn read_into(
+        &mut self,
+        reader: &mut (impl AsyncRead + Unpin),
+        seq: u32,
+        max: usize,
+        buf: &mut Vec<u8>,
+    ) -> Result<Packet> {
+        // Read the length, which is in the first encrypted block unless detached.
+        let head = if self.is_length_detached() {
+            std::mem::size_of::<u32>()
+        } else {
+            self.cipher.block_size()
+        };
+        buf.clear();
+        buf.resize(head, 0);
+        reader.read_exact(buf).await?;
 
-        // fill with random
-        padded.resize_with(padded.len() + padding as usize, || rng.gen());
+        let mut length = [0u8; 4];
+        if self.is_aead() {
+            length.copy_from_slice(&buf[..4]);
+            self.decryptor().decrypt_length(seq, &mut length)?;
+        } else {
+            if !self.hmac.etm() {
+                self.decryptor().decrypt(buf)?;
+            }
 
-        Ok(padded)
-    }
+            length.copy_from_slice(&buf[..4]);
+        }
+        let len = u32::from_be_bytes(length) as usize;
 
-    fn encrypt<B: AsMut<[u8]>>(&mut self, mut buf: B) -> Result<(), Self::Err> {
-        if self.cipher != Cipher::None {
-            self.cipher.encrypt(
-                &mut self.state,
-                self.chain.key.expose_secret(),
-                self.chain.iv.expose_secret(),
-                buf.as_mut(),
-            )?;
+        // Refuse the packet before allocating anything for it, the length is yet to be authenticated.
+        if len > max {
+            return Err(Error::PacketTooLarge(len));
         }
 
-        Ok(())
+        // The packet must be aligned to the block size, with at least the first block.
+        let aligned = if self.is_length_detached() {
+            len
+        } else {
+            length.len() + len
+        };
+        if aligned % self.align() != 0 || length.len() + len < head {
+            return Err(Error::Padding);
+        }
+
+        // Read the rest of the packet along with it's trailing authentication code.
+        let tag = if self.is_aead() {
+            self.cipher.tag_size()
+        } else {
+            self.hmac.size()
+        };
+        buf.resize(length.len() + len + tag, 0);
+        reader.read_exact(&mut buf[head..]).await?;
+
+        let (buf, mac) = buf.split_at_mut(length.len() + len);
+
+        if self.is_aead() {
+            self.decryptor().open(seq, buf, mac)?;
+        } else if self.hmac.etm() {
+            self.hmac
+                .verify(seq, buf, self.chain.hmac.expose_secret(), mac)?;
+            self.decryptor().decrypt(&mut buf[length.len()..])?;
+        } else {
+            self.decryptor().decrypt(&mut buf[head..])?;
+            self.hmac
+                .verify(seq, buf, self.chain.hmac.expose_secret(), mac)?;
+        }
+
+        self.stats.wire_bytes += (buf.len() + mac.len()) as u64;
+
+        let payload = self.unpad(&buf[length.len()..])?;
+
+        Ok(Packet {
+            payload: self.decompress(payload)?,
+        })
     }
 
-    fn seal<B: AsRef<[u8]>>(&mut self, buf: B, seq: u32) -> Result<Vec<u8>, Self::Err> {
-        Ok(self
-            .hmac
-            .sign(seq, buf.as_ref(), self.chain.hmac.expose_secret()))
+    /// Compress, pad, encrypt and authenticate the [`Packet`] to the `writer`.
+    pub(crate) async fn write(
+        &mut self,
+        writer: &mut (impl AsyncWrite + Unpin),
+        packet: &Packet,
+        seq: u32,
+    ) -> Result<()> {
+        // The packet is assembled and encrypted in place, in the buffer reused across packets.
+        let mut buf = std::mem::take(&mut self.buffer);
+        let written = self.write_into(writer, packet, seq, &mut buf).await;
+        self.buffer = buf;
+        self.shrink();
+
+        written
+    }
+
+    async fn write_into(
+        &mut self,
+        writer: &mut (impl AsyncWrite + Unpin),
+        packet: &Packet,
+        seq: u32,
+        buf: &mut Vec<u8>,
+    ) -> Result<()> {
+        // Leave room for the length and padding size, which are known after compression.
+        let head = std::mem::size_of::<u32>() + std::mem::size_of::<u8>();
+        buf.clear();
+        buf.resize(head, 0);
+        self.compress(&packet.payload, buf)?;
+
+        let padding = self.padding(buf.len() - head);
+        let start = buf.len();
+        buf.resize(start + padding, 0);
+        if self.padding == PaddingMode::Random {
+            self.rng.fill_bytes(&mut buf[start..]);
+        }
+
+        let len = (buf.len() - std::mem::size_of::<u32>()) as u32;
+        buf[..4].copy_from_slice(&len.to_be_bytes());
+        buf[4] = padding as u8;
+
+        let mac = if self.is_aead() {
+            self.encryptor().seal(seq, buf)?
+        } else if self.hmac.etm() {
+            self.encryptor().encrypt(&mut buf[4..])?;
+
+            self.hmac.sign(seq, buf, self.chain.hmac.expose_secret())
+        } else {
+            let mac = self.hmac.sign(seq, buf, self.chain.hmac.expose_secret());
+
+            self.encryptor().encrypt(buf)?;
+
+            mac
+        };
+        buf.extend_from_slice(&mac);
+        self.stats.wire_bytes += buf.len() as u64;
+
+        writer.write_all(buf).await?;
+
+        Ok(())
     }
 }
@@ -25,7 +25,12 @@ pub async fn server() -> Result<(SocketAddr, impl futures::Future<Output = Resul
             )
             .unwrap()],
             ..Default::default()
-        };
+        }
+        .none_cipher()
+        .none_mac();
+        #[cfg(feature = "legacy-kex")]
+        let server = server.legacy_kexs();
+
         let mut session = Session::new(stream, server).await?;
 
         // Trigger rekeying, since the threshold set is 1K.
@@ -49,6 +54,7 @@ pub async fn server() -> Result<(SocketAddr, impl futures::Future<Output = Resul
 
         if session.recv().await?.to::<Request>().is_ok() {
             session.send(&userauth::Success).await?;
+            session.activate_compression();
         }
 
         if let Ok(open) = session.recv().await?.to::<ChannelOpen>() {
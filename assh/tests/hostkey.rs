@@ -0,0 +1,731 @@
+#![allow(clippy::unwrap_used)]
+
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use async_std::net::{TcpListener, TcpStream};
+use futures::io::BufReader;
+
+use assh::{
+    algorithm::{HostKey, HostKeySigner, Key, SignFuture},
+    side::{
+        client::{Client, HostKeyPolicy, KnownHosts, PinnedFingerprints},
+        server::Server,
+    },
+    Error, Result, Session,
+};
+use ssh_key::{
+    certificate::{Builder, CertType},
+    Certificate, EcdsaCurve, PrivateKey, PublicKey,
+};
+use ssh_packet::{trans::DisconnectReason, Id};
+
+fn server(keys: Vec<PrivateKey>) -> Server {
+    Server {
+        keys,
+        ..Default::default()
+    }
+}
+
+fn client(keys: Vec<Key>) -> Client {
+    let mut client = Client::default();
+    client.algorithms.keys = keys;
+
+    client
+}
+
+fn ed25519() -> PrivateKey {
+    PrivateKey::random(&mut rand::thread_rng(), ssh_key::Algorithm::Ed25519).unwrap()
+}
+
+fn ecdsa() -> PrivateKey {
+    PrivateKey::random(
+        &mut rand::thread_rng(),
+        ssh_key::Algorithm::Ecdsa {
+            curve: EcdsaCurve::NistP256,
+        },
+    )
+    .unwrap()
+}
+
+/// Certify the `key` with the `ca`, valid for the `principal` within the `validity` window.
+fn certify(
+    key: &PrivateKey,
+    ca: &PrivateKey,
+    cert_type: CertType,
+    principal: &str,
+    validity: (Duration, Duration),
+) -> Certificate {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+
+    let mut builder = Builder::new_with_random_nonce(
+        &mut rand::thread_rng(),
+        key.public_key(),
+        (now - validity.0).as_secs(),
+        (now + validity.1).as_secs(),
+    )
+    .unwrap();
+    builder.cert_type(cert_type).unwrap();
+    builder.valid_principal(principal).unwrap();
+
+    builder.sign(ca).unwrap()
+}
+
+/// A _server_ presenting the `certificate` for it's `key`.
+fn certified(key: PrivateKey, certificate: Certificate) -> Server {
+    Server {
+        keys: vec![key],
+        certificates: vec![certificate],
+        ..Default::default()
+    }
+}
+
+const VALID: (Duration, Duration) = (Duration::from_secs(60), Duration::from_secs(3600));
+
+/// Perform the initial key-exchange between the `client` and the `server`.
+async fn handshake(client: Client, server: Server) -> Result<()> {
+    let socket = TcpListener::bind(("127.0.0.1", 0)).await?;
+    let addr = socket.local_addr()?;
+
+    futures::try_join!(
+        async {
+            Session::new(BufReader::new(TcpStream::connect(addr).await?), client)
+                .await?
+                .rekey()
+                .await
+        },
+        async {
+            Session::new(BufReader::new(socket.accept().await?.0), server)
+                .await?
+                .rekey()
+                .await
+        },
+    )?;
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn ecdsa_key_is_selected() -> Result<()> {
+    handshake(
+        client(vec![Key::Ecdsa {
+            curve: EcdsaCurve::NistP256,
+        }]),
+        server(vec![ed25519(), ecdsa()]),
+    )
+    .await
+}
+
+#[async_std::test]
+async fn ed25519_key_is_selected() -> Result<()> {
+    handshake(client(vec![Key::Ed25519]), server(vec![ecdsa(), ed25519()])).await
+}
+
+#[async_std::test]
+async fn missing_key_is_rejected() {
+    let result = handshake(
+        client(vec![Key::Ecdsa {
+            curve: EcdsaCurve::NistP256,
+        }]),
+        server(vec![ed25519()]),
+    )
+    .await;
+
+    assert!(matches!(result, Err(Error::Disconnected(_))));
+}
+
+#[async_std::test]
+async fn rejected_host_key_aborts_the_exchange() {
+    let result = handshake(
+        client(vec![Key::Ed25519]).host_key_verifier(|_, _| async { false }),
+        server(vec![ed25519()]),
+    )
+    .await;
+
+    assert!(matches!(
+        result,
+        Err(Error::Disconnected(assh::error::DisconnectedError {
+            reason: DisconnectReason::HostKeyNotVerifiable,
+            ..
+        }))
+    ));
+}
+
+#[async_std::test]
+async fn host_key_is_verified_on_each_exchange() -> Result<()> {
+    let key = ed25519();
+    let expected = key.public_key().clone();
+    let verified = Arc::new(AtomicUsize::new(0));
+
+    let counter = verified.clone();
+    let client = client(vec![Key::Ed25519]).host_key_verifier(move |key: PublicKey, id: Id| {
+        counter.fetch_add(1, Ordering::SeqCst);
+        let trusted = key == expected && id.to_string().contains("assh@server");
+
+        async move { trusted }
+    });
+
+    let socket = TcpListener::bind(("127.0.0.1", 0)).await?;
+    let addr = socket.local_addr()?;
+
+    // The initial exchange, followed by a re-key.
+    futures::try_join!(
+        async {
+            let mut session =
+                Session::new(BufReader::new(TcpStream::connect(addr).await?), client).await?;
+            session.rekey().await?;
+            session.rekey().await
+        },
+        async {
+            let mut session =
+                Session::new(BufReader::new(socket.accept().await?.0), server(vec![key])).await?;
+            session.rekey().await?;
+            session.rekey().await
+        },
+    )?;
+
+    assert_eq!(verified.load(Ordering::SeqCst), 2);
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn known_hosts_verifier_trusts_known_keys() -> Result<()> {
+    let key = ed25519();
+    let known = KnownHosts::parse(&format!(
+        "server.example.com {}",
+        key.public_key().to_openssh()?
+    ));
+
+    handshake(
+        client(vec![Key::Ed25519])
+            .host_key_verifier(known.clone().verifier("server.example.com", 22)),
+        server(vec![key]),
+    )
+    .await?;
+
+    let result = handshake(
+        client(vec![Key::Ed25519]).host_key_verifier(known.verifier("other.example.com", 22)),
+        server(vec![ed25519()]),
+    )
+    .await;
+    assert!(matches!(result, Err(Error::Disconnected(_))));
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn rejected_fingerprint_is_reported() -> Result<()> {
+    let key = ed25519();
+    let fingerprint = key.public_key().fingerprint(Default::default()).to_string();
+
+    handshake(
+        client(vec![Key::Ed25519])
+            .host_key_verifier(PinnedFingerprints::new([fingerprint.as_str()])?),
+        server(vec![key.clone()]),
+    )
+    .await?;
+
+    let result = handshake(
+        client(vec![Key::Ed25519]).host_key_verifier(PinnedFingerprints::new([
+            "SHA256:47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU",
+        ])?),
+        server(vec![key]),
+    )
+    .await;
+    assert!(
+        matches!(result, Err(Error::Disconnected(ref err)) if err.description.contains(&fingerprint))
+    );
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn trusted_certificate_is_accepted() -> Result<()> {
+    let (key, ca) = (ed25519(), ed25519());
+    let certificate = certify(&key, &ca, CertType::Host, "*.example.com", VALID);
+
+    // The verifier is not consulted for a valid certificate.
+    handshake(
+        client(vec![Key::Ed25519])
+            .certificate_authority(ca.public_key().clone())
+            .hostname("server.example.com")
+            .host_key_verifier(|_, _| async { false }),
+        certified(key, certificate),
+    )
+    .await
+}
+
+#[async_std::test]
+async fn plain_key_is_used_without_certificate_authorities() -> Result<()> {
+    let (key, ca) = (ed25519(), ed25519());
+    let certificate = certify(&key, &ca, CertType::Host, "server.example.com", VALID);
+
+    handshake(
+        client(vec![Key::Ed25519]).host_key_verifier(|_, _| async { true }),
+        certified(key, certificate),
+    )
+    .await
+}
+
+#[async_std::test]
+async fn invalid_certificates_are_rejected() {
+    let ca = ed25519();
+    let now = Duration::from_secs(0);
+    let cases = [
+        (
+            ca.clone(),
+            CertType::Host,
+            "server.example.com",
+            (Duration::from_secs(3600), now),
+            Error::CertificateExpired,
+        ),
+        (
+            ca.clone(),
+            CertType::Host,
+            "other.example.com",
+            VALID,
+            Error::CertificatePrincipal,
+        ),
+        (
+            ca.clone(),
+            CertType::User,
+            "server.example.com",
+            VALID,
+            Error::CertificateType,
+        ),
+        (
+            ed25519(),
+            CertType::Host,
+            "server.example.com",
+            VALID,
+            Error::CertificateUntrusted,
+        ),
+    ];
+
+    for (signer, cert_type, principal, validity, expected) in cases {
+        let key = ed25519();
+        let certificate = certify(&key, &signer, cert_type, principal, validity);
+
+        let result = handshake(
+            client(vec![Key::Ed25519])
+                .certificate_authority(ca.public_key().clone())
+                .hostname("server.example.com"),
+            certified(key, certificate),
+        )
+        .await;
+
+        assert!(
+            matches!(
+                result,
+                Err(Error::Disconnected(assh::error::DisconnectedError {
+                    reason: DisconnectReason::HostKeyNotVerifiable,
+                    ref description,
+                    ..
+                })) if *description == expected.to_string()
+            ),
+            "expected `{expected}`, got {result:?}"
+        );
+    }
+}
+
+#[async_std::test]
+async fn host_key_algorithms_are_preferred_in_order() -> Result<()> {
+    let socket = TcpListener::bind(("127.0.0.1", 0)).await?;
+    let addr = socket.local_addr()?;
+
+    let client = Client::default().host_key_algorithms(&["ecdsa-sha2-nistp256", "ssh-ed25519"]);
+
+    let (client, server) = futures::try_join!(
+        async {
+            let mut session =
+                Session::new(BufReader::new(TcpStream::connect(addr).await?), client).await?;
+            session.rekey().await?;
+
+            Ok::<_, Error>(session.algorithms().cloned())
+        },
+        async {
+            let mut session = Session::new(
+                BufReader::new(socket.accept().await?.0),
+                server(vec![ed25519(), ecdsa()]),
+            )
+            .await?;
+            session.rekey().await?;
+
+            Ok(session.algorithms().cloned())
+        },
+    )?;
+
+    assert_eq!(client.unwrap().host_key, "ecdsa-sha2-nistp256");
+    assert_eq!(server.unwrap().host_key, "ecdsa-sha2-nistp256");
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn disjoint_host_key_algorithms_are_reported() {
+    let result = handshake(
+        Client::default().host_key_algorithms(&["ssh-ed25519-cert-v01@openssh.com"]),
+        server(vec![ed25519()]),
+    )
+    .await;
+
+    assert!(
+        matches!(
+            result,
+            Err(Error::Disconnected(ref err))
+                if err.description.contains("`ssh-ed25519-cert-v01@openssh.com`")
+                    && err.description.contains("`ssh-ed25519`")
+        ),
+        "{result:?}"
+    );
+}
+
+#[async_std::test]
+async fn verified_host_key_is_exposed() -> Result<()> {
+    let (key, ca) = (ed25519(), ed25519());
+    let certificate = certify(&key, &ca, CertType::Host, "server.example.com", VALID);
+
+    let cases = [
+        (
+            client(vec![Key::Ed25519]),
+            HostKey::Key(key.public_key().clone()),
+        ),
+        (
+            client(vec![Key::Ed25519])
+                .certificate_authority(ca.public_key().clone())
+                .hostname("server.example.com"),
+            HostKey::Certificate(certificate.clone().into()),
+        ),
+    ];
+
+    for (client, expected) in cases {
+        let socket = TcpListener::bind(("127.0.0.1", 0)).await?;
+        let addr = socket.local_addr()?;
+        let server = certified(key.clone(), certificate.clone());
+
+        let (client, server) = futures::try_join!(
+            async {
+                let mut session =
+                    Session::new(BufReader::new(TcpStream::connect(addr).await?), client).await?;
+                session.rekey().await?;
+
+                Ok::<_, Error>((
+                    session.server_host_key().cloned(),
+                    session.algorithms().cloned(),
+                ))
+            },
+            async {
+                let mut session =
+                    Session::new(BufReader::new(socket.accept().await?.0), server).await?;
+                session.rekey().await?;
+
+                Ok(session.server_host_key().cloned())
+            },
+        )?;
+
+        let (host_key, algorithms) = client;
+        assert_eq!(host_key.unwrap().to_bytes()?, expected.to_bytes()?);
+        assert_eq!(server.unwrap().to_bytes()?, expected.to_bytes()?);
+        assert_eq!(
+            algorithms
+                .unwrap()
+                .host_key
+                .ends_with("-cert-v01@openssh.com"),
+            matches!(expected, HostKey::Certificate(_))
+        );
+    }
+
+    Ok(())
+}
+
+/// A signer only exposing the public part of it's key, as a remote KMS would.
+#[derive(Debug)]
+struct Remote {
+    key: PrivateKey,
+    signed: Arc<AtomicUsize>,
+}
+
+impl HostKeySigner for Remote {
+    fn public_key(&self) -> &PublicKey {
+        self.key.public_key()
+    }
+
+    fn algorithm(&self) -> Key {
+        HostKeySigner::algorithm(&self.key)
+    }
+
+    fn sign<'a>(&'a self, data: &'a [u8]) -> SignFuture<'a> {
+        Box::pin(async move {
+            async_std::task::yield_now().await;
+            self.signed.fetch_add(1, Ordering::SeqCst);
+
+            HostKeySigner::sign(&self.key, data).await
+        })
+    }
+}
+
+#[async_std::test]
+async fn external_signer_signs_the_exchange() -> Result<()> {
+    let key = ed25519();
+    let expected = key.public_key().clone();
+    let signed = Arc::new(AtomicUsize::new(0));
+
+    handshake(
+        client(vec![Key::Ed25519]).host_key_verifier(move |key: PublicKey, _| {
+            let trusted = key == expected;
+
+            async move { trusted }
+        }),
+        server(vec![ecdsa()]).host_key_signer(Remote {
+            key,
+            signed: signed.clone(),
+        }),
+    )
+    .await?;
+
+    assert_eq!(signed.load(Ordering::SeqCst), 1);
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn dsa_and_sha1_host_keys_are_rejected() -> Result<()> {
+    let dsa = PrivateKey::random(&mut rand::thread_rng(), ssh_key::Algorithm::Dsa).unwrap();
+
+    handshake(client(vec![Key::Dsa]), server(vec![dsa.clone()])).await?;
+
+    for policy in [
+        HostKeyPolicy {
+            allow_dsa: false,
+            ..Default::default()
+        },
+        HostKeyPolicy {
+            allow_sha1_signatures: false,
+            ..Default::default()
+        },
+    ] {
+        let result = handshake(
+            client(vec![Key::Dsa]).host_key_policy(policy),
+            server(vec![dsa.clone()]),
+        )
+        .await;
+
+        let expected = Error::WeakHostKey {
+            algorithm: "ssh-dss".into(),
+            bits: 1024,
+        };
+        assert!(
+            matches!(result, Err(Error::Disconnected(ref err)) if err.description == expected.to_string())
+        );
+    }
+
+    Ok(())
+}
+
+/// A `ssh-agent` listening in a scratch directory, killed when dropped.
+#[cfg(unix)]
+struct SshAgent {
+    dir: std::path::PathBuf,
+    process: std::process::Child,
+}
+
+#[cfg(unix)]
+impl SshAgent {
+    /// Spawn an agent holding freshly generated keys, from each of the `ssh-keygen` arguments.
+    fn spawn(keys: &[&[&str]]) -> Self {
+        use std::process::{Command, Stdio};
+
+        let dir = std::env::temp_dir().join(format!("assh-agent-{:016x}", rand::random::<u64>()));
+        std::fs::create_dir(&dir).unwrap();
+
+        let process = Command::new("ssh-agent")
+            .arg("-D")
+            .arg("-a")
+            .arg(dir.join("socket"))
+            .stdout(Stdio::null())
+            .spawn()
+            .unwrap();
+        let agent = Self { dir, process };
+
+        while !agent.socket().exists() {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        for (index, args) in keys.iter().enumerate() {
+            let path = agent.dir.join(format!("key-{index}"));
+
+            assert!(Command::new("ssh-keygen")
+                .args(["-q", "-N", ""])
+                .args(*args)
+                .arg("-f")
+                .arg(&path)
+                .status()
+                .unwrap()
+                .success());
+            assert!(Command::new("ssh-add")
+                .arg(&path)
+                .env("SSH_AUTH_SOCK", agent.socket())
+                .stderr(Stdio::null())
+                .status()
+                .unwrap()
+                .success());
+        }
+
+        agent
+    }
+
+    fn socket(&self) -> std::path::PathBuf {
+        self.dir.join("socket")
+    }
+
+    fn kill(&mut self) {
+        self.process.kill().unwrap();
+        self.process.wait().unwrap();
+    }
+}
+
+#[cfg(unix)]
+impl Drop for SshAgent {
+    fn drop(&mut self) {
+        self.process.kill().ok();
+        self.process.wait().ok();
+
+        std::fs::remove_dir_all(&self.dir).ok();
+    }
+}
+
+#[cfg(unix)]
+#[async_std::test]
+async fn agent_keys_sign_the_exchange() -> Result<()> {
+    use assh::side::server::Agent;
+    use ssh_key::HashAlg;
+
+    let agent = SshAgent::spawn(&[&["-t", "ed25519"], &["-t", "rsa"]]);
+    let keys = Agent::new(agent.socket()).identities().await?;
+    assert_eq!(keys.len(), 2);
+
+    // The connection to the agent is kept, and not dialed again for each signature.
+    std::fs::remove_file(agent.socket())?;
+
+    for (algorithm, hash) in [
+        (Key::Ed25519, HashAlg::Sha512),
+        (
+            Key::Rsa {
+                hash: Some(HashAlg::Sha512),
+            },
+            HashAlg::Sha512,
+        ),
+        (
+            Key::Rsa {
+                hash: Some(HashAlg::Sha256),
+            },
+            HashAlg::Sha256,
+        ),
+    ] {
+        let server = keys.iter().fold(Server::default(), |server, key| {
+            server.host_key_signer(key.clone().rsa_hash(hash))
+        });
+
+        handshake(client(vec![algorithm]), server).await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[async_std::test]
+async fn unreachable_agent_is_reported() -> Result<()> {
+    use assh::side::server::Agent;
+
+    let mut agent = SshAgent::spawn(&[&["-t", "ed25519"]]);
+
+    assert!(matches!(
+        Agent::new(agent.dir.join("missing")).identities().await,
+        Err(Error::Agent { .. })
+    ));
+
+    let keys = Agent::new(agent.socket()).identities().await?;
+    agent.kill();
+
+    let socket = TcpListener::bind(("127.0.0.1", 0)).await?;
+    let addr = socket.local_addr()?;
+
+    let (_, server) = futures::join!(
+        async {
+            Session::new(
+                BufReader::new(TcpStream::connect(addr).await?),
+                client(vec![Key::Ed25519]),
+            )
+            .await?
+            .rekey()
+            .await
+        },
+        async {
+            Session::new(
+                BufReader::new(socket.accept().await?.0),
+                Server::default().host_key_signer(keys[0].clone()),
+            )
+            .await?
+            .rekey()
+            .await
+        },
+    );
+
+    // The failure is reported to the peer in the disconnection message.
+    assert!(matches!(
+        server,
+        Err(Error::Disconnected(ref err)) if err.description.contains("ssh-agent")
+    ));
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[async_std::test]
+async fn weak_host_keys_are_rejected() -> Result<()> {
+    use assh::side::server::Agent;
+
+    let rsa = Key::Rsa {
+        hash: Some(ssh_key::HashAlg::Sha512),
+    };
+    let policy = HostKeyPolicy {
+        min_rsa_bits: 3072,
+        ..Default::default()
+    };
+
+    let agent = SshAgent::spawn(&[&["-t", "rsa", "-b", "2048"]]);
+    let weak = Agent::new(agent.socket()).identities().await?.remove(0);
+    let agent = SshAgent::spawn(&[&["-t", "rsa", "-b", "3072"]]);
+    let strong = Agent::new(agent.socket()).identities().await?.remove(0);
+
+    handshake(
+        client(vec![rsa.clone()]),
+        Server::default().host_key_signer(weak.clone()),
+    )
+    .await?;
+
+    let result = handshake(
+        client(vec![rsa.clone()]).host_key_policy(policy.clone()),
+        Server::default().host_key_signer(weak),
+    )
+    .await;
+    let expected = Error::WeakHostKey {
+        algorithm: "rsa-sha2-512".into(),
+        bits: 2048,
+    };
+    assert!(
+        matches!(result, Err(Error::Disconnected(ref err)) if err.description == expected.to_string())
+    );
+
+    handshake(
+        client(vec![rsa]).host_key_policy(policy),
+        Server::default().host_key_signer(strong),
+    )
+    .await
+}
@@ -0,0 +1,824 @@
+#![allow(clippy::unwrap_used)]
+#![cfg_attr(not(all(feature = "aes-ctr", feature = "zlib")), allow(unused_imports))]
+
+use std::time::Duration;
+
+use async_std::{
+    net::{TcpListener, TcpStream},
+    stream::StreamExt,
+};
+use futures::{io::BufReader, AsyncReadExt, AsyncWriteExt};
+
+use assh::{
+    algorithm::{
+        kex::{KexFuture, KexMeta, KexStream},
+        Cipher, CipherAlgorithm, CipherState, Compress, HostKey, HostKeySigner, Kex, KexAlgorithm,
+    },
+    side::{client::Client, server::Server},
+    Error, Result, Session,
+};
+use ssh_packet::{
+    arch::{ascii, NameList},
+    trans::{
+        Disconnect, DisconnectReason, Ignore, KexEcdhInit, KexEcdhReply, KexInit, NewKeys,
+        ServiceRequest,
+    },
+    Id, IntoPacket, Packet,
+};
+
+/// Write an unencrypted _packet_ to the `stream`, as done prior to the first key-exchange.
+async fn send(stream: &mut TcpStream, message: impl IntoPacket) {
+    let payload = message.into_packet().payload;
+
+    let mut padding = 8 - (payload.len() + 5) % 8;
+    if padding < 4 {
+        padding += 8;
+    }
+
+    let mut buffer = Vec::new();
+    buffer.extend_from_slice(&((1 + payload.len() + padding) as u32).to_be_bytes());
+    buffer.push(padding as u8);
+    buffer.extend_from_slice(&payload);
+    buffer.resize(buffer.len() + padding, 0);
+
+    stream.write_all(&buffer).await.unwrap();
+}
+
+/// Read an unencrypted _packet_ from the `stream`, as done prior to the first key-exchange.
+async fn recv(stream: &mut BufReader<TcpStream>) -> Packet {
+    let mut length = [0u8; 4];
+    stream.read_exact(&mut length).await.unwrap();
+
+    let mut buffer = vec![0u8; u32::from_be_bytes(length) as usize];
+    stream.read_exact(&mut buffer).await.unwrap();
+
+    let padding = buffer[0] as usize;
+
+    Packet {
+        payload: buffer[1..buffer.len() - padding].to_vec(),
+    }
+}
+
+fn kexinit(kexs: &[&str]) -> KexInit<'static> {
+    KexInit {
+        cookie: Default::default(),
+        kex_algorithms: NameList::from_iter(kexs),
+        server_host_key_algorithms: NameList::from_iter(["ssh-ed25519"]),
+        encryption_algorithms_client_to_server: NameList::from_iter([
+            "chacha20-poly1305@openssh.com",
+            "aes128-ctr",
+        ]),
+        encryption_algorithms_server_to_client: NameList::from_iter([
+            "chacha20-poly1305@openssh.com",
+            "aes128-ctr",
+        ]),
+        mac_algorithms_client_to_server: NameList::from_iter(["hmac-sha2-256"]),
+        mac_algorithms_server_to_client: NameList::from_iter(["hmac-sha2-256"]),
+        compression_algorithms_client_to_server: NameList::from_iter(["none"]),
+        compression_algorithms_server_to_client: NameList::from_iter(["none"]),
+        languages_client_to_server: Default::default(),
+        languages_server_to_client: Default::default(),
+        first_kex_packet_follows: false.into(),
+    }
+}
+
+fn server() -> Server {
+    Server {
+        keys: vec![ssh_key::PrivateKey::random(
+            &mut rand::thread_rng(),
+            ssh_key::Algorithm::Ed25519,
+        )
+        .unwrap()],
+        ..Default::default()
+    }
+}
+
+/// Spawn a session for the `server` and connect a raw peer to it.
+async fn connect(
+    server: Server,
+) -> Result<(
+    TcpStream,
+    BufReader<TcpStream>,
+    KexInit<'static>,
+    impl futures::Future<Output = Result<()>>,
+)> {
+    let socket = TcpListener::bind(("127.0.0.1", 0)).await?;
+    let addr = socket.local_addr()?;
+
+    let handle = async_std::task::spawn(async move {
+        let stream = BufReader::new(socket.incoming().next().await.unwrap()?);
+        let mut session = Session::new(stream, server).await?;
+
+        session.recv().await.map(drop)
+    });
+
+    let mut stream = TcpStream::connect(addr).await?;
+    Id::v2("mock", None::<&str>).to_writer(&mut stream).await?;
+
+    let mut reader = BufReader::new(stream.clone());
+    Id::from_reader(&mut reader).await?;
+
+    let kexinit = recv(&mut reader)
+        .await
+        .to::<KexInit>()
+        .expect("Expected the peer's `KexInit`");
+
+    Ok((stream, reader, kexinit, handle))
+}
+
+#[async_std::test]
+async fn strict_disconnects_on_interleaved_messages() -> Result<()> {
+    let (mut stream, mut reader, _, handle) = connect(server()).await?;
+
+    send(
+        &mut stream,
+        &Ignore {
+            data: vec![0; 4].into(),
+        },
+    )
+    .await;
+    send(
+        &mut stream,
+        &kexinit(&["curve25519-sha256", "kex-strict-c-v00@openssh.com"]),
+    )
+    .await;
+
+    let disconnect = recv(&mut reader)
+        .await
+        .to::<Disconnect>()
+        .expect("Expected the peer to disconnect");
+
+    assert!(matches!(
+        disconnect.reason,
+        DisconnectReason::KeyExchangeFailed
+    ));
+    assert!(matches!(handle.await, Err(Error::Disconnected(_))));
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn strict_disconnects_on_messages_interleaved_mid_kex() -> Result<()> {
+    let (mut stream, mut reader, _, handle) = connect(server()).await?;
+
+    send(
+        &mut stream,
+        &kexinit(&["curve25519-sha256", "kex-strict-c-v00@openssh.com"]),
+    )
+    .await;
+    send(
+        &mut stream,
+        &KexEcdhInit {
+            q_c: vec![9; 32].into(),
+        },
+    )
+    .await;
+
+    assert!(recv(&mut reader).await.to::<KexEcdhReply>().is_ok());
+    assert!(recv(&mut reader).await.to::<NewKeys>().is_ok());
+
+    send(
+        &mut stream,
+        &Ignore {
+            data: vec![0; 4].into(),
+        },
+    )
+    .await;
+
+    let disconnect = recv(&mut reader)
+        .await
+        .to::<Disconnect>()
+        .expect("Expected the peer to disconnect");
+
+    assert!(matches!(
+        disconnect.reason,
+        DisconnectReason::KeyExchangeFailed
+    ));
+    assert!(matches!(
+        handle.await,
+        Err(Error::Disconnected(err)) if err.description == Error::UnexpectedMessage.to_string()
+    ));
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn lenient_tolerates_interleaved_messages() -> Result<()> {
+    let (mut stream, mut reader, _, _handle) = connect(server()).await?;
+
+    send(
+        &mut stream,
+        &Ignore {
+            data: vec![0; 4].into(),
+        },
+    )
+    .await;
+    send(&mut stream, &kexinit(&["curve25519-sha256"])).await;
+    send(
+        &mut stream,
+        &KexEcdhInit {
+            q_c: vec![9; 32].into(),
+        },
+    )
+    .await;
+
+    assert!(recv(&mut reader).await.to::<KexEcdhReply>().is_ok());
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn service_request_before_initial_kexinit_is_refused() -> Result<()> {
+    let (mut stream, mut reader, _, handle) = connect(server()).await?;
+
+    send(
+        &mut stream,
+        &ServiceRequest {
+            service_name: ascii!("ssh-userauth"),
+        },
+    )
+    .await;
+    send(&mut stream, &kexinit(&["curve25519-sha256"])).await;
+
+    let disconnect = recv(&mut reader)
+        .await
+        .to::<Disconnect>()
+        .expect("Expected the peer to disconnect");
+
+    assert!(matches!(
+        disconnect.reason,
+        DisconnectReason::KeyExchangeFailed
+    ));
+    assert!(matches!(handle.await, Err(Error::Disconnected(_))));
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn empty_payload_is_refused() -> Result<()> {
+    let (mut stream, mut reader, _, handle) = connect(server()).await?;
+
+    // A packet whose padding spans all of it, leaving no message number.
+    let mut wire = vec![0, 0, 0, 12, 11];
+    wire.resize(4 + 12, 0);
+    stream.write_all(&wire).await?;
+
+    let disconnect = recv(&mut reader)
+        .await
+        .to::<Disconnect>()
+        .expect("Expected the peer to disconnect");
+
+    assert!(matches!(disconnect.reason, DisconnectReason::ProtocolError));
+    assert!(matches!(handle.await, Err(Error::Disconnected(_))));
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn wrong_guess_is_discarded() -> Result<()> {
+    let mut server = server();
+    server.algorithms.kexs = vec![Kex::Curve25519Sha256];
+
+    let (mut stream, mut reader, _, _handle) = connect(server).await?;
+
+    send(
+        &mut stream,
+        &KexInit {
+            first_kex_packet_follows: true.into(),
+            ..kexinit(&["ecdh-sha2-nistp256", "curve25519-sha256"])
+        },
+    )
+    .await;
+
+    // A guessed `ecdh-sha2-nistp256` packet, which is invalid for `curve25519-sha256`.
+    send(
+        &mut stream,
+        &KexEcdhInit {
+            q_c: vec![4; 65].into(),
+        },
+    )
+    .await;
+    send(
+        &mut stream,
+        &KexEcdhInit {
+            q_c: vec![9; 32].into(),
+        },
+    )
+    .await;
+
+    assert!(recv(&mut reader).await.to::<KexEcdhReply>().is_ok());
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn wrong_guess_is_discarded_after_transport_messages() -> Result<()> {
+    let mut server = server();
+    server.algorithms.kexs = vec![Kex::Curve25519Sha256];
+
+    let (mut stream, mut reader, _, _handle) = connect(server).await?;
+
+    send(
+        &mut stream,
+        &KexInit {
+            first_kex_packet_follows: true.into(),
+            ..kexinit(&["ecdh-sha2-nistp256", "curve25519-sha256"])
+        },
+    )
+    .await;
+    send(
+        &mut stream,
+        &Ignore {
+            data: vec![0; 4].into(),
+        },
+    )
+    .await;
+
+    // A guessed `ecdh-sha2-nistp256` packet, which is invalid for `curve25519-sha256`.
+    send(
+        &mut stream,
+        &KexEcdhInit {
+            q_c: vec![4; 65].into(),
+        },
+    )
+    .await;
+    send(
+        &mut stream,
+        &KexEcdhInit {
+            q_c: vec![9; 32].into(),
+        },
+    )
+    .await;
+
+    assert!(recv(&mut reader).await.to::<KexEcdhReply>().is_ok());
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn stalled_kex_times_out() -> Result<()> {
+    let mut server = server();
+    server.kex_timeout = Duration::from_millis(200);
+
+    let (mut stream, mut reader, _, handle) = connect(server).await?;
+
+    // Stall the exchange by never sending the `KexEcdhInit`.
+    send(&mut stream, &kexinit(&["curve25519-sha256"])).await;
+
+    let disconnect = recv(&mut reader)
+        .await
+        .to::<Disconnect>()
+        .expect("Expected the peer to disconnect");
+
+    assert!(matches!(disconnect.reason, DisconnectReason::ProtocolError));
+    assert!(matches!(handle.await, Err(Error::Disconnected(_))));
+
+    Ok(())
+}
+
+#[cfg(feature = "ecdh-nistp")]
+#[async_std::test]
+async fn configured_kexs_are_advertised_in_order() -> Result<()> {
+    let server = server().kexs(&[Kex::Curve25519Sha256, Kex::EcdhSha2Nistp256]);
+
+    let (mut stream, mut reader, advertised, _handle) = connect(server).await?;
+
+    assert_eq!(
+        advertised
+            .kex_algorithms
+            .into_iter()
+            .map(|name| name.into_string())
+            .collect::<Vec<_>>(),
+        [
+            "curve25519-sha256",
+            "ecdh-sha2-nistp256",
+            "kex-strict-s-v00@openssh.com"
+        ]
+    );
+
+    // The client's preference prevails in the negociation.
+    send(
+        &mut stream,
+        &kexinit(&["ecdh-sha2-nistp256", "curve25519-sha256"]),
+    )
+    .await;
+    send(
+        &mut stream,
+        &KexEcdhInit {
+            q_c: p256::SecretKey::random(&mut rand::thread_rng())
+                .public_key()
+                .to_sec1_bytes()
+                .to_vec()
+                .into(),
+        },
+    )
+    .await;
+
+    assert!(recv(&mut reader).await.to::<KexEcdhReply>().is_ok());
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn empty_kexs_are_rejected() {
+    let stream = futures::io::Cursor::new(Vec::new());
+
+    assert!(matches!(
+        Session::new(stream, server().kexs(&[])).await,
+        Err(Error::Config(_))
+    ));
+}
+
+#[async_std::test]
+async fn pre_exchanged_ids_are_trusted() -> Result<()> {
+    let socket = TcpListener::bind(("127.0.0.1", 0)).await?;
+    let addr = socket.local_addr()?;
+
+    let client_id = Id::v2("exotic_proxy-1.0", Some("peeked first"));
+    let server_id: Id = "SSH-2.0-fabricated".parse()?;
+
+    // Both sides exchange the identification strings themselves, unlike the configured ones.
+    let (client, server) = futures::try_join!(
+        async {
+            let mut stream = BufReader::new(TcpStream::connect(addr).await?);
+            client_id.to_writer(&mut stream).await?;
+            stream.flush().await?;
+            let peer_id = Id::from_reader(&mut stream).await?;
+
+            let mut session =
+                Session::new_with_ids(stream, Client::default(), client_id.clone(), peer_id)?;
+            session.rekey().await?;
+
+            Ok::<_, Error>(session)
+        },
+        async {
+            let mut stream = BufReader::new(socket.accept().await?.0);
+            let peer_id = Id::from_reader(&mut stream).await?;
+            server_id.to_writer(&mut stream).await?;
+            stream.flush().await?;
+
+            let mut session = Session::new_with_ids(stream, server(), server_id.clone(), peer_id)?;
+            session.rekey().await?;
+
+            Ok::<_, Error>(session)
+        },
+    )?;
+
+    assert_eq!(client.session_id(), server.session_id());
+    assert_eq!(client.peer_id(), server.id());
+    assert_eq!(server.peer_id(), client.id());
+    assert_eq!(server.peer_id().to_string(), client_id.to_string());
+
+    Ok(())
+}
+
+#[cfg(all(feature = "aes-gcm", feature = "zlib"))]
+#[async_std::test]
+async fn configured_name_lists_are_advertised_in_order() -> Result<()> {
+    let server = server()
+        .ciphers(&[Cipher::Aes256Gcm, Cipher::ChaCha20Poly1305])
+        .macs(&[])
+        .compressions_server_to_client(&[Compress::Zlib, Compress::None]);
+
+    let (_stream, _reader, advertised, _handle) = connect(server).await?;
+
+    let names = |list: &NameList| {
+        list.into_iter()
+            .map(|name| name.into_string())
+            .collect::<Vec<_>>()
+    };
+    for ciphers in [
+        &advertised.encryption_algorithms_client_to_server,
+        &advertised.encryption_algorithms_server_to_client,
+    ] {
+        assert_eq!(
+            names(ciphers),
+            ["aes256-gcm@openssh.com", "chacha20-poly1305@openssh.com"]
+        );
+    }
+    assert!(names(&advertised.mac_algorithms_client_to_server).is_empty());
+    assert_eq!(
+        names(&advertised.compression_algorithms_server_to_client),
+        ["zlib", "none"]
+    );
+
+    Ok(())
+}
+
+#[cfg(feature = "aes-ctr")]
+#[async_std::test]
+async fn empty_name_lists_are_rejected() {
+    for server in [
+        server().ciphers(&[]),
+        server().ciphers(&[Cipher::Aes256Ctr]).macs(&[]),
+        server().compressions_client_to_server(&[]),
+    ] {
+        let stream = futures::io::Cursor::new(Vec::new());
+
+        assert!(matches!(
+            Session::new(stream, server).await,
+            Err(Error::Config(_))
+        ));
+    }
+}
+
+#[cfg(all(feature = "aes-ctr", feature = "aes-gcm"))]
+#[async_std::test]
+async fn negociation_errors_report_the_offers() -> Result<()> {
+    let socket = TcpListener::bind(("127.0.0.1", 0)).await?;
+    let addr = socket.local_addr()?;
+
+    let (client, _) = futures::join!(
+        async {
+            let mut session = Session::new(
+                BufReader::new(TcpStream::connect(addr).await?),
+                Client::default().ciphers(&[Cipher::Aes128Ctr]),
+            )
+            .await?;
+
+            session.rekey().await
+        },
+        async {
+            let mut session = Session::new(
+                BufReader::new(socket.accept().await?.0),
+                server().ciphers(&[Cipher::Aes256Gcm]),
+            )
+            .await?;
+
+            session.rekey().await
+        },
+    );
+
+    let err = client.unwrap_err().to_string();
+    assert!(
+        err.contains(
+            "the client offered `aes128-ctr` while the server offered `aes256-gcm@openssh.com`"
+        ),
+        "{err}"
+    );
+
+    Ok(())
+}
+
+#[cfg(feature = "curve448")]
+#[async_std::test]
+async fn malformed_curve448_point_is_rejected() -> Result<()> {
+    let server = server().kexs(&[Kex::Curve448Sha512]);
+
+    let (mut stream, mut reader, _, handle) = connect(server).await?;
+
+    send(&mut stream, &kexinit(&["curve448-sha512"])).await;
+    send(
+        &mut stream,
+        &KexEcdhInit {
+            q_c: vec![9; 32].into(),
+        },
+    )
+    .await;
+
+    let disconnect = recv(&mut reader)
+        .await
+        .to::<Disconnect>()
+        .expect("Expected the peer to disconnect");
+
+    assert!(matches!(
+        disconnect.reason,
+        DisconnectReason::KeyExchangeFailed
+    ));
+    assert!(matches!(handle.await, Err(Error::Disconnected(_))));
+
+    Ok(())
+}
+
+/// A custom algorithm, delegating the exchange to a built-in one.
+#[derive(Debug)]
+struct Vendor;
+
+impl KexAlgorithm for Vendor {
+    fn name(&self) -> &str {
+        "vendor-hybrid@example.com"
+    }
+
+    fn as_client<'a>(
+        &'a self,
+        stream: &'a mut KexStream<'_>,
+        client: KexMeta<'a>,
+        server: KexMeta<'a>,
+    ) -> KexFuture<'a> {
+        Kex::Curve25519Sha256.as_client(stream, client, server)
+    }
+
+    fn as_server<'a>(
+        &'a self,
+        stream: &'a mut KexStream<'_>,
+        client: KexMeta<'a>,
+        server: KexMeta<'a>,
+        key: &'a dyn HostKeySigner,
+        host_key: &'a HostKey,
+    ) -> KexFuture<'a> {
+        Kex::Curve25519Sha256.as_server(stream, client, server, key, host_key)
+    }
+}
+
+#[async_std::test]
+async fn custom_kex_algorithm_is_negociated() -> Result<()> {
+    let socket = TcpListener::bind(("127.0.0.1", 0)).await?;
+    let addr = socket.local_addr()?;
+
+    let (mut client, mut server) = futures::try_join!(
+        async {
+            let client = Client::default().kexs(&[]).kex_algorithm(Vendor);
+
+            Session::new(BufReader::new(TcpStream::connect(addr).await?), client).await
+        },
+        async {
+            let server = server().kexs(&[]).kex_algorithm(Vendor);
+
+            Session::new(BufReader::new(socket.accept().await?.0), server).await
+        },
+    )?;
+
+    let message = ServiceRequest {
+        service_name: ascii!("ssh-userauth"),
+    };
+    let ((), packet) = futures::try_join!(client.send(&message), server.recv())?;
+
+    assert!(client.session_id().is_some());
+    assert!(packet.to::<ServiceRequest>().is_ok());
+
+    Ok(())
+}
+
+/// A custom cipher, delegating the encryption to a built-in one.
+#[cfg(feature = "aes-ctr")]
+#[derive(Debug)]
+struct VendorCipher;
+
+#[cfg(feature = "aes-ctr")]
+impl CipherAlgorithm for VendorCipher {
+    fn name(&self) -> &str {
+        "vendor-ctr@example.com"
+    }
+
+    fn block_size(&self) -> usize {
+        Cipher::Aes256Ctr.block_size()
+    }
+
+    fn key_size(&self) -> usize {
+        Cipher::Aes256Ctr.key_size()
+    }
+
+    fn iv_size(&self) -> usize {
+        Cipher::Aes256Ctr.iv_size()
+    }
+
+    fn encryptor(&self, key: &[u8], iv: &[u8]) -> Box<dyn CipherState> {
+        Cipher::Aes256Ctr.encryptor(key, iv)
+    }
+
+    fn decryptor(&self, key: &[u8], iv: &[u8]) -> Box<dyn CipherState> {
+        Cipher::Aes256Ctr.decryptor(key, iv)
+    }
+}
+
+#[cfg(all(feature = "aes-ctr", feature = "hmac-sha2"))]
+#[async_std::test]
+async fn custom_cipher_algorithm_is_advertised_last() -> Result<()> {
+    let (_stream, _reader, advertised, _handle) =
+        connect(server().cipher_algorithm(VendorCipher)).await?;
+
+    for names in [
+        &advertised.encryption_algorithms_client_to_server,
+        &advertised.encryption_algorithms_server_to_client,
+    ] {
+        assert_eq!(
+            names.into_iter().next_back().as_deref(),
+            Some("vendor-ctr@example.com")
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(all(feature = "aes-ctr", feature = "hmac-sha2"))]
+#[async_std::test]
+async fn custom_cipher_algorithm_is_negociated() -> Result<()> {
+    let socket = TcpListener::bind(("127.0.0.1", 0)).await?;
+    let addr = socket.local_addr()?;
+
+    let (mut client, mut server) = futures::try_join!(
+        async {
+            let mut client = Client::default().cipher_algorithm(VendorCipher);
+            client.algorithms.ciphers.clear();
+
+            Session::new(BufReader::new(TcpStream::connect(addr).await?), client).await
+        },
+        async {
+            let mut server = server().cipher_algorithm(VendorCipher);
+            server.algorithms.ciphers.clear();
+
+            Session::new(BufReader::new(socket.accept().await?.0), server).await
+        },
+    )?;
+
+    let message = ServiceRequest {
+        service_name: ascii!("ssh-userauth"),
+    };
+    let ((), packet) = futures::try_join!(client.send(&message), server.recv())?;
+    assert!(packet.to::<ServiceRequest>().is_ok());
+
+    let ((), packet) = futures::try_join!(server.send(&message), client.recv())?;
+    assert!(packet.to::<ServiceRequest>().is_ok());
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn legacy_kexs_are_not_advertised_by_default() -> Result<()> {
+    let (_stream, _reader, advertised, _handle) = connect(server()).await?;
+
+    assert!(!advertised
+        .kex_algorithms
+        .into_iter()
+        .any(|name| name.starts_with("diffie-hellman-")));
+
+    Ok(())
+}
+
+#[cfg(feature = "hmac-sha2")]
+#[async_std::test]
+async fn sha1_macs_can_be_removed() -> Result<()> {
+    let (_stream, _reader, advertised, _handle) = connect(server().without_sha1_macs()).await?;
+
+    for macs in [
+        &advertised.mac_algorithms_client_to_server,
+        &advertised.mac_algorithms_server_to_client,
+    ] {
+        assert!(!macs.into_iter().any(|name| name.contains("sha1")));
+        assert!(macs.into_iter().any(|name| name.contains("sha2")));
+    }
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn none_is_not_advertised_by_default() -> Result<()> {
+    let (_stream, _reader, advertised, _handle) = connect(server()).await?;
+
+    for names in [
+        &advertised.encryption_algorithms_client_to_server,
+        &advertised.encryption_algorithms_server_to_client,
+        &advertised.mac_algorithms_client_to_server,
+        &advertised.mac_algorithms_server_to_client,
+    ] {
+        assert!(!names.into_iter().any(|name| name.as_ref() == "none"));
+    }
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn none_can_be_advertised() -> Result<()> {
+    let (_stream, _reader, advertised, _handle) =
+        connect(server().none_cipher().none_mac()).await?;
+
+    for names in [
+        &advertised.encryption_algorithms_client_to_server,
+        &advertised.encryption_algorithms_server_to_client,
+        &advertised.mac_algorithms_client_to_server,
+        &advertised.mac_algorithms_server_to_client,
+    ] {
+        assert_eq!(names.into_iter().next_back().as_deref(), Some("none"));
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "zlib")]
+#[async_std::test]
+async fn compressions_can_differ_per_direction() -> Result<()> {
+    let mut server = server();
+    server.algorithms.compressions_client_to_server = vec![Compress::None];
+    server.algorithms.compressions_server_to_client = vec![Compress::Zlib, Compress::None];
+
+    let (_stream, _reader, advertised, _handle) = connect(server).await?;
+
+    assert_eq!(
+        advertised
+            .compression_algorithms_client_to_server
+            .into_iter()
+            .map(|name| name.to_string())
+            .collect::<Vec<_>>(),
+        ["none"]
+    );
+    assert_eq!(
+        advertised
+            .compression_algorithms_server_to_client
+            .into_iter()
+            .map(|name| name.to_string())
+            .collect::<Vec<_>>(),
+        ["zlib", "none"]
+    );
+
+    Ok(())
+}
@@ -0,0 +1,266 @@
+#![allow(clippy::unwrap_used)]
+
+use std::path::{Path, PathBuf};
+
+use assh::side::client::{HostKeyVerifier, HostStatus, KnownHosts, TofuPolicy, TrustOnFirstUse};
+use ssh_key::PublicKey;
+use ssh_packet::Id;
+
+fn fixture(name: &str) -> KnownHosts {
+    KnownHosts::read_file(
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(name),
+    )
+    .unwrap()
+}
+
+fn key(openssh: &str) -> PublicKey {
+    PublicKey::from_openssh(openssh).unwrap()
+}
+
+const ED25519: &str =
+    "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIKGBCON+qDxtSr/HvfFwadbKAvxlf6jPUcIHjYyVeeIV";
+const ECDSA: &str = "ecdsa-sha2-nistp256 AAAAE2VjZHNhLXNoYTItbmlzdHAyNTYAAAAIbmlzdHAyNTYAAABBBLJ3s6XanFQC3LXogA3PcaCu1saWVPi7pVQNKkcLdtvflW01+OQISbK2tB4uOGj/RF2dqXrQOsr39H1DrEPrAAE=";
+const OTHER: &str =
+    "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIOPfPb2VcpgKLF793WtXdtEqoa3U/i+zRvvMOUwOs81m";
+const CA: &str = "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIGVVX/3JK2YCvAgWl5K3pUSb+Kk/Eqd+SNOq6inoGgeF";
+const REVOKED: &str =
+    "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIAqbYRs7IfeweFYGl3bBDpazS4nWoA1Z+6RqOWhxqXaO";
+
+#[test]
+fn entries_are_parsed() {
+    assert_eq!(fixture("known_hosts").entries().len(), 6);
+    assert_eq!(fixture("known_hosts.hashed").entries().len(), 2);
+}
+
+#[test]
+fn malformed_entries_are_skipped() {
+    let known = KnownHosts::parse(&format!(
+        "garbage\nhost.example.com ssh-ed25519 !!!\nhost.example.com {ED25519}\n"
+    ));
+
+    assert_eq!(known.entries().len(), 1);
+}
+
+#[test]
+fn plain_hosts_are_matched() {
+    let known = fixture("known_hosts");
+
+    assert_eq!(
+        known.check("plain.example.com", 22, &key(ED25519)),
+        HostStatus::Match
+    );
+    assert_eq!(
+        known.check("PLAIN.example.com", 22, &key(ED25519)),
+        HostStatus::Match
+    );
+    assert_eq!(
+        known.check("192.0.2.1", 22, &key(ED25519)),
+        HostStatus::Match
+    );
+    assert_eq!(
+        known.check("plain.example.com", 22, &key(OTHER)),
+        HostStatus::Mismatch
+    );
+    assert_eq!(
+        known.check("plain.example.com", 22, &key(ECDSA)),
+        HostStatus::Unknown
+    );
+    assert_eq!(
+        known.check("plain.example.com", 2222, &key(ED25519)),
+        HostStatus::Unknown
+    );
+}
+
+#[test]
+fn ports_are_matched() {
+    let known = fixture("known_hosts");
+
+    assert_eq!(
+        known.check("alt.example.com", 2222, &key(ECDSA)),
+        HostStatus::Match
+    );
+    assert_eq!(
+        known.check("alt.example.com", 22, &key(ECDSA)),
+        HostStatus::Unknown
+    );
+}
+
+#[test]
+fn wildcards_and_negations_are_matched() {
+    let known = fixture("known_hosts");
+
+    assert_eq!(
+        known.check("host.wild.example.com", 22, &key(ED25519)),
+        HostStatus::Match
+    );
+    assert_eq!(
+        known.check("host.wild.example.com", 22, &key(OTHER)),
+        HostStatus::Mismatch
+    );
+    assert_eq!(
+        known.check("bad.wild.example.com", 22, &key(ED25519)),
+        HostStatus::Unknown
+    );
+}
+
+#[test]
+fn markers_are_honored() {
+    let known = fixture("known_hosts");
+
+    assert_eq!(
+        known.check("revoked.example.com", 22, &key(REVOKED)),
+        HostStatus::Revoked
+    );
+    assert_eq!(
+        known.check("ca.example.com", 22, &key(CA)),
+        HostStatus::Unknown
+    );
+}
+
+#[test]
+fn hashed_hosts_are_matched() {
+    let known = fixture("known_hosts.hashed");
+
+    assert_eq!(
+        known.check("hashed.example.com", 22, &key(ED25519)),
+        HostStatus::Match
+    );
+    assert_eq!(
+        known.check("hashed.example.com", 2200, &key(ECDSA)),
+        HostStatus::Match
+    );
+    assert_eq!(
+        known.check("hashed.example.com", 22, &key(OTHER)),
+        HostStatus::Mismatch
+    );
+    assert_eq!(
+        known.check("other.example.com", 22, &key(ED25519)),
+        HostStatus::Unknown
+    );
+}
+
+/// A fresh path in the temporary directory, for a `known_hosts` file yet to be created.
+fn scratch(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("assh-{name}-{:016x}", rand::random::<u64>()))
+}
+
+fn id() -> Id {
+    Id::v2("test", None::<&str>)
+}
+
+#[async_std::test]
+async fn unknown_hosts_are_rejected_when_strict() {
+    let path = scratch("strict");
+    let tofu = TrustOnFirstUse::new(&path, "server.example.com", 22);
+
+    assert!(!tofu.verify(&key(ED25519), &id()).await);
+    assert!(!path.exists());
+}
+
+#[async_std::test]
+async fn new_hosts_are_persisted() {
+    let path = scratch("accept-new");
+    let tofu =
+        TrustOnFirstUse::new(&path, "server.example.com", 2222).policy(TofuPolicy::AcceptNew);
+
+    assert!(tofu.verify(&key(ED25519), &id()).await);
+    assert_eq!(
+        std::fs::read_to_string(&path).unwrap(),
+        format!("[server.example.com]:2222 {ED25519}\n")
+    );
+
+    // The persisted key is now known, and any other key hard-fails.
+    let strict = TrustOnFirstUse::new(&path, "server.example.com", 2222);
+    assert!(strict.verify(&key(ED25519), &id()).await);
+    assert!(!tofu.verify(&key(OTHER), &id()).await);
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[async_std::test]
+async fn concurrent_new_hosts_are_all_persisted() {
+    let path = scratch("concurrent");
+    let hosts = (0..8)
+        .map(|n| format!("server{n}.example.com"))
+        .collect::<Vec<_>>();
+
+    let verified = futures::future::join_all(hosts.iter().map(|host| {
+        let tofu = TrustOnFirstUse::new(&path, host, 22).policy(TofuPolicy::AcceptNew);
+
+        async move { tofu.verify(&key(ED25519), &id()).await }
+    }))
+    .await;
+    assert!(verified.into_iter().all(|verified| verified));
+
+    // None of the concurrent updates overwrote another.
+    let known = KnownHosts::read_file(&path).unwrap();
+    assert_eq!(known.entries().len(), hosts.len());
+    for host in &hosts {
+        assert_eq!(known.check(host, 22, &key(ED25519)), HostStatus::Match);
+    }
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[async_std::test]
+async fn new_hosts_are_persisted_hashed() {
+    let path = scratch("hashed");
+    std::fs::write(&path, format!("plain.example.com {ECDSA}")).unwrap();
+
+    let tofu = TrustOnFirstUse::new(&path, "server.example.com", 22)
+        .policy(TofuPolicy::AcceptNew)
+        .hashed();
+    assert!(tofu.verify(&key(ED25519), &id()).await);
+
+    let contents = std::fs::read_to_string(&path).unwrap();
+    assert!(contents.lines().nth(1).unwrap().starts_with("|1|"));
+
+    let known = KnownHosts::read_file(&path).unwrap();
+    assert_eq!(
+        known.check("server.example.com", 22, &key(ED25519)),
+        HostStatus::Match
+    );
+    assert_eq!(
+        known.check("plain.example.com", 22, &key(ECDSA)),
+        HostStatus::Match
+    );
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[async_std::test]
+async fn declined_hosts_are_not_persisted() {
+    let path = scratch("declined");
+    let tofu = TrustOnFirstUse::new(&path, "server.example.com", 22)
+        .policy(TofuPolicy::AcceptNew)
+        .confirm(|key: PublicKey, _| {
+            let fingerprint = key.fingerprint(Default::default()).to_string();
+
+            async move { fingerprint == "SHA256:+VdMSprho3utB0AIXEvpTs/GCiW8cUAGYrCdiEAKhDA" }
+        });
+
+    assert!(!tofu.verify(&key(OTHER), &id()).await);
+    assert!(!path.exists());
+
+    assert!(tofu.verify(&key(ED25519), &id()).await);
+    assert!(path.exists());
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[async_std::test]
+async fn mismatched_keys_are_rejected_whatever_the_policy() {
+    let path = scratch("any");
+    std::fs::write(&path, format!("server.example.com {ED25519}\n")).unwrap();
+
+    let tofu = TrustOnFirstUse::new(&path, "server.example.com", 22).policy(TofuPolicy::AcceptAny);
+    assert!(!tofu.verify(&key(OTHER), &id()).await);
+    assert!(tofu.verify(&key(ECDSA), &id()).await);
+
+    // Keys accepted this way are not persisted.
+    assert_eq!(KnownHosts::read_file(&path).unwrap().entries().len(), 1);
+
+    std::fs::remove_file(path).unwrap();
+}
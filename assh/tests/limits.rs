@@ -0,0 +1,60 @@
+#![allow(clippy::unwrap_used)]
+
+use std::time::Duration;
+
+use async_std::net::{TcpListener, TcpStream};
+use futures::{io::BufReader, AsyncReadExt, AsyncWriteExt};
+use futures_time::future::FutureExt;
+
+use assh::{error::DisconnectedError, side::server::Server, Error, Result, Session};
+use ssh_packet::{trans::DisconnectReason, Id};
+
+#[async_std::test]
+async fn oversized_packet_disconnects_the_peer() -> Result<()> {
+    let socket = TcpListener::bind(("127.0.0.1", 0)).await?;
+    let addr = socket.local_addr()?;
+
+    let peer = async_std::task::spawn(async move {
+        let mut stream = TcpStream::connect(addr).await?;
+
+        Id::v2("peer", None::<&str>).to_writer(&mut stream).await?;
+        stream.write_all(&0xFFFFFFF0u32.to_be_bytes()).await?;
+        stream.write_all(&[0; 4]).await?;
+        stream.flush().await?;
+
+        // Wait for the server to hang up on us.
+        let mut buf = Vec::new();
+        stream.read_to_end(&mut buf).await?;
+
+        Ok::<_, Error>(buf)
+    });
+
+    let server = Server {
+        keys: vec![ssh_key::PrivateKey::random(
+            &mut rand::thread_rng(),
+            ssh_key::Algorithm::Ed25519,
+        )
+        .unwrap()],
+        ..Default::default()
+    };
+    let mut session = Session::new(BufReader::new(socket.accept().await?.0), server).await?;
+
+    let err = session
+        .recv()
+        .timeout(futures_time::time::Duration::from(Duration::from_secs(5)))
+        .await?
+        .unwrap_err();
+
+    assert!(matches!(
+        err,
+        Error::Disconnected(DisconnectedError {
+            reason: DisconnectReason::ProtocolError,
+            ..
+        })
+    ));
+
+    drop(session);
+    peer.await?;
+
+    Ok(())
+}
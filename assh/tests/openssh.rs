@@ -9,20 +9,110 @@ use ssh_packet::connect::ChannelRequest;
 mod common;
 
 #[rstest]
-#[case("3des-cbc", "hmac-md5", "curve25519-sha256")]
-#[case("aes128-cbc", "hmac-sha1", "curve25519-sha256")]
-#[case("aes192-cbc", "hmac-sha2-256", "curve25519-sha256")]
-#[case("aes256-cbc", "hmac-sha2-512", "curve25519-sha256")]
-#[case("3des-cbc", "hmac-md5-etm@openssh.com", "curve25519-sha256")]
-#[case("aes128-cbc", "hmac-sha1-etm@openssh.com", "curve25519-sha256")]
-#[case("aes192-cbc", "hmac-sha2-256-etm@openssh.com", "curve25519-sha256")]
-#[case("aes256-cbc", "hmac-sha2-512-etm@openssh.com", "curve25519-sha256")]
-#[case("aes128-ctr", "hmac-sha1", "curve25519-sha256")]
-#[case("aes192-ctr", "hmac-sha2-256", "curve25519-sha256")]
-#[case("aes256-ctr", "hmac-sha2-512", "curve25519-sha256")]
-#[case("aes128-ctr", "hmac-sha1-etm@openssh.com", "curve25519-sha256")]
-#[case("aes192-ctr", "hmac-sha2-256-etm@openssh.com", "curve25519-sha256")]
-#[case("aes256-ctr", "hmac-sha2-512-etm@openssh.com", "curve25519-sha256")]
+#[cfg_attr(
+    all(feature = "tdes-cbc", feature = "hmac-md5", feature = "curve25519"),
+    case("3des-cbc", "hmac-md5", "curve25519-sha256")
+)]
+#[cfg_attr(
+    all(feature = "aes-cbc", feature = "hmac-sha1", feature = "curve25519"),
+    case("aes128-cbc", "hmac-sha1", "curve25519-sha256")
+)]
+#[cfg_attr(
+    all(feature = "aes-cbc", feature = "hmac-sha2", feature = "curve25519"),
+    case("aes192-cbc", "hmac-sha2-256", "curve25519-sha256")
+)]
+#[cfg_attr(
+    all(feature = "aes-cbc", feature = "hmac-sha2", feature = "curve25519"),
+    case("aes256-cbc", "hmac-sha2-512", "curve25519-sha256")
+)]
+#[cfg_attr(
+    all(feature = "tdes-cbc", feature = "hmac-md5", feature = "curve25519"),
+    case("3des-cbc", "hmac-md5-etm@openssh.com", "curve25519-sha256")
+)]
+#[cfg_attr(
+    all(feature = "aes-cbc", feature = "hmac-sha1", feature = "curve25519"),
+    case("aes128-cbc", "hmac-sha1-etm@openssh.com", "curve25519-sha256")
+)]
+#[cfg_attr(
+    all(feature = "aes-cbc", feature = "hmac-sha2", feature = "curve25519"),
+    case("aes192-cbc", "hmac-sha2-256-etm@openssh.com", "curve25519-sha256")
+)]
+#[cfg_attr(
+    all(feature = "aes-cbc", feature = "hmac-sha2", feature = "curve25519"),
+    case("aes256-cbc", "hmac-sha2-512-etm@openssh.com", "curve25519-sha256")
+)]
+#[cfg_attr(
+    all(feature = "aes-ctr", feature = "hmac-sha1", feature = "curve25519"),
+    case("aes128-ctr", "hmac-sha1", "curve25519-sha256")
+)]
+#[cfg_attr(
+    all(feature = "aes-ctr", feature = "hmac-sha2", feature = "curve25519"),
+    case("aes192-ctr", "hmac-sha2-256", "curve25519-sha256")
+)]
+#[cfg_attr(
+    all(feature = "aes-ctr", feature = "hmac-sha2", feature = "curve25519"),
+    case("aes256-ctr", "hmac-sha2-512", "curve25519-sha256")
+)]
+#[cfg_attr(
+    all(feature = "aes-ctr", feature = "hmac-sha1", feature = "curve25519"),
+    case("aes128-ctr", "hmac-sha1-etm@openssh.com", "curve25519-sha256")
+)]
+#[cfg_attr(
+    all(feature = "aes-ctr", feature = "hmac-sha2", feature = "curve25519"),
+    case("aes192-ctr", "hmac-sha2-256-etm@openssh.com", "curve25519-sha256")
+)]
+#[cfg_attr(
+    all(feature = "aes-ctr", feature = "hmac-sha2", feature = "curve25519"),
+    case("aes256-ctr", "hmac-sha2-512-etm@openssh.com", "curve25519-sha256")
+)]
+#[cfg_attr(
+    all(feature = "aes-ctr", feature = "hmac-sha1", feature = "curve25519"),
+    case("aes128-ctr", "hmac-sha1-96", "curve25519-sha256")
+)]
+#[cfg_attr(
+    all(feature = "aes-ctr", feature = "umac", feature = "curve25519"),
+    case("aes128-ctr", "umac-64@openssh.com", "curve25519-sha256")
+)]
+#[cfg_attr(
+    all(feature = "aes-ctr", feature = "umac", feature = "curve25519"),
+    case("aes256-ctr", "umac-128@openssh.com", "curve25519-sha256")
+)]
+#[cfg_attr(
+    all(feature = "aes-ctr", feature = "hmac-sha2", feature = "ecdh-nistp"),
+    case("aes128-ctr", "hmac-sha2-256", "ecdh-sha2-nistp256")
+)]
+#[cfg_attr(
+    all(feature = "aes-ctr", feature = "hmac-sha2", feature = "ecdh-nistp"),
+    case("aes192-ctr", "hmac-sha2-256", "ecdh-sha2-nistp384")
+)]
+#[cfg_attr(
+    all(feature = "aes-ctr", feature = "hmac-sha2", feature = "ecdh-nistp"),
+    case("aes256-ctr", "hmac-sha2-512", "ecdh-sha2-nistp521")
+)]
+#[cfg_attr(
+    all(feature = "aes-ctr", feature = "hmac-sha2", feature = "sntrup761"),
+    case("aes256-ctr", "hmac-sha2-512", "sntrup761x25519-sha512@openssh.com")
+)]
+#[cfg_attr(
+    all(feature = "chacha20-poly1305", feature = "curve25519"),
+    case("chacha20-poly1305@openssh.com", "hmac-sha2-256", "curve25519-sha256")
+)]
+#[cfg_attr(
+    all(feature = "aes-gcm", feature = "curve25519"),
+    case("aes128-gcm@openssh.com", "hmac-sha2-256", "curve25519-sha256")
+)]
+#[cfg_attr(
+    all(feature = "aes-gcm", feature = "curve25519"),
+    case("aes256-gcm@openssh.com", "hmac-sha2-256", "curve25519-sha256")
+)]
+#[cfg_attr(
+    all(feature = "aes-ctr", feature = "hmac-sha1", feature = "legacy-kex"),
+    case("aes128-ctr", "hmac-sha1", "diffie-hellman-group14-sha1")
+)]
+#[cfg_attr(
+    all(feature = "aes-ctr", feature = "hmac-sha1", feature = "legacy-kex"),
+    case("aes128-ctr", "hmac-sha1", "diffie-hellman-group1-sha1")
+)]
 async fn against_openssh_client(
     #[case] cipher: &str,
     #[case] mac: &str,
@@ -0,0 +1,228 @@
+#![allow(clippy::unwrap_used)]
+
+use async_std::net::{TcpListener, TcpStream};
+use futures::{channel::oneshot, io::BufReader, FutureExt};
+
+use assh::{
+    side::{client::Client, server::Server, Side},
+    Result, Session,
+};
+use ssh_packet::{
+    arch::ascii,
+    connect::ChannelData,
+    trans::{Ignore, ServiceRequest},
+};
+
+type Pair = (
+    Session<BufReader<TcpStream>, Client>,
+    Session<BufReader<TcpStream>, Server>,
+);
+
+/// Create a pair of connected sessions, and perform the initial key-exchange.
+async fn pair(client: Client, server: Server) -> Result<Pair> {
+    let socket = TcpListener::bind(("127.0.0.1", 0)).await?;
+    let addr = socket.local_addr()?;
+
+    let (mut client, mut server) = futures::try_join!(
+        async { Session::new(BufReader::new(TcpStream::connect(addr).await?), client).await },
+        async { Session::new(BufReader::new(socket.accept().await?.0), server).await },
+    )?;
+
+    exchange(&mut client, &mut server).await?;
+
+    Ok((client, server))
+}
+
+/// Send a message from the `client` to the `server`, and ensure it's received as-is.
+async fn exchange(
+    client: &mut Session<BufReader<TcpStream>, Client>,
+    server: &mut Session<BufReader<TcpStream>, Server>,
+) -> Result<()> {
+    let message = ServiceRequest {
+        service_name: ascii!("ssh-userauth"),
+    };
+    let ((), packet) = futures::try_join!(client.send(&message), server.recv())?;
+
+    assert!(packet.to::<ServiceRequest>().is_ok());
+
+    Ok(())
+}
+
+/// Send `count` messages from the `session` as fast as possible, then receive as many from the peer,
+/// ensuring they are in order, and keep answering the peer's re-keys until it signals being `done`.
+async fn flood<S: Side>(
+    session: &mut Session<BufReader<TcpStream>, S>,
+    count: u32,
+    (finished, done): (oneshot::Sender<()>, oneshot::Receiver<()>),
+) -> Result<()> {
+    for channel in 0..count {
+        let message = ChannelData {
+            recipient_channel: channel,
+            data: vec![0; 256].into(),
+        };
+        session.send(&message).await?;
+    }
+
+    for channel in 0..count {
+        let packet = session.recv().await?;
+
+        assert_eq!(
+            packet.to::<ChannelData>().unwrap().recipient_channel,
+            channel
+        );
+    }
+
+    finished.send(()).ok();
+
+    futures::select! {
+        _ = done.fuse() => Ok(()),
+        packet = session.recv().fuse() => panic!("Unexpected packet: {:?}", packet?),
+    }
+}
+
+fn server() -> Server {
+    Server {
+        keys: vec![ssh_key::PrivateKey::random(
+            &mut rand::thread_rng(),
+            ssh_key::Algorithm::Ed25519,
+        )
+        .unwrap()],
+        ..Default::default()
+    }
+}
+
+#[async_std::test]
+async fn manual_rekey_keeps_session_id() -> Result<()> {
+    let (mut client, mut server) = pair(Default::default(), server()).await?;
+    let session_id = client.session_id().unwrap().to_vec();
+
+    let message = ServiceRequest {
+        service_name: ascii!("ssh-userauth"),
+    };
+    let ((), packet) = futures::try_join!(
+        async {
+            client.rekey().await?;
+            client.send(&message).await
+        },
+        server.recv(),
+    )?;
+
+    assert!(packet.to::<ServiceRequest>().is_ok());
+
+    exchange(&mut client, &mut server).await?;
+
+    assert_eq!(client.session_id(), Some(session_id.as_slice()));
+    assert_eq!(server.session_id(), Some(session_id.as_slice()));
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn concurrent_rekeys() -> Result<()> {
+    let (mut client, mut server) = pair(Default::default(), server()).await?;
+    let session_id = client.session_id().unwrap().to_vec();
+
+    futures::try_join!(client.rekey(), server.rekey())?;
+
+    exchange(&mut client, &mut server).await?;
+
+    assert_eq!(client.session_id(), Some(session_id.as_slice()));
+    assert_eq!(server.session_id(), Some(session_id.as_slice()));
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn rekey_after_bytes_threshold() -> Result<()> {
+    let client = Client {
+        rekey_bytes: 1024,
+        ..Default::default()
+    };
+    let (mut client, mut server) = pair(client, server()).await?;
+    let session_id = client.session_id().unwrap().to_vec();
+
+    for _ in 0..16 {
+        let message = Ignore {
+            data: vec![0; 4096].into(),
+        };
+        let ((), packet) = futures::try_join!(
+            async {
+                client.send(&message).await?;
+                client
+                    .send(&ServiceRequest {
+                        service_name: ascii!("ssh-userauth"),
+                    })
+                    .await
+            },
+            server.recv(),
+        )?;
+
+        assert!(packet.to::<ServiceRequest>().is_ok());
+    }
+
+    assert_eq!(client.session_id(), Some(session_id.as_slice()));
+    assert_eq!(server.session_id(), Some(session_id.as_slice()));
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn writable_performs_the_due_rekey() -> Result<()> {
+    let client = Client {
+        rekey_bytes: 1024,
+        ..Default::default()
+    };
+    let (mut client, mut server) = pair(client, server()).await?;
+    let exchanges = client.stats().unwrap().exchanges;
+
+    let ((), packet) = futures::try_join!(
+        async {
+            client
+                .send(&Ignore {
+                    data: vec![0; 4096].into(),
+                })
+                .await?;
+
+            client.writable().await?;
+            assert_eq!(client.stats().unwrap().exchanges, exchanges + 1);
+
+            client
+                .send(&ServiceRequest {
+                    service_name: ascii!("ssh-userauth"),
+                })
+                .await
+        },
+        server.recv(),
+    )?;
+
+    assert!(packet.to::<ServiceRequest>().is_ok());
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn rekeys_while_both_sides_flood() -> Result<()> {
+    let client = Client {
+        rekey_bytes: 1024,
+        ..Default::default()
+    };
+    let server = Server {
+        rekey_bytes: 1024,
+        ..server()
+    };
+    let (mut client, mut server) = pair(client, server).await?;
+    let session_id = client.session_id().unwrap().to_vec();
+
+    let (client_finished, server_done) = oneshot::channel();
+    let (server_finished, client_done) = oneshot::channel();
+    futures::try_join!(
+        flood(&mut client, 64, (client_finished, client_done)),
+        flood(&mut server, 64, (server_finished, server_done)),
+    )?;
+
+    assert!(client.stats().unwrap().exchanges > 2);
+    assert_eq!(client.session_id(), Some(session_id.as_slice()));
+    assert_eq!(server.session_id(), Some(session_id.as_slice()));
+
+    Ok(())
+}
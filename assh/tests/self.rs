@@ -5,6 +5,7 @@ use futures::io::BufReader;
 use rstest::rstest;
 
 use assh::{
+    algorithm::{Cipher, Compress},
     side::client::{Algorithms, Client},
     Error, Result, Session,
 };
@@ -17,27 +18,136 @@ use ssh_packet::{
 mod common;
 
 #[rstest]
-#[case("3des-cbc", "hmac-md5", "curve25519-sha256")]
-#[case("aes128-cbc", "hmac-sha1", "curve25519-sha256")]
-#[case("aes192-cbc", "hmac-sha2-256", "curve25519-sha256")]
-#[case("aes256-cbc", "hmac-sha2-512", "curve25519-sha256")]
-#[case("3des-cbc", "hmac-md5-etm@openssh.com", "curve25519-sha256")]
-#[case("aes128-cbc", "hmac-sha1-etm@openssh.com", "curve25519-sha256")]
-#[case("aes192-cbc", "hmac-sha2-256-etm@openssh.com", "curve25519-sha256")]
-#[case("aes256-cbc", "hmac-sha2-512-etm@openssh.com", "curve25519-sha256")]
-#[case("aes128-ctr", "hmac-sha1", "curve25519-sha256")]
-#[case("aes192-ctr", "hmac-sha2-256", "curve25519-sha256")]
-#[case("aes256-ctr", "hmac-sha2-512", "curve25519-sha256")]
-#[case("aes128-ctr", "hmac-sha1-etm@openssh.com", "curve25519-sha256")]
-#[case("aes192-ctr", "hmac-sha2-256-etm@openssh.com", "curve25519-sha256")]
-#[case("aes256-ctr", "hmac-sha2-512-etm@openssh.com", "curve25519-sha256")]
+#[cfg_attr(
+    all(feature = "tdes-cbc", feature = "hmac-md5", feature = "curve25519"),
+    case("3des-cbc", "hmac-md5", "curve25519-sha256")
+)]
+#[cfg_attr(
+    all(feature = "aes-cbc", feature = "hmac-sha1", feature = "curve25519"),
+    case("aes128-cbc", "hmac-sha1", "curve25519-sha256")
+)]
+#[cfg_attr(
+    all(feature = "aes-cbc", feature = "hmac-sha2", feature = "curve25519"),
+    case("aes192-cbc", "hmac-sha2-256", "curve25519-sha256")
+)]
+#[cfg_attr(
+    all(feature = "aes-cbc", feature = "hmac-sha2", feature = "curve25519"),
+    case("aes256-cbc", "hmac-sha2-512", "curve25519-sha256")
+)]
+#[cfg_attr(
+    all(feature = "tdes-cbc", feature = "hmac-md5", feature = "curve25519"),
+    case("3des-cbc", "hmac-md5-etm@openssh.com", "curve25519-sha256")
+)]
+#[cfg_attr(
+    all(feature = "aes-cbc", feature = "hmac-sha1", feature = "curve25519"),
+    case("aes128-cbc", "hmac-sha1-etm@openssh.com", "curve25519-sha256")
+)]
+#[cfg_attr(
+    all(feature = "aes-cbc", feature = "hmac-sha2", feature = "curve25519"),
+    case("aes192-cbc", "hmac-sha2-256-etm@openssh.com", "curve25519-sha256")
+)]
+#[cfg_attr(
+    all(feature = "aes-cbc", feature = "hmac-sha2", feature = "curve25519"),
+    case("aes256-cbc", "hmac-sha2-512-etm@openssh.com", "curve25519-sha256")
+)]
+#[cfg_attr(
+    all(feature = "aes-ctr", feature = "hmac-sha1", feature = "curve25519"),
+    case("aes128-ctr", "hmac-sha1", "curve25519-sha256")
+)]
+#[cfg_attr(
+    all(feature = "aes-ctr", feature = "hmac-sha2", feature = "curve25519"),
+    case("aes192-ctr", "hmac-sha2-256", "curve25519-sha256")
+)]
+#[cfg_attr(
+    all(feature = "aes-ctr", feature = "hmac-sha2", feature = "curve25519"),
+    case("aes256-ctr", "hmac-sha2-512", "curve25519-sha256")
+)]
+#[cfg_attr(
+    all(feature = "aes-ctr", feature = "hmac-sha1", feature = "curve25519"),
+    case("aes128-ctr", "hmac-sha1-etm@openssh.com", "curve25519-sha256")
+)]
+#[cfg_attr(
+    all(feature = "aes-ctr", feature = "hmac-sha2", feature = "curve25519"),
+    case("aes192-ctr", "hmac-sha2-256-etm@openssh.com", "curve25519-sha256")
+)]
+#[cfg_attr(
+    all(feature = "aes-ctr", feature = "hmac-sha2", feature = "curve25519"),
+    case("aes256-ctr", "hmac-sha2-512-etm@openssh.com", "curve25519-sha256")
+)]
+#[cfg_attr(
+    all(feature = "aes-ctr", feature = "hmac-sha1", feature = "curve25519"),
+    case("aes128-ctr", "hmac-sha1-96", "curve25519-sha256")
+)]
+#[cfg_attr(
+    all(feature = "aes-ctr", feature = "umac", feature = "curve25519"),
+    case("aes128-ctr", "umac-64@openssh.com", "curve25519-sha256")
+)]
+#[cfg_attr(
+    all(feature = "aes-ctr", feature = "umac", feature = "curve25519"),
+    case("aes256-ctr", "umac-128@openssh.com", "curve25519-sha256")
+)]
+#[cfg_attr(
+    all(feature = "aes-ctr", feature = "hmac-sha2", feature = "curve448"),
+    case("aes256-ctr", "hmac-sha2-512", "curve448-sha512")
+)]
+#[cfg_attr(
+    all(feature = "aes-ctr", feature = "hmac-sha2", feature = "ecdh-nistp"),
+    case("aes128-ctr", "hmac-sha2-256", "ecdh-sha2-nistp256")
+)]
+#[cfg_attr(
+    all(feature = "aes-ctr", feature = "hmac-sha2", feature = "ecdh-nistp"),
+    case("aes192-ctr", "hmac-sha2-256", "ecdh-sha2-nistp384")
+)]
+#[cfg_attr(
+    all(feature = "aes-ctr", feature = "hmac-sha2", feature = "ecdh-nistp"),
+    case("aes256-ctr", "hmac-sha2-512", "ecdh-sha2-nistp521")
+)]
+#[cfg_attr(
+    all(feature = "aes-ctr", feature = "hmac-sha2", feature = "sntrup761"),
+    case("aes256-ctr", "hmac-sha2-512", "sntrup761x25519-sha512@openssh.com")
+)]
+#[cfg_attr(
+    all(
+        feature = "chacha20-poly1305",
+        feature = "hmac-sha2",
+        feature = "curve25519"
+    ),
+    case("chacha20-poly1305@openssh.com", "hmac-sha2-256", "curve25519-sha256")
+)]
+#[cfg_attr(
+    all(feature = "chacha20-poly1305", feature = "curve25519"),
+    case("chacha20-poly1305@openssh.com", "none", "curve25519-sha256")
+)]
+#[cfg_attr(
+    all(feature = "aes-gcm", feature = "hmac-sha2", feature = "curve25519"),
+    case("aes128-gcm@openssh.com", "hmac-sha2-256", "curve25519-sha256")
+)]
+#[cfg_attr(
+    all(feature = "aes-gcm", feature = "hmac-sha2", feature = "curve25519"),
+    case("aes256-gcm@openssh.com", "hmac-sha2-256", "curve25519-sha256")
+)]
+#[cfg_attr(feature = "curve25519", case("none", "none", "curve25519-sha256"))]
+#[cfg_attr(
+    all(feature = "aes-ctr", feature = "hmac-sha1", feature = "legacy-kex"),
+    case("aes128-ctr", "hmac-sha1", "diffie-hellman-group14-sha1")
+)]
+#[cfg_attr(
+    all(feature = "aes-ctr", feature = "hmac-sha1", feature = "legacy-kex"),
+    case("aes128-ctr", "hmac-sha1", "diffie-hellman-group1-sha1")
+)]
 async fn end_to_end(
     #[case] cipher: &str,
     #[case] mac: &str,
     #[case] kex: &str,
+    #[values("none", "zlib", "zlib@openssh.com")] compression: &str,
 ) -> Result<(), Box<dyn std::error::Error>> {
     use ssh_packet::arch::ascii;
 
+    // The compressions disabled by the features of the build cannot be negociated.
+    let Ok(algorithm) = compression.parse::<Compress>() else {
+        return Ok(());
+    };
+
     tracing_subscriber::fmt()
         .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
         .try_init()
@@ -45,7 +155,9 @@ async fn end_to_end(
 
     let (addr, handle) = common::server().await?;
 
-    tracing::info!("cipher::{cipher}, mac::{mac}, kex::{kex}, bound to {addr}");
+    tracing::info!(
+        "cipher::{cipher}, mac::{mac}, kex::{kex}, compression::{compression}, bound to {addr}"
+    );
 
     let stream = BufReader::new(TcpStream::connect(addr).await?);
     let mut client = Session::new(
@@ -58,7 +170,8 @@ async fn end_to_end(
                 ..Default::default()
             },
             ..Default::default()
-        },
+        }
+        .compressions(&[algorithm]),
     )
     .await?;
 
@@ -85,6 +198,7 @@ async fn end_to_end(
         .await?
         .to::<Success>()
         .expect("Auth refused by peer");
+    client.activate_compression();
 
     client
         .send(&ChannelOpen {
@@ -116,3 +230,436 @@ async fn end_to_end(
 
     Ok(())
 }
+
+#[async_std::test]
+async fn aead_cipher_ignores_macs() -> Result<(), Box<dyn std::error::Error>> {
+    use ssh_packet::arch::ascii;
+
+    let (addr, handle) = common::server().await?;
+
+    let stream = BufReader::new(TcpStream::connect(addr).await?);
+    let mut client = Session::new(
+        stream,
+        Client {
+            algorithms: Algorithms {
+                ciphers: vec![Cipher::ChaCha20Poly1305],
+                macs: vec![],
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    )
+    .await?;
+
+    client
+        .send(&ServiceRequest {
+            service_name: ascii!("ssh-userauth"),
+        })
+        .await?;
+    client
+        .recv()
+        .await?
+        .to::<ServiceAccept>()
+        .expect("Service refused by peer");
+
+    drop(client);
+    handle.await.ok();
+
+    Ok(())
+}
+
+#[cfg(feature = "zlib")]
+#[async_std::test]
+async fn compression_can_differ_per_direction() -> Result<(), Box<dyn std::error::Error>> {
+    use ssh_packet::arch::ascii;
+
+    let (addr, handle) = common::server().await?;
+
+    let stream = BufReader::new(TcpStream::connect(addr).await?);
+    let mut client = Session::new(
+        stream,
+        Client {
+            compression_level: 9,
+            algorithms: Algorithms {
+                compressions_client_to_server: vec![Compress::None],
+                compressions_server_to_client: vec![Compress::Zlib],
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    )
+    .await?;
+
+    for _ in 0..4 {
+        client
+            .send(&ServiceRequest {
+                service_name: ascii!("ssh-userauth"),
+            })
+            .await?;
+    }
+    client
+        .recv()
+        .await?
+        .to::<ServiceAccept>()
+        .expect("Service refused by peer");
+
+    drop(client);
+    handle.await.ok();
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn server_sig_algs_are_advertised() -> Result<(), Box<dyn std::error::Error>> {
+    use ssh_packet::arch::ascii;
+
+    let (addr, handle) = common::server().await?;
+
+    let stream = BufReader::new(TcpStream::connect(addr).await?);
+    let mut client = Session::new(stream, Client::default()).await?;
+
+    client
+        .send(&ServiceRequest {
+            service_name: ascii!("ssh-userauth"),
+        })
+        .await?;
+    client
+        .recv()
+        .await?
+        .to::<ServiceAccept>()
+        .expect("Service refused by peer");
+
+    assert_eq!(
+        client.server_sig_algs(),
+        Some(
+            &[
+                "ssh-ed25519",
+                "sk-ssh-ed25519@openssh.com",
+                "ecdsa-sha2-nistp256",
+                "sk-ecdsa-sha2-nistp256@openssh.com",
+                "ecdsa-sha2-nistp384",
+                "ecdsa-sha2-nistp521",
+                "rsa-sha2-512",
+                "rsa-sha2-256",
+                "ssh-rsa",
+                "ssh-dss"
+            ]
+            .map(String::from)[..]
+        )
+    );
+
+    drop(client);
+    handle.await.ok();
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn into_inner_recovers_the_stream() -> Result<(), Box<dyn std::error::Error>> {
+    use assh::error::{DisconnectedBy, DisconnectedError};
+    use ssh_packet::{arch::ascii, trans::DisconnectReason};
+
+    let (addr, handle) = common::server().await?;
+
+    let stream = BufReader::new(TcpStream::connect(addr).await?);
+    let mut client = Session::new(stream, Client::default()).await?;
+
+    client
+        .send(&ServiceRequest {
+            service_name: ascii!("ssh-userauth"),
+        })
+        .await?;
+    let err = client
+        .disconnect(DisconnectReason::ByApplication, "done")
+        .await;
+
+    let (stream, pending, disconnected) = client.into_inner();
+
+    assert!(pending.is_empty());
+    assert!(matches!(
+        disconnected,
+        Some(DisconnectedError {
+            by: DisconnectedBy::Us,
+            reason: DisconnectReason::ByApplication,
+            description,
+        }) if description == err.description
+    ));
+
+    stream.get_ref().shutdown(std::net::Shutdown::Both)?;
+    handle.await.ok();
+
+    Ok(())
+}
+
+#[async_std::test]
+async fn framed_session_sends_and_receives_packets() -> Result<(), Box<dyn std::error::Error>> {
+    use futures::{SinkExt, TryStreamExt};
+    use ssh_packet::{arch::ascii, IntoPacket};
+
+    let (addr, handle) = common::server().await?;
+
+    let stream = BufReader::new(TcpStream::connect(addr).await?);
+    let mut framed = assh::Framed::new(Session::new(stream, Client::default()).await?);
+
+    framed
+        .send(
+            ServiceRequest {
+                service_name: ascii!("ssh-userauth"),
+            }
+            .into_packet(),
+        )
+        .await?;
+    framed
+        .try_next()
+        .await?
+        .expect("Peer disconnected before accepting the service")
+        .to::<ServiceAccept>()
+        .expect("Service refused by peer");
+
+    let client = framed.into_inner().await?;
+
+    drop(client);
+    handle.await.ok();
+
+    Ok(())
+}
+
+/// A layer recording the disconnections it's notified of.
+#[derive(Clone, Default)]
+struct Recorder(std::sync::Arc<std::sync::Mutex<Vec<assh::error::DisconnectedError>>>);
+
+impl assh::layer::Layer for Recorder {
+    fn on_disconnect(&mut self, disconnected: &assh::error::DisconnectedError) {
+        self.0.lock().unwrap().push(disconnected.clone());
+    }
+}
+
+#[async_std::test]
+async fn layers_are_notified_of_disconnections() -> Result<(), Box<dyn std::error::Error>> {
+    use assh::{
+        error::{DisconnectedBy, DisconnectedError},
+        side::server::Server,
+    };
+    use async_std::net::TcpListener;
+    use ssh_packet::trans::DisconnectReason;
+
+    let socket = TcpListener::bind(("127.0.0.1", 0)).await?;
+    let addr = socket.local_addr()?;
+
+    let (server, client) = (Recorder::default(), Recorder::default());
+
+    let handle = async_std::task::spawn({
+        let server = server.clone();
+
+        async move {
+            let (stream, _) = socket.accept().await?;
+            let config = Server {
+                keys: vec![ssh_key::PrivateKey::random(
+                    &mut rand::thread_rng(),
+                    ssh_key::Algorithm::Ed25519,
+                )
+                .unwrap()],
+                ..Default::default()
+            };
+            let mut session = Session::new(BufReader::new(stream), config)
+                .await?
+                .with_layer(server);
+
+            session.recv().await
+        }
+    });
+
+    let stream = BufReader::new(TcpStream::connect(addr).await?);
+    let mut session = Session::new(stream, Client::default())
+        .await?
+        .with_layer(client.clone());
+
+    // Complete the key-exchange before disconnecting.
+    session.writable().await?;
+    let _ = session
+        .disconnect(DisconnectReason::ByApplication, "done")
+        .await;
+
+    assert!(matches!(handle.await, Err(Error::Disconnected(_))));
+
+    // Each side is notified once, of the disconnection sent by the client.
+    assert!(matches!(
+        &client.0.lock().unwrap()[..],
+        [DisconnectedError {
+            by: DisconnectedBy::Us,
+            reason: DisconnectReason::ByApplication,
+            description,
+        }] if description == "done"
+    ));
+    assert!(matches!(
+        &server.0.lock().unwrap()[..],
+        [DisconnectedError {
+            by: DisconnectedBy::Them,
+            reason: DisconnectReason::ByApplication,
+            description,
+        }] if description == "done"
+    ));
+
+    Ok(())
+}
+
+/// A service answering it's request with it's index `N`.
+struct Indexed<const N: usize>;
+
+impl<const N: usize> assh::service::Handler for Indexed<N> {
+    type Err = Error;
+    type Ok<IO: assh::Pipe, S: assh::side::Side> = usize;
+
+    const SERVICE_NAME: ssh_packet::arch::Ascii<'static> = match N {
+        0 => ssh_packet::arch::ascii!("first@assh.rs"),
+        1 => ssh_packet::arch::ascii!("second@assh.rs"),
+        _ => ssh_packet::arch::ascii!("third@assh.rs"),
+    };
+
+    async fn on_request<IO, S>(&mut self, _: Session<IO, S>) -> Result<Self::Ok<IO, S>>
+    where
+        IO: assh::Pipe,
+        S: assh::side::Side,
+    {
+        Ok(N)
+    }
+}
+
+#[async_std::test]
+async fn services_are_dispatched_by_name() -> Result<(), Box<dyn std::error::Error>> {
+    use assh::{error::DisconnectedError, service::Dispatched, side::server::Server};
+    use async_std::net::TcpListener;
+    use ssh_packet::{arch::Ascii, trans::DisconnectReason};
+
+    let socket = TcpListener::bind(("127.0.0.1", 0)).await?;
+    let addr = socket.local_addr()?;
+
+    let names = [
+        "first@assh.rs",
+        "second@assh.rs",
+        "third@assh.rs",
+        "unknown@assh.rs",
+    ];
+
+    let handle = async_std::task::spawn(async move {
+        let mut outcomes = Vec::new();
+
+        for _ in 0..names.len() {
+            let (stream, _) = socket.accept().await?;
+            let config = Server {
+                keys: vec![ssh_key::PrivateKey::random(
+                    &mut rand::thread_rng(),
+                    ssh_key::Algorithm::Ed25519,
+                )
+                .unwrap()],
+                ..Default::default()
+            };
+
+            outcomes.push(
+                Session::new(BufReader::new(stream), config)
+                    .await?
+                    .handle((Indexed::<0>, Indexed::<1>, Indexed::<2>))
+                    .await,
+            );
+        }
+
+        Ok::<_, Error>(outcomes)
+    });
+
+    let mut accepted = Vec::new();
+    for name in names {
+        let stream = BufReader::new(TcpStream::connect(addr).await?);
+        let mut session = Session::new(stream, Client::default()).await?;
+
+        session
+            .send(&ServiceRequest {
+                service_name: Ascii::borrowed(name)?,
+            })
+            .await?;
+        accepted.push(
+            session
+                .recv()
+                .await
+                .is_ok_and(|packet| packet.to::<ServiceAccept>().is_ok()),
+        );
+    }
+
+    let outcomes = handle.await?;
+
+    assert_eq!(accepted, [true, true, true, false]);
+    assert!(matches!(
+        &outcomes[..],
+        [
+            Ok(Dispatched::First(0)),
+            Ok(Dispatched::Second(1)),
+            Ok(Dispatched::Third(2)),
+            Err(Error::Disconnected(DisconnectedError {
+                reason: DisconnectReason::ServiceNotAvailable,
+                ..
+            }))
+        ]
+    ));
+
+    Ok(())
+}
+
+/// The marker naming the echo service of [`closures_handle_and_request_services`].
+struct Echo;
+
+impl assh::service::ServiceName for Echo {
+    const SERVICE_NAME: ssh_packet::arch::Ascii<'static> = ssh_packet::arch::ascii!("echo@assh.rs");
+}
+
+#[async_std::test]
+async fn closures_handle_and_request_services() -> Result<(), Box<dyn std::error::Error>> {
+    use assh::{
+        service::{handler_fn, request_fn},
+        side::server::Server,
+    };
+    use async_std::net::TcpListener;
+    use ssh_packet::Packet;
+
+    let socket = TcpListener::bind(("127.0.0.1", 0)).await?;
+    let addr = socket.local_addr()?;
+
+    let handle = async_std::task::spawn(async move {
+        let (stream, _) = socket.accept().await?;
+        let config = Server {
+            keys: vec![ssh_key::PrivateKey::random(
+                &mut rand::thread_rng(),
+                ssh_key::Algorithm::Ed25519,
+            )
+            .unwrap()],
+            ..Default::default()
+        };
+
+        Session::new(BufReader::new(stream), config)
+            .await?
+            .handle(handler_fn(Echo, |mut session| async move {
+                let packet = session.recv().await?;
+                session.send(packet.clone()).await?;
+
+                Ok::<_, Error>(packet.payload)
+            }))
+            .await
+    });
+
+    let stream = BufReader::new(TcpStream::connect(addr).await?);
+    let echoed = Session::new(stream, Client::default())
+        .await?
+        .request(request_fn(Echo, |mut session| async move {
+            session
+                .send(Packet {
+                    payload: vec![192, 1, 2, 3],
+                })
+                .await?;
+
+            Ok::<_, Error>(session.recv().await?.payload)
+        }))
+        .await?;
+
+    assert_eq!(echoed, [192, 1, 2, 3]);
+    assert_eq!(handle.await?, [192, 1, 2, 3]);
+
+    Ok(())
+}